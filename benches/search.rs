@@ -0,0 +1,105 @@
+//! How long loading and searching a large history takes: parsing it,
+//! counting its commands, preparing a [`Searcher`], and each keystroke of a
+//! query as it is typed.
+//!
+//! Run with `cargo bench`. The history is made up, but the same every run.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::hint::black_box;
+use th_rs::history::Format;
+use th_rs::search::rank::Ranking;
+use th_rs::search::{self, CaseMode, Filter, Searcher};
+
+const PROGRAMS: [&str; 12] = [
+    "git", "cargo", "ls", "cd", "docker", "kubectl", "grep", "make", "ssh", "vim", "npm", "curl",
+];
+const WORDS: [&str; 16] = [
+    "status",
+    "build",
+    "--release",
+    "-la",
+    "src",
+    "main",
+    "origin",
+    "push",
+    "run",
+    "logs",
+    "deploy",
+    "test",
+    "README.md",
+    "/var/log",
+    "--force",
+    "localhost:8080",
+];
+
+/// A `.bash_history` with timestamps, of `lines` commands.
+fn bash_history(lines: usize) -> String {
+    // A linear congruential generator, for the same history every run.
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = |n: usize| {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+        (seed >> 33) as usize % n
+    };
+    let mut history = String::new();
+    for i in 0..lines {
+        history.push_str(&format!(
+            "#{}\n{}",
+            1_700_000_000 + i,
+            PROGRAMS[next(PROGRAMS.len())]
+        ));
+        for _ in 0..1 + next(4) {
+            history.push(' ');
+            history.push_str(WORDS[next(WORDS.len())]);
+        }
+        // Numbers make most commands distinct, as in a real history.
+        history.push_str(&format!(" {}\n", next(lines)));
+    }
+    history
+}
+
+fn benches(c: &mut Criterion) {
+    let history = bash_history(1_000_000);
+    let entries = Format::Bash.parse(history.as_bytes());
+    let ranking = Ranking::default();
+    let frequency = search::build_frequency_map(entries.clone(), &ranking);
+
+    c.bench_function("parse 1M bash lines", |b| {
+        b.iter(|| Format::Bash.parse(black_box(history.as_bytes())))
+    });
+    c.bench_function("count 1M commands", |b| {
+        b.iter_batched(
+            || entries.clone(),
+            |entries| search::build_frequency_map(entries, &ranking),
+            BatchSize::LargeInput,
+        )
+    });
+    c.bench_function("prepare a searcher", |b| {
+        b.iter(|| Searcher::new(&frequency, CaseMode::Smart, &ranking, Filter::default()))
+    });
+
+    let query = "git push origin";
+    let mut group = c.benchmark_group("keystroke");
+    group.sample_size(10);
+    group.bench_function("first", |b| {
+        b.iter_batched(
+            || Searcher::new(&frequency, CaseMode::Smart, &ranking, Filter::default()),
+            |mut searcher| searcher.search(black_box("x")).len(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("typing \"git push origin\"", |b| {
+        b.iter_batched(
+            || Searcher::new(&frequency, CaseMode::Smart, &ranking, Filter::default()),
+            |mut searcher| {
+                for end in 1..=query.len() {
+                    black_box(searcher.search(&query[..end]).len());
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(search_benches, benches);
+criterion_main!(search_benches);
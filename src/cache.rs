@@ -0,0 +1,297 @@
+//! The frequency map counted from history files, kept between runs in
+//! `~/.cache/th_rs` so that starting up need not parse them all again.
+//!
+//! Along with the map, the cache notes how long each file was and a
+//! checksum of the bytes just before its end. A file that has only grown
+//! since is read from where the cache left off; one changed in any other
+//! way, as when a shell trims its history, is read again from the start.
+//! Both are noted before the files are read, and only that much of them
+//! read, so that what a shell appends meanwhile is counted on the next load.
+
+use crate::error::Result;
+use crate::history::{Entry, Source};
+use crate::paths;
+use crate::search::rank::{self, Ranking};
+use crate::search::{self, FrequencyMap};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::process;
+
+/// How many bytes before the end of a file the checksum covers.
+const CHECKED_BYTES: u64 = 4096;
+
+/// Bumped whenever what the cache holds changes shape, as when
+/// [`search::CommandStats`] gains a field, so that an older cache is passed
+/// over rather than misread.
+const FORMAT: u32 = 3;
+
+/// What the cache file holds.
+#[derive(Serialize, Deserialize)]
+struct Cached {
+    /// The th_rs that wrote it; another version starts over.
+    version: String,
+    /// How the entries were read and prepared before they were counted.
+    settings: String,
+    files: Vec<FileState>,
+    /// When the map's frecency was worked out, in Unix seconds.
+    counted_at: i64,
+    frequency: FrequencyMap,
+}
+
+/// How much of a history file the cached map has counted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FileState {
+    len: u64,
+    checksum: u64,
+}
+
+/// How long the files were before they were read, for [`Cache::save`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    files: Vec<FileState>,
+}
+
+impl Snapshot {
+    /// How many bytes of each file to read, in the order of the sources.
+    pub fn lengths(&self) -> Vec<u64> {
+        self.files.iter().map(|state| state.len).collect()
+    }
+}
+
+/// The cache for one set of history files.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    path: PathBuf,
+    sources: Vec<Source>,
+    settings: String,
+}
+
+impl Cache {
+    /// The cache for the map counted from `sources`. `settings` describes
+    /// everything else the map depends on, such as the ignore list; a cache
+    /// written with different settings is not used.
+    pub fn new(sources: &[Source], settings: String) -> Result<Cache> {
+        let mut hasher = Fnv::default();
+        for source in sources {
+            hasher.write(source.path.as_os_str().as_encoded_bytes());
+            hasher.write(&[0]);
+            hasher.write(source.format.name().as_bytes());
+            hasher.write(&[0]);
+        }
+        let name = format!("frequency-{:016x}.bin", hasher.finish());
+        Ok(Cache {
+            path: paths::cache_dir()?.join(name),
+            sources: sources.to_vec(),
+            settings,
+        })
+    }
+
+    /// The cached map, with anything appended to the files since counted
+    /// in after going through `prepare`, and frecency worked out again per
+    /// `ranking`. `None` when there is no cache to use.
+    pub fn load(
+        &self,
+        ranking: &Ranking,
+        mut prepare: impl FnMut(&mut Vec<Entry>),
+    ) -> Option<FrequencyMap> {
+        // The version comes first, and is read alone first: a cache of
+        // another shape could otherwise be misread as holding anything.
+        let file = File::open(&self.path).ok()?;
+        let version: String = bincode::deserialize_from(BufReader::new(file)).ok()?;
+        if version != self::version() {
+            return None;
+        }
+        let file = File::open(&self.path).ok()?;
+        let cached: Cached = bincode::deserialize_from(BufReader::new(file)).ok()?;
+        if cached.settings != self.settings || cached.files.len() != self.sources.len() {
+            return None;
+        }
+        let mut frequency = cached.frequency;
+        // Aged before what was appended is counted in, already as of now.
+        search::decay(&mut frequency, ranking, cached.counted_at);
+        let mut files = Vec::with_capacity(cached.files.len());
+        let mut grown = false;
+        for (source, state) in self.sources.iter().zip(cached.files) {
+            let appended = appended(source, &state, self.sources.len() > 1)?;
+            if appended.is_empty() {
+                files.push(state);
+            } else {
+                files.push(state_of(source, Some(state.len + appended.len() as u64)).ok()?);
+                let mut entries = source.format.parse(appended.as_slice());
+                for entry in &mut entries {
+                    entry.shell = source.shell;
+                }
+                prepare(&mut entries);
+                search::add_entries(&mut frequency, entries, ranking);
+                grown = true;
+            }
+        }
+        if grown {
+            // Saved again so that next time starts from here.
+            let _ = self.write(&frequency, files);
+        }
+        Some(frequency)
+    }
+
+    /// How long each file is now, to be taken before reading them: as much
+    /// of each as is read, per [`Snapshot::lengths`], and no more.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let files = self
+            .sources
+            .iter()
+            .map(|source| state_of(source, None))
+            .collect::<io::Result<_>>()?;
+        Ok(Snapshot { files })
+    }
+
+    /// Saves `frequency`, counted from each file as far as `snapshot` says
+    /// it went. Whatever was appended past that is read on the next load.
+    pub fn save(&self, frequency: &FrequencyMap, snapshot: Snapshot) -> Result<()> {
+        self.write(frequency, snapshot.files)
+    }
+
+    fn write(&self, frequency: &FrequencyMap, files: Vec<FileState>) -> Result<()> {
+        let cached = Cached {
+            version: version(),
+            settings: self.settings.clone(),
+            files,
+            counted_at: rank::now(),
+            frequency: frequency.clone(),
+        };
+        if let Some(dir) = self.path.parent() {
+            paths::create_private_dir(dir)?;
+        }
+        // Written aside and renamed, so that a run reading the cache at the
+        // same time sees either the old one or the new, under a name of its
+        // own so that runs writing at once do not write into each other's.
+        let (tmp, file) = self.temp_file()?;
+        let written =
+            bincode::serialize_into(BufWriter::new(file), &cached).map_err(io::Error::other);
+        written
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|err| {
+                let _ = fs::remove_file(&tmp);
+                err.into()
+            })
+    }
+
+    /// A new file beside the cache to write it to, readable by the user
+    /// alone, since the cache holds every command in the history.
+    fn temp_file(&self) -> io::Result<(PathBuf, File)> {
+        let mut attempt = 0;
+        loop {
+            let mut tmp = self.path.clone().into_os_string();
+            tmp.push(format!(".{}-{}.tmp", process::id(), attempt));
+            let tmp = PathBuf::from(tmp);
+            match paths::private_file().create_new(true).open(&tmp) {
+                Ok(file) => return Ok((tmp, file)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// What a cache written by this th_rs says it was written by: the version,
+/// and the shape of what it holds.
+fn version() -> String {
+    format!("{}+{}", env!("CARGO_PKG_VERSION"), FORMAT)
+}
+
+/// What was appended to `source` since it was as `state`: whole lines
+/// only, since the shell may still be writing the last. `None` when the
+/// file changed otherwise and has to be read again. A missing file counts
+/// as empty when `merged` with others, as when loading them.
+fn appended(source: &Source, state: &FileState, merged: bool) -> Option<Vec<u8>> {
+    let mut file = match File::open(&source.path) {
+        Ok(file) => file,
+        Err(err) if merged && err.kind() == io::ErrorKind::NotFound => {
+            return (state.len == 0).then(Vec::new);
+        }
+        Err(_) => return None,
+    };
+    if state_of(source, Some(state.len)).ok()? != *state {
+        return None;
+    }
+    let mut appended = Vec::new();
+    file.seek(SeekFrom::Start(state.len))
+        .and_then(|_| file.read_to_end(&mut appended))
+        .ok()?;
+    let complete = appended
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    appended.truncate(complete);
+    Some(appended)
+}
+
+/// The state of `source` up to `len` bytes, or all of it.
+fn state_of(source: &Source, len: Option<u64>) -> io::Result<FileState> {
+    let mut file = match File::open(&source.path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(FileState {
+                len: 0,
+                checksum: checksum(&[]),
+            });
+        }
+        Err(err) => return Err(err),
+    };
+    let len = match len {
+        Some(len) => len,
+        None => file.metadata()?.len(),
+    };
+    let start = len.saturating_sub(CHECKED_BYTES);
+    let mut checked = Vec::new();
+    file.seek(SeekFrom::Start(start))?;
+    file.take(len - start).read_to_end(&mut checked)?;
+    Ok(FileState {
+        len,
+        checksum: checksum(&checked),
+    })
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv::default();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// FNV-1a, for the checksums and the cache's name: unlike the standard
+/// library's hasher, it gives the same hash in every build of th_rs, so a
+/// cache still matches its files after an upgrade.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_the_same_in_every_build() {
+        // The published FNV-1a test vectors.
+        assert_eq!(checksum(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(checksum(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(checksum(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
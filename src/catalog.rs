@@ -0,0 +1,130 @@
+//! A team's shared commands, each with what it does, kept in a TOML file
+//! that can be checked in next to the code it is for.
+//!
+//! The config file lists the catalogs to read:
+//!
+//! ```toml
+//! catalogs = ["~/src/ops/commands.toml"]
+//! ```
+//!
+//! and each catalog holds commands, with a description and tags if wanted:
+//!
+//! ```toml
+//! [[command]]
+//! command = "kubectl -n prod rollout restart deploy/api"
+//! description = "Restart the API pods"
+//! tags = ["deploy", "k8s"]
+//! ```
+//!
+//! Catalog commands are searched with the history, and match by their
+//! description too; the picker shows it dimmed after the command. Chosen,
+//! they are like any other command. `th_rs catalog validate` checks a file.
+
+use crate::config;
+use crate::error::{Result, ThError};
+use crate::search::query;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One command in a catalog.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Item {
+    pub command: String,
+    /// What the command does, shown after it.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Words to find it by, like `deploy`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// A catalog file as written.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default, rename = "command")]
+    commands: Vec<Item>,
+}
+
+/// The commands of the catalog in `text`.
+pub fn parse(text: &str) -> Result<Vec<Item>, String> {
+    toml::from_str::<File>(text)
+        .map(|file| file.commands)
+        .map_err(|err| err.to_string())
+}
+
+/// Reads the catalog at `path`, which may start with `~/`.
+pub fn read(path: &Path) -> Result<Vec<Item>> {
+    let path = config::expand_home(path);
+    let text = fs::read_to_string(&path).map_err(|source| ThError::HistoryFile {
+        path: path.clone(),
+        source,
+    })?;
+    parse(&text).map_err(|message| ThError::Catalog { path, message })
+}
+
+/// Reads every catalog in `paths`, in order.
+pub fn read_all(paths: &[PathBuf]) -> Result<Vec<Item>> {
+    let mut items = Vec::new();
+    for path in paths {
+        items.extend(read(path)?);
+    }
+    Ok(items)
+}
+
+/// What is wrong with `items` that reading them lets pass: blank commands,
+/// commands listed twice, and tags that are blank, hold spaces, or start
+/// with `#`.
+pub fn problems(items: &[Item]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut seen = HashSet::new();
+    for (n, item) in items.iter().enumerate().map(|(i, item)| (i + 1, item)) {
+        if item.command.trim().is_empty() {
+            problems.push(format!("command {} is blank", n));
+        } else if !seen.insert(item.command.as_str()) {
+            problems.push(format!("command {} is listed before: {}", n, item.command));
+        }
+        if item
+            .description
+            .as_deref()
+            .is_some_and(|d| d.trim().is_empty())
+        {
+            problems.push(format!("command {} has a blank description", n));
+        }
+        for tag in &item.tags {
+            if !query::is_tag(tag) {
+                problems.push(format!(
+                    "command {} has a tag that is blank, holds spaces, or starts with #: {:?}",
+                    n, tag
+                ));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_problems_reading_lets_pass() {
+        let text = r#"
+            [[command]]
+            command = "make deploy"
+            description = "Ship it"
+            tags = ["deploy"]
+
+            [[command]]
+            command = "make deploy"
+            tags = ["two words"]
+        "#;
+        let items = parse(text).unwrap();
+        assert_eq!(items[0].description.as_deref(), Some("Ship it"));
+        assert_eq!(problems(&items).len(), 2);
+        assert!(parse("[[command]]\ncommand = \"ls\"\ndescripton = \"typo\"\n").is_err());
+    }
+}
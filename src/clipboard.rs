@@ -0,0 +1,17 @@
+//! Putting text on the system clipboard.
+//!
+//! The text is sent to the terminal in an OSC 52 escape sequence, which the
+//! terminal forwards to the clipboard of the machine it runs on. Unlike
+//! talking to X11 or the Windows clipboard directly, this also works over SSH
+//! and needs no display server. Most terminals support it; tmux needs
+//! `set -g set-clipboard on`.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::io::{self, Write};
+
+/// Asks the terminal behind `out` to copy `text` to the clipboard.
+pub fn copy<W: Write>(out: &mut W, text: &str) -> io::Result<()> {
+    write!(out, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    out.flush()
+}
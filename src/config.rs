@@ -0,0 +1,220 @@
+//! User configuration, read from `~/.config/th_rs/config.toml`.
+//!
+//! Every setting is optional; a missing file means all defaults. Command-line
+//! flags override whatever the file says.
+//!
+//! A profile is a set of settings to use over the rest, e.g. other history
+//! files and ignore patterns for work:
+//!
+//! ```toml
+//! history_files = ["~/.zsh_history"]
+//!
+//! [profile.work]
+//! history_files = ["~/work/.zsh_history"]
+//! ignore.patterns = ["client-"]
+//! theme.prompt = "yellow"
+//! ```
+//!
+//! Tables are merged key by key, so `theme.prompt` leaves the other colors;
+//! anything else, lists included, replaces what the rest of the file says.
+
+use crate::error::{Result, ThError};
+use crate::history::backup::BackupConfig;
+use crate::history::ignore::IgnoreConfig;
+use crate::history::normalize;
+use crate::history::provider::ProviderConfig;
+use crate::paths;
+use crate::search::rank::Ranking;
+use crate::search::CaseMode;
+use crate::security::danger::DangerConfig;
+use crate::shell::Shell;
+use crate::store::StoreConfig;
+use crate::sync::SyncConfig;
+use crate::ui::inline::Height;
+use crate::ui::keys::KeysConfig;
+use crate::ui::theme::ThemeConfig;
+use crate::ui::Join;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Settings loaded from the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// How letter case is treated when matching: `smart`, `sensitive`, or
+    /// `insensitive`.
+    pub case: CaseMode,
+    /// How results are ordered.
+    pub ranking: Ranking,
+    /// The shells whose history files are merged into one search. Empty
+    /// means only the current shell's.
+    pub sources: Vec<Shell>,
+    /// Extra history files to search, e.g. a relocated `HISTFILE` or a
+    /// backup. Their format is detected from the name and contents. A
+    /// leading `~/` means the home directory.
+    pub history_files: Vec<PathBuf>,
+    /// Whether a shell's history file is looked for in its startup files,
+    /// e.g. a `HISTFILE=` line in `~/.zshrc`, when `HISTFILE` is not in the
+    /// environment.
+    pub histfile_from_rc: bool,
+    /// How many seconds a history fetched with `--remote` is searched
+    /// before it is fetched again; ten minutes if not given.
+    pub remote_ttl: Option<u64>,
+    /// Other places to find commands in, like terminal transcripts; see
+    /// [`crate::history::provider`].
+    pub providers: Vec<ProviderConfig>,
+    /// How commands marked with Tab are combined: `newline` or `and`
+    /// (`&&`).
+    pub join: Join,
+    /// The picker's colors.
+    pub theme: ThemeConfig,
+    /// Keys bound to picker actions in place of the defaults.
+    pub keys: KeysConfig,
+    /// Commands left out of the index, e.g. ones carrying secrets.
+    pub ignore: IgnoreConfig,
+    /// Commands that need a yes before the picker runs them.
+    pub danger: DangerConfig,
+    /// How commands are tidied before identical ones are counted together.
+    pub normalize: normalize::Rules,
+    /// Show what look like secrets in the picker as they are, rather than
+    /// masked until Ctrl-R.
+    pub show_secrets: bool,
+    /// Whether Esc in the picker switches to a normal mode with vim-style
+    /// keys.
+    pub vim: bool,
+    /// Whether the picker takes the mouse, to click and scroll through
+    /// results, at the cost of the terminal's own text selection.
+    pub mouse: bool,
+    /// Draw the picker inline, in this many rows or this percentage of the
+    /// terminal below the prompt, rather than on the alternate screen.
+    pub height: Option<Height>,
+    /// Whether each result in the picker shows a sparkline of its runs
+    /// over the last month.
+    pub sparkline: bool,
+    /// A shell command that explains the command it is given on stdin, run
+    /// by the picker's explain key (alt-e).
+    pub explain: Option<String>,
+    /// Whether the chosen command is added to the end of the shell's
+    /// history file.
+    pub append_on_select: bool,
+    /// How many copies of history files th_rs keeps from before it changed
+    /// them.
+    pub backup: BackupConfig,
+    /// Catalogs of shared commands with descriptions, searched with the
+    /// history; see [`crate::catalog`].
+    pub catalogs: Vec<PathBuf>,
+    /// Whether the history store is encrypted, and with what.
+    pub store: StoreConfig,
+    /// Where `th_rs sync` merges the store with other machines'.
+    pub sync: SyncConfig,
+}
+
+impl Config {
+    /// Loads the config from its default location, with the settings of
+    /// `profile` over the rest if one is given.
+    pub fn load(profile: Option<&str>) -> Result<Config> {
+        match default_path() {
+            Some(path) => Config::load_from(&path, profile),
+            None => Config::from_str("", profile).map_err(|message| ThError::Config {
+                path: PathBuf::new(),
+                message,
+            }),
+        }
+    }
+
+    /// Loads the config from `path`, with the settings of `profile` over
+    /// the rest if one is given; a file that does not exist yields the
+    /// defaults.
+    pub fn load_from(path: &Path, profile: Option<&str>) -> Result<Config> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Config::from_str(&text, profile).map_err(|message| ThError::Config {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    /// Reads the config in `text`, with `[profile.<profile>]` merged over
+    /// the rest.
+    fn from_str(text: &str, profile: Option<&str>) -> Result<Config, String> {
+        let Some(name) = profile else {
+            return toml::from_str(text).map_err(|err| err.to_string());
+        };
+        let mut table: toml::Table = toml::from_str(text).map_err(|err| err.to_string())?;
+        let profiles = match table.remove("profile") {
+            Some(toml::Value::Table(profiles)) => profiles,
+            _ => toml::Table::new(),
+        };
+        let Some(toml::Value::Table(settings)) = profiles.get(name) else {
+            let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            return Err(match names.as_slice() {
+                [] => format!("no profile named {}: there is no [profile.NAME]", name),
+                names => format!("no profile named {} (there are {})", name, names.join(", ")),
+            });
+        };
+        merge(&mut table, settings.clone());
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(|err: toml::de::Error| format!("in profile {}: {}", name, err))
+    }
+}
+
+/// Puts the values of `over` in `base`, merging tables key by key.
+fn merge(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Expands a leading `~/` in a path from the config file.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), paths::home_dir()) {
+        (Ok(rest), Ok(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `config.toml` in the th_rs config directory, e.g.
+/// `~/.config/th_rs/config.toml` or `%APPDATA%\th_rs\config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    paths::config_dir().ok().map(|dir| dir.join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"
+        vim = true
+        history_files = ["~/.zsh_history"]
+        theme.prompt = "blue"
+        theme.selection = "gray"
+
+        [profile.work]
+        history_files = ["~/work/.zsh_history"]
+        theme.prompt = "yellow"
+    "#;
+
+    #[test]
+    fn profiles_override_the_rest() {
+        let plain = Config::from_str(TEXT, None).unwrap();
+        assert_eq!(plain.history_files, [PathBuf::from("~/.zsh_history")]);
+        let work = Config::from_str(TEXT, Some("work")).unwrap();
+        assert!(work.vim);
+        assert_eq!(work.history_files, [PathBuf::from("~/work/.zsh_history")]);
+        assert_eq!(work.theme.prompt, Some("yellow".parse().unwrap()));
+        assert_eq!(work.theme.selection, plain.theme.selection);
+        let err = Config::from_str(TEXT, Some("home")).unwrap_err();
+        assert_eq!(err, "no profile named home (there are work)");
+    }
+}
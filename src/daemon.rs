@@ -0,0 +1,498 @@
+//! Keeping history loaded between searches, for `th_rs daemon`.
+//!
+//! Parsing a large history file costs time on every Ctrl-R. A daemon pays it
+//! once: it loads history at startup, follows the files as shells append to
+//! them, and answers over a Unix domain socket. The picker, `query`, and
+//! `list` ask it first, and read history themselves when no daemon is running
+//! or it serves other files than they would read.
+//!
+//! The protocol is one JSON object per line each way. A [`Request`] names its
+//! method, e.g. `{"method":"query","pattern":"git","limit":5}`, and is
+//! answered with a [`Response`], here `{"matches":[["git status",{...}]]}`.
+//! A connection can carry any number of requests.
+//!
+//! The daemon may have been started anywhere, so a search says where the
+//! client is, as `cwd`, and is ranked for that directory and its repository
+//! rather than the daemon's.
+
+use crate::error::Result;
+use crate::history::{normalize, Entry};
+use crate::paths;
+use crate::search::rank::Ranking;
+use crate::search::{self, CaseMode, CommandStats, FrequencyMap};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[cfg(unix)]
+pub use unix::{bind, Client, Server};
+
+#[cfg(not(unix))]
+pub use unsupported::Client;
+
+/// Where the daemon listens: `daemon.sock` in the runtime directory, e.g.
+/// `$XDG_RUNTIME_DIR/th_rs/daemon.sock`.
+pub fn socket_path() -> Result<PathBuf> {
+    Ok(paths::runtime_dir()?.join("daemon.sock"))
+}
+
+/// What a client asks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum Request {
+    /// Which history is served, and how often it has changed.
+    Hello,
+    /// Every command and its statistics, for a picker to search itself.
+    Frequency,
+    /// The commands matching `pattern`, best first, as `th_rs query` prints
+    /// them.
+    Query {
+        pattern: String,
+        /// At most this many; all of them if absent.
+        #[serde(default)]
+        limit: Option<usize>,
+        /// Defaults to the daemon's configured case mode.
+        #[serde(default)]
+        case: Option<CaseMode>,
+        /// Where the client is; nowhere in particular if absent.
+        #[serde(default)]
+        cwd: Option<String>,
+    },
+    /// The commands completing `prefix`, best first, as `th_rs complete`
+    /// prints them.
+    Complete {
+        prefix: String,
+        limit: usize,
+        #[serde(default)]
+        cwd: Option<String>,
+    },
+}
+
+/// What the daemon answers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Response {
+    Hello(Status),
+    Frequency {
+        generation: u64,
+        commands: FrequencyMap,
+    },
+    Matches(Vec<(String, CommandStats)>),
+    /// The request could not be read.
+    Error(String),
+}
+
+/// The answer to [`Request::Hello`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    /// The history files loaded, in order.
+    pub sources: Vec<PathBuf>,
+    /// Goes up whenever the history changes, so that a client can tell
+    /// whether what it fetched is still current.
+    pub generation: u64,
+}
+
+/// The history a daemon serves.
+#[derive(Debug, Default)]
+pub struct History {
+    generation: u64,
+    frequency: FrequencyMap,
+}
+
+impl History {
+    pub fn new(frequency: FrequencyMap) -> History {
+        History {
+            generation: 0,
+            frequency,
+        }
+    }
+
+    /// Counts runs appended since the history was loaded, folding commands
+    /// together per `normalize`.
+    pub fn add(&mut self, entries: Vec<Entry>, ranking: &Ranking, normalize: &normalize::Rules) {
+        search::add_entries(&mut self.frequency, entries, ranking);
+        search::fold(&mut self.frequency, normalize);
+        self.generation += 1;
+    }
+
+    /// Replaces everything, after a history file was rewritten.
+    pub fn replace(&mut self, frequency: FrequencyMap) {
+        self.frequency = frequency;
+        self.generation += 1;
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::{History, Request, Response, Status};
+    use crate::error::{Result, ThError};
+    use crate::search::rank::{self, Ranking};
+    use crate::search::{self, CaseMode, CommandStats, Filter, FrequencyMap};
+    use std::borrow::Cow;
+    use std::fs;
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, RwLock};
+    use std::thread;
+    use std::time::Duration;
+
+    /// How long a client waits for an answer before giving up on the
+    /// daemon and reading history itself.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Listens on `path`, which only the user can connect to. A socket left
+    /// behind by a daemon that has exited is replaced; one that still
+    /// answers is an error.
+    ///
+    /// The socket's directory is th_rs's own and is made private first, as
+    /// the socket is made at the mode the umask gives and only narrowed
+    /// after.
+    pub fn bind(path: &Path) -> Result<UnixListener> {
+        if UnixStream::connect(path).is_ok() {
+            return Err(ThError::DaemonRunning(path.to_path_buf()));
+        }
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        if let Some(dir) = path.parent() {
+            if let Some(parent) = dir.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match fs::DirBuilder::new().mode(0o700).create(dir) {
+                Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err.into()),
+                _ => {}
+            }
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+
+    /// Answers requests about one history, from any number of clients at
+    /// once.
+    pub struct Server {
+        sources: Vec<PathBuf>,
+        case: CaseMode,
+        ranking: Ranking,
+        history: RwLock<History>,
+    }
+
+    impl Server {
+        /// Serves `history`, loaded from `sources`, searching it per `case`
+        /// and `ranking` unless a query says otherwise.
+        pub fn new(
+            sources: Vec<PathBuf>,
+            case: CaseMode,
+            ranking: Ranking,
+            history: History,
+        ) -> Self {
+            Server {
+                sources,
+                case,
+                ranking,
+                history: RwLock::new(history),
+            }
+        }
+
+        /// The history, to be updated as its files change.
+        pub fn history(&self) -> &RwLock<History> {
+            &self.history
+        }
+
+        /// The ranking the history was loaded with.
+        pub fn ranking(&self) -> &Ranking {
+            &self.ranking
+        }
+
+        /// The ranking for a client in `cwd`, which boosts what ran there
+        /// and in its repository.
+        fn ranking_for(&self, cwd: Option<String>) -> Cow<'_, Ranking> {
+            match cwd {
+                None => Cow::Borrowed(&self.ranking),
+                Some(cwd) => Cow::Owned(Ranking {
+                    repo: rank::repo_root(Path::new(&cwd)),
+                    cwd: Some(cwd),
+                    ..self.ranking.clone()
+                }),
+            }
+        }
+
+        /// Accepts connections on `listener` until it fails, each on a
+        /// thread of its own.
+        pub fn serve(self: Arc<Self>, listener: UnixListener) -> Result<()> {
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let server = Arc::clone(&self);
+                thread::spawn(move || {
+                    // A client that went away mid-answer is its own concern.
+                    let _ = server.handle(stream);
+                });
+            }
+            Ok(())
+        }
+
+        fn handle(&self, stream: UnixStream) -> io::Result<()> {
+            let mut writer = stream.try_clone()?;
+            for line in BufReader::new(stream).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let response = match serde_json::from_str(&line) {
+                    Ok(request) => self.answer(request),
+                    Err(err) => Response::Error(format!("invalid request: {}", err)),
+                };
+                serde_json::to_writer(&mut writer, &response)?;
+                writer.write_all(b"\n")?;
+            }
+            Ok(())
+        }
+
+        fn answer(&self, request: Request) -> Response {
+            // A thread that panicked while updating left the map as usable
+            // as anything else it would have served.
+            let history = self.history.read().unwrap_or_else(|err| err.into_inner());
+            match request {
+                Request::Hello => Response::Hello(Status {
+                    sources: self.sources.clone(),
+                    generation: history.generation,
+                }),
+                Request::Frequency => Response::Frequency {
+                    generation: history.generation,
+                    commands: history.frequency.clone(),
+                },
+                Request::Query {
+                    pattern,
+                    limit,
+                    case,
+                    cwd,
+                } => {
+                    let case = case.unwrap_or(self.case);
+                    let matches = search::search(
+                        &history.frequency,
+                        &pattern,
+                        case,
+                        &self.ranking_for(cwd),
+                        &Filter::default(),
+                    );
+                    Response::Matches(
+                        matches
+                            .into_iter()
+                            .take(limit.unwrap_or(usize::MAX))
+                            .map(|(command, stats)| (command.to_string(), stats.clone()))
+                            .collect(),
+                    )
+                }
+                Request::Complete { prefix, limit, cwd } => {
+                    let completions = search::complete(
+                        &history.frequency,
+                        &prefix,
+                        &self.ranking_for(cwd),
+                        &Filter::default(),
+                        limit,
+                    );
+                    Response::Matches(
+                        completions
+                            .into_iter()
+                            .map(|(command, stats)| (command.to_string(), stats.clone()))
+                            .collect(),
+                    )
+                }
+            }
+        }
+    }
+
+    /// A connection to a running daemon.
+    pub struct Client {
+        reader: BufReader<UnixStream>,
+        writer: UnixStream,
+    }
+
+    impl Client {
+        /// Connects to the daemon listening on `path`; fails at once if
+        /// there is none.
+        pub fn connect(path: &Path) -> io::Result<Client> {
+            let stream = UnixStream::connect(path)?;
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            Ok(Client {
+                writer: stream.try_clone()?,
+                reader: BufReader::new(stream),
+            })
+        }
+
+        /// Which history the daemon serves.
+        pub fn hello(&mut self) -> io::Result<Status> {
+            match self.call(&Request::Hello)? {
+                Response::Hello(status) => Ok(status),
+                other => Err(unexpected(other)),
+            }
+        }
+
+        /// Everything the daemon knows, and its generation.
+        pub fn frequency(&mut self) -> io::Result<(u64, FrequencyMap)> {
+            match self.call(&Request::Frequency)? {
+                Response::Frequency {
+                    generation,
+                    commands,
+                } => Ok((generation, commands)),
+                other => Err(unexpected(other)),
+            }
+        }
+
+        /// The commands matching `pattern`, best first, for a client in
+        /// `cwd`.
+        pub fn query(
+            &mut self,
+            pattern: &str,
+            limit: Option<usize>,
+            case: CaseMode,
+            cwd: Option<&str>,
+        ) -> io::Result<Vec<(String, CommandStats)>> {
+            let request = Request::Query {
+                pattern: pattern.to_string(),
+                limit,
+                case: Some(case),
+                cwd: cwd.map(str::to_string),
+            };
+            match self.call(&request)? {
+                Response::Matches(matches) => Ok(matches),
+                other => Err(unexpected(other)),
+            }
+        }
+
+        /// The commands completing `prefix`, best first, for a client in
+        /// `cwd`.
+        pub fn complete(
+            &mut self,
+            prefix: &str,
+            limit: usize,
+            cwd: Option<&str>,
+        ) -> io::Result<Vec<(String, CommandStats)>> {
+            let request = Request::Complete {
+                prefix: prefix.to_string(),
+                limit,
+                cwd: cwd.map(str::to_string),
+            };
+            match self.call(&request)? {
+                Response::Matches(matches) => Ok(matches),
+                other => Err(unexpected(other)),
+            }
+        }
+
+        fn call(&mut self, request: &Request) -> io::Result<Response> {
+            serde_json::to_writer(&mut self.writer, request)?;
+            self.writer.write_all(b"\n")?;
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            Ok(serde_json::from_str(&line)?)
+        }
+    }
+
+    fn unexpected(response: Response) -> io::Error {
+        let message = match response {
+            Response::Error(message) => message,
+            _ => "unexpected answer from the daemon".to_string(),
+        };
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+}
+
+/// Where there are no Unix domain sockets there is never a daemon to ask.
+#[cfg(not(unix))]
+mod unsupported {
+    use super::Status;
+    use crate::search::{CaseMode, CommandStats, FrequencyMap};
+    use std::convert::Infallible;
+    use std::io;
+    use std::path::Path;
+
+    /// A connection that cannot be made.
+    pub struct Client(Infallible);
+
+    impl Client {
+        /// Always fails, so that callers read history themselves.
+        pub fn connect(_path: &Path) -> io::Result<Client> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        pub fn hello(&mut self) -> io::Result<Status> {
+            match self.0 {}
+        }
+
+        pub fn frequency(&mut self) -> io::Result<(u64, FrequencyMap)> {
+            match self.0 {}
+        }
+
+        pub fn query(
+            &mut self,
+            _pattern: &str,
+            _limit: Option<usize>,
+            _case: CaseMode,
+            _cwd: Option<&str>,
+        ) -> io::Result<Vec<(String, CommandStats)>> {
+            match self.0 {}
+        }
+
+        pub fn complete(
+            &mut self,
+            _prefix: &str,
+            _limit: usize,
+            _cwd: Option<&str>,
+        ) -> io::Result<Vec<(String, CommandStats)>> {
+            match self.0 {}
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn answers_over_its_socket_for_where_the_client_is() {
+        let dir = std::env::temp_dir().join(format!("th_rs-daemon-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let ranking = Ranking {
+            cwd_boost: 100.0,
+            ..Ranking::default()
+        };
+        let mut entries = vec![Entry::new("make"); 3];
+        entries.push(Entry {
+            cwd: Some("/src/app".to_string()),
+            ..Entry::new("make test")
+        });
+        let mut frequency = FrequencyMap::new();
+        search::add_entries(&mut frequency, entries, &ranking);
+        let path = dir.join("daemon.sock");
+        let listener = bind(&path).unwrap();
+        let server = Arc::new(Server::new(
+            vec![PathBuf::from("history")],
+            CaseMode::Smart,
+            ranking,
+            History::new(frequency),
+        ));
+        thread::spawn(move || server.serve(listener));
+
+        let mut client = Client::connect(&path).unwrap();
+        let (generation, commands) = client.frequency().unwrap();
+        assert_eq!(generation, 0);
+        assert_eq!(commands["make"].count, 3);
+        assert_eq!(commands["make test"].count, 1);
+        let mut query = |cwd| -> Vec<String> {
+            let matches = client.query("make", None, CaseMode::Smart, cwd).unwrap();
+            matches.into_iter().map(|(command, _)| command).collect()
+        };
+        assert_eq!(query(None), ["make", "make test"]);
+        assert_eq!(query(Some("/src/app")), ["make test", "make"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
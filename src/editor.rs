@@ -0,0 +1,113 @@
+//! Changing a command in the user's text editor, for those too long or of
+//! too many lines to edit in the picker.
+//!
+//! The editor is `$VISUAL`, else `$EDITOR`, else `vi` (Notepad on Windows).
+//! Like git, th_rs runs it through `sh`, so that it can take arguments, as
+//! in `EDITOR="code --wait"`, and quotes, as in a path with spaces. On
+//! Windows it is split at whitespace instead.
+//!
+//! The file the command is written to is readable by the user alone, as the
+//! command may hold a secret and the temporary directory is shared.
+
+use crate::paths;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Opens `text` in the editor and returns what was saved, or `None` if the
+/// editor failed or the file was emptied. The caller hands the terminal
+/// over first: out of raw mode and off the alternate screen.
+pub fn edit(text: &str) -> io::Result<Option<String>> {
+    let (path, mut file) = temp_file()?;
+    let result = file
+        .write_all(text.as_bytes())
+        .and_then(|()| file.write_all(b"\n"))
+        .and_then(|()| {
+            drop(file);
+            run(&path)
+        })
+        .and_then(|saved| match saved {
+            true => fs::read_to_string(&path).map(Some),
+            false => Ok(None),
+        });
+    let _ = fs::remove_file(&path);
+    Ok(result?
+        .map(|edited| edited.trim_end_matches(['\n', '\r']).to_string())
+        .filter(|edited| !edited.trim().is_empty()))
+}
+
+/// The editor to use, as the user wrote it.
+fn editor() -> String {
+    let configured = ["VISUAL", "EDITOR"].into_iter().find_map(|var| {
+        env::var(var)
+            .ok()
+            .filter(|editor| !editor.trim().is_empty())
+    });
+    match configured {
+        Some(editor) => editor,
+        None if cfg!(windows) => "notepad".to_string(),
+        None => "vi".to_string(),
+    }
+}
+
+/// The command that runs `editor` on `path`: through `sh`, which reads its
+/// quotes and arguments, with the path passed on as `"$@"`.
+#[cfg(unix)]
+fn command(editor: &str, path: &Path) -> Command {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(editor)
+        .arg(path);
+    command
+}
+
+/// The command that runs `editor` on `path`, its arguments split at
+/// whitespace.
+#[cfg(not(unix))]
+fn command(editor: &str, path: &Path) -> Command {
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or(editor));
+    command.args(words).arg(path);
+    command
+}
+
+/// Runs the editor on `path`, returning whether it exited successfully, as
+/// editors do when their file was saved (vim's `:cq` fails).
+fn run(path: &Path) -> io::Result<bool> {
+    let editor = editor();
+    let mut command = command(&editor, path);
+    // Under `$(th_rs --print)` stdout is captured and stdin may be a pipe,
+    // but the editor needs the terminal.
+    #[cfg(unix)]
+    if let Ok(tty) = OpenOptions::new().read(true).write(true).open("/dev/tty") {
+        command
+            .stdin(Stdio::from(tty.try_clone()?))
+            .stdout(Stdio::from(tty));
+    }
+    let status = command.status().map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("could not run the editor {}: {}", editor, err),
+        )
+    })?;
+    Ok(status.success())
+}
+
+/// A new file in the temporary directory, named so that editors color it as
+/// shell.
+fn temp_file() -> io::Result<(PathBuf, fs::File)> {
+    let dir = env::temp_dir();
+    let mut attempt = 0;
+    loop {
+        let path = dir.join(format!("th_rs-{}-{}.sh", std::process::id(), attempt));
+        match paths::private_file().create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
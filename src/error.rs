@@ -0,0 +1,66 @@
+//! The crate-wide error type.
+
+use crate::shell::Shell;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong while loading or searching history.
+#[derive(Debug, thiserror::Error)]
+pub enum ThError {
+    /// There is no home directory (`HOME`, or `USERPROFILE` on Windows), so
+    /// default history locations are unknown.
+    #[error("could not determine the home directory")]
+    NoHomeDir,
+    /// `SHELL` is unset, so the history format is unknown.
+    #[error("could not determine the shell (SHELL is not set)")]
+    NoShell,
+    /// `SHELL` names a shell whose history we cannot read.
+    #[error("unsupported shell: {0}")]
+    UnsupportedShell(String),
+    /// `th_rs init` has no snippet for a shell whose history it reads.
+    #[error("th_rs init has no snippet for {0}; bind a key to `th_rs --print` by hand")]
+    NoInit(Shell),
+    /// The history file exists in theory but could not be opened.
+    #[error("failed to open history file at {}: {source}", path.display())]
+    HistoryFile {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// The config file exists but is not valid.
+    #[error("invalid config file {}: {message}", path.display())]
+    Config { path: PathBuf, message: String },
+    /// A catalog file is not valid.
+    #[error("invalid catalog {}: {message}", path.display())]
+    Catalog { path: PathBuf, message: String },
+    /// A file given to `th_rs import` is not an export th_rs can read.
+    #[error("invalid export file {}: {message}", path.display())]
+    Export { path: PathBuf, message: String },
+    /// The history store could not be read or written.
+    #[error("history store: {0}")]
+    Store(#[from] rusqlite::Error),
+    /// `th_rs daemon` was started while another one is serving.
+    #[error("a daemon is already listening on {}", .0.display())]
+    DaemonRunning(PathBuf),
+    /// The encrypted store could not be opened or encrypted.
+    #[error("store encryption: {0}")]
+    Encryption(String),
+    /// `th_rs sync` could not reach the remote or make sense of it.
+    #[error("sync: {0}")]
+    Sync(String),
+    /// A `[[providers]]` entry in the config could not be read.
+    #[error("history provider {name}: {message}")]
+    Provider { name: String, message: String },
+    /// `--remote` could not fetch the history of another machine.
+    #[error("remote history: {0}")]
+    Remote(String),
+    /// `th_rs restore` was given a backup that does not exist.
+    #[error("backup: {0}")]
+    Backup(String),
+    /// Any other I/O failure, typically from the terminal.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// A `Result` defaulting to [`ThError`].
+pub type Result<T, E = ThError> = std::result::Result<T, E>;
@@ -0,0 +1,44 @@
+//! Running a selected command in the user's shell.
+
+use crate::shell::Shell;
+use std::env;
+use std::io;
+use std::process::{Command, ExitStatus};
+
+/// Builds a process that runs `command` through the user's shell: `$SHELL -c`,
+/// falling back to `sh`, or PowerShell on Windows. It inherits the terminal
+/// unless redirected.
+pub fn command(command: &str) -> Command {
+    let shell = env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "powershell" } else { "sh" }.to_string());
+    let flag = match Shell::from_path(&shell) {
+        Some(Shell::PowerShell) => "-Command",
+        _ => "-c",
+    };
+    let mut process = Command::new(shell);
+    process.arg(flag).arg(command);
+    process
+}
+
+/// Runs `command` in the user's shell and waits for it to finish.
+pub fn run(command: &str) -> io::Result<ExitStatus> {
+    self::command(command).status()
+}
+
+/// The exit code a shell would report for `status`: the child's own code, or
+/// `128 + signal` when it was killed by a signal.
+pub fn exit_code(status: ExitStatus) -> u8 {
+    if let Some(code) = status.code() {
+        return code as u8;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128u8.wrapping_add(signal as u8);
+        }
+    }
+    1
+}
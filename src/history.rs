@@ -0,0 +1,857 @@
+//! Loading shell command history from disk.
+
+pub mod backup;
+pub mod bash;
+pub mod fish;
+pub mod histfile;
+pub mod ignore;
+pub mod ksh;
+pub mod mapped;
+pub mod normalize;
+pub mod nushell;
+pub mod powershell;
+pub mod project;
+pub mod provider;
+pub mod prune;
+pub mod remote;
+pub mod tail;
+pub mod tcsh;
+pub mod zsh;
+
+use crate::error::{Result, ThError};
+use crate::paths;
+use crate::shell::Shell;
+use backup::Backups;
+use mapped::Mapped;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+/// A single command read from a history file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The command line as typed; multi-line commands keep their newlines.
+    pub command: String,
+    /// When the command was started, in seconds since the Unix epoch.
+    pub timestamp: Option<i64>,
+    /// How long the command ran, in seconds.
+    pub duration: Option<u64>,
+    /// The directory the command ran in, for history that records it.
+    pub cwd: Option<String>,
+    /// How the command exited, for history that records it.
+    pub exit: Option<i32>,
+    /// The shell whose history the command came from.
+    pub shell: Option<Shell>,
+}
+
+impl Entry {
+    /// Creates an entry with no timing metadata.
+    pub fn new(command: impl Into<String>) -> Self {
+        Entry {
+            command: command.into(),
+            timestamp: None,
+            duration: None,
+            cwd: None,
+            exit: None,
+            shell: None,
+        }
+    }
+}
+
+/// The on-disk layout of a history file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One command per line, no metadata.
+    Plain,
+    /// `.bash_history`, optionally with `HISTTIMEFORMAT` timestamps.
+    Bash,
+    /// `.zsh_history`, optionally with `EXTENDED_HISTORY` metadata.
+    Zsh,
+    /// fish's YAML-like `fish_history`.
+    Fish,
+    /// PSReadLine's `ConsoleHost_history.txt`.
+    PowerShell,
+    /// Nushell's `history.txt`.
+    Nushell,
+    /// Nushell's `history.sqlite3`, a database rather than lines of text.
+    NushellSqlite,
+    /// `.sh_history` of ksh93 and mksh, which is binary, or of the BSD
+    /// kshs, which is plain text.
+    Ksh,
+    /// tcsh's `.history`, with `#+<epoch>` timestamps.
+    Tcsh,
+}
+
+impl Format {
+    /// Every format, for listing in help and errors.
+    pub const ALL: [Format; 9] = [
+        Format::Plain,
+        Format::Bash,
+        Format::Zsh,
+        Format::Fish,
+        Format::PowerShell,
+        Format::Nushell,
+        Format::NushellSqlite,
+        Format::Ksh,
+        Format::Tcsh,
+    ];
+
+    /// The format's name, as used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Plain => "plain",
+            Format::Bash => "bash",
+            Format::Zsh => "zsh",
+            Format::Fish => "fish",
+            Format::PowerShell => "powershell",
+            Format::Nushell => "nu",
+            Format::NushellSqlite => "nu-sqlite",
+            Format::Ksh => "ksh",
+            Format::Tcsh => "tcsh",
+        }
+    }
+
+    /// Whether history in this format is a database, which is read, added
+    /// to, and followed through SQLite rather than line by line.
+    pub fn is_database(self) -> bool {
+        self == Format::NushellSqlite
+    }
+
+    /// Whether history in this format only ever grows by whole lines at its
+    /// end, so that what was appended can be read on its own, as the cache
+    /// and [`tail::Tail`] do. Databases do not, nor do ksh93's and mksh's
+    /// binary files, whose records need not end in a line break.
+    pub fn appends_lines(self) -> bool {
+        !matches!(self, Format::NushellSqlite | Format::Ksh)
+    }
+
+    /// The shell that writes this format, if it is shell-specific.
+    pub fn shell(self) -> Option<Shell> {
+        match self {
+            Format::Plain => None,
+            Format::Bash => Some(Shell::Bash),
+            Format::Zsh => Some(Shell::Zsh),
+            Format::Fish => Some(Shell::Fish),
+            Format::PowerShell => Some(Shell::PowerShell),
+            Format::Nushell | Format::NushellSqlite => Some(Shell::Nushell),
+            Format::Ksh => Some(Shell::Ksh),
+            Format::Tcsh => Some(Shell::Tcsh),
+        }
+    }
+
+    /// Guesses the format of a history file from its name, falling back to
+    /// the first few lines of its contents, and finally to plain.
+    pub fn detect(path: &Path) -> Format {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if name.contains("zsh_history") {
+            return Format::Zsh;
+        }
+        if name.contains("fish_history") {
+            return Format::Fish;
+        }
+        if name.contains("bash_history") {
+            return Format::Bash;
+        }
+        if name.contains("ConsoleHost_history") {
+            return Format::PowerShell;
+        }
+        // `.sh_history` and `.mksh_history`.
+        if name.contains("sh_history") {
+            return Format::Ksh;
+        }
+        if name == ".history" {
+            return Format::Tcsh;
+        }
+        if nushell::is_database(path) {
+            return Format::NushellSqlite;
+        }
+        let in_nushell = path
+            .parent()
+            .and_then(Path::file_name)
+            .is_some_and(|dir| dir == "nushell");
+        if in_nushell && name == "history.txt" {
+            return Format::Nushell;
+        }
+        let Ok(file) = File::open(path) else {
+            return Format::Plain;
+        };
+        let mut reader = BufReader::new(file);
+        let Ok(start) = reader.fill_buf() else {
+            return Format::Plain;
+        };
+        if ksh::is_binary(start) {
+            return Format::Ksh;
+        }
+        // Only whole lines, of those the buffer holds.
+        let whole = start
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(start.len(), |last| last + 1);
+        for line in lines(&start[..whole]).take(20) {
+            if line.starts_with("- cmd:") {
+                return Format::Fish;
+            }
+            if line.starts_with(": ") && zsh::has_metadata(&line) {
+                return Format::Zsh;
+            }
+            if bash::parse_timestamp(&line).is_some() {
+                return Format::Bash;
+            }
+            if tcsh::parse_timestamp(&line).is_some() {
+                return Format::Tcsh;
+            }
+        }
+        Format::Plain
+    }
+
+    /// Whether `next` belongs to the same entry as the lines before it,
+    /// `entry`, in a file of this format. Neither has its last line ending.
+    fn continues(self, entry: &str, next: &str) -> bool {
+        match self {
+            Format::Plain => false,
+            Format::Bash => bash::continues(entry, next),
+            Format::Zsh => entry.ends_with('\\'),
+            Format::Fish => !next.starts_with("- cmd:"),
+            Format::PowerShell => entry.ends_with('`'),
+            Format::Nushell | Format::NushellSqlite | Format::Ksh => false,
+            Format::Tcsh => tcsh::parse_timestamp(entry).is_some(),
+        }
+    }
+
+    /// Splits `text`, a whole history file in this format, into the text of
+    /// each entry, line endings included.
+    fn records(self, text: &str) -> impl Iterator<Item = &str> {
+        let mut start = 0;
+        // Records end after a line break, which is never inside a character.
+        self.byte_records(text.as_bytes()).map(move |record| {
+            let record = &text[start..start + record.len()];
+            start += record.len();
+            record
+        })
+    }
+
+    /// Like [`Format::records`], for a file that need not be UTF-8.
+    fn byte_records(self, bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+        let mut rest = bytes;
+        iter::from_fn(move || {
+            if rest.is_empty() {
+                return None;
+            }
+            let mut end = 0;
+            for line in rest.split_inclusive(|&b| b == b'\n') {
+                let bare = String::from_utf8_lossy(trim_line_end(line));
+                let entry = String::from_utf8_lossy(trim_line_end(&rest[..end]));
+                if end > 0 && !self.continues(&entry, &bare) {
+                    break;
+                }
+                end += line.len();
+            }
+            let (record, tail) = rest.split_at(end);
+            rest = tail;
+            Some(record)
+        })
+    }
+
+    /// Removes every entry for `command` from `text`, a whole history file in
+    /// this format, leaving all other lines exactly as they were. Entries
+    /// count as `command` once normalized per `rules`, as they were when
+    /// loaded. Returns the new text and how many entries were removed.
+    pub fn remove(self, text: &str, command: &str, rules: &normalize::Rules) -> (String, usize) {
+        let mut kept = String::with_capacity(text.len());
+        let mut removed = 0;
+        for record in self.records(text) {
+            let entries = self.parse(record.as_bytes());
+            if entries
+                .iter()
+                .any(|entry| normalize::same(&entry.command, command, rules))
+            {
+                removed += 1;
+            } else {
+                kept.push_str(record);
+            }
+        }
+        (kept, removed)
+    }
+
+    /// The entry this format's shell would write for `command`, started at
+    /// `timestamp`, with its line ending. Plain, PowerShell, Nushell text,
+    /// and plain ksh history have nowhere to put the time. A database has no
+    /// lines, so gets nothing; see [`Source::append`].
+    pub fn record(self, command: &str, timestamp: i64) -> Vec<u8> {
+        match self {
+            Format::Plain => format!("{}\n", command).into_bytes(),
+            Format::Bash => bash::record(command, timestamp).into_bytes(),
+            Format::Zsh => zsh::record(command, timestamp),
+            Format::Fish => fish::record(command, timestamp).into_bytes(),
+            Format::PowerShell => powershell::record(command).into_bytes(),
+            Format::Nushell => nushell::record(command).into_bytes(),
+            Format::NushellSqlite => Vec::new(),
+            Format::Ksh => ksh::record(command).into_bytes(),
+            Format::Tcsh => tcsh::record(command, timestamp).into_bytes(),
+        }
+    }
+
+    /// Parses `bytes`, history in this format, into entries that each hold
+    /// a copy of their command. A database is not text and parses to
+    /// nothing; [`Source::load`] reads it instead.
+    pub fn parse(self, bytes: &[u8]) -> Vec<Entry> {
+        match self {
+            Format::Plain => parse_lines(bytes),
+            Format::Bash => bash::parse(bytes),
+            Format::Zsh => zsh::parse(bytes),
+            Format::Fish => fish::parse(bytes),
+            Format::PowerShell => powershell::parse(bytes),
+            Format::Nushell => nushell::parse(bytes),
+            Format::NushellSqlite => Vec::new(),
+            Format::Ksh => ksh::parse(bytes),
+            Format::Tcsh => tcsh::parse(bytes),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Format::ALL
+            .into_iter()
+            .find(|format| format.name() == s)
+            .ok_or_else(|| {
+                format!(
+"unknown history format: {} (expected plain, bash, zsh, fish, powershell, nu, nu-sqlite, ksh, or tcsh)",
+                    s
+                )
+            })
+    }
+}
+
+/// Roughly how much of a file [`Source::load_in_chunks`] parses at a time.
+const CHUNK_BYTES: usize = 256 * 1024;
+
+/// A history file together with how to read it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    /// The shell that wrote the file, if known.
+    pub shell: Option<Shell>,
+    /// Where the file lives.
+    pub path: PathBuf,
+    /// How the file is laid out.
+    pub format: Format,
+}
+
+impl Source {
+    /// The default history file for `shell`, relative to `home_dir`.
+    pub fn for_shell(shell: Shell, home_dir: &Path) -> Source {
+        let (path, format) = match shell {
+            Shell::Bash => (home_dir.join(".bash_history"), Format::Bash),
+            Shell::Zsh => (home_dir.join(".zsh_history"), Format::Zsh),
+            Shell::Fish => (
+                home_dir.join(".local/share/fish/fish_history"),
+                Format::Fish,
+            ),
+            Shell::PowerShell => (
+                powershell_history_dir(home_dir).join("ConsoleHost_history.txt"),
+                Format::PowerShell,
+            ),
+            // Whichever file_format Nushell is set to; it makes the database
+            // only when using it.
+            Shell::Nushell => {
+                let dir = nushell_config_dir(home_dir);
+                match dir.join("history.sqlite3") {
+                    database if database.is_file() => (database, Format::NushellSqlite),
+                    _ => (dir.join("history.txt"), Format::Nushell),
+                }
+            }
+            Shell::Ksh => (home_dir.join(".sh_history"), Format::Ksh),
+            Shell::Mksh => (home_dir.join(".mksh_history"), Format::Ksh),
+            Shell::Tcsh => (home_dir.join(".history"), Format::Tcsh),
+        };
+        Source {
+            shell: Some(shell),
+            path,
+            format,
+        }
+    }
+
+    /// The history file of `shell`: where `HISTFILE` says, or with
+    /// `read_rc` where the shell's startup files set it, or else the
+    /// default. See [`histfile`].
+    pub fn locate(shell: Shell, home_dir: &Path, read_rc: bool) -> Source {
+        let default = Source::for_shell(shell, home_dir);
+        let moved = histfile::from_env(shell, home_dir).or_else(|| {
+            read_rc
+                .then(|| histfile::from_rc(shell, home_dir))
+                .flatten()
+        });
+        match moved {
+            Some(path) => Source { path, ..default },
+            None => default,
+        }
+    }
+
+    /// A history file at a custom location, read as `format` or, if that is
+    /// `None`, as whatever [`Format::detect`] guesses.
+    pub fn from_path(path: PathBuf, format: Option<Format>) -> Source {
+        let format = format.unwrap_or_else(|| Format::detect(&path));
+        Source {
+            shell: format.shell(),
+            path,
+            format,
+        }
+    }
+
+    /// Reads and parses the file, tagging every entry with its shell.
+    pub fn load(&self) -> Result<Vec<Entry>> {
+        let _span = self.span().entered();
+        let started = Instant::now();
+        let mut entries = if self.format.is_database() {
+            nushell::read(&self.path, 0)?.0
+        } else {
+            let bytes = Mapped::open(&self.path).map_err(|source| ThError::HistoryFile {
+                path: self.path.clone(),
+                source,
+            })?;
+            self.format.parse(&bytes[..])
+        };
+        for entry in &mut entries {
+            entry.shell = self.shell;
+        }
+        tracing::debug!(
+            entries = entries.len(),
+            took = ?started.elapsed(),
+            "parsed"
+        );
+        Ok(entries)
+    }
+
+    /// The span what is logged about reading the file is in.
+    fn span(&self) -> tracing::Span {
+        tracing::debug_span!(
+            "load",
+            path = %self.path.display(),
+            format = %self.format
+        )
+    }
+
+    /// Reads and parses the file a piece at a time, passing each piece's
+    /// entries to `each` as soon as they are parsed, so that a caller can
+    /// show the start of a large history before the end has been read.
+    pub fn load_in_chunks(&self, each: impl FnMut(Vec<Entry>)) -> Result<()> {
+        self.load_in_chunks_to(u64::MAX, each)
+    }
+
+    /// Like [`Source::load_in_chunks`], but reading no more than the first
+    /// `len` bytes of the file, as long as it was when a cache noted it; see
+    /// [`crate::cache::Cache::snapshot`]. A file that does not only grow by
+    /// lines is read whole.
+    pub fn load_in_chunks_to(&self, len: u64, mut each: impl FnMut(Vec<Entry>)) -> Result<()> {
+        // A database has no lines to split, and binary ksh history's
+        // records need not end at one.
+        if !self.format.appends_lines() {
+            each(self.load()?);
+            return Ok(());
+        }
+        let _span = self.span().entered();
+        let started = Instant::now();
+        let file = Mapped::open(&self.path).map_err(|source| ThError::HistoryFile {
+            path: self.path.clone(),
+            source,
+        })?;
+        let mapped = file.is_mapped();
+        let len = usize::try_from(len).map_or(file.len(), |len| len.min(file.len()));
+        let bytes = &file[..len];
+        let mut start = 0;
+        let mut end = 0;
+        let mut parsed = 0;
+        let mut flush = |chunk: &[u8]| {
+            let mut entries = self.format.parse(chunk);
+            for entry in &mut entries {
+                entry.shell = self.shell;
+            }
+            parsed += entries.len();
+            each(entries);
+        };
+        for record in self.format.byte_records(bytes) {
+            end += record.len();
+            if end - start >= CHUNK_BYTES {
+                flush(&bytes[start..end]);
+                start = end;
+            }
+        }
+        if start < end {
+            flush(&bytes[start..end]);
+        }
+        tracing::debug!(
+            entries = parsed,
+            bytes = bytes.len(),
+            mapped = mapped,
+            took = ?started.elapsed(),
+            "parsed"
+        );
+        Ok(())
+    }
+
+    /// Appends an entry for `command`, started at `timestamp`, to the file as
+    /// its shell would have, creating the file if there is none. A last line
+    /// left without its line ending gets one first. The file is backed up to
+    /// `backups` if it was not in the last day.
+    pub fn append(&self, command: &str, timestamp: i64, backups: &Backups) -> Result<()> {
+        self.back_up_daily(backups)?;
+        if self.format.is_database() {
+            return nushell::append(&self.path, command, timestamp);
+        }
+        self.check_not_binary()?;
+        let with_path = |source| ThError::HistoryFile {
+            path: self.path.clone(),
+            source,
+        };
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .map_err(with_path)?;
+        let mut record = Vec::new();
+        if file.metadata().map_err(with_path)?.len() > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::End(-1))
+                .and_then(|_| file.read_exact(&mut last))
+                .map_err(with_path)?;
+            if last[0] != b'\n' {
+                record.push(b'\n');
+            }
+        }
+        record.extend(self.format.record(command, timestamp));
+        file.write_all(&record).map_err(with_path)
+    }
+
+    /// Rewrites the file without any entry that counts as `command` per
+    /// `rules`, returning how many were removed. The file is backed up to
+    /// `backups` first, then replaced atomically, keeping its permissions, so
+    /// a failure leaves the original intact.
+    ///
+    /// A shell that is still running keeps its own copy of the history in
+    /// memory and may write the command back when it exits.
+    pub fn delete(
+        &self,
+        command: &str,
+        rules: &normalize::Rules,
+        backups: &Backups,
+    ) -> Result<usize> {
+        if self.format.is_database() {
+            self.back_up(backups)?;
+            return nushell::delete(&self.path, |entry| normalize::same(entry, command, rules));
+        }
+        let with_path = |source| ThError::HistoryFile {
+            path: self.path.clone(),
+            source,
+        };
+        self.check_not_binary()?;
+        let text = fs::read_to_string(&self.path).map_err(with_path)?;
+        let (kept, removed) = self.format.remove(&text, command, rules);
+        if removed == 0 {
+            return Ok(0);
+        }
+        self.back_up(backups)?;
+        self.replace(&kept)?;
+        Ok(removed)
+    }
+
+    /// Removes the runs `rules` prune, counting commands as the same once
+    /// normalized per `normalize`, and returns how many runs of each went.
+    /// With `dry_run`, only says what would go. The file is backed up to
+    /// `backups` first.
+    pub fn prune(
+        &self,
+        rules: &prune::Rules,
+        normalize: &normalize::Rules,
+        dry_run: bool,
+        backups: &Backups,
+    ) -> Result<BTreeMap<String, usize>> {
+        let item = |entry: &Entry| prune::Item {
+            command: normalize::key(&normalize::normalize(&entry.command, normalize), normalize)
+                .into_owned(),
+            timestamp: entry.timestamp,
+            runs: 1,
+        };
+        if self.format.is_database() {
+            let rows = nushell::rows(&self.path)?;
+            let items: Vec<prune::Item> = rows.iter().map(|(_, entry)| item(entry)).collect();
+            let keep = prune::keep(&items, rules);
+            let removed = prune::removed(&items, &keep);
+            if !dry_run && !removed.is_empty() {
+                self.back_up(backups)?;
+                let ids: Vec<i64> = rows
+                    .iter()
+                    .zip(&keep)
+                    .filter(|(_, &keep)| !keep)
+                    .map(|((id, _), _)| *id)
+                    .collect();
+                nushell::delete_ids(&self.path, &ids)?;
+            }
+            return Ok(removed);
+        }
+        self.check_not_binary()?;
+        let text = fs::read_to_string(&self.path).map_err(|source| ThError::HistoryFile {
+            path: self.path.clone(),
+            source,
+        })?;
+        // A record with no entry, like a comment, always stays.
+        let records: Vec<(&str, Option<prune::Item>)> = self
+            .format
+            .records(&text)
+            .map(|record| {
+                let entries = self.format.parse(record.as_bytes());
+                (record, entries.first().map(item))
+            })
+            .collect();
+        let items: Vec<prune::Item> = records
+            .iter()
+            .filter_map(|(_, item)| item.clone())
+            .collect();
+        let keep = prune::keep(&items, rules);
+        let removed = prune::removed(&items, &keep);
+        if dry_run || removed.is_empty() {
+            return Ok(removed);
+        }
+        let mut keep = keep.into_iter();
+        let kept: String = records
+            .iter()
+            .filter(|(_, item)| item.is_none() || keep.next().unwrap_or(true))
+            .map(|(record, _)| *record)
+            .collect();
+        self.back_up(backups)?;
+        self.replace(&kept)?;
+        Ok(removed)
+    }
+
+    /// Backs up the file to `backups`, a database by way of SQLite.
+    fn back_up(&self, backups: &Backups) -> Result<()> {
+        let saved = if self.format.is_database() {
+            backups.save_with(&self.path, |copy| nushell::copy(&self.path, copy))
+        } else {
+            backups.save(&self.path)
+        };
+        saved.map(drop)
+    }
+
+    /// Backs up the file to `backups`, unless it was in the last day.
+    fn back_up_daily(&self, backups: &Backups) -> Result<()> {
+        if backups.backed_up_today(&self.path)? {
+            return Ok(());
+        }
+        self.back_up(backups)
+    }
+
+    /// Replaces the file with `text` atomically, keeping its permissions, so
+    /// a failure leaves the original intact.
+    fn replace(&self, text: &str) -> Result<()> {
+        let with_path = |source| ThError::HistoryFile {
+            path: self.path.clone(),
+            source,
+        };
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".th_rs-tmp");
+        let tmp = PathBuf::from(tmp);
+        let permissions = fs::metadata(&self.path).map_err(with_path)?.permissions();
+        fs::write(&tmp, text)
+            .and_then(|_| fs::set_permissions(&tmp, permissions))
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|err| {
+                let _ = fs::remove_file(&tmp);
+                with_path(err)
+            })
+    }
+
+    /// Fails for ksh93's and mksh's binary history, which th_rs reads but
+    /// does not write.
+    fn check_not_binary(&self) -> Result<()> {
+        if self.format != Format::Ksh {
+            return Ok(());
+        }
+        let mut start = Vec::new();
+        match File::open(&self.path) {
+            Ok(file) => file.take(2).read_to_end(&mut start).map(drop),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+        .and_then(|_| match ksh::is_binary(&start) {
+            true => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "ksh93 and mksh history is binary, which th_rs does not write",
+            )),
+            false => Ok(()),
+        })
+        .map_err(|source| ThError::HistoryFile {
+            path: self.path.clone(),
+            source,
+        })
+    }
+}
+
+/// Where PSReadLine keeps its history: under `%APPDATA%` on Windows, and
+/// under `~/.local/share/powershell` for PowerShell on other systems.
+fn powershell_history_dir(home_dir: &Path) -> PathBuf {
+    if cfg!(windows) {
+        dirs::data_dir()
+            .unwrap_or_else(|| home_dir.join("AppData").join("Roaming"))
+            .join("Microsoft")
+            .join("Windows")
+            .join("PowerShell")
+            .join("PSReadLine")
+    } else {
+        home_dir.join(".local/share/powershell/PSReadLine")
+    }
+}
+
+/// Where Nushell keeps its config and history: under `XDG_CONFIG_HOME` when
+/// it is set, otherwise the system's config directory, e.g. `~/.config` on
+/// Linux and `%APPDATA%` on Windows.
+fn nushell_config_dir(home_dir: &Path) -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(dirs::config_dir)
+        .unwrap_or_else(|| home_dir.join(".config"))
+        .join("nushell")
+}
+
+/// Finds the history file of the current shell.
+///
+/// The shell is the one th_rs was started from (see [`Shell::running`]),
+/// or if none is found, taken from `SHELL` and matched on its file name,
+/// so `/opt/homebrew/bin/zsh` is treated the same as `/bin/zsh`. Without
+/// `SHELL`, Windows is assumed to be running PowerShell. Where its file is
+/// comes from [`Source::locate`].
+pub fn detect_source(read_rc: bool) -> Result<Source> {
+    let home_dir = paths::home_dir()?;
+    let shell = match (Shell::running(), env::var("SHELL")) {
+        (Some(shell), _) => shell,
+        (None, Ok(shell)) => Shell::from_path(&shell).ok_or(ThError::UnsupportedShell(shell))?,
+        (None, Err(_)) => Shell::fallback().ok_or(ThError::NoShell)?,
+    };
+    Ok(Source::locate(shell, &home_dir, read_rc))
+}
+
+/// The history files of `shells`, per [`Source::locate`].
+pub fn sources_for(shells: &[Shell], read_rc: bool) -> Result<Vec<Source>> {
+    let home_dir = paths::home_dir()?;
+    Ok(shells
+        .iter()
+        .map(|&shell| Source::locate(shell, &home_dir, read_rc))
+        .collect())
+}
+
+/// The history file of every supported shell that exists.
+pub fn detect_all_sources(read_rc: bool) -> Result<Vec<Source>> {
+    let mut sources = sources_for(&Shell::ALL, read_rc)?;
+    sources.retain(|source| source.path.is_file());
+    Ok(sources)
+}
+
+/// Loads and concatenates several history files. Files that do not exist
+/// are skipped, since not every listed shell is installed everywhere; any
+/// other failure is an error.
+pub fn load_all(sources: &[Source]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for source in sources {
+        match source.load() {
+            Ok(loaded) => entries.extend(loaded),
+            Err(ThError::HistoryFile { source: err, .. })
+                if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(entries)
+}
+
+/// Like [`load_all`], but passing the entries to `each` a chunk at a time,
+/// as [`Source::load_in_chunks`] does, so that they never all have to be
+/// held at once.
+pub fn load_all_in_chunks(sources: &[Source], each: impl FnMut(Vec<Entry>)) -> Result<()> {
+    load_all_in_chunks_to(sources, &vec![u64::MAX; sources.len()], each)
+}
+
+/// Like [`load_all_in_chunks`], but reading no more of each source than
+/// the length beside it in `lengths`, as [`Source::load_in_chunks_to`] does.
+pub fn load_all_in_chunks_to(
+    sources: &[Source],
+    lengths: &[u64],
+    mut each: impl FnMut(Vec<Entry>),
+) -> Result<()> {
+    for (source, &len) in sources.iter().zip(lengths) {
+        match source.load_in_chunks_to(len, &mut each) {
+            Ok(()) => {}
+            Err(ThError::HistoryFile { source: err, .. })
+                if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Loads shell command history from the appropriate file.
+pub fn load_history() -> Result<Vec<Entry>> {
+    detect_source(false)?.load()
+}
+
+/// The lines of `bytes` without their line endings, like [`str::lines`],
+/// borrowed from `bytes` wherever they are UTF-8. Bytes which are not
+/// become U+FFFD instead of ending the lines there: a history file with one
+/// bad byte loses no entries.
+pub(crate) fn lines(bytes: &[u8]) -> impl Iterator<Item = Cow<'_, str>> {
+    decoded_lines(bytes, String::from_utf8_lossy)
+}
+
+/// Like [`lines`], but turning lines that are not UTF-8 into text with
+/// `decode`.
+pub(crate) fn decoded_lines<'a>(
+    bytes: &'a [u8],
+    decode: impl Fn(&'a [u8]) -> Cow<'a, str>,
+) -> impl Iterator<Item = Cow<'a, str>> {
+    // Like `split`, but with no empty line after a final line break.
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    let lines = (!bytes.is_empty()).then(|| bytes.split(|&b| b == b'\n'));
+    lines.into_iter().flatten().map(move |line| {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        match std::str::from_utf8(line) {
+            Ok(line) => Cow::Borrowed(line),
+            Err(_) => decode(line),
+        }
+    })
+}
+
+/// `text` without the line breaks at its end.
+fn trim_line_end(text: &[u8]) -> &[u8] {
+    let end = text
+        .iter()
+        .rposition(|&b| b != b'\n' && b != b'\r')
+        .map_or(0, |last| last + 1);
+    &text[..end]
+}
+
+/// Parses a history file with one command per line and no metadata.
+pub fn parse_lines(bytes: &[u8]) -> Vec<Entry> {
+    lines(bytes)
+        .filter_map(|line| {
+            let command = line.trim_end();
+            (!command.is_empty()).then(|| Entry::new(command))
+        })
+        .collect()
+}
@@ -0,0 +1,345 @@
+//! Copies of history files taken before th_rs changes them, which
+//! `th_rs restore` puts back.
+//!
+//! Before deleting from, pruning, or appending to a history file, or
+//! pruning the store, th_rs copies it to a directory of its own under
+//! `~/.local/share/th_rs/backups/`, named for when it was taken (in UTC), like
+//! `20261014T093015.123`. Beside the copy, `origin` holds where it came from.
+//! Only the newest backups of each file are kept, as many as the `[backup]`
+//! section of the config file says:
+//!
+//! ```toml
+//! [backup]
+//! keep = 10
+//! enabled = true
+//! ```
+//!
+//! Appending only adds a line, so a file is backed up before an append only
+//! if it was not in the last day; see [`Backups::backed_up_today`].
+
+use crate::error::{Result, ThError};
+use crate::paths;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How the names of backup directories start.
+const NAME_FORMAT: &str = "%Y%m%dT%H%M%S%.3f";
+
+/// How long a backup covers the appends that follow it, in seconds.
+const DAY: i64 = 24 * 60 * 60;
+
+/// The `[backup]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Whether files are backed up at all.
+    pub enabled: bool,
+    /// How many backups of each file are kept.
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            enabled: true,
+            keep: 10,
+        }
+    }
+}
+
+impl BackupConfig {
+    /// The backups this section keeps, in th_rs's data directory.
+    pub fn backups(&self) -> Result<Backups> {
+        let keep = if self.enabled { self.keep } else { 0 };
+        Ok(Backups::in_dir(paths::data_dir()?.join("backups"), keep))
+    }
+}
+
+/// One backup of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    /// The name of its directory, which sorts as the backups were taken.
+    pub id: String,
+    /// The file it is a copy of.
+    pub origin: PathBuf,
+    /// The copy.
+    pub copy: PathBuf,
+}
+
+impl Backup {
+    /// When it was taken, in Unix seconds.
+    pub fn taken(&self) -> Option<i64> {
+        let name = self.id.split('-').next()?;
+        let taken = NaiveDateTime::parse_from_str(name, NAME_FORMAT).ok()?;
+        Some(taken.and_utc().timestamp())
+    }
+
+    /// Where it comes among the backups: by when it was taken, then by
+    /// the number after its time, for those taken in the same millisecond.
+    fn order(&self) -> (&str, usize) {
+        number(&self.id)
+    }
+}
+
+/// The time an id starts with and the number after it, 0 for none.
+fn number(id: &str) -> (&str, usize) {
+    match id.split_once('-') {
+        Some((stamp, n)) => (stamp, n.parse().unwrap_or(0)),
+        None => (id, 0),
+    }
+}
+
+/// Where backups go, and how many of each file are kept; none are taken
+/// if that is 0.
+#[derive(Debug, Clone)]
+pub struct Backups {
+    dir: PathBuf,
+    keep: usize,
+    /// The time backups are named for: the clock's, but for tests.
+    now: fn() -> DateTime<Utc>,
+}
+
+impl Backups {
+    pub fn in_dir(dir: PathBuf, keep: usize) -> Backups {
+        Backups {
+            dir,
+            keep,
+            now: Utc::now,
+        }
+    }
+
+    /// Copies `origin` to a new backup, unless it does not exist.
+    pub fn save(&self, origin: &Path) -> Result<Option<Backup>> {
+        self.save_with(origin, |copy| {
+            fs::copy(origin, copy).map(drop).map_err(Into::into)
+        })
+    }
+
+    /// Whether `origin` has a backup from the last day.
+    pub fn backed_up_today(&self, origin: &Path) -> Result<bool> {
+        let Ok(origin) = fs::canonicalize(origin) else {
+            return Ok(false);
+        };
+        let now = (self.now)().timestamp();
+        Ok(self.list()?.into_iter().any(|backup| {
+            backup.origin == origin && backup.taken().is_some_and(|taken| now - taken < DAY)
+        }))
+    }
+
+    /// Backs up `origin`, unless it does not exist, with `write` making the
+    /// copy at the path it is given, e.g. for a database that is better
+    /// copied by SQLite. Older backups of the same file past the number
+    /// kept are removed.
+    pub fn save_with(
+        &self,
+        origin: &Path,
+        write: impl FnOnce(&Path) -> Result<()>,
+    ) -> Result<Option<Backup>> {
+        if self.keep == 0 {
+            return Ok(None);
+        }
+        let origin = match fs::canonicalize(origin) {
+            Ok(origin) => origin,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let Some(name) = origin.file_name() else {
+            return Ok(None);
+        };
+        fs::create_dir_all(&self.dir)?;
+        let stamp = (self.now)().format(NAME_FORMAT).to_string();
+        // Numbered after any backup still kept from the same millisecond,
+        // so that this one sorts as the newest.
+        let next = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter_map(|id| match number(&id) {
+                (taken, n) if taken == stamp => Some(n + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let (id, dir) = (next..)
+            .map(|n| match n {
+                0 => stamp.clone(),
+                n => format!("{}-{}", stamp, n),
+            })
+            .find_map(|id| {
+                let dir = self.dir.join(&id);
+                match fs::create_dir(&dir) {
+                    Ok(()) => Some(Ok((id, dir))),
+                    Err(err) if err.kind() == io::ErrorKind::AlreadyExists => None,
+                    Err(err) => Some(Err(err)),
+                }
+            })
+            .expect("some name is free")?;
+        let backup = Backup {
+            id,
+            copy: dir.join(name),
+            origin,
+        };
+        let written = fs::write(
+            dir.join("origin"),
+            backup.origin.to_string_lossy().as_bytes(),
+        )
+        .map_err(Into::into)
+        .and_then(|()| write(&backup.copy));
+        if let Err(err) = written {
+            let _ = fs::remove_dir_all(&dir);
+            return Err(err);
+        }
+        self.expire(&backup.origin)?;
+        Ok(Some(backup))
+    }
+
+    /// Every backup, newest first. Directories that are not backups are
+    /// passed over.
+    pub fn list(&self) -> Result<Vec<Backup>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut backups = Vec::new();
+        for entry in entries {
+            let dir = entry?.path();
+            let (Some(id), Ok(origin)) = (
+                dir.file_name().and_then(|id| id.to_str()),
+                fs::read_to_string(dir.join("origin")),
+            ) else {
+                continue;
+            };
+            let origin = PathBuf::from(origin);
+            let Some(name) = origin.file_name() else {
+                continue;
+            };
+            backups.push(Backup {
+                id: id.to_string(),
+                copy: dir.join(name),
+                origin,
+            });
+        }
+        backups.sort_by(|a, b| b.order().cmp(&a.order()));
+        Ok(backups)
+    }
+
+    /// The backup called `id`.
+    pub fn find(&self, id: &str) -> Result<Backup> {
+        self.list()?
+            .into_iter()
+            .find(|backup| backup.id == id)
+            .ok_or_else(|| ThError::Backup(format!("no backup named {}", id)))
+    }
+
+    /// Puts `backup` back where it came from, atomically, first backing up
+    /// what is there now so that the restore can be undone in turn. Returns
+    /// that backup, if one was taken.
+    pub fn restore(&self, backup: &Backup) -> Result<Option<Backup>> {
+        let mut tmp = backup.origin.clone().into_os_string();
+        tmp.push(".th_rs-tmp");
+        let tmp = PathBuf::from(tmp);
+        let with_path = |source| ThError::HistoryFile {
+            path: backup.origin.clone(),
+            source,
+        };
+        // Copied out first, as backing up the current file may expire it.
+        fs::copy(&backup.copy, &tmp).map_err(with_path)?;
+        let current = self.save(&backup.origin).inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })?;
+        fs::rename(&tmp, &backup.origin).map_err(|err| {
+            let _ = fs::remove_file(&tmp);
+            with_path(err)
+        })?;
+        Ok(current)
+    }
+
+    /// Removes the backups of `origin` past the newest ones kept.
+    fn expire(&self, origin: &Path) -> Result<()> {
+        let old = self
+            .list()?
+            .into_iter()
+            .filter(|backup| backup.origin == origin)
+            .skip(self.keep);
+        for backup in old {
+            fs::remove_dir_all(self.dir.join(&backup.id))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_newest_backups_and_restores_them() {
+        let root = std::env::temp_dir().join(format!("th_rs-backup-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let history = root.join(".bash_history");
+        let backups = Backups::in_dir(root.join("backups"), 2);
+        for text in ["one\n", "two\n", "three\n"] {
+            fs::write(&history, text).unwrap();
+            backups.save(&history).unwrap().unwrap();
+        }
+        let list = backups.list().unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(fs::read_to_string(&list[1].copy).unwrap(), "two\n");
+
+        fs::write(&history, "changed\n").unwrap();
+        let undo = backups.restore(&list[1]).unwrap().unwrap();
+        assert_eq!(fs::read_to_string(&history).unwrap(), "two\n");
+        assert_eq!(fs::read_to_string(undo.copy).unwrap(), "changed\n");
+        assert!(backups.save(&root.join("missing")).unwrap().is_none());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn numbers_backups_taken_in_the_same_millisecond_in_order() {
+        let root = std::env::temp_dir().join(format!("th_rs-backup-same-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let history = root.join(".zsh_history");
+        fs::write(&history, "ls\n").unwrap();
+        let backups = Backups {
+            now: || DateTime::from_timestamp_millis(1_792_000_000_123).unwrap(),
+            ..Backups::in_dir(root.join("backups"), 3)
+        };
+        // Past ten, so that the numbers sort as numbers and not as text.
+        for n in 0..12 {
+            let text = format!("{}\n", n);
+            let write = |copy: &Path| fs::write(copy, &text).map_err(Into::into);
+            backups.save_with(&history, write).unwrap().unwrap();
+        }
+        let stamp = "20261014T174640.123";
+        let ids = |backups: &Backups| -> Vec<String> {
+            backups.list().unwrap().into_iter().map(|b| b.id).collect()
+        };
+        assert_eq!(
+            ids(&backups),
+            [
+                format!("{stamp}-11"),
+                format!("{stamp}-10"),
+                format!("{stamp}-9")
+            ]
+        );
+        let list = backups.list().unwrap();
+        assert_eq!(fs::read_to_string(&list[0].copy).unwrap(), "11\n");
+
+        let undo = backups.restore(&list[2]).unwrap().unwrap();
+        assert_eq!(fs::read_to_string(&history).unwrap(), "9\n");
+        assert_eq!(undo.id, format!("{stamp}-12"));
+        assert_eq!(
+            ids(&backups),
+            [
+                format!("{stamp}-12"),
+                format!("{stamp}-11"),
+                format!("{stamp}-10")
+            ]
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
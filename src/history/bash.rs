@@ -0,0 +1,313 @@
+//! Parser for `.bash_history`.
+//!
+//! Bash writes one command per line. When `HISTTIMEFORMAT` is set, each
+//! command is preceded by a comment line holding its start time:
+//!
+//! ```text
+//! #1697040000
+//! git status
+//! ```
+//!
+//! With `shopt -s lithist`, a command typed over several lines, like a
+//! heredoc or a loop, is written over as many. In a file with timestamps
+//! such a command runs up to the next timestamp, as bash reads it back.
+//! Without them nothing marks where it ends, so a line continues the
+//! command before it whenever bash would still have been waiting for more
+//! of that command: see [`incomplete`].
+
+use super::Entry;
+
+/// Parses bash history, picking up `#<epoch>` timestamp lines if present.
+pub fn parse(bytes: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut timestamp = None;
+    let mut pending: Option<Entry> = None;
+
+    for line in super::lines(bytes) {
+        if let Some(epoch) = parse_timestamp(&line) {
+            push_entry(&mut entries, pending.take());
+            timestamp = Some(epoch);
+            continue;
+        }
+        match &mut pending {
+            Some(entry) if entry.timestamp.is_some() || incomplete(&entry.command) => {
+                entry.command.push('\n');
+                entry.command.push_str(&line);
+            }
+            _ => {
+                push_entry(&mut entries, pending.take());
+                pending = Some(Entry {
+                    timestamp: timestamp.take(),
+                    ..Entry::new(line)
+                });
+            }
+        }
+    }
+    push_entry(&mut entries, pending);
+    entries
+}
+
+fn push_entry(entries: &mut Vec<Entry>, entry: Option<Entry>) {
+    let Some(mut entry) = entry else {
+        return;
+    };
+    // A leading space is kept: it marks commands not to be recorded,
+    // which the ignore list looks for. Normalizing trims it later.
+    let trimmed = entry.command.trim_end();
+    if trimmed.is_empty() {
+        return;
+    }
+    entry.command.truncate(trimmed.len());
+    entries.push(entry);
+}
+
+/// The lines bash writes for `command` with `HISTTIMEFORMAT` set. With the
+/// timestamp ahead of it, a command of several lines reads back whole.
+pub(crate) fn record(command: &str, timestamp: i64) -> String {
+    format!("#{}\n{}\n", timestamp, command)
+}
+
+/// Reads a `#1697040000` timestamp comment.
+pub(crate) fn parse_timestamp(line: &str) -> Option<i64> {
+    let digits = line.strip_prefix('#')?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Whether the line `next` belongs to `entry`, the lines of an entry so far
+/// (its timestamp line included), the way [`parse`] groups them.
+pub(crate) fn continues(entry: &str, next: &str) -> bool {
+    if parse_timestamp(next).is_some() {
+        return false;
+    }
+    match entry.split_once('\n') {
+        _ if parse_timestamp(entry).is_some() => true,
+        Some((first, _)) if parse_timestamp(first).is_some() => true,
+        _ => incomplete(entry),
+    }
+}
+
+/// Compound commands, and what closes each.
+const OPENERS: [&str; 7] = ["if", "case", "for", "select", "while", "until", "{"];
+const CLOSERS: [&str; 4] = ["fi", "esac", "done", "}"];
+
+/// Keywords after which a command starts.
+const KEYWORDS: [&str; 8] = ["then", "else", "elif", "do", "if", "while", "until", "!"];
+
+/// Whether bash, having read `command`, would wait for another line of it:
+/// inside a quote, after a trailing backslash or a `|`, `&&`, or `||`,
+/// before the end of a heredoc, or inside an `if`, `case`, loop, or `{`
+/// block not yet closed.
+///
+/// This follows bash's grammar only as far as telling these apart takes;
+/// command substitutions, for one, are read as plain words.
+pub fn incomplete(command: &str) -> bool {
+    let mut quote: Option<char> = None;
+    // Compound commands opened and not closed.
+    let mut depth = 0i32;
+    // Heredocs whose bodies come on the next lines: their delimiters, and
+    // whether leading tabs are stripped before comparing (`<<-`).
+    let mut heredocs: Vec<(String, bool)> = Vec::new();
+    let mut wants_more = false;
+    // Whether the line so far ended in a backslash.
+    let mut escaped = false;
+    let mut starts_command = true;
+    let mut naming = false;
+    let mut word = String::new();
+    let mut end_word = |word: &mut String, starts_command: &mut bool, naming: &mut bool| {
+        if word.is_empty() {
+            return;
+        }
+        if *naming {
+            // The name after `function`, then `{` or `()`.
+            *naming = false;
+            *starts_command = true;
+        } else if *starts_command {
+            let word = word.as_str();
+            if OPENERS.contains(&word) {
+                depth += 1;
+            } else if CLOSERS.contains(&word) {
+                depth -= 1;
+            }
+            *naming = word == "function";
+            *starts_command = KEYWORDS.contains(&word) || word == "{" || word == "}";
+        }
+        word.clear();
+    };
+
+    for line in command.split('\n') {
+        if let Some((delimiter, strip_tabs)) = heredocs.first() {
+            let body = match strip_tabs {
+                true => line.trim_start_matches('\t'),
+                false => line,
+            };
+            if body == delimiter {
+                heredocs.remove(0);
+            }
+            continue;
+        }
+        // A line break ends a word, and a command like `;` does, unless it
+        // is quoted or escaped.
+        if quote.is_none() && !escaped {
+            end_word(&mut word, &mut starts_command, &mut naming);
+            starts_command = true;
+        }
+        escaped = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if let Some(q) = quote {
+                if c == '\\' && q == '"' {
+                    chars.next();
+                } else if c == q {
+                    quote = None;
+                }
+                continue;
+            }
+            match c {
+                _ if c.is_whitespace() => end_word(&mut word, &mut starts_command, &mut naming),
+                '#' if word.is_empty() => break,
+                '\\' => match chars.next() {
+                    Some(_) => {
+                        word.push('\\');
+                        wants_more = false;
+                    }
+                    None => escaped = true,
+                },
+                '"' | '\'' => {
+                    word.push(c);
+                    quote = Some(c);
+                    wants_more = false;
+                }
+                '|' | '&' | ';' | '(' | ')' | '<' | '>' => {
+                    end_word(&mut word, &mut starts_command, &mut naming);
+                    let mut operator = String::from(c);
+                    while let Some(c) = chars.next_if(|&c| matches!(c, '|' | '&' | ';' | '<' | '>'))
+                    {
+                        operator.push(c);
+                    }
+                    if operator == "<<" {
+                        let strip_tabs = chars.next_if_eq(&'-').is_some();
+                        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                        let delimiter: String = chars
+                            .by_ref()
+                            .take_while(|&c| !c.is_whitespace() && !"|&;()<>".contains(c))
+                            .filter(|&c| !matches!(c, '"' | '\'' | '\\'))
+                            .collect();
+                        heredocs.push((delimiter, strip_tabs));
+                        starts_command = false;
+                        continue;
+                    }
+                    starts_command = !operator.contains(['<', '>']);
+                    wants_more = operator.ends_with('|') || operator == "&&";
+                }
+                _ => {
+                    word.push(c);
+                    wants_more = false;
+                }
+            }
+        }
+    }
+    if quote.is_none() {
+        end_word(&mut word, &mut starts_command, &mut naming);
+    }
+    quote.is_some() || escaped || wants_more || !heredocs.is_empty() || depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(history: &str) -> Vec<String> {
+        parse(history.as_bytes())
+            .into_iter()
+            .map(|entry| entry.command)
+            .collect()
+    }
+
+    #[test]
+    fn joins_the_lines_of_a_command_between_timestamps() {
+        let history = "#1\nfor f in *; do\n  echo $f\ndone\n#2\nls\n";
+        assert_eq!(commands(history), ["for f in *; do\n  echo $f\ndone", "ls"]);
+    }
+
+    #[test]
+    fn takes_each_timestamp_for_the_command_after_it() {
+        let history = "pwd\n#1697040000\ngit status\n#1697040005\nmake\n";
+        let entries: Vec<(String, Option<i64>)> = parse(history.as_bytes())
+            .into_iter()
+            .map(|entry| (entry.command, entry.timestamp))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("pwd".to_string(), None),
+                ("git status".to_string(), Some(1697040000)),
+                ("make".to_string(), Some(1697040005)),
+            ]
+        );
+    }
+
+    #[test]
+    fn joins_lines_bash_would_have_waited_for() {
+        let history = "\
+cat <<-EOF > notes
+\tone; two
+\tEOF
+echo \"a
+b\"
+make \\
+  install
+git log |
+  head
+if true; then
+  echo { yes }
+fi
+echo done
+";
+        assert_eq!(
+            commands(history),
+            [
+                "cat <<-EOF > notes\n\tone; two\n\tEOF",
+                "echo \"a\nb\"",
+                "make \\\n  install",
+                "git log |\n  head",
+                "if true; then\n  echo { yes }\nfi",
+                "echo done",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_lines_that_are_not_utf8() {
+        let history: &[u8] = b"ls\r\nbad \xff here\nafter\n";
+        let commands: Vec<String> = parse(history).into_iter().map(|e| e.command).collect();
+        assert_eq!(commands, ["ls", "bad \u{fffd} here", "after"]);
+    }
+
+    #[test]
+    fn reads_back_records_it_writes() {
+        let command = "for f in *; do\n  echo \"$f\nx\"\ndone";
+        let history = record(command, 7) + &record("ls", 8);
+        let entries = parse(history.as_bytes());
+        assert_eq!(entries[0].command, command);
+        assert_eq!(entries[0].timestamp, Some(7));
+        assert_eq!(entries[1].command, "ls");
+    }
+
+    #[test]
+    fn keeps_complete_lines_apart() {
+        let history = "echo if # for\nf() { ls; }\nfunction g { pwd; }\necho 'it''s' && ls\nls\n";
+        assert_eq!(
+            commands(history),
+            [
+                "echo if # for",
+                "f() { ls; }",
+                "function g { pwd; }",
+                "echo 'it''s' && ls",
+                "ls",
+            ]
+        );
+    }
+}
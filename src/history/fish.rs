@@ -0,0 +1,114 @@
+//! Parser for fish's `fish_history`.
+//!
+//! Fish writes a YAML-like list of blocks rather than one command per line:
+//!
+//! ```text
+//! - cmd: git commit -m 'wip'
+//!   when: 1697040000
+//!   paths:
+//!     - src/main.rs
+//! ```
+//!
+//! Inside `cmd`, newlines are written as `\n` and backslashes as `\\`.
+
+use super::Entry;
+
+/// Parses fish history blocks into entries.
+pub fn parse(bytes: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut current: Option<Entry> = None;
+
+    for line in super::lines(bytes) {
+        if let Some(cmd) = line.strip_prefix("- cmd:") {
+            entries.extend(current.take());
+            let command = unescape(cmd.trim_start());
+            current = (!command.trim().is_empty()).then(|| Entry::new(command));
+        } else if let Some(when) = line.trim_start().strip_prefix("when:") {
+            if let Some(entry) = current.as_mut() {
+                entry.timestamp = when.trim().parse().ok();
+            }
+        }
+        // `paths:` lists and any unknown keys carry nothing we index.
+    }
+    entries.extend(current);
+    entries
+}
+
+/// The block fish writes for `command`, started at `timestamp`.
+pub(crate) fn record(command: &str, timestamp: i64) -> String {
+    let escaped = command.replace('\\', "\\\\").replace('\n', "\\n");
+    format!("- cmd: {}\n  when: {}\n", escaped, timestamp)
+}
+
+/// Reverses fish's escaping of `\n` and `\\` in the `cmd` field.
+fn unescape(raw: &str) -> String {
+    let mut command = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            command.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => command.push('\n'),
+            Some('\\') => command.push('\\'),
+            Some(other) => {
+                command.push('\\');
+                command.push(other);
+            }
+            None => command.push('\\'),
+        }
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(history: &str) -> Vec<(String, Option<i64>)> {
+        parse(history.as_bytes())
+            .into_iter()
+            .map(|entry| (entry.command, entry.timestamp))
+            .collect()
+    }
+
+    #[test]
+    fn reads_when_as_the_timestamp() {
+        let history = "- cmd: git status\n  when: 1697040000\n- cmd: ls\n";
+        assert_eq!(
+            entries(history),
+            [
+                ("git status".to_string(), Some(1697040000)),
+                ("ls".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_the_paths_list() {
+        let history = "- cmd: vim src/main.rs\n  when: 1697040000\n  paths:\n    - src/main.rs\n    - when: 5\n- cmd: cargo test\n  when: 1697040009\n";
+        assert_eq!(
+            entries(history),
+            [
+                ("vim src/main.rs".to_string(), Some(1697040000)),
+                ("cargo test".to_string(), Some(1697040009)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unescapes_newlines_and_backslashes() {
+        let history = "- cmd: echo a\\nb\n- cmd: printf 'x\\\\ny'\n- cmd: echo \\t\n";
+        assert_eq!(
+            entries(history),
+            [
+                ("echo a\nb".to_string(), None),
+                ("printf 'x\\ny'".to_string(), None),
+                ("echo \\t".to_string(), None),
+            ]
+        );
+        let command = "for f in *\n  echo \\$f\nend";
+        assert_eq!(parse(record(command, 0).as_bytes())[0].command, command);
+    }
+}
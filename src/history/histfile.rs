@@ -0,0 +1,246 @@
+//! Finding a history file moved from where its shell keeps it by default.
+//!
+//! Shells read `HISTFILE` (tcsh: `histfile`) to know where to keep their
+//! history, and users relocate it, e.g. to `$XDG_STATE_HOME/zsh/history`.
+//! The variable is seldom exported, but the `th_rs init` snippets pass it
+//! along. When it is not in the environment, the shell's startup files can
+//! be read for where they set it.
+//!
+//! Startup files are only skimmed: lines like `HISTFILE=...`, `export
+//! HISTFILE=...`, or tcsh's `set histfile = ...`, with `~`, `$VAR`, and
+//! `${VAR:-default}` expanded from the variables those files set before it
+//! or, failing those, from the environment. The last one wins, as it would
+//! when the shell runs them.
+
+use crate::shell::Shell;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `HISTFILE` from the environment, if `shell` is the current shell, whose
+/// variable it would be.
+pub fn from_env(shell: Shell, home_dir: &Path) -> Option<PathBuf> {
+    let current =
+        Shell::running().or_else(|| env::var("SHELL").ok().as_deref().and_then(Shell::from_path));
+    if current != Some(shell) {
+        return None;
+    }
+    let name = match shell {
+        Shell::Tcsh => "histfile",
+        _ => "HISTFILE",
+    };
+    let value = env::var(name).ok().filter(|value| !value.is_empty())?;
+    let path = expand_tilde(&value, home_dir);
+    path.is_absolute().then_some(path)
+}
+
+/// Where `shell`'s startup files under `home_dir` set its history file,
+/// if they do.
+pub fn from_rc(shell: Shell, home_dir: &Path) -> Option<PathBuf> {
+    let mut vars = HashMap::new();
+    let mut found = None;
+    for file in rc_files(shell, home_dir) {
+        let Ok(text) = fs::read_to_string(&file) else {
+            continue;
+        };
+        let lookup = |name: &str| env::var(name).ok();
+        if let Some(path) = scan(&text, shell, home_dir, &mut vars, lookup) {
+            found = Some(path);
+        }
+    }
+    found.filter(|path| path.is_absolute())
+}
+
+/// The startup files `shell` reads, in the order it reads them.
+fn rc_files(shell: Shell, home_dir: &Path) -> Vec<PathBuf> {
+    let names: &[&str] = match shell {
+        Shell::Bash => &[".profile", ".bash_profile", ".bashrc"],
+        Shell::Zsh => {
+            let dir = env::var_os("ZDOTDIR")
+                .filter(|dir| !dir.is_empty())
+                .map_or_else(|| home_dir.to_path_buf(), PathBuf::from);
+            return [".zshenv", ".zprofile", ".zshrc"]
+                .iter()
+                .map(|name| dir.join(name))
+                .collect();
+        }
+        Shell::Ksh => &[".profile", ".kshrc"],
+        Shell::Mksh => &[".profile", ".mkshrc"],
+        Shell::Tcsh => &[".cshrc", ".tcshrc"],
+        Shell::Fish | Shell::PowerShell | Shell::Nushell => &[],
+    };
+    names.iter().map(|name| home_dir.join(name)).collect()
+}
+
+/// The history file the last assignment in `text` sets, expanded. The
+/// plain variables assigned along the way are kept in `vars`, for later
+/// lines and files; `lookup` gives the rest.
+fn scan(
+    text: &str,
+    shell: Shell,
+    home_dir: &Path,
+    vars: &mut HashMap<String, String>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Option<PathBuf> {
+    let variable = match shell {
+        Shell::Tcsh => "histfile",
+        _ => "HISTFILE",
+    };
+    let mut found = None;
+    for line in text.lines() {
+        let Some((name, value)) = assignment(line, shell) else {
+            continue;
+        };
+        let Some(value) = expand(value, home_dir, vars, &lookup) else {
+            continue;
+        };
+        if name == variable {
+            found = Some(PathBuf::from(&value));
+        }
+        vars.insert(name.to_string(), value);
+    }
+    found
+}
+
+/// The name and unexpanded value of the variable `line` assigns, if it
+/// assigns one.
+fn assignment(line: &str, shell: Shell) -> Option<(&str, &str)> {
+    let mut line = line.trim_start();
+    let (name, value) = if shell == Shell::Tcsh {
+        line = line
+            .strip_prefix("set")?
+            .strip_prefix(char::is_whitespace)?;
+        let (name, value) = line.split_once('=')?;
+        (name.trim(), value.trim_start())
+    } else {
+        for keyword in ["export", "typeset", "declare", "local", "readonly"] {
+            if let Some(rest) = line
+                .strip_prefix(keyword)
+                .and_then(|rest| rest.strip_prefix(char::is_whitespace))
+            {
+                line = rest.trim_start();
+                // Flags like `typeset -x`.
+                while let Some(rest) = line.strip_prefix('-') {
+                    line = rest.split_once(char::is_whitespace)?.1.trim_start();
+                }
+                break;
+            }
+        }
+        line.split_once('=')?
+    };
+    let is_name = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_');
+    is_name.then_some((name, value))
+}
+
+/// `value` as the shell would take it: unquoted, with `~` and variables
+/// expanded, up to the first unquoted blank, `;`, or `#`. `None` if it uses
+/// a variable with no value, or anything more than this reads.
+fn expand(
+    value: &str,
+    home_dir: &Path,
+    vars: &HashMap<String, String>,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let var = |name: &str| vars.get(name).cloned().or_else(|| lookup(name));
+    let mut out = String::new();
+    let mut quote = None;
+    let mut chars = value.chars().peekable();
+    if value.starts_with("~/") || value == "~" {
+        out.push_str(home_dir.to_str()?);
+        chars.next();
+    }
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\'', None) | ('"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (_, Some('\'')) => out.push(c),
+            ('\\', _) => out.push(chars.next()?),
+            ('`', _) => return None,
+            ('$', _) if chars.next_if_eq(&'(').is_some() => return None,
+            ('$', _) if chars.next_if_eq(&'{').is_some() => {
+                let inner: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let value = match inner.split_once(":-") {
+                    Some((name, default)) => var(name)
+                        .filter(|value| !value.is_empty())
+                        .or_else(|| expand(default, home_dir, vars, lookup))?,
+                    None => var(&inner)?,
+                };
+                out.push_str(&value);
+            }
+            ('$', _) => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|&c| c.is_ascii_alphanumeric() || c == '_') {
+                    name.push(c);
+                }
+                match name.is_empty() {
+                    true => out.push('$'),
+                    false => out.push_str(&var(&name)?),
+                }
+            }
+            (' ' | '\t' | ';' | '#', None) => break,
+            _ => out.push(c),
+        }
+    }
+    (quote.is_none() && !out.is_empty()).then_some(out)
+}
+
+/// `value` with a leading `~/` taken as `home_dir`.
+fn expand_tilde(value: &str, home_dir: &Path) -> PathBuf {
+    match value.strip_prefix("~/") {
+        Some(rest) => home_dir.join(rest),
+        None => PathBuf::from(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan_with(text: &str, shell: Shell, env: &[(&str, &str)]) -> Option<PathBuf> {
+        let lookup = |name: &str| {
+            env.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        };
+        scan(
+            text,
+            shell,
+            Path::new("/home/me"),
+            &mut HashMap::new(),
+            lookup,
+        )
+    }
+
+    #[test]
+    fn finds_the_last_histfile_set() {
+        let rc = "\
+# HISTFILE=~/not-this
+export HISTSIZE=1000
+HISTFILE=~/.old_history
+STATE=\"${XDG_STATE_HOME:-$HOME/.local/state}\"
+export HISTFILE=\"$STATE/bash/history\" # moved
+";
+        assert_eq!(
+            scan_with(rc, Shell::Bash, &[("HOME", "/home/me")]),
+            Some(PathBuf::from("/home/me/.local/state/bash/history"))
+        );
+        assert_eq!(
+            scan_with(rc, Shell::Bash, &[("XDG_STATE_HOME", "/state")]),
+            Some(PathBuf::from("/state/bash/history"))
+        );
+    }
+
+    #[test]
+    fn reads_tcsh_set_and_skips_what_it_cannot_expand() {
+        let rc = "set histfile = ~/.tcsh/history\nset savehist = (1000 merge)\n";
+        assert_eq!(
+            scan_with(rc, Shell::Tcsh, &[]),
+            Some(PathBuf::from("/home/me/.tcsh/history"))
+        );
+        let rc = "typeset -x HISTFILE=$(mktemp)\nHISTFILE=$UNSET/history\n";
+        assert_eq!(scan_with(rc, Shell::Ksh, &[]), None);
+    }
+}
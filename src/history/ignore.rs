@@ -0,0 +1,102 @@
+//! Keeping secrets and noise out of the index.
+//!
+//! A command matching any ignore pattern is dropped as it is loaded, so it
+//! never shows up in results, counts, or the store. Besides the patterns in
+//! the config file, a built-in set drops commands that start with a space
+//! (bash's `HISTCONTROL=ignorespace` convention for "do not record this")
+//! and ones that look like they carry a password or token.
+//!
+//! ```toml
+//! [ignore]
+//! patterns = ["^(ls|cd|pwd)$", "^mysql .*-p"]
+//! builtin = true
+//! ```
+
+use regex::{Regex, RegexSet};
+use serde::Deserialize;
+
+/// The patterns ignored unless `builtin = false`.
+pub const BUILTIN: [&str; 3] = [
+    r"^[ \t]",
+    r"(?i)\b(password|passwd)=",
+    r"(?i)\bexport\s+\w*(TOKEN|SECRET|PASSWORD|API_?KEY)\w*=",
+];
+
+/// A regular expression from the config file's ignore patterns.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Regex::new(&s)
+            .map(Pattern)
+            .map_err(|err| format!("invalid ignore pattern: {}", err))
+    }
+}
+
+/// The `[ignore]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IgnoreConfig {
+    /// Regular expressions; a command matching any of them is left out.
+    pub patterns: Vec<Pattern>,
+    /// Whether the [`BUILTIN`] patterns apply too.
+    pub builtin: bool,
+}
+
+impl Default for IgnoreConfig {
+    fn default() -> Self {
+        IgnoreConfig {
+            patterns: Vec::new(),
+            builtin: true,
+        }
+    }
+}
+
+impl IgnoreConfig {
+    /// The commands this section leaves out.
+    pub fn ignore(&self) -> Ignore {
+        Ignore::new(&self.patterns, self.builtin)
+    }
+}
+
+/// The commands to leave out.
+#[derive(Debug, Clone)]
+pub struct Ignore {
+    set: RegexSet,
+}
+
+impl Default for Ignore {
+    /// Just the built-in patterns.
+    fn default() -> Self {
+        Ignore::new(&[], true)
+    }
+}
+
+impl Ignore {
+    /// Ignores commands matching any of `patterns`, and the [`BUILTIN`] ones
+    /// too if `builtin`.
+    pub fn new(patterns: &[Pattern], builtin: bool) -> Ignore {
+        let builtin = BUILTIN.iter().copied().filter(|_| builtin);
+        let patterns = patterns.iter().map(|Pattern(regex)| regex.as_str());
+        Ignore {
+            set: RegexSet::new(builtin.chain(patterns))
+                .expect("patterns were checked as they were read"),
+        }
+    }
+
+    /// Ignores nothing, for `--no-ignore`.
+    pub fn none() -> Ignore {
+        Ignore {
+            set: RegexSet::empty(),
+        }
+    }
+
+    /// Whether `command` is to be left out.
+    pub fn is_ignored(&self, command: &str) -> bool {
+        self.set.is_match(command)
+    }
+}
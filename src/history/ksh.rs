@@ -0,0 +1,111 @@
+//! Parser for the KornShell family's history files.
+//!
+//! ksh93 and mksh keep binary history, in `.sh_history` and usually
+//! `.mksh_history`. A ksh93 file starts with the bytes `0x81 0x01`, and
+//! each command follows ending in a newline and a NUL. Every so often
+//! comes a marker holding the number of the next command: `0x82`, a NUL,
+//! three bytes of number, and a NUL. A record starting with `0x81` takes
+//! back the command before it.
+//!
+//! An mksh file starts with `0xab 0xcd`, and each command is `0xff`, four
+//! bytes of line number, the command, and a NUL.
+//!
+//! The kshs of the BSDs write one command per line, as plain text.
+
+use super::Entry;
+
+const KSH93_MAGIC: [u8; 2] = [0x81, 0x01];
+const MKSH_MAGIC: [u8; 2] = [0xab, 0xcd];
+
+/// What starts an mksh record, and ksh93's markers and take-backs.
+const MKSH_COMMAND: u8 = 0xff;
+const KSH93_NUMBER: u8 = 0x82;
+const KSH93_UNDO: u8 = 0x81;
+
+/// Parses ksh history, binary or plain.
+pub fn parse(bytes: &[u8]) -> Vec<Entry> {
+    match bytes {
+        [0xab, 0xcd, rest @ ..] => parse_mksh(rest),
+        [0x81, 0x01, rest @ ..] => parse_ksh93(rest),
+        // What was appended to a binary file, read on its own.
+        rest @ [MKSH_COMMAND, ..] => parse_mksh(rest),
+        rest if rest.contains(&0) => parse_ksh93(rest),
+        _ => super::parse_lines(bytes),
+    }
+}
+
+/// Whether a file starting with `start` is binary ksh93 or mksh history.
+pub(crate) fn is_binary(start: &[u8]) -> bool {
+    start.starts_with(&KSH93_MAGIC) || start.starts_with(&MKSH_MAGIC)
+}
+
+/// The line the BSD kshs write for `command`. ksh93 and mksh write binary
+/// records instead, which th_rs does not.
+pub(crate) fn record(command: &str) -> String {
+    format!("{}\n", command)
+}
+
+fn parse_ksh93(mut rest: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    while !rest.is_empty() {
+        if let [KSH93_NUMBER, 0, _, _, _, 0, after @ ..] = rest {
+            rest = after;
+            continue;
+        }
+        let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        let (record, after) = rest.split_at(end);
+        rest = after.get(1..).unwrap_or_default();
+        match record {
+            [KSH93_UNDO, ..] => {
+                entries.pop();
+            }
+            _ => push_entry(&mut entries, record),
+        }
+    }
+    entries
+}
+
+fn parse_mksh(mut rest: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    // The line number may hold any byte, so each record is found by its
+    // start, which no UTF-8 text holds.
+    while let Some(start) = rest.iter().position(|&b| b == MKSH_COMMAND) {
+        let Some(record) = rest.get(start + 5..) else {
+            break;
+        };
+        let end = record.iter().position(|&b| b == 0).unwrap_or(record.len());
+        push_entry(&mut entries, &record[..end]);
+        rest = record.get(end + 1..).unwrap_or_default();
+    }
+    entries
+}
+
+fn push_entry(entries: &mut Vec<Entry>, record: &[u8]) {
+    let command = String::from_utf8_lossy(record);
+    let command = command.trim_end();
+    if !command.is_empty() {
+        entries.push(Entry::new(command));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(history: &[u8]) -> Vec<String> {
+        parse(history)
+            .into_iter()
+            .map(|entry| entry.command)
+            .collect()
+    }
+
+    #[test]
+    fn splits_binary_history_at_its_records() {
+        // A number marker, a taken-back command, and one over two lines.
+        let ksh93 = b"\x81\x01ls\n\0\x82\0\0\0\x02\0git status\n\0oops\n\0\x81\0echo 'a\nb'\n\0";
+        assert_eq!(commands(ksh93), ["ls", "git status", "echo 'a\nb'"]);
+        // Line numbers holding NULs, which do not end a record.
+        let mksh = b"\xab\xcd\xff\0\0\0\x01ls\0\xff\0\0\0\x02cat <<EOF\nx\nEOF\0";
+        assert_eq!(commands(mksh), ["ls", "cat <<EOF\nx\nEOF"]);
+    }
+}
@@ -0,0 +1,154 @@
+//! Reading a whole history file by mapping it into memory.
+//!
+//! A history of hundreds of megabytes is mapped rather than read into a
+//! buffer of its own, which spares copying the file into memory before it
+//! is parsed. Parsing still copies every command out into an [`Entry`] of
+//! its own, and the mapping goes once the file is parsed, so the entries
+//! take as much memory as if it had been read. Small files, and any the
+//! system will not map, are read as usual.
+//!
+//! [`Entry`]: super::Entry
+//!
+//! Shells rewrite their history by renaming a new file over the old one,
+//! which leaves a mapping of the old file intact. A file truncated in place
+//! while mapped would fault when read past its new end, so the mapping is
+//! only held while the file is parsed.
+
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+/// Files shorter than this are read rather than mapped, which is as quick.
+const MAP_BYTES: u64 = 1024 * 1024;
+
+/// The bytes of a file, mapped or read.
+pub struct Mapped {
+    bytes: Bytes,
+}
+
+enum Bytes {
+    Read(Vec<u8>),
+    #[cfg(unix)]
+    Mapped(Map),
+}
+
+impl Mapped {
+    /// Maps the file at `path`, or reads it if it is small or cannot be
+    /// mapped.
+    pub fn open(path: &Path) -> io::Result<Mapped> {
+        #[cfg(unix)]
+        {
+            let file = fs::File::open(path)?;
+            let len = file.metadata()?.len();
+            if len >= MAP_BYTES {
+                match Map::new(&file, len) {
+                    Some(map) => {
+                        return Ok(Mapped {
+                            bytes: Bytes::Mapped(map),
+                        })
+                    }
+                    None => tracing::debug!(
+                        error = %io::Error::last_os_error(),
+                        "could not map the file, so reading it"
+                    ),
+                }
+            }
+        }
+        Ok(Mapped {
+            bytes: Bytes::Read(fs::read(path)?),
+        })
+    }
+
+    /// Whether the file was mapped rather than read.
+    pub fn is_mapped(&self) -> bool {
+        !matches!(self.bytes, Bytes::Read(_))
+    }
+}
+
+impl Deref for Mapped {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.bytes {
+            Bytes::Read(bytes) => bytes,
+            #[cfg(unix)]
+            Bytes::Mapped(map) => map.bytes(),
+        }
+    }
+}
+
+/// A read-only, private mapping of a whole file.
+#[cfg(unix)]
+struct Map {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Map {
+    fn new(file: &fs::File, len: u64) -> Option<Map> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = usize::try_from(len).ok()?;
+        // SAFETY: a fresh mapping of `len` bytes of an open file, checked
+        // for failure; it outlives the file descriptor, as mappings do.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        (ptr != libc::MAP_FAILED).then_some(Map { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` readable bytes until dropped.
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Map {
+    fn drop(&mut self) {
+        // SAFETY: unmaps exactly what `new` mapped, once.
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+// The mapping is read-only, so it can be read from any thread.
+#[cfg(unix)]
+unsafe impl Send for Map {}
+#[cfg(unix)]
+unsafe impl Sync for Map {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_large_files_and_reads_small_ones() {
+        let dir = std::env::temp_dir().join(format!("th_rs-mapped-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let small = dir.join("small");
+        fs::write(&small, "ls\n").unwrap();
+        let mapped = Mapped::open(&small).unwrap();
+        assert!(!mapped.is_mapped());
+        assert_eq!(&mapped[..], b"ls\n");
+
+        let large = dir.join("large");
+        let text = "git status\n".repeat(MAP_BYTES as usize / 10);
+        fs::write(&large, &text).unwrap();
+        let mapped = Mapped::open(&large).unwrap();
+        assert_eq!(mapped.is_mapped(), cfg!(unix));
+        assert_eq!(&mapped[..], text.as_bytes());
+        drop(mapped);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
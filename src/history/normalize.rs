@@ -0,0 +1,308 @@
+//! Tidying commands before they are counted, so that trivially different
+//! spellings of one command are one entry.
+//!
+//! By default surrounding whitespace is trimmed and runs of spaces are
+//! collapsed, so `ls -la` and `ls  -la ` are the same; whitespace inside
+//! quotes is left alone. Leading `sudo` and `VAR=value` assignments can be
+//! stripped too, counting `sudo apt update` as `apt update`, but that changes
+//! what is run when the command is chosen, so it is off unless configured in
+//! the `[normalize]` section.
+//!
+//! Commands can also be counted together without being rewritten: with
+//! `ignore_case`, `Git status` and `git status` are one command, and with
+//! `ignore_spaces`, so are commands differing only in whitespace, even
+//! inside quotes. They are counted by their [`key`], and shown as they were
+//! last run.
+
+use super::Entry;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// Which normalizations to apply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+    /// Remove whitespace around the command.
+    pub trim: bool,
+    /// Turn runs of spaces and tabs outside quotes into one space. Commands
+    /// of several lines are left as they are, since a here-document's
+    /// spacing is part of its text.
+    pub collapse_spaces: bool,
+    /// Drop a leading `sudo`.
+    pub strip_sudo: bool,
+    /// Drop leading environment assignments like `RUST_LOG=debug`.
+    pub strip_env: bool,
+    /// Count commands differing only in letter case as one.
+    pub ignore_case: bool,
+    /// Count commands differing only in whitespace as one, wherever it is.
+    pub ignore_spaces: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            trim: true,
+            collapse_spaces: true,
+            strip_sudo: false,
+            strip_env: false,
+            ignore_case: false,
+            ignore_spaces: false,
+        }
+    }
+}
+
+impl Rules {
+    /// Whether commands spelled differently are counted as one, by
+    /// [`key`].
+    pub fn folds(&self) -> bool {
+        self.ignore_case || self.ignore_spaces
+    }
+}
+
+/// `command` with `rules` applied.
+pub fn normalize<'a>(command: &'a str, rules: &Rules) -> Cow<'a, str> {
+    let mut command = Cow::Borrowed(command);
+    if rules.trim {
+        command = trim(command);
+    }
+    if rules.collapse_spaces && !command.contains('\n') {
+        if let Some(collapsed) = collapse_spaces(&command) {
+            command = Cow::Owned(collapsed);
+        }
+    }
+    // `sudo FOO=1 make` and `FOO=1 sudo make` both come down to `make`.
+    loop {
+        let rest = match (rules.strip_env, rules.strip_sudo) {
+            (true, _) if env_assignment(&command).is_some() => env_assignment(&command),
+            (_, true) => sudo(&command),
+            _ => None,
+        };
+        match rest {
+            Some(rest) if !rest.is_empty() => command = Cow::Owned(rest.to_string()),
+            _ => return command,
+        }
+    }
+}
+
+/// What `command`, already normalized, is counted by: itself, lowercased
+/// with `ignore_case`, and with every run of whitespace one space and none
+/// around it with `ignore_spaces`.
+pub fn key<'a>(command: &'a str, rules: &Rules) -> Cow<'a, str> {
+    let mut key = Cow::Borrowed(command);
+    if rules.ignore_spaces {
+        let spaced = command.split(' ').any(str::is_empty)
+            || command.contains(|c: char| c.is_whitespace() && c != ' ');
+        if spaced {
+            let words: Vec<&str> = command.split_whitespace().collect();
+            key = Cow::Owned(words.join(" "));
+        }
+    }
+    if rules.ignore_case && key.chars().any(char::is_uppercase) {
+        key = Cow::Owned(key.to_lowercase());
+    }
+    key
+}
+
+/// Whether `entry`, as read from history, counts as `command` under
+/// `rules`.
+pub fn same(entry: &str, command: &str, rules: &Rules) -> bool {
+    let entry = normalize(entry, rules);
+    if !rules.folds() {
+        return entry == command;
+    }
+    key(&entry, rules) == key(command, rules)
+}
+
+/// Normalizes the commands of `entries` in place, dropping those left empty.
+pub fn normalize_entries(entries: &mut Vec<Entry>, rules: &Rules) {
+    entries.retain_mut(|entry| {
+        let normalized = normalize(&entry.command, rules);
+        if normalized != entry.command.as_str() {
+            entry.command = normalized.into_owned();
+        }
+        !entry.command.is_empty()
+    });
+}
+
+fn trim(command: Cow<'_, str>) -> Cow<'_, str> {
+    match command {
+        Cow::Borrowed(command) => Cow::Borrowed(command.trim()),
+        Cow::Owned(command) => Cow::Owned(command.trim().to_string()),
+    }
+}
+
+/// `command` with runs of blanks outside quotes made single spaces, or
+/// `None` if there are none to collapse.
+fn collapse_spaces(command: &str) -> Option<String> {
+    let mut collapsed = String::with_capacity(command.len());
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut changed = false;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            // Backslashes escape outside quotes and inside double ones.
+            (None | Some('"'), '\\') => escaped = true,
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, ' ' | '\t') => {
+                let mut run = c != ' ';
+                while let Some(' ' | '\t') = chars.peek() {
+                    chars.next();
+                    run = true;
+                }
+                changed |= run;
+                collapsed.push(' ');
+                continue;
+            }
+            _ => {}
+        }
+        collapsed.push(c);
+    }
+    changed.then_some(collapsed)
+}
+
+/// What follows a leading `NAME=value` assignment, if `command` starts with
+/// one. Quoted values are skipped as a whole.
+fn env_assignment(command: &str) -> Option<&str> {
+    let (name, rest) = command.split_once('=')?;
+    let mut chars = name.chars();
+    let starts_well = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    if !starts_well || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    let end = match rest.chars().next() {
+        Some(q @ ('"' | '\'')) => rest[1..].find(q)? + 2,
+        _ => rest.find([' ', '\t']).unwrap_or(rest.len()),
+    };
+    // An assignment alone is a command of its own, not a prefix.
+    let after = rest[end..].strip_prefix([' ', '\t'])?;
+    Some(after.trim_start())
+}
+
+/// What follows a leading `sudo`, unless it has options of its own, which
+/// may take arguments and so hide where the command starts.
+fn sudo(command: &str) -> Option<&str> {
+    let rest = command.strip_prefix("sudo")?.strip_prefix([' ', '\t'])?;
+    let rest = rest.trim_start();
+    (!rest.starts_with('-')).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all() -> Rules {
+        Rules {
+            strip_sudo: true,
+            strip_env: true,
+            ..Rules::default()
+        }
+    }
+
+    #[test]
+    fn trims_and_collapses_by_default() {
+        let rules = Rules::default();
+        assert_eq!(normalize("  ls   -la\t", &rules), "ls -la");
+        assert_eq!(normalize("ls\t-la", &rules), "ls -la");
+        assert_eq!(normalize("ls -la", &rules), "ls -la");
+    }
+
+    #[test]
+    fn leaves_commands_alone_when_nothing_applies() {
+        assert!(matches!(
+            normalize("git status", &Rules::default()),
+            Cow::Borrowed(_)
+        ));
+        let none = Rules {
+            trim: false,
+            collapse_spaces: false,
+            ..Rules::default()
+        };
+        assert_eq!(normalize("  ls  -la ", &none), "  ls  -la ");
+    }
+
+    #[test]
+    fn keeps_spaces_inside_quotes() {
+        let rules = Rules::default();
+        assert_eq!(
+            normalize(r#"echo  "a   b"  'c   d'"#, &rules),
+            r#"echo "a   b" 'c   d'"#
+        );
+        assert_eq!(
+            normalize(r#"echo "say \"hi  there\""  x"#, &rules),
+            r#"echo "say \"hi  there\"" x"#
+        );
+        assert_eq!(normalize(r"echo a\  b", &rules), r"echo a\  b");
+    }
+
+    #[test]
+    fn leaves_multi_line_commands_spacing() {
+        let heredoc = "cat <<EOF\n  two  spaces\nEOF";
+        assert_eq!(normalize(heredoc, &Rules::default()), heredoc);
+    }
+
+    #[test]
+    fn strips_sudo_when_asked() {
+        assert_eq!(normalize("sudo apt update", &all()), "apt update");
+        assert_eq!(normalize("sudo  apt update", &all()), "apt update");
+        assert_eq!(
+            normalize("sudo apt update", &Rules::default()),
+            "sudo apt update"
+        );
+        // Options may take arguments, so where the command starts is unclear.
+        assert_eq!(normalize("sudo -u web make", &all()), "sudo -u web make");
+        assert_eq!(
+            normalize("sudoedit /etc/hosts", &all()),
+            "sudoedit /etc/hosts"
+        );
+        assert_eq!(normalize("sudo", &all()), "sudo");
+    }
+
+    #[test]
+    fn strips_env_assignments_when_asked() {
+        assert_eq!(normalize("RUST_LOG=debug cargo run", &all()), "cargo run");
+        assert_eq!(normalize("A=1 B=2 make", &all()), "make");
+        assert_eq!(normalize(r#"MSG="a b" ./notify"#, &all()), "./notify");
+        assert_eq!(normalize("sudo FOO=1 make", &all()), "make");
+        assert_eq!(normalize("FOO=1 sudo make", &all()), "make");
+        // Not assignments, or nothing after them.
+        assert_eq!(normalize("export FOO=1", &all()), "export FOO=1");
+        assert_eq!(normalize("FOO=1", &all()), "FOO=1");
+        assert_eq!(normalize("1X=2 make", &all()), "1X=2 make");
+        assert_eq!(
+            normalize("git log --format=%H", &all()),
+            "git log --format=%H"
+        );
+    }
+
+    #[test]
+    fn keys_ignore_case_and_spaces_when_asked() {
+        let rules = Rules {
+            ignore_case: true,
+            ignore_spaces: true,
+            ..Rules::default()
+        };
+        assert_eq!(key("Git Status", &rules), "git status");
+        assert_eq!(key("echo 'a   b'\n", &rules), "echo 'a b'");
+        assert!(matches!(key("git status", &rules), Cow::Borrowed(_)));
+        assert_eq!(key("Git Status", &Rules::default()), "Git Status");
+        assert!(same("  GIT  status", "git Status", &rules));
+        assert!(!same("GIT status", "git status", &Rules::default()));
+    }
+
+    #[test]
+    fn normalizing_entries_merges_and_drops_empty_ones() {
+        let mut entries = vec![
+            Entry::new("ls  -la"),
+            Entry::new("   "),
+            Entry::new("ls -la"),
+        ];
+        normalize_entries(&mut entries, &Rules::default());
+        let commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["ls -la", "ls -la"]);
+    }
+}
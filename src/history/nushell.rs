@@ -0,0 +1,219 @@
+//! Reading Nushell's history, kept by its line editor, reedline, in either
+//! of two ways, per `$env.config.history.file_format`.
+//!
+//! As `plaintext` it is `history.txt`, one command per line, with the line
+//! breaks of a command typed over several lines written as `<\n>`.
+//!
+//! As `sqlite` it is `history.sqlite3`, one row per run in `history`, which
+//! also has when the run started and how long it took (in milliseconds),
+//! where it ran, and its exit status. Rows only ever get added, with ids
+//! counting up, so a database is followed by the last id read rather than
+//! by how long the file is.
+
+use super::Entry;
+use crate::error::{Result, ThError};
+use rusqlite::{Connection, OpenFlags};
+use std::io;
+use std::path::Path;
+
+/// How a line break inside a command is written in `history.txt`.
+const NEWLINE: &str = "<\\n>";
+
+/// Parses `history.txt`.
+pub fn parse(bytes: &[u8]) -> Vec<Entry> {
+    super::lines(bytes)
+        .filter_map(|line| {
+            let command = line.trim_end().replace(NEWLINE, "\n");
+            (!command.is_empty()).then(|| Entry::new(command))
+        })
+        .collect()
+}
+
+/// The line reedline writes to `history.txt` for `command`.
+pub(crate) fn record(command: &str) -> String {
+    format!("{}\n", command.replace('\n', NEWLINE))
+}
+
+/// Every run in the database at `path` after the row `after`, oldest first,
+/// with the id of the last row; `after` itself when there are none.
+pub fn read(path: &Path, after: u64) -> Result<(Vec<Entry>, u64)> {
+    let conn = open(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, command_line, start_timestamp, duration_ms, exit_status, cwd
+         FROM history WHERE id > ?1 ORDER BY id",
+    )?;
+    let mut rows = stmt.query([after])?;
+    let mut entries = Vec::new();
+    let mut last = after;
+    while let Some(row) = rows.next()? {
+        last = row.get(0)?;
+        let command: String = row.get(1)?;
+        if command.trim().is_empty() {
+            continue;
+        }
+        let millis: Option<i64> = row.get(2)?;
+        let duration: Option<i64> = row.get(3)?;
+        entries.push(Entry {
+            timestamp: millis.map(|millis| millis / 1000),
+            duration: duration.map(|millis| (millis.max(0) / 1000) as u64),
+            exit: row.get(4)?,
+            cwd: row
+                .get::<_, Option<String>>(5)?
+                .filter(|cwd| !cwd.is_empty()),
+            ..Entry::new(command)
+        });
+    }
+    Ok((entries, last))
+}
+
+/// The id of the last row in the database at `path`, or 0 if it has none
+/// or does not exist yet.
+pub fn last_id(path: &Path) -> Result<u64> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let conn = open(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let last: Option<u64> = conn.query_row("SELECT max(id) FROM history", [], |row| row.get(0))?;
+    Ok(last.unwrap_or(0))
+}
+
+/// Adds a run of `command`, started at `timestamp`, to the database at
+/// `path`, as Nushell would record one with nothing else known about it.
+pub fn append(path: &Path, command: &str, timestamp: i64) -> Result<()> {
+    let conn = open(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    conn.execute(
+        "INSERT INTO history (command_line, start_timestamp) VALUES (?1, ?2)",
+        rusqlite::params![command, timestamp * 1000],
+    )?;
+    Ok(())
+}
+
+/// Removes every run whose command `matches` from the database at `path`,
+/// returning how many were removed.
+pub fn delete(path: &Path, matches: impl Fn(&str) -> bool) -> Result<usize> {
+    let mut conn = open(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    let tx = conn.transaction()?;
+    let ids: Vec<i64> = {
+        let mut stmt = tx.prepare("SELECT id, command_line FROM history")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))?;
+        rows.filter_map(|row| match row {
+            Ok((id, command)) if matches(&command) => Some(Ok(id)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
+        .collect::<rusqlite::Result<_>>()?
+    };
+    for id in &ids {
+        tx.execute("DELETE FROM history WHERE id = ?1", [id])?;
+    }
+    tx.commit()?;
+    Ok(ids.len())
+}
+
+/// Every run in the database at `path` with its row id, oldest first.
+pub fn rows(path: &Path) -> Result<Vec<(i64, Entry)>> {
+    let conn = open(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt =
+        conn.prepare("SELECT id, command_line, start_timestamp FROM history ORDER BY id")?;
+    let rows = stmt.query_map([], |row| {
+        let millis: Option<i64> = row.get(2)?;
+        Ok((
+            row.get(0)?,
+            Entry {
+                timestamp: millis.map(|millis| millis / 1000),
+                ..Entry::new(row.get::<_, String>(1)?)
+            },
+        ))
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Copies the database at `path` to `to`, which must not exist yet, with
+/// whatever is still in its write-ahead log.
+pub fn copy(path: &Path, to: &Path) -> Result<()> {
+    let conn = open(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.execute("VACUUM INTO ?1", [to.to_string_lossy()])?;
+    Ok(())
+}
+
+/// Removes the rows `ids` from the database at `path`.
+pub fn delete_ids(path: &Path, ids: &[i64]) -> Result<()> {
+    let mut conn = open(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    let tx = conn.transaction()?;
+    for id in ids {
+        tx.execute("DELETE FROM history WHERE id = ?1", [id])?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Whether `path` is a database of Nushell's, rather than any other SQLite
+/// file, such as atuin's: one with a `history` table of `command_line`s.
+pub fn is_database(path: &Path) -> bool {
+    crate::store::atuin::is_database(path)
+        && open(path, OpenFlags::SQLITE_OPEN_READ_ONLY).is_ok_and(|conn| {
+            conn.prepare("SELECT command_line FROM history LIMIT 0")
+                .is_ok()
+        })
+}
+
+/// Opens the database at `path`, which has to exist already: Nushell makes
+/// it, with its own schema.
+fn open(path: &Path, flags: OpenFlags) -> Result<Connection> {
+    if !path.exists() {
+        return Err(ThError::HistoryFile {
+            path: path.to_path_buf(),
+            source: io::ErrorKind::NotFound.into(),
+        });
+    }
+    let conn = Connection::open_with_flags(path, flags)?;
+    // Nushell may be writing at the same moment.
+    conn.busy_timeout(std::time::Duration::from_secs(2))?;
+    Ok(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Format;
+    use std::fs;
+
+    #[test]
+    fn reads_commands_typed_over_several_lines() {
+        let history = "ls\nfor x in 1..3 {<\\n>  print $x<\\n>}\n\ngit status\n";
+        let commands: Vec<String> = parse(history.as_bytes())
+            .into_iter()
+            .map(|entry| entry.command)
+            .collect();
+        assert_eq!(
+            commands,
+            ["ls", "for x in 1..3 {\n  print $x\n}", "git status"]
+        );
+        assert_eq!(
+            parse(record(&commands[1]).as_bytes())[0].command,
+            commands[1]
+        );
+    }
+
+    #[test]
+    fn tells_its_database_from_other_sqlite_files() {
+        let dir = std::env::temp_dir().join(format!("th_rs-nushell-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let nushell = dir.join("history.sqlite3");
+        let atuin = dir.join("history.db");
+        Connection::open(&nushell)
+            .unwrap()
+            .execute_batch("CREATE TABLE history (id INTEGER PRIMARY KEY, command_line TEXT)")
+            .unwrap();
+        Connection::open(&atuin)
+            .unwrap()
+            .execute_batch("CREATE TABLE history (id TEXT PRIMARY KEY, command TEXT)")
+            .unwrap();
+        assert!(is_database(&nushell));
+        assert!(!is_database(&atuin));
+        assert!(!is_database(&dir.join("missing.sqlite3")));
+        assert_eq!(Format::detect(&nushell), Format::NushellSqlite);
+        assert_ne!(Format::detect(&atuin), Format::NushellSqlite);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
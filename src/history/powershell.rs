@@ -0,0 +1,40 @@
+//! Parser for PSReadLine's `ConsoleHost_history.txt`.
+//!
+//! PSReadLine writes one command per line with no metadata. A command that
+//! spans several lines has a backtick at the end of every line but the last.
+
+use super::Entry;
+
+/// Parses PSReadLine history, joining backtick-continued lines.
+pub fn parse(bytes: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut pending = String::new();
+
+    for line in super::lines(bytes) {
+        if !pending.is_empty() {
+            pending.push('\n');
+        }
+        match line.strip_suffix('`') {
+            Some(continued) => pending.push_str(continued),
+            None => {
+                pending.push_str(&line);
+                push_entry(&mut entries, &pending);
+                pending.clear();
+            }
+        }
+    }
+    push_entry(&mut entries, &pending);
+    entries
+}
+
+/// The lines PSReadLine writes for `command`.
+pub(crate) fn record(command: &str) -> String {
+    format!("{}\n", command.replace('\n', "`\n"))
+}
+
+fn push_entry(entries: &mut Vec<Entry>, command: &str) {
+    let trimmed = command.trim();
+    if !trimmed.is_empty() {
+        entries.push(Entry::new(trimmed));
+    }
+}
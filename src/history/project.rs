@@ -0,0 +1,84 @@
+//! Commands a project ships for everyone working on it, in a
+//! `.th_rs_history` file at its root.
+//!
+//! Started anywhere inside the project, th_rs searches the file along with
+//! the user's own history, and ranks its commands higher by the ranking's
+//! `project_boost`. The file holds one command per line; blank lines and
+//! lines starting with `#` are skipped:
+//!
+//! ```text
+//! # build and test
+//! cargo test --workspace
+//! make -C docs serve
+//! ```
+//!
+//! The commands count as run once, with no time, in the project's root.
+//! They are only offered, like any other history, never run unasked.
+
+use super::provider::Provider;
+use super::Entry;
+use crate::error::{Result, ThError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the file a project keeps its commands in.
+pub const FILE_NAME: &str = ".th_rs_history";
+
+/// The root of the project `dir` is in: the nearest directory at or above it
+/// holding a [`FILE_NAME`].
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|dir| dir.join(FILE_NAME).is_file())
+        .map(Path::to_path_buf)
+}
+
+/// The commands of a `.th_rs_history` file, run in `root`.
+pub fn parse(text: &str, root: &Path) -> Vec<Entry> {
+    let root = root.to_string_lossy();
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| Entry {
+            cwd: Some(root.to_string()),
+            ..Entry::new(line)
+        })
+        .collect()
+}
+
+/// The `.th_rs_history` of the project at `root`.
+pub struct Project {
+    root: PathBuf,
+}
+
+impl Project {
+    pub fn new(root: PathBuf) -> Project {
+        Project { root }
+    }
+}
+
+impl Provider for Project {
+    fn name(&self) -> String {
+        self.root.join(FILE_NAME).display().to_string()
+    }
+
+    fn load(&self) -> Result<Vec<Entry>> {
+        let path = self.root.join(FILE_NAME);
+        let text =
+            fs::read_to_string(&path).map_err(|source| ThError::HistoryFile { path, source })?;
+        Ok(parse(&text, &self.root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let text = "# build\ncargo build\n\n  make docs  \n";
+        let entries = parse(text, Path::new("/src/app"));
+        let commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["cargo build", "make docs"]);
+        assert_eq!(entries[0].cwd.as_deref(), Some("/src/app"));
+    }
+}
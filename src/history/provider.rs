@@ -0,0 +1,371 @@
+//! Commands from places other than a shell's history file, searched along
+//! with it: terminal transcripts, such as `script(1)` typescripts of
+//! `docker exec` or `kubectl exec` sessions and tmux pane dumps, files of
+//! JSON lines, and programs that print them.
+//!
+//! Each is a [`Provider`], listed under `[[providers]]` in the config file:
+//!
+//! ```toml
+//! [[providers]]
+//! kind = "transcripts"
+//! path = "~/typescripts"
+//! prompt = '^root@[0-9a-f]+:[^#]*# '
+//!
+//! [[providers]]
+//! kind = "jsonl"
+//! path = "~/kube/exec.jsonl"
+//!
+//! [[providers]]
+//! kind = "command"
+//! run = "kubectl config get-contexts -o name"
+//! template = "kubectl config use-context {}"
+//! ```
+//!
+//! A JSON line holds `command` (or `cmd`), and may hold `timestamp` (or
+//! `time`), in Unix seconds or RFC 3339, `cwd`, `exit`, and `duration` in
+//! seconds. A program may also print plain lines, each a candidate of its
+//! own, which is how plugins that know nothing of th_rs offer commands.
+//! Unlike history files they are read in full each time, and are not
+//! followed for new commands.
+
+use super::{Entry, Source};
+use crate::config;
+use crate::error::{Result, ThError};
+use crate::exec;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+/// What a prompt looks like in a transcript, unless the config says:
+/// `[user@host dir]`, `user@host:dir`, or the like, then `$`, `#`, `%`, or
+/// `❯`, and a space. A bare `# ` is too often a comment in some output.
+const DEFAULT_PROMPT: &str = r"^(?:\[[^\]]*\]|[\w.@:~/-]+)\s?[$#%❯] ";
+
+/// Somewhere commands can be found.
+pub trait Provider: Send + Sync {
+    /// What to call it in messages: usually its path.
+    fn name(&self) -> String;
+
+    /// Every command it holds, oldest first where it knows.
+    fn load(&self) -> Result<Vec<Entry>>;
+}
+
+impl Provider for Source {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn load(&self) -> Result<Vec<Entry>> {
+        Source::load(self)
+    }
+}
+
+/// One `[[providers]]` entry in the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ProviderConfig {
+    /// A transcript of a terminal, or a directory of them, in which
+    /// commands are the rest of each line after a prompt matching `prompt`.
+    Transcripts {
+        path: PathBuf,
+        prompt: Option<String>,
+    },
+    /// A file of JSON objects, one per line.
+    Jsonl { path: PathBuf },
+    /// A program, run in the user's shell, that prints candidates: one per
+    /// line, as JSON objects or plain text, and put into `template` if
+    /// given, e.g. `kubectl config use-context {}`.
+    Command {
+        run: String,
+        template: Option<String>,
+    },
+}
+
+impl ProviderConfig {
+    /// The provider this entry describes.
+    pub fn build(&self) -> Result<Box<dyn Provider>> {
+        Ok(match self {
+            ProviderConfig::Transcripts { path, prompt } => {
+                let prompt = prompt.as_deref().unwrap_or(DEFAULT_PROMPT);
+                let prompt = Regex::new(prompt).map_err(|err| ThError::Provider {
+                    name: path.display().to_string(),
+                    message: format!("invalid prompt: {}", err),
+                })?;
+                Box::new(Transcripts {
+                    path: config::expand_home(path),
+                    prompt,
+                })
+            }
+            ProviderConfig::Jsonl { path } => Box::new(JsonLines {
+                path: config::expand_home(path),
+            }),
+            ProviderConfig::Command { run, template } => Box::new(Program {
+                run: run.clone(),
+                template: template.clone(),
+            }),
+        })
+    }
+}
+
+/// Terminal transcripts: see [`ProviderConfig::Transcripts`].
+pub struct Transcripts {
+    path: PathBuf,
+    prompt: Regex,
+}
+
+impl Provider for Transcripts {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn load(&self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for file in files(&self.path)? {
+            let bytes = fs::read(&file).map_err(|source| ThError::HistoryFile {
+                path: file.clone(),
+                source,
+            })?;
+            entries.extend(parse_transcript(&bytes, &self.prompt));
+        }
+        Ok(entries)
+    }
+}
+
+/// A file of JSON lines: see [`ProviderConfig::Jsonl`].
+pub struct JsonLines {
+    path: PathBuf,
+}
+
+impl Provider for JsonLines {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn load(&self) -> Result<Vec<Entry>> {
+        match fs::read(&self.path) {
+            Ok(bytes) => Ok(parse_json_lines(&bytes)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(source) => Err(ThError::HistoryFile {
+                path: self.path.clone(),
+                source,
+            }),
+        }
+    }
+}
+
+/// A program printing JSON lines: see [`ProviderConfig::Command`].
+pub struct Program {
+    run: String,
+    template: Option<String>,
+}
+
+impl Provider for Program {
+    fn name(&self) -> String {
+        self.run.clone()
+    }
+
+    fn load(&self) -> Result<Vec<Entry>> {
+        let failed = |message| ThError::Provider {
+            name: self.name(),
+            message,
+        };
+        let output = exec::command(&self.run)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|err| failed(err.to_string()))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(failed(format!("{}: {}", output.status, stderr.trim())));
+        }
+        Ok(parse_candidates(&output.stdout, self.template.as_deref()))
+    }
+}
+
+/// The file at `path`, or the files directly in the directory there by
+/// name; none if there is nothing there.
+fn files(path: &Path) -> Result<Vec<PathBuf>> {
+    let with_path = |source| ThError::HistoryFile {
+        path: path.to_path_buf(),
+        source,
+    };
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(with_path(err)),
+    };
+    if !meta.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path).map_err(with_path)? {
+        let entry = entry.map_err(with_path)?;
+        if entry.file_type().is_ok_and(|kind| kind.is_file()) {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The commands typed after a prompt in the terminal output `bytes`. The
+/// output is taken as the terminal would have shown it: escape sequences
+/// dropped, backspaces undoing the character before, and a carriage return
+/// starting the line over, as a line editor redrawing it does.
+fn parse_transcript(bytes: &[u8], prompt: &Regex) -> Vec<Entry> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut entries = Vec::new();
+    for line in text.split('\n') {
+        let line = rendered(line);
+        let Some(found) = prompt.find(&line) else {
+            continue;
+        };
+        let command = line[found.end()..].trim();
+        if !command.is_empty() {
+            entries.push(Entry::new(command));
+        }
+    }
+    entries
+}
+
+/// A line of terminal output as it was left on the screen.
+pub(crate) fn rendered(line: &str) -> String {
+    let mut shown = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: parameters up to a final byte.
+                Some('[') => while chars.next().is_some_and(|c| !('@'..='~').contains(&c)) {},
+                // OSC: up to BEL or ESC \.
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\x08' => {
+                shown.pop();
+            }
+            // A redraw from the start of the line that ends with nothing
+            // more keeps what was there.
+            '\r' if chars.peek().is_some() => shown.clear(),
+            c if c.is_control() && c != '\t' => {}
+            c => shown.push(c),
+        }
+    }
+    shown
+}
+
+/// The commands in JSON lines; lines that are not objects with a command
+/// are skipped.
+fn parse_json_lines(bytes: &[u8]) -> Vec<Entry> {
+    let text = String::from_utf8_lossy(bytes);
+    text.lines().filter_map(json_entry).collect()
+}
+
+/// The candidates a program printed: JSON lines as for [`parse_json_lines`],
+/// or any other line taken whole. With a `template`, each becomes the
+/// template with its `{}` replaced by the candidate.
+fn parse_candidates(bytes: &[u8], template: Option<&str>) -> Vec<Entry> {
+    let text = String::from_utf8_lossy(bytes);
+    text.lines()
+        .filter_map(|line| {
+            let mut entry = match json_entry(line) {
+                Some(entry) => entry,
+                None if line.trim().is_empty() => return None,
+                None => Entry::new(line.trim_end()),
+            };
+            if let Some(template) = template {
+                entry.command = template.replace("{}", &entry.command);
+            }
+            Some(entry)
+        })
+        .collect()
+}
+
+/// The command in a JSON line, with whatever else it says about the run.
+fn json_entry(line: &str) -> Option<Entry> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let field = |names: &[&str]| names.iter().find_map(|name| value.get(*name));
+    let command = field(&["command", "cmd"])?.as_str()?.trim_end();
+    if command.trim().is_empty() {
+        return None;
+    }
+    let timestamp = field(&["timestamp", "time"]).and_then(|time| match time {
+        Value::Number(seconds) => seconds.as_i64(),
+        Value::String(time) => chrono::DateTime::parse_from_rfc3339(time)
+            .ok()
+            .map(|time| time.timestamp()),
+        _ => None,
+    });
+    Some(Entry {
+        timestamp,
+        cwd: field(&["cwd"]).and_then(Value::as_str).map(str::to_string),
+        exit: field(&["exit"])
+            .and_then(Value::as_i64)
+            .and_then(|exit| i32::try_from(exit).ok()),
+        duration: field(&["duration"]).and_then(Value::as_u64),
+        ..Entry::new(command)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(entries: Vec<Entry>) -> Vec<String> {
+        entries.into_iter().map(|entry| entry.command).collect()
+    }
+
+    #[test]
+    fn finds_commands_after_prompts_in_a_typescript() {
+        let typescript = "Script started on 2024-05-01 10:00:00+00:00\r\n\
+\x1b]0;root@4f2a: /app\x07root@4f2a:/app# ls -l\x1b[K\r\n\
+total 0\r\n\
+root@4f2a:/app# cat pg.conf\r\n\
+# listen = 5432\r\n\
+[me@box ~]$ git statsu\x08\x08us\r\n\
+[me@box ~]$ \r\n";
+        let prompt = Regex::new(DEFAULT_PROMPT).unwrap();
+        assert_eq!(
+            commands(parse_transcript(typescript.as_bytes(), &prompt)),
+            ["ls -l", "cat pg.conf", "git status"]
+        );
+    }
+
+    #[test]
+    fn reads_json_lines_with_either_kind_of_time() {
+        let log = r#"{"cmd": "kubectl get pods", "time": "2024-05-01T10:00:00Z", "exit": 1}
+not json
+{"command": "docker ps", "timestamp": 1714557600, "cwd": "/srv"}
+{"other": "field"}
+"#;
+        let entries = parse_json_lines(log.as_bytes());
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "kubectl get pods");
+        assert_eq!(entries[0].timestamp, Some(1714557600));
+        assert_eq!(entries[0].exit, Some(1));
+        assert_eq!(entries[1].cwd.as_deref(), Some("/srv"));
+    }
+
+    #[test]
+    fn takes_plain_lines_as_candidates_for_a_template() {
+        let output = "prod\n\n{\"command\": \"staging\", \"timestamp\": 5}\n";
+        let entries = parse_candidates(output.as_bytes(), Some("kubectl config use-context {}"));
+        assert_eq!(
+            commands(entries.clone()),
+            [
+                "kubectl config use-context prod",
+                "kubectl config use-context staging"
+            ]
+        );
+        assert_eq!(entries[1].timestamp, Some(5));
+    }
+}
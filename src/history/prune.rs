@@ -0,0 +1,112 @@
+//! Deciding what `th_rs prune` removes from a history.
+//!
+//! A history is pruned run by run, oldest first. A run goes if it is from
+//! before the cut-off, if its command ran fewer times than the minimum in
+//! all, or, when deduplicating, if a later run of the same command is kept.
+//! Runs with no time are never too old. Commands count as the same once
+//! normalized, as they do in the picker.
+
+use std::collections::{BTreeMap, HashMap};
+
+/// What to prune.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    /// Runs started before this Unix time go.
+    pub before: Option<i64>,
+    /// Commands run fewer times than this go, every run of them.
+    pub min_count: usize,
+    /// Whether only the latest run of each command is kept.
+    pub dedupe: bool,
+}
+
+/// A run that may be pruned, or for the store's imported history, several
+/// runs of a command counted together.
+#[derive(Debug, Clone)]
+pub struct Item {
+    /// The command, normalized.
+    pub command: String,
+    pub timestamp: Option<i64>,
+    /// How many runs this stands for.
+    pub runs: usize,
+}
+
+/// Which of `items`, oldest first, `rules` keep.
+pub fn keep(items: &[Item], rules: &Rules) -> Vec<bool> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for item in items {
+        *counts.entry(&item.command).or_default() += item.runs;
+    }
+    let mut keep: Vec<bool> = items
+        .iter()
+        .map(|item| {
+            let old =
+                matches!((item.timestamp, rules.before), (Some(at), Some(before)) if at < before);
+            !old && counts[item.command.as_str()] >= rules.min_count
+        })
+        .collect();
+    if rules.dedupe {
+        let mut latest: HashMap<&str, usize> = HashMap::new();
+        for (index, item) in items.iter().enumerate() {
+            if keep[index] {
+                if let Some(earlier) = latest.insert(&item.command, index) {
+                    keep[earlier] = false;
+                }
+            }
+        }
+    }
+    keep
+}
+
+/// How many runs of each command go, of `items` where `keep` is false.
+pub fn removed(items: &[Item], keep: &[bool]) -> BTreeMap<String, usize> {
+    let mut removed = BTreeMap::new();
+    for (item, _) in items.iter().zip(keep).filter(|(_, &keep)| !keep) {
+        *removed.entry(item.command.clone()).or_default() += item.runs;
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(command: &str, timestamp: Option<i64>) -> Item {
+        Item {
+            command: command.to_string(),
+            timestamp,
+            runs: 1,
+        }
+    }
+
+    #[test]
+    fn removes_old_rare_and_repeated_runs() {
+        let items = [
+            item("ls", Some(100)),
+            item("make", None),
+            item("ls", Some(500)),
+            item("vim notes", Some(600)),
+            item("ls", Some(700)),
+        ];
+        let old = Rules {
+            before: Some(400),
+            min_count: 1,
+            dedupe: false,
+        };
+        assert_eq!(keep(&items, &old), [false, true, true, true, true]);
+        let rare = Rules {
+            min_count: 2,
+            ..Rules::default()
+        };
+        assert_eq!(keep(&items, &rare), [true, false, true, false, true]);
+        let dedupe = Rules {
+            dedupe: true,
+            ..Rules::default()
+        };
+        let kept = keep(&items, &dedupe);
+        assert_eq!(kept, [false, true, false, true, true]);
+        assert_eq!(
+            removed(&items, &kept),
+            BTreeMap::from([("ls".to_string(), 2)])
+        );
+    }
+}
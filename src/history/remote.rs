@@ -0,0 +1,138 @@
+//! Searching another machine's history, for `th_rs --remote user@host`.
+//!
+//! The remote's history file is copied over `ssh` into the cache directory
+//! and read from there like any other. A copy younger than the TTL is used
+//! as it is, so that searching the same host again does not reconnect.
+//!
+//! A remote is `host` or `user@host`, whose login shell's history is found
+//! from its `SHELL`, or `host:path` for a file of its own. The format is
+//! detected from the file's name and contents, as for `--history-file`.
+
+use super::Source;
+use crate::error::{Result, ThError};
+use crate::paths;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+/// How long a fetched copy is used before fetching again, unless the
+/// config says otherwise.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Sets `f` to the remote login shell's history file, relative to the home
+/// directory `ssh` starts in.
+const FIND_HISTORY: &str = r#"case "${SHELL##*/}" in
+  zsh) f=.zsh_history ;;
+  fish) f=${XDG_DATA_HOME:-.local/share}/fish/fish_history ;;
+  nu) f=${XDG_CONFIG_HOME:-.config}/nushell/history.sqlite3
+      [ -f "$f" ] || f=${XDG_CONFIG_HOME:-.config}/nushell/history.txt ;;
+  mksh|lksh) f=.mksh_history ;;
+  ksh*|oksh|pdksh) f=.sh_history ;;
+  tcsh|csh) f=.history ;;
+  *) f=.bash_history ;;
+esac"#;
+
+/// The history of `target`, from a copy fetched at most `ttl` ago or else
+/// fetched now.
+pub fn fetch(target: &str, ttl: Duration) -> Result<Source> {
+    let (host, path) = match target.split_once(':') {
+        Some((host, path)) => (host, Some(path)),
+        None => (target, None),
+    };
+    if host.is_empty() || path == Some("") {
+        return Err(ThError::Remote(format!(
+            "invalid remote: {} (expected host, user@host, or host:path)",
+            target
+        )));
+    }
+    let dir = paths::cache_dir()?.join("remote").join(dir_name(target));
+    // `fetched` names the copy, and was written when it was made.
+    let marker = dir.join("fetched");
+    if let Some(copy) = fresh_copy(&dir, &marker, ttl) {
+        return Ok(Source::from_path(copy, None));
+    }
+
+    let find = match path {
+        Some(path) => format!("f={}", quote(path.strip_prefix("~/").unwrap_or(path))),
+        None => FIND_HISTORY.to_string(),
+    };
+    let script = format!("{}\nprintf '%s\\n' \"$f\"\nexec cat -- \"$f\"\n", find);
+    // Given to `sh` on its stdin, since the login shell that would run it
+    // as an argument could be fish or tcsh. After `--`, a host starting
+    // with `-` is not taken for an option.
+    let mut child = Command::new("ssh")
+        .arg("--")
+        .arg(host)
+        .arg("sh -s")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| ThError::Remote(format!("could not run ssh: {}", err)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    let newline = output.stdout.iter().position(|&b| b == b'\n');
+    let (Some(newline), true) = (newline, output.status.success()) else {
+        return Err(ThError::Remote(format!(
+            "could not read history from {}: {}",
+            host,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    };
+    let remote_path = String::from_utf8_lossy(&output.stdout[..newline]).into_owned();
+
+    // The copy keeps the file's name, and its directory's for the likes of
+    // `nushell/history.txt`, which is how its format is told.
+    let remote_path = Path::new(&remote_path);
+    let mut relative = PathBuf::new();
+    if let Some(parent) = remote_path.parent().and_then(Path::file_name) {
+        relative.push(parent);
+    }
+    relative.push(remote_path.file_name().unwrap_or("history".as_ref()));
+    let copy = dir.join(&relative);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    // Readable by the user alone, like the history it copies.
+    paths::create_private_dir(&dir)?;
+    fs::create_dir_all(copy.parent().unwrap_or(&dir))?;
+    paths::private_file()
+        .create_new(true)
+        .open(&copy)?
+        .write_all(&output.stdout[newline + 1..])?;
+    fs::write(&marker, relative.to_string_lossy().as_bytes())?;
+    Ok(Source::from_path(copy, None))
+}
+
+/// The copy in `dir`, if `marker` says it was fetched within `ttl`.
+fn fresh_copy(dir: &Path, marker: &Path, ttl: Duration) -> Option<PathBuf> {
+    let fetched = fs::metadata(marker).and_then(|meta| meta.modified()).ok()?;
+    let age = SystemTime::now()
+        .duration_since(fetched)
+        .unwrap_or_default();
+    if age > ttl {
+        return None;
+    }
+    let copy = dir.join(fs::read_to_string(marker).ok()?);
+    copy.is_file().then_some(copy)
+}
+
+/// A directory name for `target` that keeps it recognizable.
+fn dir_name(target: &str) -> String {
+    target
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' | '@' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// `s` quoted for the remote's shell.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
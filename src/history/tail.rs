@@ -0,0 +1,116 @@
+//! Following a history file as the shell appends to it.
+//!
+//! Shells add to their history file as commands run (zsh with
+//! `INC_APPEND_HISTORY`, fish always, bash with `history -a`). Rather than
+//! re-reading the whole file, a [`Tail`] remembers how far it has read and
+//! parses only what was written since. For a database, how far means the
+//! last row read.
+
+use super::{nushell, Entry, Source};
+use crate::error::{Result, ThError};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// What happened to a history file since it was last read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// Nothing new.
+    Unchanged,
+    /// These entries were appended.
+    Appended(Vec<Entry>),
+    /// The file got shorter, so it was rewritten rather than appended to,
+    /// e.g. trimmed to `HISTSIZE`; or it changed, and is in a format whose
+    /// additions cannot be read alone (see [`Format::appends_lines`]). It
+    /// has to be read again from the start.
+    ///
+    /// [`Format::appends_lines`]: super::Format::appends_lines
+    Rewritten,
+}
+
+/// A history file and how much of it has been read.
+#[derive(Debug, Clone)]
+pub struct Tail {
+    source: Source,
+    /// Bytes read, or for a database the id of the last row read.
+    offset: u64,
+}
+
+impl Tail {
+    /// Starts following `source` from its current end, for a file whose
+    /// contents have just been loaded. A file that does not exist yet is
+    /// followed from its start once it appears.
+    pub fn new(source: Source) -> Tail {
+        let offset = match source.format.is_database() {
+            true => nushell::last_id(&source.path).unwrap_or(0),
+            false => source.path.metadata().map(|m| m.len()).unwrap_or(0),
+        };
+        Tail { source, offset }
+    }
+
+    /// Reads whatever complete entries were written since the last call.
+    ///
+    /// A final line with no newline yet is left for next time, since the
+    /// shell may still be writing it.
+    pub fn poll(&mut self) -> Result<Change> {
+        if self.source.format.is_database() {
+            return self.poll_database();
+        }
+        let with_path = |source| ThError::HistoryFile {
+            path: self.source.path.clone(),
+            source,
+        };
+        let mut file = match File::open(&self.source.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Change::Unchanged),
+            Err(err) => return Err(with_path(err)),
+        };
+        let len = file.metadata().map_err(with_path)?.len();
+        if len == self.offset {
+            return Ok(Change::Unchanged);
+        }
+        // What is added to binary history cannot be read on its own.
+        if len < self.offset || !self.source.format.appends_lines() {
+            self.offset = len;
+            return Ok(Change::Rewritten);
+        }
+
+        let mut appended = Vec::new();
+        file.seek(SeekFrom::Start(self.offset))
+            .and_then(|_| file.read_to_end(&mut appended))
+            .map_err(with_path)?;
+        let complete = match appended.iter().rposition(|&b| b == b'\n') {
+            Some(newline) => &appended[..=newline],
+            None => return Ok(Change::Unchanged),
+        };
+        self.offset += complete.len() as u64;
+
+        let mut entries = self.source.format.parse(complete);
+        for entry in &mut entries {
+            entry.shell = self.source.shell;
+        }
+        Ok(Change::Appended(entries))
+    }
+
+    /// Like [`Tail::poll`], for a database: the rows added since the last
+    /// one read. Fewer rows than read means some were deleted, as by
+    /// `history --clear`.
+    fn poll_database(&mut self) -> Result<Change> {
+        if !self.source.path.exists() {
+            return Ok(Change::Unchanged);
+        }
+        let last = nushell::last_id(&self.source.path)?;
+        if last < self.offset {
+            self.offset = last;
+            return Ok(Change::Rewritten);
+        }
+        let (mut entries, last) = nushell::read(&self.source.path, self.offset)?;
+        self.offset = last;
+        if entries.is_empty() {
+            return Ok(Change::Unchanged);
+        }
+        for entry in &mut entries {
+            entry.shell = self.source.shell;
+        }
+        Ok(Change::Appended(entries))
+    }
+}
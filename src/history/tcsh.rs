@@ -0,0 +1,66 @@
+//! Parser for tcsh's `.history`, as `savehist` writes it.
+//!
+//! Each command is preceded by a comment holding its start time:
+//!
+//! ```text
+//! #+1697040000
+//! ls -la
+//! ```
+
+use super::Entry;
+
+/// Parses tcsh history, picking up `#+<epoch>` timestamp lines.
+pub fn parse(bytes: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut timestamp = None;
+    for line in super::lines(bytes) {
+        if let Some(epoch) = parse_timestamp(&line) {
+            timestamp = Some(epoch);
+            continue;
+        }
+        let command = line.trim_end();
+        if !command.is_empty() {
+            entries.push(Entry {
+                timestamp: timestamp.take(),
+                ..Entry::new(command)
+            });
+        }
+    }
+    entries
+}
+
+/// The lines tcsh writes for `command`.
+pub(crate) fn record(command: &str, timestamp: i64) -> String {
+    format!("#+{}\n{}\n", timestamp, command)
+}
+
+/// Reads a `#+1697040000` timestamp comment.
+pub(crate) fn parse_timestamp(line: &str) -> Option<i64> {
+    let digits = line.strip_prefix("#+")?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_each_timestamp_for_the_command_after_it() {
+        let history = "#+1697040000\nls -la\npwd\n#+1697040009\nmake\n";
+        let entries: Vec<(String, Option<i64>)> = parse(history.as_bytes())
+            .into_iter()
+            .map(|entry| (entry.command, entry.timestamp))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("ls -la".to_string(), Some(1697040000)),
+                ("pwd".to_string(), None),
+                ("make".to_string(), Some(1697040009)),
+            ]
+        );
+    }
+}
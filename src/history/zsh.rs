@@ -0,0 +1,195 @@
+//! Parser for `.zsh_history`.
+//!
+//! With `EXTENDED_HISTORY` set, zsh prefixes every entry with its start time
+//! and duration: `: 1697040000:0;git status`. Without it, entries are bare
+//! commands. Either way, an entry spanning several lines is written with a
+//! trailing backslash on every line but the last.
+//!
+//! zsh also "metafies" the history it writes: some bytes, among them parts
+//! of many non-ASCII characters, are written as the byte `0x83` followed by
+//! the original XOR `0x20`. See [`decode`].
+
+use super::Entry;
+use std::borrow::Cow;
+
+/// Marks a metafied byte.
+const META: u8 = 0x83;
+
+/// Parses zsh history, with or without extended metadata.
+pub fn parse(bytes: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut pending: Option<Entry> = None;
+
+    for line in super::decoded_lines(bytes, decode) {
+        let mut entry = match pending.take() {
+            Some(mut entry) => {
+                entry.command.push('\n');
+                entry.command.push_str(&line);
+                entry
+            }
+            None => parse_line(&line),
+        };
+
+        if entry.command.ends_with('\\') {
+            entry.command.pop();
+            pending = Some(entry);
+        } else {
+            push_entry(&mut entries, entry);
+        }
+    }
+    // A file cut off mid-entry still yields what was written so far.
+    if let Some(entry) = pending {
+        push_entry(&mut entries, entry);
+    }
+    entries
+}
+
+fn push_entry(entries: &mut Vec<Entry>, mut entry: Entry) {
+    // As for bash, a leading space is kept for the ignore list.
+    let trimmed = entry.command.trim_end();
+    if trimmed.is_empty() {
+        return;
+    }
+    entry.command.truncate(trimmed.len());
+    entries.push(entry);
+}
+
+/// A line of zsh history that is not UTF-8 as text: unmetafied, which makes
+/// the characters zsh metafied whole again, and with any bytes still
+/// invalid replaced by U+FFFD. Lines that are UTF-8 are taken as they are:
+/// metafying a character always leaves an extra byte that breaks UTF-8.
+pub(crate) fn decode(line: &[u8]) -> Cow<'_, str> {
+    if let Ok(line) = std::str::from_utf8(line) {
+        return Cow::Borrowed(line);
+    }
+    let mut unmetafied = Vec::with_capacity(line.len());
+    let mut bytes = line.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            META => unmetafied.extend(bytes.next().map(|&byte| byte ^ 0x20)),
+            _ => unmetafied.push(byte),
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&unmetafied).into_owned())
+}
+
+/// The entry zsh writes for `command` with `EXTENDED_HISTORY` set, started
+/// at `timestamp` and with no duration recorded: continued lines end in a
+/// backslash, and the bytes zsh reserves for itself are metafied.
+pub(crate) fn record(command: &str, timestamp: i64) -> Vec<u8> {
+    let text = format!(": {}:0;{}\n", timestamp, command.replace('\n', "\\\n"));
+    let mut record = Vec::with_capacity(text.len());
+    for byte in text.bytes() {
+        // Meta itself, and the bytes zsh uses for tokens. Metafying a byte
+        // zsh would not have is harmless, as unmetafying restores it.
+        if byte == 0 || (META..=0xa2).contains(&byte) {
+            record.extend([META, byte ^ 0x20]);
+        } else {
+            record.push(byte);
+        }
+    }
+    record
+}
+
+/// Whether `line` starts with extended-history metadata.
+pub(crate) fn has_metadata(line: &str) -> bool {
+    split_metadata(line).is_some()
+}
+
+/// Splits `: <start>:<elapsed>;<command>` into its parts.
+fn split_metadata(line: &str) -> Option<(i64, u64, &str)> {
+    let (meta, command) = line.strip_prefix(": ")?.split_once(';')?;
+    let (start, elapsed) = meta.split_once(':')?;
+    let start = start.trim().parse().ok()?;
+    let elapsed = elapsed.trim().parse().ok()?;
+    Some((start, elapsed, command))
+}
+
+/// Splits the `: <start>:<elapsed>;` prefix off the first line of an entry.
+fn parse_line(line: &str) -> Entry {
+    match split_metadata(line) {
+        Some((start, elapsed, command)) => Entry {
+            timestamp: Some(start),
+            duration: Some(elapsed),
+            ..Entry::new(command)
+        },
+        None => Entry::new(line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(history: &[u8]) -> Vec<(String, Option<i64>, Option<u64>)> {
+        parse(history)
+            .into_iter()
+            .map(|entry| (entry.command, entry.timestamp, entry.duration))
+            .collect()
+    }
+
+    #[test]
+    fn splits_off_the_start_time_and_duration() {
+        let history = b": 1697040000:12;cargo build --release\n: 1697040100:0;echo a; echo b\n";
+        assert_eq!(
+            entries(history),
+            [
+                (
+                    "cargo build --release".to_string(),
+                    Some(1697040000),
+                    Some(12)
+                ),
+                ("echo a; echo b".to_string(), Some(1697040100), Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn joins_lines_continued_with_a_backslash() {
+        let history = b": 1697040000:3;for f in *; do\\\n  echo $f\\\ndone\n: 1697040009:0;ls\n";
+        assert_eq!(
+            entries(history),
+            [
+                (
+                    "for f in *; do\n  echo $f\ndone".to_string(),
+                    Some(1697040000),
+                    Some(3)
+                ),
+                ("ls".to_string(), Some(1697040009), Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_plain_lines_among_extended_ones() {
+        let history = b"ls -la\n: 1697040000:1;git status\npwd\n";
+        assert_eq!(
+            entries(history),
+            [
+                ("ls -la".to_string(), None, None),
+                ("git status".to_string(), Some(1697040000), Some(1)),
+                ("pwd".to_string(), None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn unmetafies_characters_back_to_utf8() {
+        // "→" is E2 86 92; zsh writes both of its trailing bytes metafied.
+        let history = b": 1697040000:0;echo \xe2\x83\xa6\x83\xb2\n";
+        assert_eq!(parse(history)[0].command, "echo →");
+        assert_eq!(parse(&record("echo →", 1697040000))[0].command, "echo →");
+    }
+
+    #[test]
+    fn replaces_invalid_bytes_rather_than_dropping_them() {
+        let history = b"ls \xff\xfe here\nnext\n";
+        assert_eq!(
+            entries(history),
+            [
+                ("ls \u{fffd}\u{fffd} here".to_string(), None, None),
+                ("next".to_string(), None, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! Terminal history search.
+//!
+//! The binary is a thin wrapper around this crate; other tools can embed the
+//! same history loading, ranking, and interactive picker directly.
+
+pub mod cache;
+pub mod catalog;
+pub mod clipboard;
+pub mod config;
+pub mod daemon;
+pub mod editor;
+pub mod error;
+pub mod exec;
+pub mod history;
+pub mod logfile;
+pub mod output;
+pub mod paths;
+pub mod search;
+pub mod security;
+pub mod shell;
+pub mod snippet;
+pub mod stats;
+pub mod store;
+pub mod sync;
+pub mod time;
+pub mod ui;
+
+pub use error::{Result, ThError};
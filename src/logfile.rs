@@ -0,0 +1,124 @@
+//! The debug log, `~/.cache/th_rs/log`, for finding out why th_rs is slow
+//! or reads a history wrong on someone else's machine.
+//!
+//! The library reports what it does through [`tracing`]: a span for each
+//! history file read and for the picker, with events for how long parsing
+//! and each search took and for what the picker was sent, so a program
+//! embedding it can collect them its own way. The binary writes them to
+//! the file only when run with `--debug` or with `TH_RS_LOG` set, to a
+//! level like `debug` (what `--debug` logs) or `trace` (every event of the
+//! picker too):
+//!
+//! ```sh
+//! TH_RS_LOG=trace th_rs
+//! ```
+//!
+//! What was typed is never logged, at any level, since a query or a command
+//! can hold a password: only how long it was. The file is readable by its
+//! owner alone.
+//!
+//! Every run appends to the file, each line starting with the time and the
+//! process id. A log grown past [`MAX_BYTES`] is moved to `log.old` first.
+
+use crate::error::Result;
+use crate::paths;
+use chrono::Utc;
+use std::fs::{self, OpenOptions};
+use std::path::PathBuf;
+use std::process;
+use std::sync::Mutex;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+
+/// How long the log grows before it is started afresh.
+pub const MAX_BYTES: u64 = 8 * 1024 * 1024;
+
+/// What to log, given `--debug` and the value of `TH_RS_LOG`: nothing
+/// unless either is set. A value that is not the name of a level, like
+/// `1`, means `debug`.
+pub fn level(debug: bool, var: Option<&str>) -> Option<LevelFilter> {
+    let from_var = var.filter(|var| !var.is_empty()).map(|var| {
+        match var.chars().all(|c| c.is_ascii_alphabetic()) {
+            true => var.parse().unwrap_or(LevelFilter::DEBUG),
+            false => LevelFilter::DEBUG,
+        }
+    });
+    match (debug, from_var) {
+        (true, Some(level)) => Some(level.max(LevelFilter::DEBUG)),
+        (true, None) => Some(LevelFilter::DEBUG),
+        (false, level) => level,
+    }
+}
+
+/// Where the log is written.
+pub fn path() -> Result<PathBuf> {
+    Ok(paths::cache_dir()?.join("log"))
+}
+
+/// Starts writing what is logged at `level` or above to the log, returning
+/// where it is.
+pub fn init(level: LevelFilter) -> Result<PathBuf> {
+    let path = path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::metadata(&path).is_ok_and(|meta| meta.len() > MAX_BYTES) {
+        fs::rename(&path, path.with_extension("old"))?;
+    }
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // A log from before th_rs made it private may be readable by all.
+        let _ = fs::set_permissions(&path, fs::Permissions::from_mode(0o600));
+        let _ = fs::set_permissions(
+            path.with_extension("old"),
+            fs::Permissions::from_mode(0o600),
+        );
+    }
+    let file = options.open(&path)?;
+    // Only the first subscriber set takes; the binary sets one at most.
+    let _ = tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_timer(Stamp { pid: process::id() })
+        .with_max_level(level)
+        .try_init();
+    Ok(path)
+}
+
+/// Starts each line with the time and the process id, so that the lines of
+/// runs logging at once can be told apart.
+struct Stamp {
+    pid: u32,
+}
+
+impl FormatTime for Stamp {
+    fn format_time(&self, w: &mut Writer<'_>) -> std::fmt::Result {
+        write!(
+            w,
+            "{} {}",
+            Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+            self.pid
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_only_when_asked() {
+        assert_eq!(level(false, None), None);
+        assert_eq!(level(false, Some("")), None);
+        assert_eq!(level(true, None), Some(LevelFilter::DEBUG));
+        assert_eq!(level(false, Some("TRACE")), Some(LevelFilter::TRACE));
+        assert_eq!(level(false, Some("1")), Some(LevelFilter::DEBUG));
+        assert_eq!(level(true, Some("warn")), Some(LevelFilter::DEBUG));
+        assert_eq!(level(true, Some("trace")), Some(LevelFilter::TRACE));
+    }
+}
@@ -1,186 +1,1983 @@
-use crossterm::{
-    cursor::{Hide, MoveTo, Show},
-    event::{self, Event, KeyCode, KeyEvent},
-    execute,
-    terminal::{
-        disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
-        LeaveAlternateScreen,
-    },
-};
-use std::collections::HashMap;
+use clap::{Parser, Subcommand};
+use std::collections::BTreeMap;
 use std::env;
-use std::fs::File;
-use std::io::{self, stdout, BufRead, BufReader, Write};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use th_rs::config::{self, Config};
+use th_rs::history::ignore::Ignore;
+use th_rs::history::normalize::{self, normalize_entries};
+use th_rs::history::provider::{Provider, ProviderConfig};
+use th_rs::history::tail::{Change, Tail};
+use th_rs::output;
+use th_rs::search::rank::{self, Ranking};
+use th_rs::search::{CaseMode, CommandStats, ExitFilter, Filter, FrequencyMap};
+use th_rs::shell::{Shell, ShellSet};
+use th_rs::stats::Stats;
+use th_rs::store::sqlite::Store;
+use th_rs::ui::inline::Height;
+use th_rs::ui::theme::ColorDepth;
+use th_rs::{
+    cache::{Cache, Snapshot},
+    catalog, clipboard, daemon, exec, history, logfile, search, store, time, ui,
+};
+
+/// Terminal history search: fuzzy-find a command you ran before.
+#[derive(Debug, Parser)]
+#[command(version, about, after_help = EXIT_STATUS)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Draw the picker on stderr and print only the selected command to
+    /// stdout, so it can be captured with `$(th_rs --print)`.
+    #[arg(long)]
+    print: bool,
+
+    /// Run the selected command in your shell instead of printing it, and
+    /// exit with its status. Ctrl-X in the picker does this without the flag.
+    #[arg(long)]
+    exec: bool,
+
+    /// Also copy the selected command to the clipboard, through the
+    /// terminal (OSC 52), so it works over SSH. Ctrl-Y in the picker copies
+    /// without printing.
+    #[arg(long)]
+    copy: bool,
+
+    /// Write the selected command to this file, and nothing else anywhere,
+    /// for wrappers like tmux popups that cannot capture stdout. The file is
+    /// left empty when nothing is selected.
+    #[arg(long, value_name = "FILE", conflicts_with = "out_fd")]
+    out: Option<PathBuf>,
+
+    /// Like --out, but write to this open file descriptor, as in
+    /// `$(th_rs --out-fd 3 3>&1 >/dev/tty)`.
+    #[arg(long, value_name = "N")]
+    out_fd: Option<u32>,
+
+    /// Open the picker with this query already typed. `-` reads it from
+    /// stdin, which is how the Ctrl-R snippets pass the shell's command line.
+    #[arg(long, value_name = "TEXT", allow_hyphen_values = true)]
+    query: Option<String>,
+
+    /// Search the th_rs history store (filled by `th_rs import`) instead of
+    /// parsing the shell's history file.
+    #[arg(long, global = true)]
+    store: bool,
+
+    /// Use the settings of `[profile.NAME]` in the config file over the
+    /// rest, e.g. to keep work history apart. Defaults to $TH_RS_PROFILE.
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+
+    /// Merge the history files of every installed shell instead of only the
+    /// current one. The config file's `sources` picks specific shells.
+    #[arg(long, global = true)]
+    all_shells: bool,
+
+    /// Search this history file instead of the shell's; repeat to merge
+    /// several. The format is detected unless --history-format is given.
+    #[arg(long, value_name = "PATH", global = true)]
+    history_file: Vec<PathBuf>,
+
+    /// Search the history of another machine, fetched over SSH: `host`,
+    /// `user@host`, or `host:path` for a file other than its login shell's.
+    /// Repeat to merge several. The copy is kept for `remote_ttl` seconds.
+    /// With `sync`, the remote to sync with in place of the configured one:
+    /// a file path, an SSH host:path, or an http(s) URL.
+    #[arg(long, value_name = "HOST", global = true)]
+    remote: Vec<String>,
+
+    /// Read every --history-file as this format: bash, zsh, fish,
+    /// powershell, nu, nu-sqlite, ksh, tcsh, or plain (one command per
+    /// line).
+    #[arg(long, value_name = "FORMAT", global = true)]
+    history_format: Option<history::Format>,
+
+    /// Search every command, even those matching the config file's ignore
+    /// patterns or the built-in ones for secrets.
+    #[arg(long, global = true)]
+    no_ignore: bool,
+
+    /// Read every history file from the start, rather than from where the
+    /// cache in ~/.cache/th_rs left off, and leave the cache alone.
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Log what th_rs does and how long it takes, like each history file
+    /// parsed and each search, to ~/.cache/th_rs/log. TH_RS_LOG=trace also
+    /// logs each key the picker gets, though never what was typed.
+    #[arg(long, global = true)]
+    debug: bool,
+
+    /// Only search commands run at or after this time: a date like
+    /// 2024-05-01, today, yesterday, a weekday, or an age like 7d.
+    #[arg(long, value_name = "TIME", global = true)]
+    since: Option<time::Span>,
+
+    /// Only search commands run before the end of this time, written as for
+    /// --since. Commands with no timestamp are left out when either is given.
+    #[arg(long, value_name = "TIME", global = true)]
+    until: Option<time::Span>,
+
+    /// Letter case matching: smart (sensitive only if the query has an
+    /// uppercase letter), sensitive, or insensitive. Defaults to the config
+    /// file's `case`, or smart.
+    #[arg(long, value_name = "MODE", global = true)]
+    case: Option<CaseMode>,
+
+    /// How commands marked with Tab in the picker are combined: newline (one
+    /// per line) or and (chained with &&). Defaults to the config file's
+    /// `join`, or newline.
+    #[arg(long, value_name = "JOIN")]
+    join: Option<ui::Join>,
+
+    /// Only show commands run in the current directory or below it, as
+    /// recorded by `th_rs init --record`. Ctrl-F in the picker toggles this.
+    #[arg(long)]
+    cwd: bool,
+
+    /// Only show commands whose last recorded run failed, to find the one
+    /// to fix. Alt-X in the picker instead hides failed commands.
+    #[arg(long)]
+    only_failed: bool,
+
+    /// Match the query against each word of a command, e.g. a file path or
+    /// a flag, rather than the whole line. Ctrl-A in the picker toggles this.
+    #[arg(long)]
+    args: bool,
+
+    /// Use vim-style modes in the picker: Esc leaves the query for a normal
+    /// mode with j/k, gg/G, and dd, and / goes back. Also set by the config
+    /// file's `vim = true`.
+    #[arg(long)]
+    vim: bool,
+
+    /// Draw the picker below the prompt, in this many rows (20) or this
+    /// share of the terminal (40%), instead of taking over the whole screen.
+    /// Defaults to the config file's `height`.
+    #[arg(long, value_name = "HEIGHT")]
+    height: Option<Height>,
+
+    /// Add the chosen command to the end of the shell's history file, or
+    /// the `--history-file`, so that it comes up first on the arrow keys
+    /// too. Also set by the config file's `append_on_select = true`.
+    #[arg(long)]
+    append_on_select: bool,
+
+    /// Show a sparkline of each command's runs over the last 30 days next
+    /// to its count, to tell habits from history long past. Needs
+    /// timestamps. Also set by the config file's `sparkline = true`.
+    #[arg(long)]
+    sparkline: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print a snippet that binds Ctrl-R to th_rs.
+    ///
+    /// bash/zsh: eval "$(th_rs init bash)"; fish: th_rs init fish | source;
+    /// PowerShell: Invoke-Expression (& th_rs init powershell | Out-String)
+    Init {
+        /// The shell to integrate with: bash, zsh, fish, powershell, or nu.
+        /// The history of ksh, mksh, and tcsh is read, but they have no
+        /// snippet.
+        shell: Shell,
+        /// Also record every command into the th_rs store with where it ran,
+        /// how it exited, and how long it took, and search the store on
+        /// Ctrl-R. Run `th_rs import` once first to keep older history.
+        #[arg(long)]
+        record: bool,
+    },
+    /// Record one run of a command into the th_rs store. The hooks of
+    /// `th_rs init --record` call this after every command.
+    Record {
+        /// The command line as typed.
+        command: String,
+        /// The shell it ran in.
+        #[arg(long)]
+        shell: Option<Shell>,
+        /// Its exit status.
+        #[arg(long, value_name = "STATUS", allow_negative_numbers = true)]
+        exit: Option<i32>,
+        /// How long it ran, in milliseconds.
+        #[arg(long, value_name = "MS")]
+        duration: Option<u64>,
+        /// The directory it ran in; the current one if not given.
+        #[arg(long, value_name = "DIR")]
+        cwd: Option<PathBuf>,
+        /// The machine it ran on; this one if not given.
+        #[arg(long, value_name = "NAME")]
+        hostname: Option<String>,
+        /// Tells apart shells running at the same time; $TH_RS_SESSION, which
+        /// the hooks set, if not given.
+        #[arg(long, value_name = "ID")]
+        session: Option<String>,
+    },
+    /// Import the current shell's history file into the th_rs store, or
+    /// every shell's with --all-shells. Given a file `th_rs export` wrote,
+    /// adds everything in it instead; given `atuin` or an atuin database,
+    /// every run atuin recorded.
+    Import {
+        /// An export to load, e.g. from another machine, or an atuin
+        /// database; `atuin` for the one atuin uses by default.
+        file: Option<PathBuf>,
+        /// The export's format: json or csv. By default, the one its
+        /// extension names, else json.
+        #[arg(long, requires = "file")]
+        format: Option<store::export::Format>,
+    },
+    /// Encrypt the th_rs store in place with the passphrase from
+    /// $TH_RS_PASSPHRASE or the passphrase_command under [store]. Set
+    /// `encrypt = true` there afterwards.
+    Encrypt,
+    /// Merge the th_rs store with other machines' through the remote
+    /// configured under [sync]: pull the shared copy, add it to the store,
+    /// and push back, encrypted, what the copy lacked.
+    Sync,
+    /// Write everything in the th_rs store to stdout, with where and how
+    /// each recorded run went, to back it up or move it to another machine.
+    Export {
+        /// Output format: json or csv.
+        #[arg(long, default_value_t = store::export::Format::Json)]
+        format: store::export::Format,
+    },
+    /// Remove stale or repeated runs from the history file, or the th_rs
+    /// store with --store, backing up the original first.
+    ///
+    /// Commands count as the same once normalized, as in the picker.
+    Prune {
+        /// Remove runs older than this age, like 90d or 1y, or from before
+        /// this date. Runs with no timestamp are kept.
+        #[arg(long, value_name = "AGE")]
+        older_than: Option<time::Span>,
+        /// Remove every run of commands run fewer times than this in all.
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        min_count: usize,
+        /// Keep only the latest run of each command.
+        #[arg(long)]
+        dedupe: bool,
+        /// List what would be removed, and change nothing.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Put back a history file as it was before th_rs last changed it.
+    ///
+    /// Deleting, pruning, and appending back up the file first, into
+    /// ~/.local/share/th_rs/backups. What is there before restoring is backed
+    /// up too, so a restore can be undone by another.
+    Restore {
+        /// The backup to restore, as --list names it; the newest if not
+        /// given.
+        id: Option<String>,
+        /// List the backups, newest first, instead of restoring one.
+        #[arg(long, conflicts_with = "id")]
+        list: bool,
+    },
+    /// Print the commands matching a pattern, best first, without the picker.
+    ///
+    /// Exits with status 1 when nothing matches.
+    Query {
+        /// The fuzzy pattern to match, as typed into the picker.
+        pattern: String,
+        /// At most this many results.
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Only commands run in the current directory or below it.
+        #[arg(long)]
+        cwd: bool,
+        /// Only commands whose last recorded run failed.
+        #[arg(long)]
+        only_failed: bool,
+        /// Match the pattern against each word of a command rather than the
+        /// whole line, listing each command that has a matching word once.
+        #[arg(long)]
+        args: bool,
+        /// Output format: plain (one command per line) or json.
+        #[arg(long, default_value_t = output::Format::Plain)]
+        format: output::Format,
+    },
+    /// Summarize history: the most run commands, the busiest hours and
+    /// weekdays, and how runs split between shells.
+    Stats {
+        /// List this many of the most run commands.
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Output format: plain (a report with bar charts) or json.
+        #[arg(long, default_value_t = output::Format::Plain)]
+        format: output::Format,
+    },
+    /// Print the most frecent commands starting with a prefix, for a prompt
+    /// or completion menu to offer, e.g. `th_rs top "git "`.
+    ///
+    /// The prefix is matched as typed, not fuzzily. Exits with 1 if no
+    /// command starts with it.
+    Top {
+        /// What the commands start with; all commands without it.
+        prefix: Option<String>,
+        /// At most this many commands.
+        #[arg(long, short = 'n', default_value_t = 10)]
+        limit: usize,
+        /// Only commands run in the current directory or below it.
+        #[arg(long)]
+        cwd: bool,
+        /// Output format: plain (one command per line) or json.
+        #[arg(long, default_value_t = output::Format::Plain)]
+        format: output::Format,
+    },
+    /// Print the commands that complete what has been typed so far, best
+    /// first, for autosuggestions, e.g. `th_rs complete --prefix "git ch"`.
+    ///
+    /// Commands have to start with the prefix as typed, and are ranked as
+    /// the picker ranks them before anything is typed. Exits with 1 if no
+    /// command completes it.
+    Complete {
+        /// What has been typed so far.
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// At most this many commands.
+        #[arg(long, short = 'n', default_value_t = 10)]
+        limit: usize,
+        /// Only commands run in the current directory or below it.
+        #[arg(long)]
+        cwd: bool,
+        /// Output format: plain (one command per line) or json.
+        #[arg(long, default_value_t = output::Format::Plain)]
+        format: output::Format,
+    },
+    /// Print the one command that best completes what has been typed, as
+    /// `complete` ranks them, for zsh-autosuggestions; see `th_rs init
+    /// zsh`. Exits with 1 if there is none.
+    Suggest {
+        /// What has been typed so far.
+        #[arg(default_value = "", allow_hyphen_values = true)]
+        prefix: String,
+    },
+    /// Print every known command, most frecent first.
+    List {
+        /// At most this many commands.
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Only commands run in the current directory or below it.
+        #[arg(long)]
+        cwd: bool,
+        /// Only commands whose last recorded run failed.
+        #[arg(long)]
+        only_failed: bool,
+        /// Output format: plain (one command per line) or json.
+        #[arg(long, default_value_t = output::Format::Plain)]
+        format: output::Format,
+    },
+    /// Keep history loaded in the background and answer searches over a
+    /// Unix socket, so that the picker, `query`, and `list` start instantly.
+    ///
+    /// They use the daemon whenever it serves the history files they would
+    /// read, and read the files themselves otherwise. Restart it after
+    /// changing the config file.
+    Daemon,
+    /// Manage bookmarked commands, which rank above all others and are
+    /// kept in the th_rs store. Ctrl-B in the picker bookmarks too.
+    Bookmarks {
+        #[command(subcommand)]
+        command: BookmarksCommand,
+    },
+    /// Manage tags on commands, kept in the th_rs store. A query finds the
+    /// commands tagged `deploy` with `#deploy`; Ctrl-T in the picker tags
+    /// too.
+    Tags {
+        #[command(subcommand)]
+        command: TagsCommand,
+    },
+    /// Manage snippets: commands saved under a name, with `{placeholders}`
+    /// that the picker asks for when one is chosen. They rank with
+    /// bookmarks and are kept in the th_rs store.
+    Snippet {
+        #[command(subcommand)]
+        command: SnippetCommand,
+    },
+    /// Work with catalogs: files of shared commands with descriptions and
+    /// tags, listed under `catalogs` in the config file and searched with
+    /// the history.
+    Catalog {
+        #[command(subcommand)]
+        command: CatalogCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CatalogCommand {
+    /// Check catalog files for mistakes: the config file's `catalogs`, or
+    /// the files given. Exits with status 1 if any has one.
+    Validate { files: Vec<PathBuf> },
+}
+
+#[derive(Debug, Subcommand)]
+enum BookmarksCommand {
+    /// Print every bookmarked command.
+    List,
+    /// Bookmark a command.
+    Add { command: String },
+    /// Remove the bookmark on a command.
+    Remove { command: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum TagsCommand {
+    /// Print every tag with a command it is on, separated by a tab.
+    List,
+    /// Tag a command, e.g. `th_rs tags add deploy "make deploy"`.
+    Add { tag: String, command: String },
+    /// Take a tag off a command.
+    Remove { tag: String, command: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum SnippetCommand {
+    /// Print every snippet, as its name and command separated by a tab.
+    List,
+    /// Save a command under a name, replacing any snippet of that name,
+    /// e.g. `th_rs snippet add deploy "kubectl -n {ns} rollout restart
+    /// deploy/{app}"`.
+    Add { name: String, command: String },
+    /// Remove a snippet.
+    Remove { name: String },
+}
+
+/// What the picker exits with when Enter finds nothing to choose.
+const NO_MATCH: u8 = 1;
+
+/// What the picker exits with on Esc or Ctrl-C, as a shell reports a
+/// command ended by SIGINT.
+const CANCELLED: u8 = 130;
+
+const EXIT_STATUS: &str = "\
+Exit status of the picker, as with fzf:
+  0    a command was chosen, copied, or run (with its own status when run)
+  1    nothing matched the query
+  130  cancelled with Esc or Ctrl-C";
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    start_log(&cli);
+    let started = Instant::now();
+    let code = match run(&cli) {
+        Ok(code) => code,
+        // The reader went away (`th_rs list | head`); that is not a failure.
+        Err(th_rs::ThError::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => {
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            tracing::error!(%err, "failed");
+            eprintln!("th_rs: {}", err);
+            ExitCode::FAILURE
+        }
+    };
+    tracing::debug!(took = ?started.elapsed(), "finished");
+    code
+}
 
-/// Loads shell command history from the appropriate file.
-fn load_history() -> io::Result<Vec<String>> {
-    let home_dir = env::var("HOME").expect("Could not determine HOME directory");
-    let shell = env::var("SHELL").expect("Could not determine SHELL");
-    let history_path = match shell.as_str() {
-        "/bin/bash" | "/usr/bin/bash" => format!("{}/.bash_history", home_dir),
-        "/bin/zsh" | "/usr/bin/zsh" => format!("{}/.zsh_history", home_dir),
-        "/usr/bin/fish" | "/bin/fish" => format!("{}/.local/share/fish/fish_history", home_dir),
-        _ => panic!("Unsupported shell: {}", shell),
+/// Starts the debug log, if --debug or TH_RS_LOG asks for it.
+fn start_log(cli: &Cli) {
+    let var = env::var("TH_RS_LOG").ok();
+    let Some(level) = logfile::level(cli.debug, var.as_deref()) else {
+        return;
+    };
+    match logfile::init(level) {
+        Ok(_) => {
+            // Not the arguments themselves, which can hold a query.
+            tracing::debug!(
+                version = env!("CARGO_PKG_VERSION"),
+                arguments = env::args_os().len().saturating_sub(1),
+                "started"
+            );
+        }
+        Err(err) => eprintln!("th_rs: could not open the debug log: {}", err),
+    }
+}
+
+fn run(cli: &Cli) -> th_rs::Result<ExitCode> {
+    if let Some(Command::Init { shell, record }) = cli.command {
+        print!(
+            "{}",
+            shell
+                .init_script(record)
+                .ok_or(th_rs::ThError::NoInit(shell))?
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let profile = cli.profile.clone().or_else(|| {
+        env::var("TH_RS_PROFILE")
+            .ok()
+            .filter(|name| !name.is_empty())
+    });
+    let mut config = Config::load(profile.as_deref())?;
+
+    if let Some(Command::Record {
+        command,
+        shell,
+        exit,
+        duration,
+        cwd,
+        hostname,
+        session,
+    }) = &cli.command
+    {
+        let mut entries = vec![history::Entry::new(command.as_str())];
+        Prepare {
+            range: time::Range::default(),
+            ..Prepare::new(cli, &config)
+        }
+        .apply(&mut entries);
+        let Some(entry) = entries.pop() else {
+            return Ok(ExitCode::SUCCESS);
+        };
+        let cwd = match cwd {
+            Some(cwd) => cwd.clone(),
+            None => env::current_dir()?,
+        };
+        let elapsed = duration.unwrap_or(0) / 1000;
+        let run = store::Run {
+            command: entry.command,
+            shell: *shell,
+            cwd: cwd.to_string_lossy().into_owned(),
+            exit: *exit,
+            duration: *duration,
+            hostname: hostname.clone().unwrap_or_else(self::hostname),
+            session: session.clone().or_else(self::session).unwrap_or_default(),
+            timestamp: rank::now() - elapsed as i64,
+        };
+        config.store.open()?.record(&run)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let cwd = env::current_dir().ok();
+    config.ranking.repo = cwd.as_deref().and_then(rank::repo_root);
+    config.ranking.project = cwd.as_deref().and_then(history::project::find);
+    config.ranking.cwd = cwd.map(|dir| dir.to_string_lossy().into_owned());
+
+    if let Some(Command::Import {
+        file: Some(file),
+        format,
+    }) = &cli.command
+    {
+        let file = if file.as_os_str() == "atuin" {
+            store::atuin::default_path()?
+        } else {
+            file.clone()
+        };
+        let records = if format.is_none() && store::atuin::is_database(&file) {
+            let prepare = Prepare::new(cli, &config);
+            let mut records = store::atuin::read(&file)?;
+            records.retain_mut(|record| match prepare.command(&record.command) {
+                Some(command) => {
+                    record.command = command;
+                    true
+                }
+                None => false,
+            });
+            records
+        } else {
+            let format = format
+                .or_else(|| store::export::Format::of_path(&file))
+                .unwrap_or_default();
+            store::export::read(&file, format)?
+        };
+        let path = store::default_path()?;
+        let loaded = config.store.open()?.load(&records)?;
+        println!(
+            "Imported {} records from {} into {}",
+            loaded,
+            file.display(),
+            path.display()
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Bookmarks { command }) = &cli.command {
+        let mut store = config.store.open()?;
+        match command {
+            BookmarksCommand::List => {
+                let mut out = io::stdout().lock();
+                for bookmark in store.bookmarks()? {
+                    writeln!(out, "{}", bookmark)?;
+                }
+            }
+            BookmarksCommand::Add { command } => {
+                if !store.bookmark(command)? {
+                    eprintln!("th_rs: already bookmarked: {}", command);
+                }
+            }
+            BookmarksCommand::Remove { command } => {
+                if !store.unbookmark(command)? {
+                    eprintln!("th_rs: not bookmarked: {}", command);
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Tags { command }) = &cli.command {
+        let mut store = config.store.open()?;
+        match command {
+            TagsCommand::List => {
+                let mut out = io::stdout().lock();
+                for (command, tag) in store.tags()? {
+                    writeln!(out, "{}\t{}", tag, command)?;
+                }
+            }
+            TagsCommand::Add { tag, command } => {
+                if !search::query::is_tag(tag) {
+                    eprintln!(
+                        "th_rs: a tag cannot be blank, hold spaces, or start with #: {:?}",
+                        tag
+                    );
+                    return Ok(ExitCode::FAILURE);
+                }
+                if !store.tag(command, tag)? {
+                    eprintln!("th_rs: already tagged {}: {}", tag, command);
+                }
+            }
+            TagsCommand::Remove { tag, command } => {
+                if !store.untag(command, tag)? {
+                    eprintln!("th_rs: not tagged {}: {}", tag, command);
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Snippet { command }) = &cli.command {
+        let mut store = config.store.open()?;
+        match command {
+            SnippetCommand::List => {
+                let mut out = io::stdout().lock();
+                for (name, command) in store.snippets()? {
+                    writeln!(out, "{}\t{}", name, command)?;
+                }
+            }
+            SnippetCommand::Add { name, command } => {
+                if store.add_snippet(name, command)? {
+                    eprintln!("th_rs: replaced snippet: {}", name);
+                }
+            }
+            SnippetCommand::Remove { name } => {
+                if !store.remove_snippet(name)? {
+                    eprintln!("th_rs: no snippet named {}", name);
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Catalog {
+        command: CatalogCommand::Validate { files },
+    }) = &cli.command
+    {
+        let files = if files.is_empty() {
+            &config.catalogs
+        } else {
+            files
+        };
+        if files.is_empty() {
+            eprintln!("th_rs: no catalogs; list them under `catalogs` in the config file");
+            return Ok(ExitCode::FAILURE);
+        }
+        let mut valid = true;
+        for file in files {
+            let problems = match catalog::read(file) {
+                Ok(items) => catalog::problems(&items),
+                Err(th_rs::ThError::Catalog { message, .. }) => vec![message],
+                Err(err) => vec![err.to_string()],
+            };
+            if problems.is_empty() {
+                println!("{}: ok", file.display());
+            }
+            for problem in &problems {
+                println!("{}: {}", file.display(), problem);
+            }
+            valid &= problems.is_empty();
+        }
+        return Ok(if valid {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    if let Some(Command::Encrypt) = cli.command {
+        if config.store.encrypt {
+            return Err(th_rs::ThError::Encryption(
+                "the store is already encrypted ([store] encrypt = true)".to_string(),
+            ));
+        }
+        let path = store::default_path()?;
+        Store::encrypt(&path, &config.store.passphrase()?)?;
+        println!(
+            "Encrypted {}; set `encrypt = true` under [store] in the config file to use it",
+            path.display()
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Sync) = cli.command {
+        // `--remote` is the global one, read here as a sync remote.
+        let remote = match cli.remote.as_slice() {
+            [] => None,
+            [remote] => Some(remote.parse().map_err(th_rs::ThError::Sync)?),
+            _ => return Err(th_rs::ThError::Sync("sync takes one --remote".to_string())),
+        };
+        let Some(remote) = remote.as_ref().or(config.sync.remote.as_ref()) else {
+            return Err(th_rs::ThError::Sync(
+                "no remote; set one under [sync] in the config file or pass --remote".to_string(),
+            ));
+        };
+        let key_path = config.sync.key_path()?;
+        let synced = th_rs::sync::sync(&mut config.store.open()?, remote, &key_path)?;
+        if synced.created_key {
+            println!(
+                "Created the sync key {}; copy it to the other machines before they sync",
+                key_path.display()
+            );
+        }
+        println!(
+            "Synced with {}: {} records in, {} out",
+            remote, synced.pulled, synced.pushed
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Export { format }) = cli.command {
+        let records = config.store.open()?.export()?;
+        store::export::write(io::stdout().lock(), &records, format)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Restore { id, list }) = &cli.command {
+        let backups = config.backup.backups()?;
+        if *list {
+            let mut out = io::stdout().lock();
+            for backup in backups.list()? {
+                writeln!(out, "{}\t{}", backup.id, backup.origin.display())?;
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+        let backup = match id {
+            Some(id) => backups.find(id)?,
+            None => backups
+                .list()?
+                .into_iter()
+                .next()
+                .ok_or_else(|| th_rs::ThError::Backup("there are no backups".to_string()))?,
+        };
+        let current = backups.restore(&backup)?;
+        println!("Restored {} from {}", backup.origin.display(), backup.id);
+        if let Some(current) = current {
+            println!(
+                "What was there is backed up as {}; `th_rs restore {}` puts it back",
+                current.id, current.id
+            );
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Prune {
+        older_than,
+        min_count,
+        dedupe,
+        dry_run,
+    }) = &cli.command
+    {
+        let rules = history::prune::Rules {
+            before: older_than.map(|span| span.start),
+            min_count: *min_count,
+            dedupe: *dedupe,
+        };
+        let backups = config.backup.backups()?;
+        if cli.store {
+            let removed = config.store.open()?.prune(&rules, *dry_run, &backups)?;
+            report_pruned(&removed, &store::default_path()?, *dry_run)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        for source in sources(cli, &config)? {
+            match source.prune(&rules, &config.normalize, *dry_run, &backups) {
+                Ok(removed) => report_pruned(&removed, &source.path, *dry_run)?,
+                Err(th_rs::ThError::HistoryFile { source: err, .. })
+                    if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Import { file: None, .. }) = cli.command {
+        let path = store::default_path()?;
+        let mut store = config.store.open()?;
+        for source in sources(cli, &config)? {
+            let mut entries = match source.load() {
+                Ok(entries) => entries,
+                Err(err) => {
+                    eprintln!("th_rs: skipping {}: {}", source.path.display(), err);
+                    continue;
+                }
+            };
+            // Everything is imported whenever it ran, but secrets stay out.
+            Prepare {
+                range: time::Range::default(),
+                ..Prepare::new(cli, &config)
+            }
+            .apply(&mut entries);
+            let imported = store.import(source.shell, &entries)?;
+            println!(
+                "Imported {} commands from {} into {}",
+                imported,
+                source.path.display(),
+                path.display()
+            );
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    let case = cli.case.unwrap_or(config.case);
+
+    if let Some(Command::Daemon) = cli.command {
+        return run_daemon(cli, &config, case);
+    }
+
+    // Scripts need to know when history is unavailable, so unlike the picker
+    // the non-interactive commands fail instead of searching nothing.
+    match &cli.command {
+        Some(Command::Query {
+            pattern,
+            limit,
+            cwd,
+            only_failed,
+            args,
+            format,
+        }) => {
+            let filter = Filter {
+                arguments: *args,
+                ..filter(*cwd, *only_failed)?
+            };
+            let found = write_search(cli, &config, pattern, Some(*limit), case, &filter, *format)?;
+            return Ok(if found {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
+        }
+        Some(Command::Stats { top, format }) => {
+            // Loading already left out runs outside --since and --until.
+            let stats = if cli.store {
+                let mut entries = config.store.open()?.entries()?;
+                Prepare::new(cli, &config).apply(&mut entries);
+                Stats::from_entries(&entries, *top)
+            } else {
+                Stats::from_entries(&load_entries(cli, &config)?, *top)
+            };
+            output::write_stats(&mut io::stdout().lock(), &stats, *format)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::List {
+            limit,
+            cwd,
+            only_failed,
+            format,
+        }) => {
+            let filter = filter(*cwd, *only_failed)?;
+            write_search(cli, &config, "", *limit, case, &filter, *format)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Top {
+            prefix,
+            limit,
+            cwd,
+            format,
+        }) => {
+            let frequency = load_frequency(cli, &config)?;
+            let prefix = prefix.as_deref().unwrap_or_default();
+            let top = search::top(&frequency, prefix, &filter(*cwd, false)?, *limit);
+            output::write_matches(&mut io::stdout().lock(), &top, *format)?;
+            return Ok(if top.is_empty() {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            });
+        }
+        Some(Command::Complete {
+            prefix,
+            limit,
+            cwd,
+            format,
+        }) => {
+            let found = write_completions(cli, &config, prefix, *cwd, *limit, *format)?;
+            return Ok(if found {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
+        }
+        Some(Command::Suggest { prefix }) => {
+            let found = write_suggestion(cli, &config, prefix)?;
+            return Ok(if found {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
+        }
+        _ => {}
+    }
+
+    // Opened first, so that a bad path fails before the picker opens.
+    let mut out = open_out(cli)?;
+    // A missing or unreadable history is not fatal: the picker opens empty
+    // and says why. History files are read in the background, so that the
+    // picker appears at once even for a large history.
+    let mut options = ui::Options {
+        case,
+        ranking: config.ranking.clone(),
+        normalize: config.normalize,
+        join: cli.join.unwrap_or(config.join),
+        theme: config.theme.theme(ColorDepth::detect()),
+        keys: config.keys.keymap(),
+        vim: cli.vim || config.vim,
+        mouse: config.mouse,
+        height: cli.height.or(config.height),
+        sparkline: cli.sparkline || config.sparkline,
+        explain: config.explain.clone(),
+        exec: cli.exec,
+        danger: config.danger.danger(),
+        show_secrets: config.show_secrets,
+        query: initial_query(cli)?,
+        cwd: env::current_dir().ok(),
+        here: cli.cwd,
+        exit: exit_filter(cli.only_failed),
+        arguments: cli.args,
+        ..Default::default()
+    };
+    let mut frequency = FrequencyMap::default();
+    let mut loading = None;
+    // The store only changes on import, so only history files are followed,
+    // either here or by a daemon holding them, along with its generation.
+    let mut tails = Vec::new();
+    let mut remote: Option<(daemon::Client, u64)> = None;
+    let no_history = |err: th_rs::ThError| Some(format!("No history loaded: {}", err));
+    if cli.store {
+        match load_frequency(cli, &config) {
+            Ok(loaded) => frequency = loaded,
+            Err(err) => options.notice = no_history(err),
+        }
+        options.shells = frequency
+            .values()
+            .fold(ShellSet::default(), |all, stats| all.union(stats.shells));
+    } else {
+        match sources(cli, &config) {
+            Ok(sources) => {
+                options.shells = sources.iter().filter_map(|s| s.shell).collect();
+                let fetched = connect_daemon(cli, &config, &sources).and_then(|mut client| {
+                    let (generation, loaded) = client.frequency().ok()?;
+                    Some((client, generation, loaded))
+                });
+                match fetched {
+                    Some((client, generation, loaded)) => {
+                        frequency = loaded;
+                        remote = Some((client, generation));
+                    }
+                    None => {
+                        tails = sources.iter().cloned().map(Tail::new).collect();
+                        let cache = frequency_cache(cli, &config, &sources);
+                        let prepare = Prepare::new(cli, &config);
+                        let cached = cache.as_ref().and_then(|cache| {
+                            cache.load(&config.ranking, |entries| prepare.apply(entries))
+                        });
+                        match (cached, providers(cli, &config)) {
+                            (_, Err(err)) => options.notice = no_history(err),
+                            (Some(cached), Ok(providers)) => {
+                                frequency = cached;
+                                if !providers.is_empty() {
+                                    let ranking = config.ranking.clone();
+                                    let loader =
+                                        spawn_loader(Vec::new(), providers, prepare, ranking, None);
+                                    loading = Some(loader);
+                                }
+                            }
+                            (None, Ok(providers)) => {
+                                let ranking = config.ranking.clone();
+                                loading =
+                                    Some(spawn_loader(sources, providers, prepare, ranking, cache))
+                            }
+                        }
+                    }
+                }
+            }
+            Err(err) => options.notice = no_history(err),
+        }
+    }
+
+    match bookmarks(&config) {
+        Ok(bookmarks) => search::add_bookmarks(&mut frequency, &bookmarks),
+        Err(err) => {
+            let notice = format!("No bookmarks loaded: {}", err);
+            options.notice.get_or_insert(notice);
+        }
+    }
+    match snippets(&config) {
+        Ok(snippets) => search::add_snippets(&mut frequency, &snippets),
+        Err(err) => {
+            let notice = format!("No snippets loaded: {}", err);
+            options.notice.get_or_insert(notice);
+        }
+    }
+    match catalog::read_all(&config.catalogs) {
+        Ok(catalog) => search::add_catalog(&mut frequency, &catalog),
+        Err(err) => {
+            let notice = format!("No catalog loaded: {}", err);
+            options.notice.get_or_insert(notice);
+        }
+    }
+    match tags(&config) {
+        Ok(tags) => search::add_tags(&mut frequency, &tags),
+        Err(err) => {
+            let notice = format!("No tags loaded: {}", err);
+            options.notice.get_or_insert(notice);
+        }
+    }
+    search::fold(&mut frequency, &config.normalize);
+    match picks(&config) {
+        Ok(picks) => options.ranking.picks = picks,
+        Err(err) => {
+            let notice = format!("No past choices loaded: {}", err);
+            options.notice.get_or_insert(notice);
+        }
+    }
+    let mut delete = |command: &str| delete_command(cli, &config, command);
+    let mut bookmark = |command: &str, on: bool| {
+        let mut store = config.store.open()?;
+        if on {
+            store.bookmark(command)?;
+        } else {
+            store.unbookmark(command)?;
+        }
+        Ok(())
+    };
+    let mut tag = |command: &str, tag: &str, on: bool| {
+        let mut store = config.store.open()?;
+        if on {
+            store.tag(command, tag)?;
+        } else {
+            store.untag(command, tag)?;
+        }
+        Ok(())
+    };
+    let mut pick = |query: &str, command: &str| config.store.open()?.record_pick(query, command);
+    let mut refresh = || match &mut remote {
+        Some((client, generation)) => {
+            let update = refresh_remote(client, generation);
+            // A daemon that stopped answering is not asked again.
+            if update.is_err() {
+                remote = None;
+            }
+            update
+        }
+        None => refresh_history(cli, &config, &mut tails),
+    };
+    let hooks = ui::Hooks {
+        loading,
+        delete: Some(&mut delete),
+        bookmark: Some(&mut bookmark),
+        tag: Some(&mut tag),
+        pick: Some(&mut pick),
+        refresh: Some(&mut refresh),
     };
+    let outcome = if cli.print {
+        // stderr is unbuffered; batch each frame into a single write.
+        let mut tty = io::BufWriter::new(io::stderr());
+        ui::run(&mut tty, &mut frequency, &options, hooks)?
+    } else {
+        ui::run(&mut io::stdout(), &mut frequency, &options, hooks)?
+    };
+    // The clipboard request goes to the terminal the picker drew on.
+    let copied = if cli.print {
+        copy_selection(&mut io::stderr(), &outcome, cli.copy)?
+    } else {
+        copy_selection(&mut io::stdout(), &outcome, cli.copy)?
+    };
+    if copied && !cli.print && out.is_none() {
+        println!("Copied to the clipboard.");
+    }
 
-    let file = File::open(&history_path)
-        .unwrap_or_else(|_| panic!("Failed to open history file at {}", history_path));
-    let reader = BufReader::new(file);
+    if cli.append_on_select || config.append_on_select {
+        if let ui::Outcome::Selected(command) | ui::Outcome::Execute(command) = &outcome {
+            if let Err(err) = append_to_history(cli, &config, command) {
+                eprintln!("th_rs: could not add the command to history: {}", err);
+            }
+        }
+    }
 
-    let mut commands = Vec::new();
-    for line in reader.lines() {
-        if let Ok(command) = line {
-            let trimmed = command.trim();
-            if !trimmed.is_empty() {
-                commands.push(trimmed.to_string());
+    // The picker has restored the terminal by now, so a command run from here
+    // gets a normal, cooked-mode terminal.
+    if let Some(out) = &mut out {
+        return match outcome {
+            ui::Outcome::Selected(command) if cli.exec => execute(&command, cli.print),
+            ui::Outcome::Execute(command) => execute(&command, cli.print),
+            ui::Outcome::Selected(command) => {
+                writeln!(out, "{}", command)?;
+                out.flush()?;
+                Ok(ExitCode::SUCCESS)
             }
+            ui::Outcome::Copy(_) => Ok(ExitCode::SUCCESS),
+            ui::Outcome::NoMatch => Ok(ExitCode::from(NO_MATCH)),
+            ui::Outcome::Cancelled | ui::Outcome::Interrupted => Ok(ExitCode::from(CANCELLED)),
+        };
+    }
+    match outcome {
+        ui::Outcome::Selected(command) if cli.exec => execute(&command, cli.print),
+        ui::Outcome::Execute(command) => execute(&command, cli.print),
+        ui::Outcome::Selected(command) if cli.print => {
+            println!("{}", command);
+            Ok(ExitCode::SUCCESS)
+        }
+        ui::Outcome::Selected(command) => {
+            println!("Selected command:\n{}", command);
+            Ok(ExitCode::SUCCESS)
         }
+        ui::Outcome::Copy(_) => Ok(ExitCode::SUCCESS),
+        // Only a chosen command goes to stdout, for scripts reading it; the
+        // status says what happened otherwise.
+        ui::Outcome::NoMatch => {
+            if !cli.print {
+                eprintln!("No matching commands found.");
+            }
+            Ok(ExitCode::from(NO_MATCH))
+        }
+        ui::Outcome::Cancelled => {
+            if !cli.print {
+                eprintln!("Exited.");
+            }
+            Ok(ExitCode::from(CANCELLED))
+        }
+        ui::Outcome::Interrupted => Ok(ExitCode::from(CANCELLED)),
     }
-    Ok(commands)
 }
 
-/// Builds a frequency map for the list of commands.
-fn build_frequency_map(commands: &[String]) -> HashMap<String, usize> {
-    let mut freq = HashMap::new();
-    for cmd in commands {
-        *freq.entry(cmd.clone()).or_insert(0) += 1;
+/// The history files to read.
+///
+/// `--history-file` and `--remote` name them outright. Otherwise they are
+/// every installed shell's with `--all-shells` or those in the config's
+/// `sources`, plus the config's `history_files`; with none of those, the
+/// current shell's.
+fn sources(cli: &Cli, config: &Config) -> th_rs::Result<Vec<history::Source>> {
+    if !cli.history_file.is_empty() || !cli.remote.is_empty() {
+        let ttl = config
+            .remote_ttl
+            .map_or(history::remote::DEFAULT_TTL, Duration::from_secs);
+        let mut sources: Vec<_> = cli
+            .history_file
+            .iter()
+            .map(|path| history::Source::from_path(path.clone(), cli.history_format))
+            .collect();
+        for target in &cli.remote {
+            sources.push(history::remote::fetch(target, ttl)?);
+        }
+        return Ok(sources);
     }
-    freq
+
+    let mut sources = if cli.all_shells {
+        history::detect_all_sources(config.histfile_from_rc)?
+    } else {
+        history::sources_for(&config.sources, config.histfile_from_rc)?
+    };
+    sources.extend(
+        config
+            .history_files
+            .iter()
+            .map(|path| history::Source::from_path(config::expand_home(path), None)),
+    );
+    if sources.is_empty() {
+        sources.push(history::detect_source(config.histfile_from_rc)?);
+    }
+    Ok(sources)
 }
 
-/// Truncates a given string to fit within the specified width.
-fn truncate_to_width(s: &str, width: u16) -> String {
-    s.chars().take(width as usize).collect()
+/// The config's `[[providers]]`, and the current project's
+/// `.th_rs_history`, searched along with the history files [`sources`] finds
+/// for itself, but not with those named outright.
+fn providers(cli: &Cli, config: &Config) -> th_rs::Result<Vec<Box<dyn Provider>>> {
+    if cli.store || !cli.history_file.is_empty() || !cli.remote.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut providers: Vec<Box<dyn Provider>> = config
+        .providers
+        .iter()
+        .map(ProviderConfig::build)
+        .collect::<th_rs::Result<_>>()?;
+    if let Some(root) = &config.ranking.project {
+        providers.push(Box::new(history::project::Project::new(root.clone())));
+    }
+    Ok(providers)
 }
 
-/// Runs the interactive command search UI.
-fn run_ui() -> io::Result<()> {
-    // Load history and compute command frequencies.
-    let commands = load_history().expect("Failed to load history");
-    let frequency = build_frequency_map(&commands);
+/// Every command in `providers`, through `prepare`.
+fn provided(
+    providers: &[Box<dyn Provider>],
+    prepare: &Prepare,
+) -> th_rs::Result<Vec<history::Entry>> {
+    let mut entries = Vec::new();
+    for provider in providers {
+        entries.extend(provider.load()?);
+    }
+    prepare.apply(&mut entries);
+    Ok(entries)
+}
 
-    let mut query = String::new();
-    let mut selected_index: usize = 0;
+/// Reads command frequencies from the store or the shells' history files.
+fn load_frequency(cli: &Cli, config: &Config) -> th_rs::Result<FrequencyMap> {
+    if cli.store {
+        let mut frequency = config
+            .store
+            .open()?
+            .frequency_map(&config.ranking, session().as_deref())?;
+        // Commands imported before they were ignored are still in the store.
+        let prepare = Prepare::new(cli, config);
+        frequency.retain(|command, stats| {
+            stats.ran_in(prepare.range) && !prepare.ignore.is_ignored(command)
+        });
+        search::fold(&mut frequency, &config.normalize);
+        return Ok(frequency);
+    }
+    load_frequency_map(cli, config, &config.ranking)
+}
 
-    // Set up terminal: enable raw mode, enter alternate screen, and hide cursor.
-    enable_raw_mode()?;
-    let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen, Hide)?;
+/// Writes the commands matching `pattern` to stdout in `format`, best first,
+/// asking the daemon when one serves this history. Returns whether there
+/// were any.
+fn write_search(
+    cli: &Cli,
+    config: &Config,
+    pattern: &str,
+    limit: Option<usize>,
+    case: CaseMode,
+    filter: &Filter,
+    format: output::Format,
+) -> th_rs::Result<bool> {
+    let limit = limit.unwrap_or(usize::MAX);
+    let extras = Extras::read(config)?;
+    let remote = daemon_for(cli, config, filter, &extras).and_then(|mut client| {
+        client
+            .query(pattern, Some(limit), case, config.ranking.cwd.as_deref())
+            .ok()
+    });
+    let local;
+    let matches: Vec<(&str, &CommandStats)> = match &remote {
+        Some(matches) => borrowed(matches),
+        None => {
+            local = extras.load(cli, config)?;
+            let mut matches = search::search(&local, pattern, case, &config.ranking, filter);
+            matches.truncate(limit);
+            matches
+        }
+    };
+    output::write_matches(&mut io::stdout().lock(), &matches, format)?;
+    Ok(!matches.is_empty())
+}
 
-    loop {
-        // Get terminal size.
-        let (term_width, _) = crossterm::terminal::size()?;
+/// Writes the commands completing `prefix` to stdout in `format`, best
+/// first, from the same commands as [`write_search`] searches. Returns
+/// whether there were any.
+fn write_completions(
+    cli: &Cli,
+    config: &Config,
+    prefix: &str,
+    here: bool,
+    limit: usize,
+    format: output::Format,
+) -> th_rs::Result<bool> {
+    let filter = filter(here, false)?;
+    let extras = Extras::read(config)?;
+    let remote = daemon_for(cli, config, &filter, &extras).and_then(|mut client| {
+        client
+            .complete(prefix, limit, config.ranking.cwd.as_deref())
+            .ok()
+    });
+    let local;
+    let completions: Vec<(&str, &CommandStats)> = match &remote {
+        Some(completions) => borrowed(completions),
+        None => {
+            local = extras.load(cli, config)?;
+            search::complete(&local, prefix, &config.ranking, &filter, limit)
+        }
+    };
+    output::write_matches(&mut io::stdout().lock(), &completions, format)?;
+    Ok(!completions.is_empty())
+}
 
-        // Filter suggestions matching the query (case-insensitive) and sort them.
-        let mut suggestions: Vec<(String, usize)> = frequency
-            .iter()
-            .filter(|(cmd, _)| cmd.to_lowercase().contains(&query.to_lowercase()))
-            .map(|(cmd, &count)| (cmd.clone(), count))
-            .collect();
+/// Writes the command completing `prefix` best, for zsh-autosuggestions,
+/// which asks on every key. A daemon serving the history is asked even
+/// when there are bookmarks or snippets it knows nothing of, so that no key
+/// waits on the history being read; without one, this is
+/// [`write_completions`] from the cache. Returns whether there was one.
+fn write_suggestion(cli: &Cli, config: &Config, prefix: &str) -> th_rs::Result<bool> {
+    let remote = sources(cli, config)
+        .ok()
+        .and_then(|sources| connect_daemon(cli, config, &sources))
+        .and_then(|mut client| {
+            client
+                .complete(prefix, 1, config.ranking.cwd.as_deref())
+                .ok()
+        });
+    let Some(suggestion) = remote else {
+        return write_completions(cli, config, prefix, false, 1, output::Format::Plain);
+    };
+    let suggestion = borrowed(&suggestion);
+    output::write_matches(&mut io::stdout().lock(), &suggestion, output::Format::Plain)?;
+    Ok(!suggestion.is_empty())
+}
+
+/// What the daemon answered, as a search of a map in hand would return it.
+fn borrowed(matches: &[(String, CommandStats)]) -> Vec<(&str, &CommandStats)> {
+    matches
+        .iter()
+        .map(|(cmd, stats)| (cmd.as_str(), stats))
+        .collect()
+}
+
+/// What is searched besides the history: the bookmarks, snippets, and tags
+/// of the store, and the catalogs' commands.
+struct Extras {
+    bookmarks: Vec<String>,
+    snippets: Vec<(String, String)>,
+    catalog: Vec<catalog::Item>,
+    tags: Vec<(String, String)>,
+}
+
+impl Extras {
+    fn read(config: &Config) -> th_rs::Result<Extras> {
+        Ok(Extras {
+            bookmarks: bookmarks(config)?,
+            snippets: snippets(config)?,
+            catalog: catalog::read_all(&config.catalogs)?,
+            tags: tags(config)?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bookmarks.is_empty()
+            && self.snippets.is_empty()
+            && self.catalog.is_empty()
+            && self.tags.is_empty()
+    }
 
-        suggestions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    /// The history, with these added.
+    fn load(&self, cli: &Cli, config: &Config) -> th_rs::Result<FrequencyMap> {
+        let mut frequency = load_frequency(cli, config)?;
+        search::add_bookmarks(&mut frequency, &self.bookmarks);
+        search::add_snippets(&mut frequency, &self.snippets);
+        search::add_catalog(&mut frequency, &self.catalog);
+        search::add_tags(&mut frequency, &self.tags);
+        search::fold(&mut frequency, &config.normalize);
+        Ok(frequency)
+    }
+}
+
+/// A connection to the daemon for a search narrowed by `filter`, if one
+/// serves the history and holds all that is searched. History files
+/// record no directories, so neither does the daemon, and it knows nothing
+/// of the `extras`, or of searching by argument.
+fn daemon_for(
+    cli: &Cli,
+    config: &Config,
+    filter: &Filter,
+    extras: &Extras,
+) -> Option<daemon::Client> {
+    if *filter != Filter::default() || !extras.is_empty() {
+        return None;
+    }
+    connect_daemon(cli, config, &sources(cli, config).ok()?)
+}
+
+/// The bookmarked commands; none if there is no store yet, rather than
+/// creating one.
+fn bookmarks(config: &Config) -> th_rs::Result<Vec<String>> {
+    if !store::default_path()?.exists() {
+        return Ok(Vec::new());
+    }
+    config.store.open()?.bookmarks()
+}
+
+/// The tagged commands, each with one of its tags; none if there is no
+/// store yet.
+fn tags(config: &Config) -> th_rs::Result<Vec<(String, String)>> {
+    if !store::default_path()?.exists() {
+        return Ok(Vec::new());
+    }
+    config.store.open()?.tags()
+}
+
+/// The commands chosen in the picker before, by query; none if there is
+/// no store yet.
+fn picks(config: &Config) -> th_rs::Result<rank::Picks> {
+    if !store::default_path()?.exists() {
+        return Ok(rank::Picks::default());
+    }
+    config.store.open()?.picks()
+}
+
+/// The snippets, as names and commands; none if there is no store yet.
+fn snippets(config: &Config) -> th_rs::Result<Vec<(String, String)>> {
+    if !store::default_path()?.exists() {
+        return Ok(Vec::new());
+    }
+    config.store.open()?.snippets()
+}
+
+/// What `--cwd` and `--only-failed` leave to search.
+fn filter(here: bool, only_failed: bool) -> th_rs::Result<Filter> {
+    Ok(Filter {
+        dir: if here {
+            Some(env::current_dir()?)
+        } else {
+            None
+        },
+        exit: exit_filter(only_failed),
+        session: false,
+        arguments: false,
+    })
+}
+
+fn exit_filter(only_failed: bool) -> ExitFilter {
+    if only_failed {
+        ExitFilter::Failed
+    } else {
+        ExitFilter::Any
+    }
+}
+
+/// A connection to the daemon, if one is running and serves the history read
+/// from `sources`. History narrowed by the command line, or read from the
+/// store, is never asked of the daemon, which holds all of the files, and
+/// neither is that of a project with a `.th_rs_history` of its own.
+fn connect_daemon(
+    cli: &Cli,
+    config: &Config,
+    sources: &[history::Source],
+) -> Option<daemon::Client> {
+    if cli.store
+        || cli.no_ignore
+        || cli.since.is_some()
+        || cli.until.is_some()
+        || config.ranking.project.is_some()
+    {
+        return None;
+    }
+    let mut client = daemon::Client::connect(&daemon::socket_path().ok()?).ok()?;
+    let served = client.hello().ok()?.sources;
+    served
+        .iter()
+        .eq(sources.iter().map(|source| &source.path))
+        .then_some(client)
+}
+
+/// How often the daemon checks its history files for new commands.
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the daemon reads its history afresh. Frecency is weighed when
+/// a run is counted, so without this, old runs would never decay.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Serves the history files on the daemon socket until the daemon is
+/// stopped.
+#[cfg(unix)]
+fn run_daemon(cli: &Cli, config: &Config, case: CaseMode) -> th_rs::Result<ExitCode> {
+    // Clients ask from anywhere, and say where with each search; where the
+    // daemon was started boosts nothing, and its project is not served.
+    let config = &Config {
+        ranking: Ranking {
+            repo: None,
+            cwd: None,
+            project: None,
+            ..config.ranking.clone()
+        },
+        ..config.clone()
+    };
+    let paths = sources(cli, config)?
+        .into_iter()
+        .map(|source| source.path)
+        .collect();
+    let frequency = load_frequency_map(cli, config, &config.ranking)?;
+    let path = daemon::socket_path()?;
+    let listener = daemon::bind(&path)?;
+    eprintln!(
+        "th_rs: serving {} commands on {}",
+        frequency.len(),
+        path.display()
+    );
+    let history = daemon::History::new(frequency);
+    let server = Arc::new(daemon::Server::new(
+        paths,
+        case,
+        config.ranking.clone(),
+        history,
+    ));
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        scope.spawn(|| follow(cli, config, &server, &stop));
+        let served = Arc::clone(&server).serve(listener);
+        stop.store(true, Ordering::Relaxed);
+        served
+    })?;
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(not(unix))]
+fn run_daemon(_: &Cli, _: &Config, _: CaseMode) -> th_rs::Result<ExitCode> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the daemon needs Unix domain sockets, which this platform lacks",
+    )
+    .into())
+}
 
-        // Limit the suggestions to a maximum.
-        let max_suggestions = 10;
-        if suggestions.len() > max_suggestions {
-            suggestions.truncate(max_suggestions);
+/// Keeps the daemon's history up to date with its files until `stop`.
+#[cfg(unix)]
+fn follow(cli: &Cli, config: &Config, server: &daemon::Server, stop: &AtomicBool) {
+    let prepare = Prepare::new(cli, config);
+    let mut tails = tails(cli, config);
+    let mut loaded_at = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(FOLLOW_INTERVAL);
+        let mut reload = loaded_at.elapsed() >= RELOAD_INTERVAL;
+        let mut appended = Vec::new();
+        for tail in &mut tails {
+            match tail.poll() {
+                Ok(Change::Unchanged) => {}
+                Ok(Change::Appended(mut entries)) => {
+                    prepare.apply(&mut entries);
+                    appended.extend(entries);
+                }
+                Ok(Change::Rewritten) => reload = true,
+                Err(err) => eprintln!("th_rs: {}", err),
+            }
+        }
+        let mut history = server
+            .history()
+            .write()
+            .unwrap_or_else(|err| err.into_inner());
+        if reload {
+            match load_frequency_map(cli, config, server.ranking()) {
+                Ok(frequency) => {
+                    history.replace(frequency);
+                    tails = self::tails(cli, config);
+                    loaded_at = Instant::now();
+                }
+                Err(err) => eprintln!("th_rs: could not reload history: {}", err),
+            }
+        } else if !appended.is_empty() {
+            history.add(appended, server.ranking(), &config.normalize);
         }
+    }
+}
+
+/// Fetches the daemon's history again if it changed since `generation`.
+fn refresh_remote(
+    client: &mut daemon::Client,
+    generation: &mut u64,
+) -> th_rs::Result<Option<ui::Update>> {
+    if client.hello()?.generation == *generation {
+        return Ok(None);
+    }
+    let (latest, frequency) = client.frequency()?;
+    *generation = latest;
+    Ok(Some(ui::Update::Reloaded(frequency)))
+}
+
+/// Reads the shells' history files, ready for searching.
+fn load_entries(cli: &Cli, config: &Config) -> th_rs::Result<Vec<history::Entry>> {
+    let mut entries = match sources(cli, config)?.as_slice() {
+        // A lone source must exist; merged ones skip uninstalled shells.
+        [source] => source.load()?,
+        sources => history::load_all(sources)?,
+    };
+    let prepare = Prepare::new(cli, config);
+    prepare.apply(&mut entries);
+    entries.extend(provided(&providers(cli, config)?, &prepare)?);
+    Ok(entries)
+}
 
-        // Adjust selected index if necessary.
-        if selected_index >= suggestions.len() {
-            selected_index = suggestions.len().saturating_sub(1);
+/// Reads the shells' history files straight into a frequency map, ranked
+/// per `ranking`. Unlike [`load_entries`] this goes a chunk at a time, so
+/// only the distinct commands are ever held, each once. What providers
+/// hold is counted in after, and never cached.
+fn load_frequency_map(
+    cli: &Cli,
+    config: &Config,
+    ranking: &Ranking,
+) -> th_rs::Result<FrequencyMap> {
+    let prepare = Prepare::new(cli, config);
+    let sources = sources(cli, config)?;
+    let providers = providers(cli, config)?;
+    let cache = frequency_cache(cli, config, &sources);
+    let started = Instant::now();
+    let cached = cache
+        .as_ref()
+        .and_then(|cache| cache.load(ranking, |entries| prepare.apply(entries)));
+    let mut frequency = match cached {
+        Some(frequency) => {
+            tracing::debug!(
+                commands = frequency.len(),
+                took = ?started.elapsed(),
+                "read the cache"
+            );
+            frequency
         }
+        None => {
+            if cache.is_some() {
+                tracing::debug!("no cache to use; reading the history files");
+            }
+            let mut frequency = FrequencyMap::new();
+            let add = |mut entries| {
+                prepare.apply(&mut entries);
+                search::add_entries(&mut frequency, entries, ranking);
+            };
+            // The cache is only ever a shortcut; failing to write it is no
+            // reason to fail.
+            let snapshot = cache.as_ref().and_then(|cache| cache.snapshot().ok());
+            let lengths = lengths(snapshot.as_ref(), &sources);
+            match sources.as_slice() {
+                [source] => source.load_in_chunks_to(lengths[0], add)?,
+                sources => history::load_all_in_chunks_to(sources, &lengths, add)?,
+            }
+            if let (Some(cache), Some(snapshot)) = (cache, snapshot) {
+                let _ = cache.save(&frequency, snapshot);
+            }
+            frequency
+        }
+    };
+    let provided = provided(&providers, &prepare)?;
+    search::add_entries(&mut frequency, provided, ranking);
+    search::fold(&mut frequency, &config.normalize);
+    Ok(frequency)
+}
 
-        // Clear the screen and display the prompt along with suggestions.
-        execute!(stdout, Clear(ClearType::All), MoveTo(0, 0))?;
-        let header =
-            "Type your search query. Use ↑/↓ to select. Press Enter to choose. (Esc to exit)";
-        writeln!(stdout, "{}", truncate_to_width(header, term_width))?;
-        writeln!(
-            stdout,
-            "{}",
-            truncate_to_width(&format!("Search: {}", query), term_width)
-        )?;
-        writeln!(stdout)?;
+/// The cache of what `sources` count up to, unless `--no-cache` or a time
+/// range given makes the count this run's alone. History in a database
+/// or a binary file, which does not only grow by lines at its end, is not
+/// cached.
+fn frequency_cache(cli: &Cli, config: &Config, sources: &[history::Source]) -> Option<Cache> {
+    if cli.no_cache
+        || !time::Range::new(cli.since, cli.until).is_unbounded()
+        || sources.iter().any(|source| !source.format.appends_lines())
+    {
+        return None;
+    }
+    // The frecency cached decays by the half-life it was worked out with.
+    let settings = format!(
+        "{:?} {:?} {} {}",
+        config.ignore, config.normalize, cli.no_ignore, config.ranking.half_life_days
+    );
+    Cache::new(sources, settings).ok()
+}
+
+/// How much of each of `sources` to read: as much as `snapshot` noted
+/// before reading them, or without one, all of it.
+fn lengths(snapshot: Option<&Snapshot>, sources: &[history::Source]) -> Vec<u64> {
+    match snapshot {
+        Some(snapshot) => snapshot.lengths(),
+        None => vec![u64::MAX; sources.len()],
+    }
+}
+
+/// What history read from files goes through before it is searched.
+#[derive(Clone)]
+struct Prepare {
+    /// The times given with --since and --until.
+    range: time::Range,
+    /// The config's ignore list, unless --no-ignore.
+    ignore: Ignore,
+    normalize: normalize::Rules,
+}
 
-        for (i, (cmd, count)) in suggestions.iter().enumerate() {
-            let line = if i == selected_index {
-                format!("> {} ({})", cmd, count)
+impl Prepare {
+    fn new(cli: &Cli, config: &Config) -> Prepare {
+        Prepare {
+            range: time::Range::new(cli.since, cli.until),
+            ignore: if cli.no_ignore {
+                Ignore::none()
             } else {
-                format!("  {} ({})", cmd, count)
-            };
-            writeln!(stdout, "{}", truncate_to_width(&line, term_width))?;
+                config.ignore.ignore()
+            },
+            normalize: config.normalize,
         }
-        stdout.flush()?;
+    }
 
-        // Process user input.
-        match event::read()? {
-            Event::Key(KeyEvent { code, .. }) => match code {
-                KeyCode::Char(c) => {
-                    query.push(c);
-                    selected_index = 0;
-                }
-                KeyCode::Backspace => {
-                    query.pop();
-                    selected_index = 0;
-                }
-                KeyCode::Up => {
-                    if selected_index > 0 {
-                        selected_index -= 1;
-                    }
+    /// Drops the entries outside the time range or ignored, then normalizes
+    /// the rest. Ignore patterns see commands as they were typed, so that one
+    /// starting with a space is still recognized.
+    fn apply(&self, entries: &mut Vec<history::Entry>) {
+        entries.retain(|entry| {
+            self.range.contains(entry.timestamp) && !self.ignore.is_ignored(&entry.command)
+        });
+        normalize_entries(entries, &self.normalize);
+    }
+
+    /// One command as [`Prepare::apply`] leaves it, whenever it ran, or
+    /// `None` if it is dropped.
+    fn command(&self, command: &str) -> Option<String> {
+        if self.ignore.is_ignored(command) {
+            return None;
+        }
+        let command = normalize::normalize(command, &self.normalize);
+        (!command.is_empty()).then(|| command.into_owned())
+    }
+}
+
+/// The id of the shell session th_rs runs in, which the hooks of
+/// `th_rs init --record` set.
+fn session() -> Option<String> {
+    env::var("TH_RS_SESSION").ok().filter(|id| !id.is_empty())
+}
+
+/// This machine's name, for runs recorded without `--hostname`.
+fn hostname() -> String {
+    let from_env = ["HOSTNAME", "HOST", "COMPUTERNAME"]
+        .into_iter()
+        .find_map(|var| env::var(var).ok().filter(|name| !name.is_empty()));
+    from_env
+        .or_else(|| {
+            ["/proc/sys/kernel/hostname", "/etc/hostname"]
+                .into_iter()
+                .find_map(|path| fs::read_to_string(path).ok())
+                .map(|name| name.trim().to_string())
+        })
+        .unwrap_or_default()
+}
+
+/// The picker's starting query: `--query`, or for `--query -` whatever is
+/// piped in. The query is a single line, so line breaks become spaces.
+fn initial_query(cli: &Cli) -> th_rs::Result<String> {
+    let query = match cli.query.as_deref() {
+        None => return Ok(String::new()),
+        Some("-") => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        }
+        Some(query) => query.to_string(),
+    };
+    Ok(query
+        .trim_end_matches(['\r', '\n'])
+        .replace("\r\n", " ")
+        .replace('\n', " "))
+}
+
+/// Where `--out` or `--out-fd` sends the selection, if either was given.
+/// The file is emptied now, so that nothing stale is left in it.
+fn open_out(cli: &Cli) -> th_rs::Result<Option<fs::File>> {
+    let path = match (&cli.out, cli.out_fd) {
+        (Some(path), _) => path.clone(),
+        // Opening the descriptor's name reaches whatever it is open on
+        // without taking it over.
+        (None, Some(fd)) if cfg!(unix) => PathBuf::from(format!("/dev/fd/{}", fd)),
+        (None, Some(_)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "--out-fd needs /dev/fd, which this platform lacks; use --out",
+            )
+            .into())
+        }
+        (None, None) => return Ok(None),
+    };
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(cli.out.is_some())
+        .truncate(cli.out.is_some())
+        .open(&path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+    Ok(Some(file))
+}
+
+/// Copies the command picked in `outcome` to the clipboard through `tty`:
+/// always for Ctrl-Y, and for an ordinary selection under `--copy`. Returns
+/// whether anything was copied.
+fn copy_selection<W: io::Write>(
+    tty: &mut W,
+    outcome: &ui::Outcome,
+    copy: bool,
+) -> io::Result<bool> {
+    match outcome {
+        ui::Outcome::Copy(command) => clipboard::copy(tty, command)?,
+        ui::Outcome::Selected(command) | ui::Outcome::Execute(command) if copy => {
+            clipboard::copy(tty, command)?
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Appends `command` to the first `--history-file`, or else the current
+/// shell's history, as run just now.
+fn append_to_history(cli: &Cli, config: &Config, command: &str) -> th_rs::Result<()> {
+    let source = match cli.history_file.first() {
+        Some(path) => history::Source::from_path(path.clone(), cli.history_format),
+        None => history::detect_source(config.histfile_from_rc)?,
+    };
+    source.append(command, rank::now(), &config.backup.backups()?)
+}
+
+/// Says how many runs pruning removed from `path`, or with `dry_run` would,
+/// listing the commands too for a dry run.
+fn report_pruned(removed: &BTreeMap<String, usize>, path: &Path, dry_run: bool) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    if dry_run {
+        for (command, runs) in removed {
+            writeln!(out, "{:>6}  {}", runs, command)?;
+        }
+    }
+    writeln!(
+        out,
+        "{} {} runs of {} commands from {}",
+        if dry_run { "Would remove" } else { "Removed" },
+        removed.values().sum::<usize>(),
+        removed.len(),
+        path.display()
+    )
+}
+
+/// Deletes `command` from the store, or from every history file it was
+/// loaded from.
+fn delete_command(cli: &Cli, config: &Config, command: &str) -> th_rs::Result<()> {
+    if cli.store {
+        let mut store = config.store.open()?;
+        if !config.normalize.folds() {
+            store.delete(command)?;
+            return Ok(());
+        }
+        // Every spelling counted as the command goes with it.
+        let spellings: Vec<String> = store
+            .frequency_map(&config.ranking, None)?
+            .into_keys()
+            .filter(|spelling| normalize::same(spelling, command, &config.normalize))
+            .collect();
+        for spelling in spellings {
+            store.delete(&spelling)?;
+        }
+        return Ok(());
+    }
+    let backups = config.backup.backups()?;
+    for source in sources(cli, config)? {
+        match source.delete(command, &config.normalize, &backups) {
+            Ok(_) => {}
+            Err(th_rs::ThError::HistoryFile { source: err, .. })
+                if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Loads `sources` on a thread of their own, sending their entries through
+/// `prepare` as they are parsed. As in [`load_entries`], a lone source must
+/// exist while merged ones skip uninstalled shells. With a `cache`, the
+/// entries are counted there too, ranked per `ranking`, and saved once all
+/// are loaded. What `providers` hold comes last, left out of the cache.
+fn spawn_loader(
+    sources: Vec<history::Source>,
+    providers: Vec<Box<dyn Provider>>,
+    prepare: Prepare,
+    ranking: Ranking,
+    cache: Option<Cache>,
+) -> mpsc::Receiver<ui::Load> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut counted = FrequencyMap::new();
+        let snapshot = cache.as_ref().and_then(|cache| cache.snapshot().ok());
+        let lengths = lengths(snapshot.as_ref(), &sources);
+        for (source, &len) in sources.iter().zip(&lengths) {
+            let sent = source.load_in_chunks_to(len, |mut entries| {
+                prepare.apply(&mut entries);
+                if snapshot.is_some() {
+                    search::add_entries(&mut counted, entries.clone(), &ranking);
                 }
-                KeyCode::Down => {
-                    if selected_index + 1 < suggestions.len() {
-                        selected_index += 1;
-                    }
+                // The picker has closed if nobody is receiving; the rest of
+                // the file is read for nothing, but harmlessly.
+                let _ = sender.send(ui::Load::Entries(entries));
+            });
+            match sent {
+                Ok(()) => {}
+                Err(th_rs::ThError::HistoryFile { source: err, .. })
+                    if sources.len() > 1 && err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    let message = format!("No history loaded: {}", err);
+                    let _ = sender.send(ui::Load::Failed(message));
+                    return;
                 }
-                KeyCode::Enter => {
-                    // Cleanup terminal before exiting.
-                    execute!(
-                        stdout,
-                        Clear(ClearType::All),
-                        MoveTo(0, 0),
-                        Show,
-                        LeaveAlternateScreen
-                    )?;
-                    disable_raw_mode()?;
-                    if !suggestions.is_empty() {
-                        println!("Selected command:\n{}", suggestions[selected_index].0);
-                    } else {
-                        println!("No matching commands found.");
-                    }
-                    return Ok(());
-                }
-                KeyCode::Esc => {
-                    // Cleanup terminal on exit.
-                    execute!(
-                        stdout,
-                        Clear(ClearType::All),
-                        MoveTo(0, 0),
-                        Show,
-                        LeaveAlternateScreen
-                    )?;
-                    disable_raw_mode()?;
-                    println!("Exited.");
-                    return Ok(());
-                }
-                _ => {}
-            },
-            Event::Resize(_, _) => {
-                // The UI will redraw on the next loop iteration.
             }
-            _ => {}
+        }
+        if let (Some(cache), Some(snapshot)) = (cache, snapshot) {
+            let _ = cache.save(&counted, snapshot);
+        }
+        match provided(&providers, &prepare) {
+            Ok(entries) => {
+                let _ = sender.send(ui::Load::Entries(entries));
+                let _ = sender.send(ui::Load::Done);
+            }
+            Err(err) => {
+                let message = format!("Only part of the history loaded: {}", err);
+                let _ = sender.send(ui::Load::Failed(message));
+            }
+        }
+    });
+    receiver
+}
+
+/// Starts following the history files just loaded, from their current end.
+fn tails(cli: &Cli, config: &Config) -> Vec<Tail> {
+    sources(cli, config)
+        .map(|sources| sources.into_iter().map(Tail::new).collect())
+        .unwrap_or_default()
+}
+
+/// Collects what was appended to the history files since they were loaded.
+/// A file that was rewritten instead means reloading them all.
+fn refresh_history(
+    cli: &Cli,
+    config: &Config,
+    tails: &mut Vec<Tail>,
+) -> th_rs::Result<Option<ui::Update>> {
+    let mut appended = Vec::new();
+    for tail in tails.iter_mut() {
+        match tail.poll()? {
+            Change::Unchanged => {}
+            Change::Appended(mut entries) => {
+                Prepare::new(cli, config).apply(&mut entries);
+                appended.extend(entries);
+            }
+            Change::Rewritten => {
+                let frequency = load_frequency(cli, config)?;
+                *tails = self::tails(cli, config);
+                return Ok(Some(ui::Update::Reloaded(frequency)));
+            }
         }
     }
+    Ok((!appended.is_empty()).then_some(ui::Update::Appended(appended)))
 }
 
-fn main() -> io::Result<()> {
-    // Run the UI and ensure that the terminal state is restored in case of an error.
-    let result = run_ui();
-    if result.is_err() {
-        let mut stdout = stdout();
-        let _ = execute!(stdout, Show, LeaveAlternateScreen);
-        let _ = disable_raw_mode();
+/// Runs `command` in the user's shell and forwards its exit status.
+///
+/// Under `--print`, stdout belongs to whoever captures the selection, so the
+/// command's own output is sent to stderr instead.
+fn execute(command: &str, print: bool) -> th_rs::Result<ExitCode> {
+    eprintln!("$ {}", command);
+    let mut process = exec::command(command);
+    if print {
+        process.stdout(io::stderr());
     }
-    result
+    let status = process.status()?;
+    Ok(ExitCode::from(exec::exit_code(status)))
 }
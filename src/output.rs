@@ -0,0 +1,196 @@
+//! Output formats for the non-interactive subcommands.
+
+use crate::search::CommandStats;
+use crate::shell::Shell;
+use crate::stats::Stats;
+use crate::time;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use unicode_width::UnicodeWidthStr;
+
+/// How results are written to stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// One command per line, best first.
+    #[default]
+    Plain,
+    /// A JSON array of objects.
+    Json,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Plain => "plain",
+            Format::Json => "json",
+        })
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format: {} (expected plain or json)", s)),
+        }
+    }
+}
+
+/// One command as written in JSON output. Every key is always present;
+/// unknown values are `null`.
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    /// The command line.
+    pub command: &'a str,
+    /// How many times it was run.
+    pub count: usize,
+    /// When it was last run, in Unix seconds.
+    pub last_used: Option<i64>,
+    /// The shell it was last run in.
+    pub shell: Option<Shell>,
+    /// Every shell it was run in.
+    pub shells: Vec<Shell>,
+    /// The directory it was last run in.
+    pub cwd: Option<&'a str>,
+    /// The exit status of its last run.
+    pub exit: Option<i32>,
+}
+
+impl<'a> Record<'a> {
+    /// The record for `command`.
+    pub fn new(command: &'a str, stats: &'a CommandStats) -> Self {
+        Record {
+            command,
+            count: stats.count,
+            last_used: stats.last_used,
+            shell: stats.shell,
+            shells: stats.shells.iter().collect(),
+            cwd: stats.cwd.as_deref(),
+            exit: stats.exit,
+        }
+    }
+}
+
+/// Writes ranked `matches` to `out` in `format`.
+pub fn write_matches<W: Write>(
+    out: &mut W,
+    matches: &[(&str, &CommandStats)],
+    format: Format,
+) -> io::Result<()> {
+    match format {
+        Format::Plain => {
+            for (command, _) in matches {
+                writeln!(out, "{}", command)?;
+            }
+        }
+        Format::Json => {
+            let records: Vec<Record> = matches
+                .iter()
+                .map(|&(command, stats)| Record::new(command, stats))
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &records)?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()
+}
+
+/// The widest a bar of the stats histograms gets.
+const BAR_WIDTH: usize = 40;
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Writes `stats` to `out` in `format`: as a report with bar charts, or as
+/// one JSON object.
+pub fn write_stats<W: Write>(out: &mut W, stats: &Stats, format: Format) -> io::Result<()> {
+    if format == Format::Json {
+        serde_json::to_writer_pretty(&mut *out, stats)?;
+        writeln!(out)?;
+        return out.flush();
+    }
+
+    writeln!(
+        out,
+        "{} commands run, {} distinct, {:.1} characters long on average",
+        stats.total, stats.unique, stats.average_length
+    )?;
+    let day = |t: Option<i64>| t.and_then(time::local_time).map(|t| t.format("%Y-%m-%d"));
+    if let (Some(first), Some(last), Some(per_day)) =
+        (day(stats.first), day(stats.last), stats.per_day())
+    {
+        writeln!(
+            out,
+            "{} of them timed, from {} to {}: {:.1} a day",
+            stats.timed, first, last, per_day
+        )?;
+    }
+
+    if !stats.top.is_empty() {
+        writeln!(out, "\nTop commands")?;
+        let width = stats.top[0].count.to_string().len();
+        for (rank, top) in stats.top.iter().enumerate() {
+            // Multi-line commands are shown on one line.
+            let command = top.value.replace('\n', "⏎");
+            writeln!(out, "{:>3}. {:>width$}  {}", rank + 1, top.count, command)?;
+        }
+    }
+
+    if stats.shells.len() > 1 {
+        writeln!(out, "\nBy shell")?;
+        let rows: Vec<(&str, usize)> = stats
+            .shells
+            .iter()
+            .map(|shell| (shell.value.map_or("other", Shell::name), shell.count))
+            .collect();
+        write_bars(out, &rows)?;
+    }
+
+    if stats.timed > 0 {
+        writeln!(out, "\nBy hour")?;
+        let hours: Vec<String> = (0..24).map(|hour| format!("{:02}", hour)).collect();
+        let rows: Vec<(&str, usize)> = hours.iter().map(String::as_str).zip(stats.hours).collect();
+        write_bars(out, &rows)?;
+
+        writeln!(out, "\nBy weekday")?;
+        let rows: Vec<(&str, usize)> = WEEKDAYS.into_iter().zip(stats.weekdays).collect();
+        write_bars(out, &rows)?;
+    }
+    out.flush()
+}
+
+/// One `#` bar per row, scaled so that the biggest count fills
+/// [`BAR_WIDTH`].
+fn write_bars<W: Write>(out: &mut W, rows: &[(&str, usize)]) -> io::Result<()> {
+    // Labels may be paths in any script, so they are padded by the columns
+    // they take rather than by their chars, as `{:<}` would.
+    let label_width = rows
+        .iter()
+        .map(|(label, _)| label.width())
+        .max()
+        .unwrap_or(0);
+    let most = rows
+        .iter()
+        .map(|&(_, count)| count)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let count_width = most.to_string().len();
+    for &(label, count) in rows {
+        // Round up, so that anything at all shows.
+        let bar = "#".repeat((count * BAR_WIDTH).div_ceil(most));
+        writeln!(
+            out,
+            "  {}{}  {:>count_width$}  {}",
+            label,
+            " ".repeat(label_width - label.width()),
+            count,
+            bar
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+//! Where the home directory is, and where th_rs keeps its own files.
+//!
+//! On Unix, including macOS, the XDG variables are honoured and the usual
+//! dotfile locations (`~/.config`, `~/.local/share`, `~/.cache`) are the
+//! fallback. On Windows the known folders are used instead, e.g.
+//! `%APPDATA%`.
+
+use crate::error::{Result, ThError};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The user's home directory: `HOME` on Unix, the profile folder
+/// (`USERPROFILE`) on Windows.
+pub fn home_dir() -> Result<PathBuf> {
+    dirs::home_dir().ok_or(ThError::NoHomeDir)
+}
+
+/// The directory holding `config.toml`.
+pub fn config_dir() -> Result<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config", dirs::config_dir)
+}
+
+/// The directory holding th_rs's own data, such as the history store.
+pub fn data_dir() -> Result<PathBuf> {
+    base_dir("XDG_DATA_HOME", ".local/share", dirs::data_dir)
+}
+
+/// The directory for files th_rs can always make again, such as the
+/// frequency cache.
+pub fn cache_dir() -> Result<PathBuf> {
+    base_dir("XDG_CACHE_HOME", ".cache", dirs::cache_dir)
+}
+
+/// The directory for th_rs's sockets: under `XDG_RUNTIME_DIR` when it is
+/// set, which is private to the user and cleared at logout, otherwise
+/// `run` in the data directory.
+pub fn runtime_dir() -> Result<PathBuf> {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Ok(PathBuf::from(dir).join("th_rs")),
+        None => Ok(data_dir()?.join("run")),
+    }
+}
+
+/// Creates `dir` and any parents it lacks, leaving `dir` itself, on Unix,
+/// for the user alone to enter: th_rs's directories hold history. One that
+/// already exists is narrowed.
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        if let Some(parent) = dir.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::DirBuilder::new().mode(0o700).create(dir) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+    }
+    #[cfg(not(unix))]
+    fs::create_dir_all(dir)
+}
+
+/// Options for writing a file that, when created, is readable by the user
+/// alone on Unix, for files holding history.
+pub fn private_file() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+fn base_dir(
+    xdg_var: &str,
+    unix_default: &str,
+    windows_default: fn() -> Option<PathBuf>,
+) -> Result<PathBuf> {
+    let base = match env::var_os(xdg_var).filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => windows_default().ok_or(ThError::NoHomeDir)?,
+        None => home_dir()?.join(unix_default),
+    };
+    Ok(base.join("th_rs"))
+}
@@ -0,0 +1,1017 @@
+//! Ranking and filtering of history commands.
+
+pub mod fuzzy;
+pub mod query;
+pub mod rank;
+pub mod words;
+
+use crate::catalog;
+use crate::history::normalize;
+use crate::history::Entry;
+use crate::shell::{Shell, ShellSet};
+use crate::time::{self, Range};
+use fuzzy::CharSet;
+use rank::Ranking;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How letter case is treated when matching.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseMode {
+    /// Ignore case unless the query contains an uppercase letter.
+    #[default]
+    Smart,
+    /// Always match case exactly.
+    Sensitive,
+    /// Never distinguish case.
+    Insensitive,
+}
+
+impl CaseMode {
+    /// Whether a search for `query` should be case-sensitive.
+    pub fn is_case_sensitive(self, query: &str) -> bool {
+        match self {
+            CaseMode::Smart => query.chars().any(char::is_uppercase),
+            CaseMode::Sensitive => true,
+            CaseMode::Insensitive => false,
+        }
+    }
+
+    /// Parses `query` for matching under this mode.
+    pub fn matcher(self, query: &str) -> query::Query {
+        query::Query::new(query, self.is_case_sensitive(query))
+    }
+}
+
+impl fmt::Display for CaseMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CaseMode::Smart => "smart",
+            CaseMode::Sensitive => "sensitive",
+            CaseMode::Insensitive => "insensitive",
+        })
+    }
+}
+
+impl FromStr for CaseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "smart" => Ok(CaseMode::Smart),
+            "sensitive" => Ok(CaseMode::Sensitive),
+            "insensitive" => Ok(CaseMode::Insensitive),
+            _ => Err(format!(
+                "unknown case mode: {} (expected smart, sensitive, or insensitive)",
+                s
+            )),
+        }
+    }
+}
+
+/// What is known about one distinct command.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommandStats {
+    /// How many times the command was run.
+    pub count: usize,
+    /// When it was last run, in Unix seconds, if any run was timestamped.
+    pub last_used: Option<i64>,
+    /// The run count decayed by age; see [`rank`].
+    pub frecency: f64,
+    /// The shell of the most recent run, when known.
+    pub shell: Option<Shell>,
+    /// Every shell the command was run in.
+    pub shells: ShellSet,
+    /// The working directory of the most recent run, when known.
+    pub cwd: Option<String>,
+    /// Every working directory the command is known to have run in.
+    pub dirs: BTreeSet<String>,
+    /// The exit status of the most recent run, when it was recorded.
+    pub exit: Option<i32>,
+    /// When the command was last run in the current shell session, in Unix
+    /// seconds, if it was at all.
+    pub in_session: Option<i64>,
+    /// Whether the user bookmarked the command, which puts it above the
+    /// rest.
+    pub bookmarked: bool,
+    /// The name of the snippet the command is, if it is one; see
+    /// [`crate::snippet`]. Snippets come before other results too, and
+    /// match by name as well as by command.
+    pub snippet: Option<String>,
+    /// What the command does, from a catalog; see [`crate::catalog`].
+    pub description: Option<String>,
+    /// The command's tags.
+    pub tags: BTreeSet<String>,
+    /// When the timestamped runs were.
+    pub runs: Runs,
+}
+
+impl CommandStats {
+    /// What the command matches by besides itself: a snippet's name, or a
+    /// catalog command's description.
+    pub fn alias(&self) -> Option<&str> {
+        self.snippet.as_deref().or(self.description.as_deref())
+    }
+
+    /// Counts `other`'s runs in with these, as runs of one command. The
+    /// shell, directory, and exit status of the latest run are kept.
+    pub fn absorb(&mut self, other: CommandStats) {
+        if other.last_used > self.last_used {
+            self.last_used = other.last_used;
+            self.shell = other.shell.or(self.shell);
+            self.cwd = other.cwd.or(self.cwd.take());
+            self.exit = other.exit.or(self.exit);
+        }
+        self.count += other.count;
+        self.frecency += other.frecency;
+        self.shells = self.shells.union(other.shells);
+        self.dirs.extend(other.dirs);
+        self.in_session = self.in_session.max(other.in_session);
+        self.bookmarked |= other.bookmarked;
+        self.snippet = self.snippet.take().or(other.snippet);
+        self.description = self.description.take().or(other.description);
+        self.tags.extend(other.tags);
+        self.runs.merge(&other.runs);
+    }
+
+    /// Whether the command was run at some time in `range`; always, for an
+    /// unbounded range. See [`Runs::overlap`].
+    pub fn ran_in(&self, range: Range) -> bool {
+        range.is_unbounded() || self.runs.overlap(range)
+    }
+}
+
+/// How many days before a command's last run [`Runs`] counts its runs by
+/// day.
+pub const RUN_DAYS: usize = 30;
+
+/// When a command's timestamped runs were, in much the same space however
+/// many there are: the first and the last, and how many fell on each of the
+/// [`RUN_DAYS`] UTC days up to the last. Only days with runs are kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Runs {
+    count: usize,
+    first: Option<i64>,
+    last: Option<i64>,
+    /// Days, as Unix days, and their runs, oldest first.
+    days: Vec<(i64, u32)>,
+}
+
+impl Runs {
+    /// Counts a run at `timestamp`, in Unix seconds.
+    pub fn add(&mut self, timestamp: i64) {
+        self.count += 1;
+        self.first = self.first.min(Some(timestamp)).or(Some(timestamp));
+        self.last = self.last.max(Some(timestamp));
+        self.add_day(timestamp.div_euclid(86_400), 1);
+        self.trim();
+    }
+
+    /// Counts `other`'s runs in with these.
+    pub fn merge(&mut self, other: &Runs) {
+        self.count += other.count;
+        self.first = self.first.min(other.first).or(self.first).or(other.first);
+        self.last = self.last.max(other.last);
+        for &(day, runs) in &other.days {
+            self.add_day(day, runs);
+        }
+        self.trim();
+    }
+
+    /// How many runs there were.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether there were none.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// When the first was, in Unix seconds.
+    pub fn first(&self) -> Option<i64> {
+        self.first
+    }
+
+    /// When the last was, in Unix seconds.
+    pub fn last(&self) -> Option<i64> {
+        self.last
+    }
+
+    /// Whether there may have been a run in `range`: whether the runs begin
+    /// before it ends and end after it begins, and when it begins on one of
+    /// the days counted, whether any of those in it had runs. A range inside
+    /// a long gap between older runs is taken as run in.
+    pub fn overlap(&self, range: Range) -> bool {
+        let (Some(first), Some(last)) = (self.first, self.last) else {
+            return false;
+        };
+        if range.since.is_some_and(|since| last < since)
+            || range.until.is_some_and(|until| first >= until)
+        {
+            return false;
+        }
+        let counted_from = (last.div_euclid(86_400) - RUN_DAYS as i64 + 1) * 86_400;
+        match range.since {
+            Some(since) if since >= counted_from => self.days.iter().any(|&(day, _)| {
+                let (start, end) = (day * 86_400, (day + 1) * 86_400);
+                range.until.is_none_or(|until| start < until) && end > since
+            }),
+            _ => true,
+        }
+    }
+
+    /// How many runs fell on each of the `days` UTC days up to the one
+    /// `now` (Unix seconds) is in, oldest first. Days more than
+    /// [`RUN_DAYS`] before the last run count none.
+    pub fn per_day(&self, days: usize, now: i64) -> Vec<usize> {
+        let today = now.div_euclid(86_400);
+        let mut per_day = vec![0; days];
+        for &(day, runs) in &self.days {
+            if let Some(count) = usize::try_from(today - day)
+                .ok()
+                .and_then(|ago| days.checked_sub(ago + 1))
+                .and_then(|at| per_day.get_mut(at))
+            {
+                *count += runs as usize;
+            }
+        }
+        per_day
+    }
+
+    fn add_day(&mut self, day: i64, runs: u32) {
+        match self.days.binary_search_by_key(&day, |&(day, _)| day) {
+            Ok(at) => self.days[at].1 += runs,
+            Err(at) => self.days.insert(at, (day, runs)),
+        }
+    }
+
+    /// Drops the days too long before the last run.
+    fn trim(&mut self) {
+        if let Some(last) = self.last {
+            let oldest = last.div_euclid(86_400) - RUN_DAYS as i64 + 1;
+            self.days.retain(|&(day, _)| day >= oldest);
+        }
+    }
+}
+
+impl Extend<i64> for Runs {
+    fn extend<I: IntoIterator<Item = i64>>(&mut self, timestamps: I) {
+        for timestamp in timestamps {
+            self.add(timestamp);
+        }
+    }
+}
+
+impl FromIterator<i64> for Runs {
+    fn from_iter<I: IntoIterator<Item = i64>>(timestamps: I) -> Runs {
+        let mut runs = Runs::default();
+        runs.extend(timestamps);
+        runs
+    }
+}
+
+/// Which commands are searched at all, whatever the query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Only commands run in this directory or one below it.
+    pub dir: Option<PathBuf>,
+    /// Which commands to keep by how their last run exited.
+    pub exit: ExitFilter,
+    /// Only commands run in the current shell session, most recent first
+    /// rather than best match first.
+    pub session: bool,
+    /// Match the query against each word of a command rather than the whole
+    /// line, to find the commands that used a file or flag; see [`words`].
+    pub arguments: bool,
+}
+
+/// Which commands to keep by the exit status of their last run. Commands
+/// with no recorded status count as having succeeded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExitFilter {
+    #[default]
+    Any,
+    /// Leave out commands whose last run failed.
+    Succeeded,
+    /// Only commands whose last run failed.
+    Failed,
+}
+
+impl Filter {
+    /// Whether a command with `stats` is searched.
+    pub fn allows(&self, stats: &CommandStats) -> bool {
+        let failed = stats.exit.is_some_and(|status| status != 0);
+        let exit = match self.exit {
+            ExitFilter::Any => true,
+            ExitFilter::Succeeded => !failed,
+            ExitFilter::Failed => failed,
+        };
+        let session = !self.session || stats.in_session.is_some();
+        exit && session
+            && self.dir.as_ref().is_none_or(|dir| {
+                stats
+                    .dirs
+                    .iter()
+                    .any(|ran_in| Path::new(ran_in).starts_with(dir))
+            })
+    }
+}
+
+/// Every distinct command mapped to its statistics.
+pub type FrequencyMap = HashMap<String, CommandStats>;
+
+/// Builds a frequency map for the list of history entries, weighting each
+/// run's contribution to frecency per `ranking`. The entries' commands
+/// become the map's keys rather than being copied into it.
+pub fn build_frequency_map(
+    entries: impl IntoIterator<Item = Entry>,
+    ranking: &Ranking,
+) -> FrequencyMap {
+    let mut freq = FrequencyMap::new();
+    add_entries(&mut freq, entries, ranking);
+    freq
+}
+
+/// Marks `bookmarks` as bookmarked in `freq`, adding those never run.
+pub fn add_bookmarks<'a>(freq: &mut FrequencyMap, bookmarks: impl IntoIterator<Item = &'a String>) {
+    for command in bookmarks {
+        freq.entry(command.clone()).or_default().bookmarked = true;
+    }
+}
+
+/// Adds `snippets`, pairs of a name and a command, to `freq`.
+pub fn add_snippets(freq: &mut FrequencyMap, snippets: &[(String, String)]) {
+    for (name, command) in snippets {
+        freq.entry(command.clone()).or_default().snippet = Some(name.clone());
+    }
+}
+
+/// Adds the commands of a catalog to `freq`, with their descriptions and
+/// tags.
+pub fn add_catalog(freq: &mut FrequencyMap, items: &[catalog::Item]) {
+    for item in items {
+        let stats = freq.entry(item.command.clone()).or_default();
+        if item.description.is_some() {
+            stats.description = item.description.clone();
+        }
+        stats.tags.extend(item.tags.iter().cloned());
+    }
+}
+
+/// Adds `tags`, pairs of a command and one of its tags, to the commands of
+/// `freq`. Tags of commands not in it are passed over.
+pub fn add_tags<'a>(freq: &mut FrequencyMap, tags: impl IntoIterator<Item = &'a (String, String)>) {
+    for (command, tag) in tags {
+        if let Some(stats) = freq.get_mut(command) {
+            stats.tags.insert(tag.clone());
+        }
+    }
+}
+
+/// Counts the commands of `freq` that `rules` count as one, like `Git
+/// status` and `git status` with `ignore_case`, together, under the
+/// spelling run last, or without times, run most. Does nothing unless the
+/// rules [fold](normalize::Rules::folds).
+pub fn fold(freq: &mut FrequencyMap, rules: &normalize::Rules) {
+    if !rules.folds() {
+        return;
+    }
+    let mut spellings: HashMap<String, Vec<String>> = HashMap::new();
+    for command in freq.keys() {
+        let key = normalize::key(command, rules).into_owned();
+        spellings.entry(key).or_default().push(command.clone());
+    }
+    for commands in spellings
+        .into_values()
+        .filter(|commands| commands.len() > 1)
+    {
+        let mut folded: Vec<(String, CommandStats)> = commands
+            .iter()
+            .filter_map(|command| freq.remove_entry(command))
+            .collect();
+        folded.sort_by(|(a, x), (b, y)| {
+            (y.last_used.cmp(&x.last_used))
+                .then(y.count.cmp(&x.count))
+                .then(a.cmp(b))
+        });
+        let mut folded = folded.into_iter();
+        let Some((command, mut stats)) = folded.next() else {
+            continue;
+        };
+        for (_, other) in folded {
+            stats.absorb(other);
+        }
+        freq.insert(command, stats);
+    }
+}
+
+/// Counts more runs into an existing frequency map, e.g. commands appended
+/// to a history file since it was first read.
+pub fn add_entries(
+    freq: &mut FrequencyMap,
+    entries: impl IntoIterator<Item = Entry>,
+    ranking: &Ranking,
+) {
+    let now = rank::now();
+    for entry in entries {
+        let stats = freq.entry(entry.command).or_default();
+        stats.count += 1;
+        if let Some(cwd) = &entry.cwd {
+            stats.dirs.insert(cwd.clone());
+        }
+        if entry.timestamp >= stats.last_used {
+            stats.last_used = entry.timestamp;
+            stats.shell = entry.shell.or(stats.shell);
+            stats.cwd = entry.cwd.or(stats.cwd.take());
+            stats.exit = entry.exit.or(stats.exit);
+        }
+        stats.frecency += ranking.run_weight(entry.timestamp, now);
+        if let Some(shell) = entry.shell {
+            stats.shells.insert(shell);
+        }
+        stats.runs.extend(entry.timestamp);
+    }
+}
+
+/// Ages every command's frecency from `then` (Unix seconds), when it was
+/// worked out per `ranking`, to now: what the timestamped runs weigh
+/// shrinks as each of them would have, and the rest weigh 1 still, as in
+/// [`add_entries`].
+pub fn decay(freq: &mut FrequencyMap, ranking: &Ranking, then: i64) {
+    // The weights all fall by the same factor, however old the run.
+    let factor = ranking.run_weight(Some(then), rank::now());
+    for stats in freq.values_mut() {
+        let untimed = stats.count.saturating_sub(stats.runs.len()) as f64;
+        stats.frecency = untimed + (stats.frecency - untimed).max(0.0) * factor;
+    }
+}
+
+/// Returns the commands matching `query`, treating case per `case`,
+/// among those `filter` allows. Time filters in the query, like `@7d`, leave
+/// out commands not run then; see [`time::split_filters`]. Tags, like
+/// `#deploy`, leave out commands without them; see [`query::split_tags`].
+///
+/// Results are ordered by match quality combined with frecency per
+/// `ranking`, plus its boost for commands run in the current repository,
+/// then alphabetically, with bookmarks and snippets before all
+/// others. See [`query`] for the operators a query may use. With an empty
+/// query this is simply the most frecent commands first, or with
+/// `ranking.empty_query` set to recent, the last run first, bookmarks
+/// included. A session filter orders them by when they last ran in the session
+/// instead, as scrolling back through it would. Searching by argument scores
+/// each command by its best-matching word.
+pub fn search<'a>(
+    frequency: &'a FrequencyMap,
+    query: &str,
+    case: CaseMode,
+    ranking: &Ranking,
+    filter: &Filter,
+) -> Vec<(&'a str, &'a CommandStats)> {
+    let (range, pattern) = time::split_filters(query);
+    let (tags, pattern) = query::split_tags(&pattern);
+    let index = filter
+        .arguments
+        .then(|| words::Index::new(frequency.keys().map(String::as_str)));
+    rank_matches(
+        candidates(frequency, range, filter)
+            .filter(|(_, stats)| query::has_tags(&stats.tags, &tags)),
+        &case.matcher(&pattern),
+        ranking,
+        filter.session,
+        index.as_ref(),
+    )
+}
+
+/// What of a picker query is matched against commands: all but its time
+/// filters and tags.
+pub fn pattern(query: &str) -> String {
+    query::split_tags(&time::split_filters(query).1)
+        .1
+        .into_owned()
+}
+
+/// The `limit` most frecent commands of `frequency` that `filter` allows
+/// and that start with `prefix` as typed, most frecent first. Unlike
+/// [`search`], nothing is matched fuzzily and bookmarks come first only if
+/// they ran most.
+pub fn top<'a>(
+    frequency: &'a FrequencyMap,
+    prefix: &str,
+    filter: &Filter,
+    limit: usize,
+) -> Vec<(&'a str, &'a CommandStats)> {
+    let mut top: Vec<(&str, &CommandStats)> = candidates(frequency, Range::default(), filter)
+        .filter(|(cmd, stats)| cmd.starts_with(prefix) && stats.count > 0)
+        .collect();
+    top.sort_unstable_by(|a, b| b.1.frecency.total_cmp(&a.1.frecency).then(a.0.cmp(b.0)));
+    top.truncate(limit);
+    top
+}
+
+/// The commands of `frequency` that `filter` allows and that start with
+/// `prefix` as typed, and are more than it, ranked as [`search`] ranks
+/// them for an empty query: bookmarks and the current repository's
+/// commands come first, as they do in the picker. At most `limit` are
+/// returned. Unlike [`top`], this weighs everything in `ranking`, not
+/// frecency alone.
+pub fn complete<'a>(
+    frequency: &'a FrequencyMap,
+    prefix: &str,
+    ranking: &Ranking,
+    filter: &Filter,
+    limit: usize,
+) -> Vec<(&'a str, &'a CommandStats)> {
+    let mut completions = rank_matches(
+        candidates(frequency, Range::default(), filter)
+            .filter(|(cmd, _)| cmd.starts_with(prefix) && *cmd != prefix),
+        &CaseMode::default().matcher(""),
+        ranking,
+        false,
+        None,
+    );
+    completions.truncate(limit);
+    completions
+}
+
+/// The commands of `frequency` run in `range` that `filter` allows.
+fn candidates<'a>(
+    frequency: &'a FrequencyMap,
+    range: Range,
+    filter: &Filter,
+) -> impl Iterator<Item = (&'a str, &'a CommandStats)> {
+    let filter = filter.clone();
+    frequency
+        .iter()
+        .filter(move |(_, stats)| stats.ran_in(range) && filter.allows(stats))
+        .map(|(cmd, stats)| (cmd.as_str(), stats))
+}
+
+/// Scores `candidates` against `query` and orders the matches as
+/// [`search`] does, most recent in the session first if `chronological`.
+/// With an `index`, commands are scored by their best-matching word.
+///
+/// From `ranking.parallel_threshold` candidates on, they are scored and
+/// sorted on every core. The results come out the same either way: matches
+/// are collected in the candidates' order, and the sort leaves no ties.
+fn rank_matches<'a>(
+    candidates: impl Iterator<Item = (&'a str, &'a CommandStats)>,
+    matcher: &query::Query,
+    ranking: &Ranking,
+    chronological: bool,
+    index: Option<&words::Index>,
+) -> Vec<(&'a str, &'a CommandStats)> {
+    let started = Instant::now();
+    let candidates: Vec<(&str, &CommandStats)> = candidates.collect();
+    let parallel = candidates.len() >= ranking.parallel_threshold;
+    let mut scored = score_all(&candidates, matcher, ranking, index, parallel);
+    order(&mut scored, matcher, ranking, chronological);
+    tracing::debug!(
+        commands = candidates.len(),
+        matches = scored.len(),
+        query_len = matcher.text().len(),
+        parallel,
+        took = ?started.elapsed(),
+        "ranked"
+    );
+    scored
+        .into_iter()
+        .map(|(_, cmd, stats)| (cmd, stats))
+        .collect()
+}
+
+/// The matches among `candidates`, with the values [`order`] sorts them
+/// by, in the candidates' order, scored on every core if `parallel`.
+fn score_all<'a>(
+    candidates: &[(&'a str, &'a CommandStats)],
+    matcher: &query::Query,
+    ranking: &Ranking,
+    index: Option<&words::Index>,
+    parallel: bool,
+) -> Vec<(f64, &'a str, &'a CommandStats)> {
+    let picks = ranking.picks.for_query(matcher.text());
+    match parallel {
+        true => candidates
+            .par_iter()
+            .map_init(
+                || index.map(|index| index.scorer(matcher)),
+                |by_word, &(cmd, stats)| {
+                    score_one(matcher, ranking, picks, by_word.as_mut(), cmd, stats)
+                },
+            )
+            .flatten()
+            .collect(),
+        false => {
+            let mut by_word = index.map(|index| index.scorer(matcher));
+            candidates
+                .iter()
+                .filter_map(|&(cmd, stats)| {
+                    score_one(matcher, ranking, picks, by_word.as_mut(), cmd, stats)
+                })
+                .collect()
+        }
+    }
+}
+
+/// Sorts the matches `score_all` found as [`rank_matches`] returns them.
+fn order(
+    scored: &mut [(f64, &str, &CommandStats)],
+    matcher: &query::Query,
+    ranking: &Ranking,
+    chronological: bool,
+) {
+    let recent = ranking.empty_query == rank::EmptyQuery::Recent && matcher.is_empty();
+    let order = |a: &(f64, &str, &CommandStats), b: &(f64, &str, &CommandStats)| {
+        if chronological {
+            return b.2.in_session.cmp(&a.2.in_session).then(a.1.cmp(b.1));
+        }
+        if recent {
+            return b.2.last_used.cmp(&a.2.last_used).then(a.1.cmp(b.1));
+        }
+        let pinned = |stats: &CommandStats| stats.bookmarked || stats.snippet.is_some();
+        pinned(b.2)
+            .cmp(&pinned(a.2))
+            .then(b.0.total_cmp(&a.0))
+            .then(a.1.cmp(b.1))
+    };
+    match scored.len() >= ranking.parallel_threshold {
+        true => scored.par_sort_unstable_by(order),
+        false => scored.sort_unstable_by(order),
+    }
+}
+
+/// The value [`rank_matches`] sorts `cmd` by, if it matches: its score by
+/// `by_word` when searching by argument, else by `matcher`, or that of its
+/// snippet name, boosted by how often it was among the `picks` for the
+/// query.
+fn score_one<'a>(
+    matcher: &query::Query,
+    ranking: &Ranking,
+    picks: Option<&HashMap<String, u32>>,
+    by_word: Option<&mut impl FnMut(&str) -> Option<i64>>,
+    cmd: &'a str,
+    stats: &'a CommandStats,
+) -> Option<(f64, &'a str, &'a CommandStats)> {
+    let name = || stats.alias().and_then(|name| matcher.score(name));
+    let score = match by_word {
+        Some(score) => score(cmd),
+        None => matcher.score(cmd),
+    };
+    let score = score.or_else(name)?;
+    let picked = picks.and_then(|picks| picks.get(cmd)).copied();
+    Some((
+        ranking
+            .score(score, cmd, stats, picked.unwrap_or(0))
+            .total(),
+        cmd,
+        stats,
+    ))
+}
+
+/// The parts of the score [`search`] ranks `cmd` by for `matcher`, if it
+/// matches, for showing how a result came to be where it is. `arguments`
+/// scores by the best-matching word, as [`Filter::arguments`] does.
+pub fn explain(
+    matcher: &query::Query,
+    ranking: &Ranking,
+    arguments: bool,
+    cmd: &str,
+    stats: &CommandStats,
+) -> Option<rank::Score> {
+    let score = match arguments {
+        true => words::fuzzy_match(matcher, cmd).map(|m| m.score),
+        false => matcher.score(cmd),
+    };
+    let name = || stats.alias().and_then(|name| matcher.score(name));
+    let score = score.or_else(name)?;
+    let picked = ranking
+        .picks
+        .for_query(matcher.text())
+        .and_then(|picks| picks.get(cmd))
+        .copied();
+    Some(ranking.score(score, cmd, stats, picked.unwrap_or(0)))
+}
+
+/// Repeated searches over one frequency map, as the query is typed.
+///
+/// Every command matching a query also matches any prefix of it, so when a
+/// character is appended only the previous matches need scoring again, and
+/// when one is deleted the results for the shorter query are still at hand.
+/// The exceptions are prefixes ending in a term that typing on can loosen,
+/// like `!f`; see [`query::narrows_as_typed`].
+/// Asking for the same query twice, as every redraw does, costs nothing.
+/// Only the pattern narrows this way; changing the query's time filters
+/// or tags starts over, though only among the commands whose characters could
+/// match, found from a [`CharSet`] of each taken once up front.
+///
+/// A search over many commands can also be done a little at a time with
+/// [`Searcher::search_for`], so that a picker can draw the matches found so
+/// far and take keys in between. Asking for another query drops the search
+/// under way at the next chunk of [`SEARCH_CHUNK`] commands.
+pub struct Searcher<'a> {
+    /// The commands `filter` allows, with the characters each could match.
+    candidates: Vec<(&'a str, &'a CommandStats, CharSet)>,
+    case: CaseMode,
+    ranking: &'a Ranking,
+    filter: Filter,
+    /// The words of every command, when searching by argument.
+    index: Option<words::Index<'a>>,
+    /// The time filters and tags of the current query.
+    range: Range,
+    tags: Vec<String>,
+    /// The results of each pattern typed on the way to the current one,
+    /// shortest first.
+    levels: Vec<(String, Vec<(&'a str, &'a CommandStats)>)>,
+    /// The search for the next level, while it is only partly done.
+    running: Option<Running<'a>>,
+}
+
+/// How many commands a search scores between looking at the time, on each
+/// core it runs on.
+pub const SEARCH_CHUNK: usize = 8_192;
+
+/// A search [`Searcher::search_for`] ran out of time for.
+struct Running<'a> {
+    query: String,
+    /// How long has been spent on it.
+    took: Duration,
+    matcher: query::Query,
+    /// The commands to score, and how many of them have been.
+    candidates: Vec<(&'a str, &'a CommandStats)>,
+    done: usize,
+    /// Whether there are enough of them to score on every core, as
+    /// [`rank_matches`] would, though each chunk alone is fewer.
+    parallel: bool,
+    scored: Vec<(f64, &'a str, &'a CommandStats)>,
+    /// The matches found so far, in order.
+    partial: Vec<(&'a str, &'a CommandStats)>,
+}
+
+impl<'a> Searcher<'a> {
+    /// Starts searching the commands of `frequency` that `filter` allows,
+    /// treating case per `case` and ordering results per `ranking`.
+    pub fn new(
+        frequency: &'a FrequencyMap,
+        case: CaseMode,
+        ranking: &'a Ranking,
+        filter: Filter,
+    ) -> Self {
+        let index = filter
+            .arguments
+            .then(|| words::Index::new(frequency.keys().map(String::as_str)));
+        let candidates = frequency
+            .iter()
+            .filter(|(_, stats)| filter.allows(stats))
+            .map(|(cmd, stats)| {
+                // Snippets also match by name, catalog commands by
+                // description.
+                let name = stats.alias().map(CharSet::of);
+                let chars = CharSet::of(cmd).union(name.unwrap_or_default());
+                (cmd.as_str(), stats, chars)
+            })
+            .collect();
+        Searcher {
+            candidates,
+            case,
+            ranking,
+            filter,
+            index,
+            range: Range::default(),
+            tags: Vec::new(),
+            levels: Vec::new(),
+            running: None,
+        }
+    }
+
+    /// The commands matching `query`, in the same order as [`search`].
+    pub fn search(&mut self, query: &str) -> &[(&'a str, &'a CommandStats)] {
+        self.advance(query, None);
+        self.results()
+    }
+
+    /// Searches for `query` for up to about `time`, going on from where the
+    /// last call left off if it was for the same query, and returns whether
+    /// the search is done. [`Searcher::results`] then holds the matches,
+    /// or those found so far.
+    pub fn search_for(&mut self, query: &str, time: Duration) -> bool {
+        self.advance(query, Instant::now().checked_add(time))
+    }
+
+    /// The matches of the latest search, or of as much of it as is done.
+    pub fn results(&self) -> &[(&'a str, &'a CommandStats)] {
+        match &self.running {
+            Some(running) => &running.partial,
+            None => self
+                .levels
+                .last()
+                .map(|(_, matches)| matches.as_slice())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Searches for `query` until done or past `deadline`.
+    fn advance(&mut self, query: &str, deadline: Option<Instant>) -> bool {
+        let (range, query) = time::split_filters(query);
+        let (tags, query) = query::split_tags(&query);
+        if range != self.range || tags != self.tags {
+            self.range = range;
+            self.tags = tags;
+            self.levels.clear();
+            self.running = None;
+        }
+        if self
+            .running
+            .as_ref()
+            .is_some_and(|running| running.query != query)
+        {
+            self.running = None;
+        }
+        while let Some((previous, _)) = self.levels.last() {
+            if *previous == query
+                || (query.starts_with(previous.as_str()) && query::narrows_as_typed(previous))
+            {
+                break;
+            }
+            self.levels.pop();
+        }
+
+        let is_cached = matches!(self.levels.last(), Some((previous, _)) if *previous == query);
+        if is_cached {
+            return true;
+        }
+        let mut running = match self.running.take() {
+            Some(running) => running,
+            None => {
+                let matcher = self.case.matcher(&query);
+                let required = matcher.chars();
+                let candidates = match self.levels.last() {
+                    Some((_, broader)) => broader.clone(),
+                    None => self
+                        .candidates
+                        .iter()
+                        .filter(|(_, stats, chars)| {
+                            chars.contains_all(required)
+                                && stats.ran_in(range)
+                                && query::has_tags(&stats.tags, &self.tags)
+                        })
+                        .map(|&(cmd, stats, _)| (cmd, stats))
+                        .collect(),
+                };
+                Running {
+                    query: query.to_string(),
+                    took: Duration::ZERO,
+                    matcher,
+                    parallel: candidates.len() >= self.ranking.parallel_threshold,
+                    candidates,
+                    done: 0,
+                    scored: Vec::new(),
+                    partial: Vec::new(),
+                }
+            }
+        };
+        let started = Instant::now();
+        let chunk = match running.parallel {
+            true => SEARCH_CHUNK * rayon::current_num_threads(),
+            false => SEARCH_CHUNK,
+        };
+        loop {
+            let end = (running.done + chunk).min(running.candidates.len());
+            let scored = score_all(
+                &running.candidates[running.done..end],
+                &running.matcher,
+                self.ranking,
+                self.index.as_ref(),
+                running.parallel,
+            );
+            running.scored.extend(scored);
+            running.done = end;
+            let finished = running.done == running.candidates.len();
+            if finished || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                order(
+                    &mut running.scored,
+                    &running.matcher,
+                    self.ranking,
+                    self.filter.session,
+                );
+                let matches = running.scored.iter().map(|&(_, cmd, stats)| (cmd, stats));
+                running.took += started.elapsed();
+                if finished {
+                    tracing::debug!(
+                        commands = running.candidates.len(),
+                        matches = running.scored.len(),
+                        query_len = running.query.len(),
+                        parallel = running.parallel,
+                        took = ?running.took,
+                        "searched"
+                    );
+                    self.levels.push((running.query, matches.collect()));
+                    return true;
+                }
+                running.partial = matches.collect();
+                self.running = Some(running);
+                return false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_in_chunks_on_every_core_past_the_threshold() {
+        // More than one chunk takes, on however many cores.
+        let commands = SEARCH_CHUNK * rayon::current_num_threads() * 2;
+        let frequency: FrequencyMap = (0..commands)
+            .map(|i| {
+                let stats = CommandStats {
+                    count: i % 7 + 1,
+                    ..CommandStats::default()
+                };
+                (format!("git checkout branch-{i}"), stats)
+            })
+            .collect();
+        let ranking = Ranking {
+            parallel_threshold: SEARCH_CHUNK * 2,
+            ..Ranking::default()
+        };
+        let mut searcher = Searcher::new(&frequency, CaseMode::Smart, &ranking, Filter::default());
+        assert!(!searcher.search_for("gco", Duration::ZERO));
+        let running = searcher.running.as_ref().expect("a search under way");
+        assert!(running.done < running.candidates.len());
+        assert!(running.parallel);
+
+        let mut searcher = Searcher::new(&frequency, CaseMode::Smart, &ranking, Filter::default());
+        let found = searcher.search("gco").to_vec();
+        let sequential = Ranking {
+            parallel_threshold: usize::MAX,
+            ..Ranking::default()
+        };
+        let expected = search(
+            &frequency,
+            "gco",
+            CaseMode::Smart,
+            &sequential,
+            &Filter::default(),
+        );
+        assert_eq!(found.len(), frequency.len());
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn counts_runs_by_day_in_bounded_space() {
+        const DAY: i64 = 86_400;
+        let now = 1000 * DAY + 3600;
+        // A run a day for a thousand days, then two today.
+        let mut runs: Runs = (0..1000).map(|day| day * DAY + 60).collect();
+        runs.extend([now, now]);
+        assert_eq!(runs.len(), 1002);
+        assert_eq!((runs.first(), runs.last()), (Some(60), Some(now)));
+        assert_eq!(runs.days.len(), RUN_DAYS);
+        assert_eq!(runs.per_day(3, now), [1, 1, 2]);
+
+        let mut earlier: Runs = [-DAY].into_iter().collect();
+        earlier.merge(&runs);
+        assert_eq!((earlier.len(), earlier.first()), (1003, Some(-DAY)));
+        assert_eq!(earlier.per_day(3, now), [1, 1, 2]);
+
+        let since = |since, until| Range {
+            since: Some(since),
+            until,
+        };
+        assert!(runs.overlap(since(now - 2 * DAY, None)));
+        assert!(!runs.overlap(since(now + DAY, None)));
+        assert!(!runs.overlap(Range {
+            since: None,
+            until: Some(0),
+        }));
+        // Within the days counted, a day without runs is not run in.
+        let sparse: Runs = [990 * DAY, 1000 * DAY].into_iter().collect();
+        assert!(!sparse.overlap(since(995 * DAY, Some(996 * DAY))));
+        assert!(sparse.overlap(since(990 * DAY, Some(991 * DAY))));
+    }
+
+    #[test]
+    fn decays_frecency_from_when_it_was_counted() {
+        let ranking = Ranking::default();
+        let now = rank::now();
+        let (then, ran) = (now - 10 * 86_400, now - 40 * 86_400);
+        let stats = CommandStats {
+            count: 3,
+            frecency: 2.0 * ranking.run_weight(Some(ran), then) + 1.0,
+            runs: [ran, ran].into_iter().collect(),
+            ..CommandStats::default()
+        };
+        let mut frequency: FrequencyMap = [("make".to_string(), stats)].into_iter().collect();
+        decay(&mut frequency, &ranking, then);
+        let expected = 2.0 * ranking.run_weight(Some(ran), now) + 1.0;
+        assert!((frequency["make"].frecency - expected).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,476 @@
+//! fzf-style fuzzy matching.
+//!
+//! A query matches a candidate when its characters appear in the candidate in
+//! order, not necessarily adjacent (`gcm` matches `git commit -m`). Among the
+//! possible alignments the best-scoring one is chosen with a Smith-Waterman
+//! style dynamic program: every matched character scores a base amount, gaps
+//! between matched characters are penalised, and characters that start a
+//! word or continue a consecutive run earn bonuses.
+
+use std::cell::RefCell;
+
+const SCORE_MATCH: i64 = 16;
+const GAP_START: i64 = -3;
+const GAP_EXTENSION: i64 = -1;
+
+/// Bonus for a match directly after whitespace (start of a word).
+const BONUS_BOUNDARY_WHITE: i64 = SCORE_MATCH / 2 + 2;
+/// Bonus for a match directly after a path or list delimiter.
+const BONUS_BOUNDARY_DELIMITER: i64 = SCORE_MATCH / 2 + 1;
+/// Bonus for a match directly after any other non-word character.
+const BONUS_BOUNDARY: i64 = SCORE_MATCH / 2;
+/// Bonus for a camelCase hump or a letter-to-digit transition.
+const BONUS_CAMEL_123: i64 = BONUS_BOUNDARY + GAP_EXTENSION;
+/// Minimum bonus for a match that extends a consecutive run.
+const BONUS_CONSECUTIVE: i64 = -(GAP_START + GAP_EXTENSION);
+/// The first query character's bonus counts double, so queries anchor on
+/// word starts.
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+const UNMATCHED: i64 = i64::MIN / 2;
+
+/// The buffers [`Matcher::align`] fills in, kept from one call to the next
+/// since it runs for every candidate at every keystroke.
+#[derive(Default)]
+struct Scratch {
+    candidate: Vec<char>,
+    folded: Vec<char>,
+    bonus: Vec<i64>,
+    prev: Vec<i64>,
+    cur: Vec<i64>,
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::default();
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    White,
+    Delimiter,
+    NonWord,
+    Lower,
+    Upper,
+    Number,
+}
+
+fn class_of(c: char) -> CharClass {
+    if c.is_ascii() {
+        return match c {
+            'a'..='z' => CharClass::Lower,
+            'A'..='Z' => CharClass::Upper,
+            '0'..='9' => CharClass::Number,
+            '/' | ',' | ':' | ';' | '|' => CharClass::Delimiter,
+            _ if c.is_ascii_whitespace() || c == '\x0b' => CharClass::White,
+            _ => CharClass::NonWord,
+        };
+    }
+    if c.is_whitespace() {
+        CharClass::White
+    } else if matches!(c, '/' | ',' | ':' | ';' | '|') {
+        CharClass::Delimiter
+    } else if c.is_lowercase() {
+        CharClass::Lower
+    } else if c.is_uppercase() {
+        CharClass::Upper
+    } else if c.is_numeric() {
+        CharClass::Number
+    } else if c.is_alphabetic() {
+        // Letters without case (CJK and friends) behave like lowercase.
+        CharClass::Lower
+    } else {
+        CharClass::NonWord
+    }
+}
+
+/// The bonus for matching a character of class `class` preceded by `prev`.
+fn bonus_for(prev: CharClass, class: CharClass) -> i64 {
+    use CharClass::*;
+    match (prev, class) {
+        (_, White | Delimiter | NonWord) => 0,
+        (White, _) => BONUS_BOUNDARY_WHITE,
+        (Delimiter, _) => BONUS_BOUNDARY_DELIMITER,
+        (NonWord, _) => BONUS_BOUNDARY,
+        (Lower, Upper) | (Lower | Upper, Number) => BONUS_CAMEL_123,
+        _ => 0,
+    }
+}
+
+fn fold(c: char) -> char {
+    if c.is_ascii() {
+        c.to_ascii_lowercase()
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+/// The letters, digits, and common punctuation in a text, folded to
+/// lowercase, as one bit each. A pattern whose set is not contained in a
+/// candidate's cannot match it, which is much cheaper to find out than by
+/// matching; other characters are left out and never rule anything out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CharSet(u64);
+
+impl CharSet {
+    pub fn of(text: &str) -> CharSet {
+        let mut set = 0;
+        for c in text.chars() {
+            // Some non-ASCII letters fold to ASCII ones, like the Kelvin sign
+            // to `k`, and so match them.
+            let c = if c.is_ascii() { c } else { fold(c) };
+            if let Some(bit) = CharSet::bit(c) {
+                set |= 1 << bit;
+            }
+        }
+        CharSet(set)
+    }
+
+    fn bit(c: char) -> Option<u32> {
+        const PUNCTUATION: &str = "-_./:=$@~'\"|&;<>()[]{}*?!#%+,";
+        match c {
+            'a'..='z' => Some(c as u32 - 'a' as u32),
+            'A'..='Z' => Some(c as u32 - 'A' as u32),
+            '0'..='9' => Some(26 + c as u32 - '0' as u32),
+            _ => PUNCTUATION.find(c).map(|at| 36 + at as u32),
+        }
+    }
+
+    pub fn union(self, other: CharSet) -> CharSet {
+        CharSet(self.0 | other.0)
+    }
+
+    /// Whether every character of `other` is in this set.
+    pub fn contains_all(self, other: CharSet) -> bool {
+        other.0 & !self.0 == 0
+    }
+}
+
+/// Where a pattern matched as a substring must sit in the candidate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Anywhere,
+    Start,
+    End,
+    /// The whole candidate.
+    Both,
+}
+
+/// A successful fuzzy match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// How good the match is; higher is better.
+    pub score: i64,
+    /// The char index in the candidate of each matched query character.
+    pub positions: Vec<usize>,
+}
+
+/// A query prepared for matching against many candidates.
+///
+/// Build one per query (i.e. per keystroke) and reuse it for every
+/// candidate, so the query is only decoded and case-folded once.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    pattern: Vec<char>,
+    case_sensitive: bool,
+}
+
+impl Matcher {
+    /// Prepares `pattern`. When `case_sensitive` is false, letters match
+    /// regardless of case.
+    pub fn new(pattern: &str, case_sensitive: bool) -> Matcher {
+        let pattern = if case_sensitive {
+            pattern.chars().collect()
+        } else {
+            pattern.chars().map(fold).collect()
+        };
+        Matcher {
+            pattern,
+            case_sensitive,
+        }
+    }
+
+    /// Scores `candidate` against the pattern.
+    ///
+    /// Returns `None` when the pattern is not a subsequence of `candidate`.
+    /// Higher scores are better matches; an empty pattern matches everything
+    /// with a score of zero.
+    pub fn score(&self, candidate: &str) -> Option<i64> {
+        self.align(candidate, false).map(|m| m.score)
+    }
+
+    /// Like [`Matcher::score`], but also reports which characters of
+    /// `candidate` the best alignment matched, e.g. for highlighting. This
+    /// keeps the whole alignment table, so rank with [`Matcher::score`] and
+    /// call this only for the candidates actually shown.
+    pub fn fuzzy_match(&self, candidate: &str) -> Option<FuzzyMatch> {
+        self.align(candidate, true)
+    }
+
+    /// Matches the pattern as one unbroken run of characters in
+    /// `candidate`, placed per `anchor`, scoring it as the fuzzy alignment
+    /// of the same run would be. Of several places it could match, the
+    /// best-scoring is chosen.
+    pub fn substring(&self, candidate: &str, anchor: Anchor) -> Option<FuzzyMatch> {
+        let pattern = &self.pattern;
+        if pattern.is_empty() {
+            return Some(FuzzyMatch {
+                score: 0,
+                positions: Vec::new(),
+            });
+        }
+        let candidate: Vec<char> = candidate.chars().collect();
+        let n = candidate.len();
+        let m = pattern.len();
+        if m > n {
+            return None;
+        }
+        let starts = match anchor {
+            Anchor::Anywhere => 0..n - m + 1,
+            Anchor::Start => 0..1,
+            Anchor::End => n - m..n - m + 1,
+            Anchor::Both if m == n => 0..1,
+            Anchor::Both => return None,
+        };
+        starts
+            .filter(|&start| {
+                candidate[start..start + m]
+                    .iter()
+                    .zip(pattern)
+                    .all(|(&c, &p)| self.fold(c) == p)
+            })
+            .map(|start| {
+                let mut prev_class = match start {
+                    0 => CharClass::White,
+                    _ => class_of(candidate[start - 1]),
+                };
+                let mut score = 0;
+                for (k, &c) in candidate[start..start + m].iter().enumerate() {
+                    let class = class_of(c);
+                    let bonus = bonus_for(prev_class, class);
+                    prev_class = class;
+                    score += SCORE_MATCH
+                        + match k {
+                            0 => bonus * BONUS_FIRST_CHAR_MULTIPLIER,
+                            _ => bonus.max(BONUS_CONSECUTIVE),
+                        };
+                }
+                (start, score)
+            })
+            .max_by_key(|&(start, score)| (score, std::cmp::Reverse(start)))
+            .map(|(start, score)| FuzzyMatch {
+                score,
+                positions: (start..start + m).collect(),
+            })
+    }
+
+    /// The characters a candidate needs to match.
+    pub fn chars(&self) -> CharSet {
+        CharSet::of(&self.pattern.iter().collect::<String>())
+    }
+
+    fn fold(&self, c: char) -> char {
+        if self.case_sensitive {
+            c
+        } else {
+            fold(c)
+        }
+    }
+
+    /// Whether the pattern is a subsequence of `candidate`, checked without
+    /// allocating. Most candidates fail this, and skip the alignment pass.
+    fn is_subsequence(&self, candidate: &str) -> bool {
+        let mut remaining = self.pattern.iter().peekable();
+        for c in candidate.chars() {
+            match remaining.peek() {
+                Some(&&p) if self.fold(c) == p => {
+                    remaining.next();
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        remaining.peek().is_none()
+    }
+
+    fn align(&self, candidate: &str, track_positions: bool) -> Option<FuzzyMatch> {
+        let pattern = &self.pattern;
+        if pattern.is_empty() {
+            return Some(FuzzyMatch {
+                score: 0,
+                positions: Vec::new(),
+            });
+        }
+        if !self.is_subsequence(candidate) {
+            return None;
+        }
+        SCRATCH.with_borrow_mut(|scratch| self.align_in(scratch, candidate, track_positions))
+    }
+
+    fn align_in(
+        &self,
+        scratch: &mut Scratch,
+        candidate: &str,
+        track_positions: bool,
+    ) -> Option<FuzzyMatch> {
+        let pattern = &self.pattern;
+        let Scratch {
+            candidate: chars,
+            folded,
+            bonus,
+            prev,
+            cur,
+        } = scratch;
+        chars.clear();
+        chars.extend(candidate.chars());
+        folded.clear();
+        folded.extend(chars.iter().map(|&c| self.fold(c)));
+
+        let mut prev_class = CharClass::White;
+        bonus.clear();
+        bonus.extend(chars.iter().map(|&c| {
+            let class = class_of(c);
+            let bonus = bonus_for(prev_class, class);
+            prev_class = class;
+            bonus
+        }));
+
+        // `prev[j]` is the best score for the previous query character ending
+        // at candidate position `j`; `cur` is the same for the current one.
+        // When positions are wanted, `origins[i][j]` remembers where query
+        // character `i - 1` sat in the best alignment ending with `i` at `j`.
+        let n = chars.len();
+        prev.clear();
+        prev.resize(n, UNMATCHED);
+        cur.clear();
+        cur.resize(n, UNMATCHED);
+        let mut origins: Vec<Vec<usize>> = Vec::new();
+        // Just past where the previous query character first matched. No
+        // character matches before its first match, so each row starts
+        // there, or at its own first match for the first.
+        let mut from = 0;
+        for (i, &p) in pattern.iter().enumerate() {
+            let first = from + folded[from..].iter().position(|&c| c == p)?;
+            let start = if i == 0 { first } else { from };
+            from = first + 1;
+            cur[..start].fill(UNMATCHED);
+            let mut origin = if track_positions && i > 0 {
+                vec![0; n]
+            } else {
+                Vec::new()
+            };
+            // Best score of an earlier alignment that would reach `j` through a
+            // gap, and where that alignment ended.
+            let mut gap = UNMATCHED;
+            let mut gap_from = 0;
+            for j in start..n {
+                if j >= 2 {
+                    let extended = gap + GAP_EXTENSION;
+                    let opened = prev[j - 2] + GAP_START;
+                    if opened >= extended {
+                        gap = opened;
+                        gap_from = j - 2;
+                    } else {
+                        gap = extended;
+                    }
+                }
+                cur[j] = UNMATCHED;
+                if folded[j] != p {
+                    continue;
+                }
+                if i == 0 {
+                    cur[j] = SCORE_MATCH + bonus[j] * BONUS_FIRST_CHAR_MULTIPLIER;
+                    continue;
+                }
+                let consecutive = if j >= 1 && prev[j - 1] > UNMATCHED {
+                    prev[j - 1] + SCORE_MATCH + bonus[j].max(BONUS_CONSECUTIVE)
+                } else {
+                    UNMATCHED
+                };
+                let gapped = if gap > UNMATCHED {
+                    gap + SCORE_MATCH + bonus[j]
+                } else {
+                    UNMATCHED
+                };
+                cur[j] = consecutive.max(gapped);
+                if track_positions && cur[j] > UNMATCHED {
+                    origin[j] = if consecutive >= gapped {
+                        j - 1
+                    } else {
+                        gap_from
+                    };
+                }
+            }
+            origins.push(origin);
+            std::mem::swap(prev, cur);
+        }
+
+        let (end, score) = prev
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, s)| s > UNMATCHED)
+            .max_by_key(|&(_, s)| s)?;
+
+        let mut positions = Vec::new();
+        if track_positions {
+            positions.resize(pattern.len(), 0);
+            let mut j = end;
+            for i in (0..pattern.len()).rev() {
+                positions[i] = j;
+                if i > 0 {
+                    j = origins[i][j];
+                }
+            }
+        }
+        Some(FuzzyMatch { score, positions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(pattern: &str, candidate: &str) -> Option<i64> {
+        Matcher::new(pattern, false).score(candidate)
+    }
+
+    fn positions(pattern: &str, candidate: &str) -> Vec<usize> {
+        let matched = Matcher::new(pattern, false).fuzzy_match(candidate);
+        matched.map(|m| m.positions).unwrap_or_default()
+    }
+
+    #[test]
+    fn matches_subsequences_in_order() {
+        assert!(score("gcm", "git commit -m").is_some());
+        assert_eq!(score("gmc", "git commit"), None);
+        assert_eq!(score("gcx", "git commit -m"), None);
+        assert_eq!(score("", "anything"), Some(0));
+        assert_eq!(Matcher::new("Git", true).score("git status"), None);
+        assert!(Matcher::new("Git", false).score("git status").is_some());
+    }
+
+    #[test]
+    fn prefers_word_starts_and_consecutive_runs() {
+        // After whitespace, a delimiter, or a camelCase hump, over mid-word.
+        assert!(score("b", "a b") > score("b", "ab"));
+        assert!(score("b", "a/b") > score("b", "ab"));
+        assert!(score("b", "aB") > score("b", "ab"));
+        assert!(score("b", "a b") > score("b", "a/b"));
+        // A run of matches over the same characters spread out.
+        assert!(score("abc", "xabcx") > score("abc", "xaxbxcx"));
+        // The first character's bonus counts double.
+        assert!(score("gc", "git commit") > score("gc", "agit commit"));
+    }
+
+    #[test]
+    fn reports_where_the_best_alignment_matched() {
+        // `-m` starts a word, unlike the `m`s of `commit`.
+        assert_eq!(positions("gcm", "git commit -m"), [0, 4, 12]);
+        // The first `st` is mid-word; the second starts one and runs on.
+        assert_eq!(positions("st", "best status"), [5, 6]);
+        let matched = Matcher::new("st", false)
+            .fuzzy_match("best status")
+            .unwrap();
+        assert_eq!(Some(matched.score), score("st", "best status"));
+    }
+}
@@ -0,0 +1,278 @@
+//! The query language, after fzf's.
+//!
+//! A query is split at blanks into terms, and a command matches when every
+//! term does. A bare term matches fuzzily; the others match a substring:
+//!
+//! | Term     | Matches commands                  |
+//! |----------|-----------------------------------|
+//! | `sbtrkt` | fuzzily matching `sbtrkt`         |
+//! | `'wild`  | containing `wild`                 |
+//! | `^music` | starting with `music`             |
+//! | `.mp3$`  | ending with `.mp3`                |
+//! | `^ls$`   | that are exactly `ls`             |
+//! | `!fire`  | not containing `fire`             |
+//! | `!^git`  | not starting with `git`           |
+//! | `!.md$`  | not ending with `.md`             |
+//! | `#dep`   | with a tag starting with `dep`    |
+//!
+//! A command's score is the sum of its matching terms' scores. A term of
+//! nothing but operators, like a lone `!` while the rest is being typed,
+//! matches everything. Tags are not matched against the command itself;
+//! see [`split_tags`]. To find a `#` in a command, write `'#`.
+
+use super::fuzzy::{Anchor, CharSet, FuzzyMatch, Matcher};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+/// One term of a query.
+#[derive(Debug, Clone)]
+struct Term {
+    matcher: Matcher,
+    /// How the term matches: fuzzily, or as a substring placed so.
+    anchor: Option<Anchor>,
+    /// Whether commands matching the term are left out.
+    inverse: bool,
+}
+
+impl Term {
+    fn parse(text: &str, case_sensitive: bool) -> Option<Term> {
+        let (inverse, text) = match text.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (exact, text) = match text.strip_prefix('\'') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (start, text) = match text.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (end, text) = match text.strip_suffix('$') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let anchor = match (start, end) {
+            (true, true) => Some(Anchor::Both),
+            (true, false) => Some(Anchor::Start),
+            (false, true) => Some(Anchor::End),
+            // As in fzf, leaving matches out is never fuzzy.
+            (false, false) if exact || inverse => Some(Anchor::Anywhere),
+            (false, false) => None,
+        };
+        (!text.is_empty()).then(|| Term {
+            matcher: Matcher::new(text, case_sensitive),
+            anchor,
+            inverse,
+        })
+    }
+
+    fn matches(&self, candidate: &str, track_positions: bool) -> Option<FuzzyMatch> {
+        match (self.anchor, track_positions) {
+            (Some(anchor), _) => self.matcher.substring(candidate, anchor),
+            (None, true) => self.matcher.fuzzy_match(candidate),
+            (None, false) => self.matcher.score(candidate).map(|score| FuzzyMatch {
+                score,
+                positions: Vec::new(),
+            }),
+        }
+    }
+}
+
+/// A query prepared for matching against many candidates, like
+/// [`Matcher`] for a single fuzzy pattern.
+#[derive(Debug, Clone)]
+pub struct Query {
+    text: String,
+    terms: Vec<Term>,
+}
+
+impl Query {
+    /// Parses `query`. When `case_sensitive` is false, letters match
+    /// regardless of case.
+    pub fn new(query: &str, case_sensitive: bool) -> Query {
+        Query {
+            text: query.to_string(),
+            terms: query
+                .split_whitespace()
+                .filter_map(|text| Term::parse(text, case_sensitive))
+                .collect(),
+        }
+    }
+
+    /// The query as typed.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether nothing was typed to match, so that everything does.
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The characters a candidate needs to match, for ruling out most
+    /// candidates at once; see [`CharSet`].
+    pub fn chars(&self) -> CharSet {
+        self.terms
+            .iter()
+            .filter(|term| !term.inverse)
+            .fold(CharSet::default(), |all, term| {
+                all.union(term.matcher.chars())
+            })
+    }
+
+    /// Scores `candidate` against every term; `None` when one does not
+    /// match it. An empty query matches everything with a score of zero.
+    pub fn score(&self, candidate: &str) -> Option<i64> {
+        self.matches(candidate, false).map(|m| m.score)
+    }
+
+    /// Like [`Query::score`], but also reports which characters of
+    /// `candidate` the terms matched, in order, for highlighting.
+    pub fn fuzzy_match(&self, candidate: &str) -> Option<FuzzyMatch> {
+        self.matches(candidate, true)
+    }
+
+    fn matches(&self, candidate: &str, track_positions: bool) -> Option<FuzzyMatch> {
+        let mut found = FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        };
+        for term in &self.terms {
+            let matched = term.matches(candidate, track_positions);
+            match (matched, term.inverse) {
+                (Some(matched), false) => {
+                    found.score += matched.score;
+                    found.positions.extend(matched.positions);
+                }
+                (None, true) => {}
+                _ => return None,
+            }
+        }
+        found.positions.sort_unstable();
+        found.positions.dedup();
+        Some(found)
+    }
+}
+
+/// Whether every command matching `query` with more typed after it also
+/// matches `query` itself. Typing on usually narrows the results, but not
+/// while the last term leaves commands out or is anchored at the end:
+/// `!fo` leaves out fewer commands than `!f`.
+pub fn narrows_as_typed(query: &str) -> bool {
+    if query.ends_with(char::is_whitespace) {
+        return true;
+    }
+    query
+        .split_whitespace()
+        .next_back()
+        .is_none_or(|last| !last.starts_with('!') && !last.ends_with('$'))
+}
+
+/// Splits a picker query into the tags it asks for, like `#deploy`,
+/// lowercased, and the pattern left to match. A lone `#` stays in the
+/// pattern, as does everything when no tags are asked for.
+pub fn split_tags(query: &str) -> (Vec<String>, Cow<'_, str>) {
+    let mut tags = Vec::new();
+    let mut words = Vec::new();
+    for word in query.split(' ') {
+        match word.strip_prefix('#').filter(|tag| !tag.is_empty()) {
+            Some(tag) => tags.push(tag.to_lowercase()),
+            None => words.push(word),
+        }
+    }
+    if tags.is_empty() {
+        return (tags, Cow::Borrowed(query));
+    }
+    let pattern: Vec<&str> = words.into_iter().filter(|w| !w.is_empty()).collect();
+    (tags, Cow::Owned(pattern.join(" ")))
+}
+
+/// Whether a command tagged `tags` has a tag starting with each of
+/// `wanted`, as [`split_tags`] gives them, ignoring case.
+pub fn has_tags(tags: &BTreeSet<String>, wanted: &[String]) -> bool {
+    wanted.iter().all(|wanted| {
+        tags.iter()
+            .any(|tag| tag.to_lowercase().starts_with(wanted.as_str()))
+    })
+}
+
+/// Whether `tag` can be a tag: not blank, without spaces, and not starting
+/// with `#`, which asks for tags in a query.
+pub fn is_tag(tag: &str) -> bool {
+    !tag.is_empty() && !tag.contains(char::is_whitespace) && !tag.starts_with('#')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matching<'a>(query: &str, commands: &[&'a str]) -> Vec<&'a str> {
+        let query = Query::new(query, false);
+        commands
+            .iter()
+            .copied()
+            .filter(|command| query.score(command).is_some())
+            .collect()
+    }
+
+    const COMMANDS: [&str; 5] = [
+        "git push origin main",
+        "git pull",
+        "cargo build --release",
+        "ls -la",
+        "ls",
+    ];
+
+    #[test]
+    fn terms_all_have_to_match() {
+        assert_eq!(matching("git ori", &COMMANDS), ["git push origin main"]);
+        assert_eq!(matching("gpl", &COMMANDS), ["git pull"]);
+        assert_eq!(matching("", &COMMANDS), COMMANDS);
+    }
+
+    #[test]
+    fn operators_match_substrings_anchors_and_exclusions() {
+        assert_eq!(
+            matching("'pu", &COMMANDS),
+            ["git push origin main", "git pull"]
+        );
+        assert_eq!(matching("'gpl", &COMMANDS), Vec::<&str>::new());
+        assert_eq!(matching("^ls", &COMMANDS), ["ls -la", "ls"]);
+        assert_eq!(matching("^ls$", &COMMANDS), ["ls"]);
+        assert_eq!(matching("main$ git", &COMMANDS), ["git push origin main"]);
+        assert_eq!(matching("!git !^ls", &COMMANDS), ["cargo build --release"]);
+        assert_eq!(
+            matching("git !", &COMMANDS),
+            ["git push origin main", "git pull"]
+        );
+    }
+
+    #[test]
+    fn highlights_every_term() {
+        let found = Query::new("^git 'pull", false)
+            .fuzzy_match("git pull")
+            .unwrap();
+        assert_eq!(found.positions, [0, 1, 2, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn typing_on_narrows_unless_the_last_term_excludes_or_ends() {
+        assert!(narrows_as_typed("git pu"));
+        assert!(narrows_as_typed("!git "));
+        assert!(!narrows_as_typed("git !pu"));
+        assert!(!narrows_as_typed("main$"));
+    }
+
+    #[test]
+    fn tags_are_split_off_and_match_by_prefix() {
+        let (tags, pattern) = split_tags("#Dep kubectl # roll");
+        assert_eq!(tags, ["dep"]);
+        assert_eq!(pattern, "kubectl # roll");
+        assert_eq!(split_tags("git log").1, "git log");
+        let tagged = BTreeSet::from(["deploy".to_string(), "k8s".to_string()]);
+        assert!(has_tags(&tagged, &tags));
+        assert!(!has_tags(&tagged, &["dep".to_string(), "prod".to_string()]));
+        assert!(has_tags(&BTreeSet::new(), &[]));
+    }
+}
@@ -0,0 +1,301 @@
+//! Frecency: ranking by frequency decayed with age.
+//!
+//! Every run of a command contributes a weight that halves every
+//! `half_life_days`, so a command run a few times this week can outrank one
+//! run thousands of times years ago. Runs without a timestamp (plain bash
+//! history) always weigh 1, which makes frecency equal to the raw count.
+//!
+//! Commands once run anywhere in the git repository th_rs is started in also
+//! get a fixed boost, when the store knows where they ran, and so do those
+//! the project th_rs is started in ships; see [`crate::history::project`].
+//!
+//! A command's score is a weighted sum of these and a few more parts, each
+//! weight set in the config file; see [`Score`]. The picker shows each
+//! result's parts with Ctrl-G, for tuning them.
+//!
+//! One part is learned rather than counted from history: the store notes
+//! which command was chosen for each query, and a command chosen before for
+//! what is being typed now ranks higher the more often it was; see
+//! [`Picks`].
+
+use super::CommandStats;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// How many characters of a query picks are remembered by. Queries that
+/// agree this far count as one.
+const PICK_PREFIX_CHARS: usize = 12;
+
+/// How results are ordered before anything is typed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyQuery {
+    /// By score, as for any query, which leaves the most frecent first.
+    #[default]
+    Frecency,
+    /// The last run first, as the shell's own Ctrl-R has them.
+    Recent,
+}
+
+/// Ranking weights, set in the config file's `[ranking]` section.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Ranking {
+    /// Age in days at which a run counts half as much as one from right now.
+    /// Zero turns decay off, ranking by raw frequency.
+    pub half_life_days: f64,
+    /// What the match quality is multiplied by. The fuzzy score of a single
+    /// well-placed character is about 16 to 26.
+    pub match_weight: f64,
+    /// How much frecency counts compared to match quality: it adds
+    /// `frecency_weight * ln(1 + frecency)`.
+    pub frecency_weight: f64,
+    /// How much the plain run count counts, whatever the runs' age: it adds
+    /// `frequency_weight * ln(1 + count)`.
+    pub frequency_weight: f64,
+    /// What a command last run just now adds, halving with age per
+    /// `half_life_days`.
+    pub recency_weight: f64,
+    /// What a command run in the current repository adds to its score.
+    pub repo_boost: f64,
+    /// What a command once run in the current directory adds.
+    pub cwd_boost: f64,
+    /// What a command in the current project's `.th_rs_history` adds.
+    pub project_boost: f64,
+    /// What each character of a command takes off, to favor short ones.
+    pub length_penalty: f64,
+    /// How much having chosen a command for the query before counts: it
+    /// adds `pick_weight * ln(1 + times chosen)`.
+    pub pick_weight: f64,
+    /// How results are ordered while the query is empty; once something is
+    /// typed, they are ranked by score.
+    pub empty_query: EmptyQuery,
+    /// The root of the git repository th_rs was started in, found at startup
+    /// rather than configured.
+    #[serde(skip)]
+    pub repo: Option<PathBuf>,
+    /// The directory th_rs was started in.
+    #[serde(skip)]
+    pub cwd: Option<String>,
+    /// The root of the project th_rs was started in, if it has a
+    /// `.th_rs_history`, found at startup.
+    #[serde(skip)]
+    pub project: Option<PathBuf>,
+    /// The commands chosen for each query before, read from the store.
+    #[serde(skip)]
+    pub picks: Picks,
+    /// How many commands a search has to go through before it is spread
+    /// across every core. Below this, starting the threads costs more than
+    /// it saves.
+    pub parallel_threshold: usize,
+}
+
+impl Default for Ranking {
+    fn default() -> Self {
+        Ranking {
+            half_life_days: 14.0,
+            match_weight: 1.0,
+            frecency_weight: 8.0,
+            frequency_weight: 0.0,
+            recency_weight: 0.0,
+            repo_boost: 16.0,
+            cwd_boost: 0.0,
+            project_boost: 16.0,
+            length_penalty: 0.0,
+            pick_weight: 8.0,
+            empty_query: EmptyQuery::Frecency,
+            repo: None,
+            cwd: None,
+            project: None,
+            picks: Picks::default(),
+            parallel_threshold: 50_000,
+        }
+    }
+}
+
+impl Ranking {
+    /// The weight of one run at `timestamp`, as seen at `now` (both Unix
+    /// seconds). Runs with no timestamp, or from the future, weigh 1.
+    pub fn run_weight(&self, timestamp: Option<i64>, now: i64) -> f64 {
+        match timestamp {
+            Some(timestamp) if self.half_life_days > 0.0 => {
+                let age_days = (now - timestamp).max(0) as f64 / SECONDS_PER_DAY;
+                0.5f64.powf(age_days / self.half_life_days)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// The parts of the score of `cmd`, whose query match scored
+    /// `match_score` and which was chosen `picked` times for the query
+    /// before. Parts whose weight is zero are not worked out.
+    pub fn score(&self, match_score: i64, cmd: &str, stats: &CommandStats, picked: u32) -> Score {
+        let recency = match stats.last_used {
+            Some(_) if self.recency_weight != 0.0 => {
+                self.recency_weight * self.run_weight(stats.last_used, now())
+            }
+            _ => 0.0,
+        };
+        let in_cwd = self
+            .cwd
+            .as_ref()
+            .is_some_and(|cwd| self.cwd_boost != 0.0 && stats.dirs.contains(cwd));
+        let length = match self.length_penalty {
+            0.0 => 0.0,
+            penalty => -penalty * cmd.chars().count() as f64,
+        };
+        Score {
+            matched: self.match_weight * match_score as f64,
+            frecency: self.frecency_weight * stats.frecency.ln_1p(),
+            frequency: self.frequency_weight * (stats.count as f64).ln_1p(),
+            recency,
+            repo: if self.in_repo(&stats.dirs) {
+                self.repo_boost
+            } else {
+                0.0
+            },
+            cwd: if in_cwd { self.cwd_boost } else { 0.0 },
+            project: if self.in_project(&stats.dirs) {
+                self.project_boost
+            } else {
+                0.0
+            },
+            length,
+            picked: self.pick_weight * (picked as f64).ln_1p(),
+        }
+    }
+
+    /// Whether any of `dirs`, where a command ran, is inside the current
+    /// repository.
+    pub fn in_repo(&self, dirs: &BTreeSet<String>) -> bool {
+        self.repo
+            .as_ref()
+            .is_some_and(|repo| dirs.iter().any(|dir| Path::new(dir).starts_with(repo)))
+    }
+
+    /// Whether `dirs`, where a command ran, include the root of the current
+    /// project, where its `.th_rs_history` commands count as run.
+    pub fn in_project(&self, dirs: &BTreeSet<String>) -> bool {
+        self.project_boost != 0.0
+            && self
+                .project
+                .as_ref()
+                .is_some_and(|root| dirs.contains(root.to_string_lossy().as_ref()))
+    }
+}
+
+/// What a command scored, part by part, weighted per [`Ranking`]. Results
+/// are sorted by the [`total`](Score::total).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Score {
+    /// How well the query matched.
+    pub matched: f64,
+    pub frecency: f64,
+    pub frequency: f64,
+    pub recency: f64,
+    /// The boost for having run in the current repository.
+    pub repo: f64,
+    /// The boost for having run in the current directory.
+    pub cwd: f64,
+    /// The boost for being one of the current project's commands.
+    pub project: f64,
+    /// The penalty for length, zero or less.
+    pub length: f64,
+    /// The boost for having been chosen for the query before.
+    pub picked: f64,
+}
+
+impl Score {
+    pub fn total(&self) -> f64 {
+        self.matched
+            + self.frecency
+            + self.frequency
+            + self.recency
+            + self.repo
+            + self.cwd
+            + self.project
+            + self.length
+            + self.picked
+    }
+
+    /// The parts that count, named, in the order they are added up.
+    pub fn parts(&self) -> Vec<(&'static str, f64)> {
+        [
+            ("match", self.matched),
+            ("frecency", self.frecency),
+            ("frequency", self.frequency),
+            ("recency", self.recency),
+            ("repo", self.repo),
+            ("cwd", self.cwd),
+            ("project", self.project),
+            ("length", self.length),
+            ("picked", self.picked),
+        ]
+        .into_iter()
+        .filter(|&(_, value)| value != 0.0)
+        .collect()
+    }
+}
+
+/// How often each command was chosen for each query, by the query's first
+/// few characters: a command chosen after typing `git pu` was chosen for
+/// `g`, `gi`, and so on up to `git pu`, since those were typed on the way.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Picks(HashMap<String, HashMap<String, u32>>);
+
+impl Picks {
+    /// What `query` is remembered by: its first few characters, in lower
+    /// case and with blanks between words as single spaces. Empty for an
+    /// empty query, which picks are not remembered for.
+    pub fn key(query: &str) -> String {
+        query
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+            .chars()
+            .take(PICK_PREFIX_CHARS)
+            .collect()
+    }
+
+    /// The keys a command chosen for `query` counts for: every prefix of
+    /// its own.
+    pub fn prefixes(query: &str) -> Vec<String> {
+        let key = Picks::key(query);
+        key.char_indices()
+            .map(|(at, c)| key[..at + c.len_utf8()].to_string())
+            .filter(|prefix| !prefix.ends_with(' '))
+            .collect()
+    }
+
+    /// Notes that `command` was chosen `count` times for queries starting
+    /// with the key `prefix`.
+    pub fn insert(&mut self, prefix: String, command: String, count: u32) {
+        self.0.entry(prefix).or_default().insert(command, count);
+    }
+
+    /// How often each command was chosen for `query`, if any was.
+    pub fn for_query(&self, query: &str) -> Option<&HashMap<String, u32>> {
+        self.0.get(&Picks::key(query))
+    }
+}
+
+/// The root of the git repository `dir` is in: the nearest directory at or
+/// above it holding a `.git`, which is a file in worktrees and submodules.
+pub fn repo_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// The current time in Unix seconds.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,139 @@
+//! Searching commands one word at a time, to find every command that used
+//! a file or flag wherever it appeared in the line.
+//!
+//! Commands are split into words much as a shell would: at blanks and at the
+//! operators `|`, `&`, `;`, `<`, `>`, `(`, and `)`, except inside quotes or
+//! after a backslash, which are then taken off. So `cat "my notes.txt" | wc`
+//! has the words `cat`, `my notes.txt`, and `wc`. Nothing is expanded, and a
+//! backslash escapes any character inside double quotes, not only the few a
+//! shell would.
+
+use super::fuzzy::FuzzyMatch;
+use super::query::Query;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// One word of a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word<'a> {
+    /// Where it is in the command, quotes included.
+    pub span: Range<usize>,
+    /// What it stands for, with its quotes and backslashes taken off.
+    pub text: Cow<'a, str>,
+}
+
+/// The words of `command`, in order.
+pub fn words(command: &str) -> Vec<Word<'_>> {
+    let mut words = Vec::new();
+    let mut chars = command.char_indices().peekable();
+    loop {
+        while chars.next_if(|&(_, c)| separates(c)).is_some() {}
+        let Some(&(start, _)) = chars.peek() else {
+            return words;
+        };
+        let mut text = String::new();
+        let mut unquoted = true;
+        let mut quote: Option<char> = None;
+        while let Some((_, c)) = chars.next_if(|&(_, c)| quote.is_some() || !separates(c)) {
+            match (quote, c) {
+                // Backslashes escape outside quotes and inside double ones.
+                (None | Some('"'), '\\') => {
+                    unquoted = false;
+                    if let Some((_, escaped)) = chars.next() {
+                        text.push(escaped);
+                    }
+                }
+                (None, '"' | '\'') => {
+                    unquoted = false;
+                    quote = Some(c);
+                }
+                (Some(q), _) if c == q => quote = None,
+                _ => text.push(c),
+            }
+        }
+        let end = chars.peek().map_or(command.len(), |&(next, _)| next);
+        words.push(Word {
+            span: start..end,
+            text: if unquoted {
+                Cow::Borrowed(&command[start..end])
+            } else {
+                Cow::Owned(text)
+            },
+        });
+    }
+}
+
+/// Whether `c` ends a word outside quotes.
+fn separates(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '|' | '&' | ';' | '<' | '>' | '(' | ')')
+}
+
+/// The best match for `matcher` among the words of `command`, with its
+/// positions counted in `command` as a whole, for highlighting.
+pub fn fuzzy_match(matcher: &Query, command: &str) -> Option<FuzzyMatch> {
+    let mut best: Option<(i64, Range<usize>)> = None;
+    for word in words(command) {
+        if let Some(score) = matcher.score(&word.text) {
+            if best.as_ref().is_none_or(|(high, _)| score > *high) {
+                best = Some((score, word.span));
+            }
+        }
+    }
+    let (_, span) = best?;
+    // The word as typed holds the characters it stands for, in order.
+    let mut found = matcher.fuzzy_match(&command[span.clone()])?;
+    let before = command[..span.start].chars().count();
+    for position in &mut found.positions {
+        *position += before;
+    }
+    Some(found)
+}
+
+/// The words of many commands, so that a query is matched against each
+/// distinct word once however many commands share it.
+#[derive(Debug, Clone, Default)]
+pub struct Index<'a> {
+    /// Every distinct word.
+    words: Vec<String>,
+    /// The words of each command, as indexes into `words`.
+    commands: HashMap<&'a str, Vec<usize>>,
+}
+
+impl<'a> Index<'a> {
+    pub fn new(commands: impl IntoIterator<Item = &'a str>) -> Index<'a> {
+        let mut index = Index::default();
+        let mut ids: HashMap<String, usize> = HashMap::new();
+        for command in commands {
+            let mut of_command: Vec<usize> = words(command)
+                .into_iter()
+                .map(|word| {
+                    if let Some(&id) = ids.get(word.text.as_ref()) {
+                        return id;
+                    }
+                    let id = index.words.len();
+                    index.words.push(word.text.to_string());
+                    ids.insert(word.text.into_owned(), id);
+                    id
+                })
+                .collect();
+            of_command.sort_unstable();
+            of_command.dedup();
+            index.commands.insert(command, of_command);
+        }
+        index
+    }
+
+    /// Scores commands by their best-matching word for `matcher`; `None` for
+    /// commands with no matching word, or not in the index.
+    pub fn scorer<'s>(&'s self, matcher: &'s Query) -> impl FnMut(&str) -> Option<i64> + 's {
+        let mut scores: Vec<Option<Option<i64>>> = vec![None; self.words.len()];
+        move |command| {
+            self.commands
+                .get(command)?
+                .iter()
+                .filter_map(|&id| *scores[id].get_or_insert_with(|| matcher.score(&self.words[id])))
+                .max()
+        }
+    }
+}
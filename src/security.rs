@@ -0,0 +1,5 @@
+//! Keeping secrets typed into commands off the screen, and destructive
+//! commands from running unasked.
+
+pub mod danger;
+pub mod redact;
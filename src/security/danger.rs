@@ -0,0 +1,174 @@
+//! Asking before running commands that could wreck the machine.
+//!
+//! A command about to be run straight from the picker (with `--exec`, or
+//! the execute key) is checked against patterns for the obviously
+//! destructive: removing `/` or the home directory recursively, writing
+//! over a disk, making a filesystem, a fork bomb. One that matches runs only
+//! after a yes. The config file can add patterns of its own:
+//!
+//! ```toml
+//! [danger]
+//! patterns = ["^kubectl delete ns", "terraform destroy"]
+//! builtin = true
+//! ```
+//!
+//! This is a seatbelt, not a sandbox: it catches the slip of picking the
+//! wrong line, and is easy to write around.
+
+use regex::{Regex, RegexSet};
+use serde::Deserialize;
+
+/// What the patterns of [`BUILTIN`] catch, in the same order.
+pub const BUILTIN_NAMES: [&str; 7] = [
+    "recursive rm of / or home",
+    "rm without root protection",
+    "dd onto a disk",
+    "making a filesystem",
+    "writing over a disk",
+    "recursive chmod or chown of /",
+    "fork bomb",
+];
+
+/// The patterns checked unless `builtin = false`.
+pub const BUILTIN: [&str; 7] = [
+    r#"\brm\s+(?:\S+\s+)*?-[a-zA-Z]*[rR]\S*\s+(?:\S+\s+)*?["']?(?:/|/\*|~|~/|~/\*|\$HOME|\$HOME/|\$HOME/\*)["']?(?:\s|[;&|)]|$)"#,
+    r"\brm\b.*\s--no-preserve-root\b",
+    r"\bdd\b.*\bof=/dev/(?:[shv]d|xvd|nvme|mmcblk|r?disk|md|mapper/)",
+    r"\bmkfs(?:\.\w+)?\s",
+    r">\s*/dev/(?:[shv]d[a-z]|xvd|nvme|mmcblk|r?disk)",
+    r"\bch(?:mod|own)\s+(?:\S+\s+)*?-\S*R\S*\s+(?:\S+\s+)*?/(?:\s|$)",
+    r":\(\)\s*\{\s*:\s*\|\s*:\s*&\s*\}\s*;\s*:",
+];
+
+/// A regular expression from the config file's danger patterns.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Regex::new(&s)
+            .map(Pattern)
+            .map_err(|err| format!("invalid danger pattern: {}", err))
+    }
+}
+
+/// The `[danger]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DangerConfig {
+    /// Regular expressions; a command matching any of them needs a yes to
+    /// run.
+    pub patterns: Vec<Pattern>,
+    /// Whether the [`BUILTIN`] patterns apply too.
+    pub builtin: bool,
+}
+
+impl Default for DangerConfig {
+    fn default() -> Self {
+        DangerConfig {
+            patterns: Vec::new(),
+            builtin: true,
+        }
+    }
+}
+
+impl DangerConfig {
+    /// The commands this section asks about.
+    pub fn danger(&self) -> Danger {
+        Danger::new(&self.patterns, self.builtin)
+    }
+}
+
+/// The commands to ask about before running.
+#[derive(Debug, Clone)]
+pub struct Danger {
+    set: RegexSet,
+    /// What each pattern in `set` catches.
+    names: Vec<String>,
+}
+
+impl Default for Danger {
+    /// Just the built-in patterns.
+    fn default() -> Self {
+        Danger::new(&[], true)
+    }
+}
+
+impl Danger {
+    /// Asks about commands matching any of `patterns`, and the [`BUILTIN`]
+    /// ones too if `builtin`.
+    pub fn new(patterns: &[Pattern], builtin: bool) -> Danger {
+        let builtin = BUILTIN
+            .iter()
+            .zip(BUILTIN_NAMES)
+            .map(|(pattern, name)| (*pattern, name.to_string()))
+            .filter(|_| builtin);
+        let patterns = patterns.iter().map(|Pattern(regex)| {
+            let pattern = regex.as_str();
+            (pattern, format!("matches {}", pattern))
+        });
+        let (patterns, names): (Vec<&str>, Vec<String>) = builtin.chain(patterns).unzip();
+        Danger {
+            set: RegexSet::new(patterns).expect("patterns were checked as they were read"),
+            names,
+        }
+    }
+
+    /// What makes `command` look destructive, if anything does.
+    pub fn check(&self, command: &str) -> Option<&str> {
+        let first = self.set.matches(command).into_iter().next()?;
+        Some(&self.names[first])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catches_the_destructive() {
+        let danger = Danger::default();
+        for command in [
+            "rm -rf /",
+            "sudo rm -fr ~/",
+            "rm -r --force $HOME/*",
+            "rm -rf --no-preserve-root /mnt",
+            "dd if=disk.img of=/dev/sdb bs=4M",
+            "mkfs.ext4 /dev/sda1",
+            "cat /dev/zero > /dev/nvme0n1",
+            "chmod -R 777 /",
+            ":(){ :|:& };:",
+        ] {
+            assert!(danger.check(command).is_some(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn leaves_everyday_commands_alone() {
+        let danger = Danger::default();
+        for command in [
+            "rm -rf ./target",
+            "rm -rf ~/tmp/build",
+            "rm /tmp/x",
+            "dd if=/dev/zero of=/dev/null count=1",
+            "chmod -R g+w ./shared",
+            "echo hi > /dev/null",
+        ] {
+            assert_eq!(danger.check(command), None, "{}", command);
+        }
+    }
+
+    #[test]
+    fn config_patterns_are_named_in_the_reason() {
+        let patterns = [Pattern::try_from("terraform destroy".to_string()).unwrap()];
+        let danger = Danger::new(&patterns, false);
+        assert_eq!(
+            danger.check("terraform destroy -auto-approve"),
+            Some("matches terraform destroy")
+        );
+        assert_eq!(danger.check("rm -rf /"), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! Shells we know how to read history from and integrate with.
+
+mod parent;
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// A supported interactive shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+    #[serde(rename = "nu")]
+    Nushell,
+    Ksh,
+    Mksh,
+    Tcsh,
+}
+
+impl Shell {
+    /// Every supported shell.
+    pub const ALL: [Shell; 8] = [
+        Shell::Bash,
+        Shell::Zsh,
+        Shell::Fish,
+        Shell::PowerShell,
+        Shell::Nushell,
+        Shell::Ksh,
+        Shell::Mksh,
+        Shell::Tcsh,
+    ];
+
+    /// The shell's conventional name, as used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::PowerShell => "powershell",
+            Shell::Nushell => "nu",
+            Shell::Ksh => "ksh",
+            Shell::Mksh => "mksh",
+            Shell::Tcsh => "tcsh",
+        }
+    }
+
+    /// Identifies a shell from a path such as `$SHELL`, by its file name.
+    /// Windows paths and `.exe` suffixes are understood, `pwsh` is
+    /// PowerShell, `nu` is Nushell, and other names of the same shells
+    /// count as them: `ksh93` and `oksh` as ksh, `lksh` as mksh, and `csh`,
+    /// which is tcsh on the BSDs and macOS, as tcsh.
+    pub fn from_path(path: &str) -> Option<Shell> {
+        let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+        let name = name.strip_suffix(".exe").unwrap_or(name);
+        match name {
+            "pwsh" => Some(Shell::PowerShell),
+            "ksh93" | "oksh" | "pdksh" => Some(Shell::Ksh),
+            "lksh" => Some(Shell::Mksh),
+            "csh" => Some(Shell::Tcsh),
+            _ => name.parse().ok(),
+        }
+    }
+
+    /// The shell th_rs was started from: its nearest ancestor process that
+    /// is a known shell, which `$SHELL`, the login shell, may not be.
+    /// `None` when there is none, or on Windows.
+    pub fn running() -> Option<Shell> {
+        static RUNNING: OnceLock<Option<Shell>> = OnceLock::new();
+        *RUNNING.get_or_init(parent::find)
+    }
+
+    /// The shell to assume when `$SHELL` is not set: PowerShell on Windows,
+    /// where nothing sets it, and none elsewhere.
+    pub fn fallback() -> Option<Shell> {
+        cfg!(windows).then_some(Shell::PowerShell)
+    }
+
+    /// The snippet that binds Ctrl-R to th_rs and puts the selection on the
+    /// shell's command line. Users `eval` (or `source`) its output.
+    ///
+    /// With `record`, it also hooks into the shell to pass every command to
+    /// `th_rs record`, and Ctrl-R searches the store they are recorded in.
+    /// ksh, mksh, and tcsh have no snippet.
+    pub fn init_script(self, record: bool) -> Option<Cow<'static, str>> {
+        let (init, hooks) = match self {
+            Shell::Bash => (
+                include_str!("shell/init.bash"),
+                include_str!("shell/record.bash"),
+            ),
+            Shell::Zsh => (
+                include_str!("shell/init.zsh"),
+                include_str!("shell/record.zsh"),
+            ),
+            Shell::Fish => (
+                include_str!("shell/init.fish"),
+                include_str!("shell/record.fish"),
+            ),
+            Shell::PowerShell => (
+                include_str!("shell/init.ps1"),
+                include_str!("shell/record.ps1"),
+            ),
+            Shell::Nushell => (
+                include_str!("shell/init.nu"),
+                include_str!("shell/record.nu"),
+            ),
+            Shell::Ksh | Shell::Mksh | Shell::Tcsh => return None,
+        };
+        if !record {
+            return Some(Cow::Borrowed(init));
+        }
+        Some(Cow::Owned(
+            init.replace("th_rs --print", "th_rs --store --print")
+                .replace("th_rs suggest", "th_rs --store suggest")
+                + hooks,
+        ))
+    }
+}
+
+/// A set of shells, e.g. every shell a command was run in. In JSON it is a
+/// list of shell names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "Vec<Shell>", from = "Vec<Shell>")]
+pub struct ShellSet(u16);
+
+impl ShellSet {
+    /// Adds `shell` to the set.
+    pub fn insert(&mut self, shell: Shell) {
+        self.0 |= 1 << shell as u16;
+    }
+
+    /// Whether `shell` is in the set.
+    pub fn contains(self, shell: Shell) -> bool {
+        self.0 & (1 << shell as u16) != 0
+    }
+
+    /// Every shell in either set.
+    pub fn union(self, other: ShellSet) -> ShellSet {
+        ShellSet(self.0 | other.0)
+    }
+
+    /// Whether the set is empty.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// How many shells are in the set.
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// The shells in the set, in [`Shell::ALL`] order.
+    pub fn iter(self) -> impl Iterator<Item = Shell> {
+        Shell::ALL
+            .into_iter()
+            .filter(move |&shell| self.contains(shell))
+    }
+}
+
+impl FromIterator<Shell> for ShellSet {
+    fn from_iter<I: IntoIterator<Item = Shell>>(iter: I) -> Self {
+        let mut set = ShellSet::default();
+        for shell in iter {
+            set.insert(shell);
+        }
+        set
+    }
+}
+
+impl From<ShellSet> for Vec<Shell> {
+    fn from(set: ShellSet) -> Self {
+        set.iter().collect()
+    }
+}
+
+impl From<Vec<Shell>> for ShellSet {
+    fn from(shells: Vec<Shell>) -> Self {
+        shells.into_iter().collect()
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Shell::ALL
+            .into_iter()
+            .find(|shell| shell.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unsupported shell: {} (expected bash, zsh, fish, powershell, nu, ksh, mksh, or tcsh)",
+                    s
+                )
+            })
+    }
+}
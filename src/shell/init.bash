@@ -0,0 +1,13 @@
+# th_rs: use the history picker for Ctrl-R.
+# Add to ~/.bashrc:  eval "$(th_rs init bash)"
+
+__th_rs_widget() {
+    local selected
+    selected=$(printf '%s' "$READLINE_LINE" | HISTFILE=$HISTFILE th_rs --print --query -) || return
+    if [[ -n $selected ]]; then
+        READLINE_LINE=$selected
+        READLINE_POINT=${#READLINE_LINE}
+    fi
+}
+
+bind -x '"\C-r": __th_rs_widget'
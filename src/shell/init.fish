@@ -0,0 +1,15 @@
+# th_rs: use the history picker for Ctrl-R.
+# Add to ~/.config/fish/config.fish:  th_rs init fish | source
+
+function __th_rs_widget
+    set -l selected (commandline -b | th_rs --print --query - | string collect)
+    if test -n "$selected"
+        commandline -r -- $selected
+    end
+    commandline -f repaint
+end
+
+bind \cr __th_rs_widget
+if bind -M insert >/dev/null 2>&1
+    bind -M insert \cr __th_rs_widget
+end
@@ -0,0 +1,14 @@
+# th_rs: use the history picker for Ctrl-R.
+# Add to $PROFILE:  Invoke-Expression (& th_rs init powershell | Out-String)
+
+Set-PSReadLineKeyHandler -Chord 'Ctrl+r' -BriefDescription 'th_rs' -ScriptBlock {
+    $line = $null
+    $cursor = $null
+    [Microsoft.PowerShell.PSConsoleReadLine]::GetBufferState([ref]$line, [ref]$cursor)
+    $selected = ($line | th_rs --print --query -) -join "`n"
+    [Microsoft.PowerShell.PSConsoleReadLine]::InvokePrompt()
+    if ($selected) {
+        [Microsoft.PowerShell.PSConsoleReadLine]::RevertLine()
+        [Microsoft.PowerShell.PSConsoleReadLine]::Insert($selected)
+    }
+}
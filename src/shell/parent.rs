@@ -0,0 +1,69 @@
+//! Finding the shell th_rs was started from by walking up the process tree.
+//!
+//! `$SHELL` is the login shell, which need not be the one running: fish
+//! started from a bash login still has `SHELL=/bin/bash`. The nearest
+//! ancestor that is a known shell is the one whose history is wanted. On
+//! Linux the tree is read from `/proc`; on other Unixes `ps` is asked.
+
+use super::Shell;
+
+/// How far up the tree to look, enough for a shell under `tmux`, `sudo`,
+/// or an editor's terminal.
+const MAX_DEPTH: usize = 16;
+
+/// The nearest ancestor of this process that is a known shell.
+#[cfg(unix)]
+pub(crate) fn find() -> Option<Shell> {
+    let mut pid = std::os::unix::process::parent_id();
+    for _ in 0..MAX_DEPTH {
+        if pid <= 1 {
+            return None;
+        }
+        let (parent, name) = process(pid)?;
+        // A login shell's name starts with `-`, as in `-bash`.
+        if let Some(shell) = Shell::from_path(name.trim_start_matches('-')) {
+            return Some(shell);
+        }
+        pid = parent;
+    }
+    None
+}
+
+#[cfg(not(unix))]
+pub(crate) fn find() -> Option<Shell> {
+    None
+}
+
+/// The parent and program name of the process `pid`.
+#[cfg(target_os = "linux")]
+fn process(pid: u32) -> Option<(u32, String)> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // `pid (comm) state ppid ...`, where comm may hold spaces and parens.
+    let (comm, rest) = stat.split_once(" (")?.1.rsplit_once(") ")?;
+    let parent = rest.split_whitespace().nth(1)?.parse().ok()?;
+    // comm is cut to 15 bytes; the first argument is whole, and is what
+    // `-bash` shows up as.
+    let name = std::fs::read(format!("/proc/{}/cmdline", pid))
+        .ok()
+        .and_then(|cmdline| {
+            let first = cmdline.split(|&b| b == 0).next()?;
+            let first = String::from_utf8_lossy(first);
+            let name = first.rsplit('/').next()?;
+            (!name.is_empty()).then(|| name.to_string())
+        })
+        .unwrap_or_else(|| comm.to_string());
+    Some((parent, name))
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn process(pid: u32) -> Option<(u32, String)> {
+    let output = std::process::Command::new("ps")
+        .args(["-o", "ppid=", "-o", "comm=", "-p", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let (parent, comm) = output.trim().split_once(char::is_whitespace)?;
+    let name = comm.trim().rsplit('/').next()?;
+    Some((parent.parse().ok()?, name.to_string()))
+}
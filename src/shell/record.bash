@@ -0,0 +1,73 @@
+
+# Record every command into the th_rs store, with where it ran, how it exited,
+# and how long it took; Ctrl-R then searches the store.
+
+# Tells this shell's commands from those of others running at the same time.
+export TH_RS_SESSION="$$-$RANDOM$RANDOM"
+__th_rs_number=$(HISTTIMEFORMAT= builtin history 1)
+__th_rs_number=${__th_rs_number%%[^ 0-9]*}
+
+__th_rs_clock() {
+    if [[ -n ${EPOCHREALTIME-} ]]; then
+        REPLY=${EPOCHREALTIME/[.,]/}
+    else
+        REPLY=$((SECONDS * 1000000))
+    fi
+}
+
+# The DEBUG trap runs before every simple command, the prompt's included, so
+# only the first one after the prompt is armed starts the clock.
+__th_rs_preexec() {
+    [[ -n ${__th_rs_armed-} && -z ${COMP_LINE-} ]] || return
+    unset __th_rs_armed
+    __th_rs_clock
+    __th_rs_start=$REPLY
+    __th_rs_cwd=$PWD
+}
+
+__th_rs_precmd() {
+    local exit_status=$? entry
+    [[ -n ${__th_rs_start-} ]] || return
+    __th_rs_clock
+    local duration=$(((REPLY - __th_rs_start) / 1000))
+    unset __th_rs_start
+    entry=$(HISTTIMEFORMAT= builtin history 1)
+    # A command kept out of history, e.g. by HISTCONTROL, leaves the last
+    # entry as it was.
+    [[ $entry =~ ^\ *([0-9]+)[*\ ]\ (.*)$ ]] || return
+    [[ ${BASH_REMATCH[1]} != "$__th_rs_number" ]] || return
+    __th_rs_number=${BASH_REMATCH[1]}
+    (th_rs record --shell bash --exit "$exit_status" --duration "$duration" \
+        --cwd "$__th_rs_cwd" --hostname "$HOSTNAME" -- "${BASH_REMATCH[2]}" \
+        </dev/null &>/dev/null &)
+}
+
+__th_rs_arm() {
+    __th_rs_armed=1
+}
+
+if [[ -n ${bash_preexec_imported-}${__bp_imported-} ]]; then
+    # bash-preexec owns the DEBUG trap and PROMPT_COMMAND, and calls these
+    # with $? as the command left it.
+    preexec_functions+=(__th_rs_preexec)
+    precmd_functions+=(__th_rs_precmd __th_rs_arm)
+else
+    PROMPT_COMMAND="__th_rs_precmd;${PROMPT_COMMAND:+$PROMPT_COMMAND;}__th_rs_arm"
+
+    # A DEBUG trap set before this one still runs, after it, with $? as it
+    # would have been. Bash only shows it to the snippet run through eval,
+    # as it is meant to be, not to one sourced from a file.
+    __th_rs_debug() {
+        local status=$?
+        __th_rs_preexec
+        return "$status"
+    }
+    __th_rs_trap=$(trap -p DEBUG)
+    __th_rs_trap=${__th_rs_trap#"trap -- "}
+    __th_rs_trap=${__th_rs_trap%" DEBUG"}
+    eval "__th_rs_trap=${__th_rs_trap:-''}"
+    if [[ $__th_rs_trap != *__th_rs_debug* ]]; then
+        trap "__th_rs_debug${__th_rs_trap:+; $__th_rs_trap}" DEBUG
+    fi
+    unset __th_rs_trap
+fi
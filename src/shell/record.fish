@@ -0,0 +1,19 @@
+
+# Record every command into the th_rs store, with where it ran, how it exited,
+# and how long it took; Ctrl-R then searches the store.
+
+# Tells this shell's commands from those of others running at the same time.
+set -gx TH_RS_SESSION "$fish_pid-"(random)(random)
+
+function __th_rs_preexec --on-event fish_preexec
+    set -g __th_rs_cwd $PWD
+end
+
+function __th_rs_postexec --on-event fish_postexec
+    set -l exit_status $status
+    test -n "$argv[1]"; or return
+    th_rs record --shell fish --exit $exit_status --duration $CMD_DURATION \
+        --cwd $__th_rs_cwd --hostname $hostname -- $argv[1] \
+        </dev/null &>/dev/null &
+    disown 2>/dev/null
+end
@@ -0,0 +1,23 @@
+
+# Record every command into the th_rs store, with where it ran, how it exited,
+# and how long it took; Ctrl-R then searches the store.
+
+# Tells this shell's commands from those of others running at the same time.
+$env:TH_RS_SESSION = "$PID-$(Get-Random)"
+$global:__th_rs_last = (Get-History -Count 1).Id
+$global:__th_rs_prompt = $function:prompt
+
+function global:prompt {
+    $success = $?
+    $exitCode = $global:LASTEXITCODE
+    $entry = Get-History -Count 1
+    if ($entry -and $entry.Id -ne $global:__th_rs_last) {
+        $global:__th_rs_last = $entry.Id
+        $status = if ($success) { 0 } elseif ($exitCode) { $exitCode } else { 1 }
+        $duration = [int64]($entry.EndExecutionTime - $entry.StartExecutionTime).TotalMilliseconds
+        th_rs record --shell powershell --exit $status --duration $duration `
+            --cwd (Get-Location).Path --hostname $env:COMPUTERNAME '--' $entry.CommandLine | Out-Null
+        $global:LASTEXITCODE = $exitCode
+    }
+    & $global:__th_rs_prompt
+}
@@ -0,0 +1,61 @@
+//! Saved commands with fields to fill in, like a small pet or navi.
+//!
+//! A snippet is a named command whose `{placeholders}` are asked for when it
+//! is chosen in the picker: `kubectl -n {ns} rollout restart deploy/{app}`
+//! asks for `ns` and `app`, then gives the command with both filled in. A
+//! placeholder is a name of letters, digits, `_`, and `-` in braces; braces
+//! after a `$`, as in `${HOME}`, and ones holding anything else, as in
+//! `awk '{print $1}'`, are left alone.
+
+use std::ops::Range;
+
+/// The placeholders of `template`, each once, in the order they first
+/// appear.
+pub fn placeholders(template: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = Vec::new();
+    for (_, name) in fields(template) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// `template` with every placeholder named in `values` replaced by its
+/// value. Placeholders without one stay as they are.
+pub fn fill(template: &str, values: &[(String, String)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut copied = 0;
+    for (range, name) in fields(template) {
+        if let Some((_, value)) = values.iter().find(|(n, _)| n == name) {
+            filled.push_str(&template[copied..range.start]);
+            filled.push_str(value);
+            copied = range.end;
+        }
+    }
+    filled.push_str(&template[copied..]);
+    filled
+}
+
+/// Every placeholder in `template`: where it is, braces included, and its
+/// name.
+fn fields(template: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    let mut from = 0;
+    std::iter::from_fn(move || loop {
+        let open = from + template.get(from..)?.find('{')?;
+        from = open + 1;
+        if template[..open].ends_with('$') {
+            continue;
+        }
+        let len = template[from..].find('}')?;
+        let name = &template[from..from + len];
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+        if valid {
+            from += len + 1;
+            return Some((open..from, name));
+        }
+    })
+}
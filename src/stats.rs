@@ -0,0 +1,116 @@
+//! Summaries of how history is used, for `th_rs stats`.
+
+use crate::history::Entry;
+use crate::shell::Shell;
+use crate::time;
+use chrono::{Datelike, Timelike};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// What a stretch of history adds up to.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    /// How many commands were run.
+    pub total: usize,
+    /// How many distinct commands there were.
+    pub unique: usize,
+    /// The mean length of a command run, in characters.
+    pub average_length: f64,
+    /// The most run commands and how often, most first.
+    pub top: Vec<Count<String>>,
+    /// Runs per shell, most first; `null` for history of no known shell.
+    pub shells: Vec<Count<Option<Shell>>>,
+    /// How many runs have a known time; only those count below.
+    pub timed: usize,
+    /// When the first and last timed runs were, in Unix seconds.
+    pub first: Option<i64>,
+    pub last: Option<i64>,
+    /// Runs in each hour of the day, local time, from midnight.
+    pub hours: [usize; 24],
+    /// Runs on each day of the week, from Monday.
+    pub weekdays: [usize; 7],
+}
+
+/// Something and how many times it was run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Count<T> {
+    pub value: T,
+    pub count: usize,
+}
+
+impl Stats {
+    /// Sums up the runs in `entries`, keeping the `top` most run commands.
+    pub fn from_entries(entries: &[Entry], top: usize) -> Stats {
+        let mut builder = Builder::default();
+        for entry in entries {
+            builder.add(&entry.command, entry.shell, entry.timestamp, 1);
+        }
+        builder.finish(top)
+    }
+
+    /// How many commands a day were run between the first and last timed
+    /// runs, counting partial days as whole ones.
+    pub fn per_day(&self) -> Option<f64> {
+        let (first, last) = (self.first?, self.last?);
+        let days = ((last - first) / 86_400 + 1) as f64;
+        Some(self.timed as f64 / days)
+    }
+}
+
+#[derive(Default)]
+struct Builder<'a> {
+    commands: HashMap<&'a str, usize>,
+    shells: HashMap<Option<Shell>, usize>,
+    total: usize,
+    length: usize,
+    stats: Stats,
+}
+
+impl<'a> Builder<'a> {
+    fn add(&mut self, command: &'a str, shell: Option<Shell>, timestamp: Option<i64>, runs: usize) {
+        *self.commands.entry(command).or_default() += runs;
+        *self.shells.entry(shell).or_default() += runs;
+        self.total += runs;
+        self.length += command.chars().count() * runs;
+        let Some(local) = timestamp.and_then(time::local_time) else {
+            return;
+        };
+        let stats = &mut self.stats;
+        stats.timed += runs;
+        stats.first = stats.first.min(timestamp).or(timestamp);
+        stats.last = stats.last.max(timestamp);
+        stats.hours[local.hour() as usize] += runs;
+        stats.weekdays[local.weekday().num_days_from_monday() as usize] += runs;
+    }
+
+    fn finish(self, top: usize) -> Stats {
+        Stats {
+            total: self.total,
+            unique: self.commands.len(),
+            average_length: match self.total {
+                0 => 0.0,
+                total => self.length as f64 / total as f64,
+            },
+            top: most_first(self.commands, |command| command.to_string())
+                .into_iter()
+                .take(top)
+                .collect(),
+            shells: most_first(self.shells, |shell| shell),
+            ..self.stats
+        }
+    }
+}
+
+/// `counts` ordered by count, most first, ties broken by value so that the
+/// output does not change from run to run.
+fn most_first<K: Ord, T>(counts: HashMap<K, usize>, value: impl Fn(K) -> T) -> Vec<Count<T>> {
+    let mut counts: Vec<(K, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+        .into_iter()
+        .map(|(key, count)| Count {
+            value: value(key),
+            count,
+        })
+        .collect()
+}
@@ -0,0 +1,114 @@
+//! Persistent history storage.
+//!
+//! Parsing a multi-megabyte history file on every launch is wasteful; the
+//! store keeps an indexed copy that th_rs can search instead. It is opt-in:
+//! shell history files remain the default source.
+//!
+//! Besides imported history files, the store holds every [`Run`] recorded by
+//! the shell hooks of `th_rs init --record`, which know more than any history
+//! file: where each command ran, how it exited, and how long it took.
+//!
+//! `th_rs export` writes all of it to a file that `th_rs import` reads back,
+//! e.g. on another machine; see [`export`].
+//!
+//! Since history holds whatever was typed, secrets included, the store can
+//! be encrypted on disk with a passphrase:
+//!
+//! ```toml
+//! [store]
+//! encrypt = true
+//! passphrase_command = "secret-tool lookup service th_rs"
+//! ```
+
+pub mod atuin;
+pub mod export;
+pub mod sqlite;
+
+use crate::error::{Result, ThError};
+use crate::exec;
+use crate::paths;
+use crate::shell::Shell;
+use serde::Deserialize;
+use sqlite::Store;
+use std::env;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+/// The `[store]` section of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    /// Whether the store is encrypted; needs th_rs built with the
+    /// `encryption` feature.
+    pub encrypt: bool,
+    /// A command printing the passphrase, e.g. one reading it from the OS
+    /// keychain, used when `TH_RS_PASSPHRASE` is not set.
+    pub passphrase_command: Option<String>,
+}
+
+impl StoreConfig {
+    /// The passphrase the store is encrypted with: `TH_RS_PASSPHRASE`, or
+    /// what `passphrase_command` prints.
+    pub fn passphrase(&self) -> Result<String> {
+        if let Some(passphrase) = env::var("TH_RS_PASSPHRASE").ok().filter(|p| !p.is_empty()) {
+            return Ok(passphrase);
+        }
+        let Some(command) = &self.passphrase_command else {
+            return Err(ThError::Encryption(
+                "no passphrase: set TH_RS_PASSPHRASE or passphrase_command under [store]"
+                    .to_string(),
+            ));
+        };
+        let output = exec::command(command)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()?;
+        let printed = String::from_utf8_lossy(&output.stdout);
+        // Only the line break that ends the output is not part of it.
+        let passphrase = printed.strip_suffix('\n').unwrap_or(&printed);
+        if !output.status.success() || passphrase.is_empty() {
+            return Err(ThError::Encryption(format!(
+                "passphrase command `{}` printed no passphrase",
+                command
+            )));
+        }
+        Ok(passphrase.to_string())
+    }
+
+    /// Opens the store at its default location, encrypted if configured.
+    pub fn open(&self) -> Result<Store> {
+        let path = default_path()?;
+        if self.encrypt {
+            Store::open_encrypted(&path, &self.passphrase()?)
+        } else {
+            Store::open(&path)
+        }
+    }
+}
+
+/// Where the SQLite store lives by default: `history.db` in the th_rs data
+/// directory, e.g. `~/.local/share/th_rs/history.db`.
+pub fn default_path() -> Result<PathBuf> {
+    Ok(paths::data_dir()?.join("history.db"))
+}
+
+/// One run of a command, as a shell hook reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// The command line as typed.
+    pub command: String,
+    /// The shell it ran in, when known.
+    pub shell: Option<Shell>,
+    /// The working directory it ran in.
+    pub cwd: String,
+    /// Its exit status, when known.
+    pub exit: Option<i32>,
+    /// How long it ran, in milliseconds.
+    pub duration: Option<u64>,
+    /// The machine it ran on.
+    pub hostname: String,
+    /// Tells apart the shells running at once, e.g. in different terminals.
+    pub session: String,
+    /// When it started, in Unix seconds.
+    pub timestamp: i64,
+}
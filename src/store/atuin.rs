@@ -0,0 +1,83 @@
+//! Reading the history database of atuin, another shell history tool, so
+//! that people coming from it keep what it recorded.
+//!
+//! Atuin keeps one row per run in `history`, with its directory, exit
+//! status, duration, session, and host, which map onto recorded runs here.
+//! Its times are in nanoseconds, and it writes -1 for a duration or status
+//! it does not know.
+
+use super::export::{Kind, Record};
+use crate::error::{Result, ThError};
+use crate::paths;
+use rusqlite::{Connection, OpenFlags};
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// What every SQLite database file starts with.
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Where atuin keeps its history by default: `atuin/history.db` in the XDG
+/// data directory, e.g. `~/.local/share/atuin/history.db`, on every system.
+pub fn default_path() -> Result<PathBuf> {
+    let data = match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => paths::home_dir()?.join(".local/share"),
+    };
+    Ok(data.join("atuin").join("history.db"))
+}
+
+/// Whether `path` is an SQLite database, rather than a th_rs export.
+pub fn is_database(path: &Path) -> bool {
+    let mut header = [0; SQLITE_HEADER.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|()| header == SQLITE_HEADER)
+}
+
+/// Every run in the atuin database at `path` that was not deleted, oldest
+/// first, as records of a th_rs export.
+pub fn read(path: &Path) -> Result<Vec<Record>> {
+    if !path.exists() {
+        return Err(ThError::HistoryFile {
+            path: path.to_path_buf(),
+            source: io::ErrorKind::NotFound.into(),
+        });
+    }
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    // Databases from before atuin could delete history have no
+    // `deleted_at`.
+    let deletable = conn
+        .prepare("SELECT deleted_at FROM history LIMIT 0")
+        .is_ok();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT command, timestamp, duration, exit, cwd, session, hostname
+         FROM history {} ORDER BY timestamp",
+        if deletable {
+            "WHERE deleted_at IS NULL"
+        } else {
+            ""
+        }
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        let nanos = |column| row.get::<_, i64>(column);
+        let known = |value: i64| (value >= 0).then_some(value);
+        // Atuin's hostname is `host:user`.
+        let hostname: String = row.get(6)?;
+        let hostname = match hostname.split_once(':') {
+            Some((host, _user)) => host.to_string(),
+            None => hostname,
+        };
+        Ok(Record {
+            timestamp: Some(nanos(1)? / 1_000_000_000),
+            duration: known(nanos(2)?).map(|duration| (duration / 1_000_000) as u64),
+            exit: known(row.get(3)?).map(|exit| exit as i32),
+            cwd: Some(row.get(4)?),
+            session: Some(row.get(5)?),
+            hostname: Some(hostname),
+            ..Record::new(Kind::Run, row.get(0)?)
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
@@ -0,0 +1,180 @@
+//! Writing the whole store to a portable file and reading it back, for
+//! `th_rs export` and `th_rs import FILE`.
+//!
+//! Both formats hold the same [`Record`]s, one for each imported command,
+//! recorded run, bookmark, snippet, and deleted command: a JSON array of
+//! objects, or CSV with a header line naming the columns. Values that are
+//! unknown or do not apply to a kind of record are `null` in JSON and empty
+//! in CSV.
+
+use crate::error::{Result, ThError};
+use crate::shell::Shell;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// How an export is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl Format {
+    /// The format named by the extension of `path`, if any.
+    pub fn of_path(path: &Path) -> Option<Format> {
+        path.extension()?
+            .to_str()?
+            .to_ascii_lowercase()
+            .parse()
+            .ok()
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+        })
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("unknown format: {} (expected json or csv)", s)),
+        }
+    }
+}
+
+/// What a [`Record`] stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// A command imported from a history file, with how often it ran.
+    History,
+    /// One run recorded by a shell hook.
+    Run,
+    /// A bookmarked command.
+    Bookmark,
+    /// A command saved under a name, with placeholders.
+    Snippet,
+    /// A command the user deleted, kept out of later imports.
+    Deleted,
+}
+
+/// One row of the store.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Record {
+    pub kind: Kind,
+    /// The command line.
+    pub command: String,
+    /// The shell it ran in.
+    #[serde(default)]
+    pub shell: Option<Shell>,
+    /// The working directory it ran in.
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// How many times it ran, for imported history.
+    #[serde(default)]
+    pub count: Option<i64>,
+    /// When it last ran, or for a run when it started, in Unix seconds.
+    #[serde(default)]
+    pub timestamp: Option<i64>,
+    /// The exit status of a run.
+    #[serde(default)]
+    pub exit: Option<i32>,
+    /// How long a run took, in milliseconds.
+    #[serde(default)]
+    pub duration: Option<u64>,
+    /// The machine a run was on.
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// The shell session of a run.
+    #[serde(default)]
+    pub session: Option<String>,
+    /// The name of a snippet.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl Record {
+    /// A record of `kind` for `command`, with nothing else known.
+    pub fn new(kind: Kind, command: String) -> Record {
+        Record {
+            kind,
+            command,
+            shell: None,
+            cwd: None,
+            count: None,
+            timestamp: None,
+            exit: None,
+            duration: None,
+            hostname: None,
+            session: None,
+            name: None,
+        }
+    }
+}
+
+/// Writes `records` to `out` in `format`.
+pub fn write<W: Write>(out: W, records: &[Record], format: Format) -> io::Result<()> {
+    let mut out = BufWriter::new(out);
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, records)?;
+            writeln!(out)?;
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(&mut out);
+            for record in records {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        }
+    }
+    out.flush()
+}
+
+/// Reads the records of the export at `path`, written in `format`.
+pub fn read(path: &Path, format: Format) -> Result<Vec<Record>> {
+    let file = File::open(path).map_err(|source| ThError::HistoryFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let invalid = |message: String| ThError::Export {
+        path: path.to_path_buf(),
+        message,
+    };
+    let records = parse(BufReader::new(file), format).map_err(invalid)?;
+    // The store cannot hold a run without a time.
+    match records
+        .iter()
+        .find(|record| record.kind == Kind::Run && record.timestamp.is_none())
+    {
+        Some(run) => Err(invalid(format!(
+            "run of `{}` has no timestamp",
+            run.command
+        ))),
+        None => Ok(records),
+    }
+}
+
+fn parse<R: Read>(input: R, format: Format) -> Result<Vec<Record>, String> {
+    match format {
+        Format::Json => serde_json::from_reader(input).map_err(|err| err.to_string()),
+        Format::Csv => csv::Reader::from_reader(input)
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.to_string()),
+    }
+}
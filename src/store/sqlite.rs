@@ -0,0 +1,733 @@
+//! SQLite-backed history store.
+//!
+//! Each row of `history` is one distinct command per shell and working
+//! directory, with how many times it ran and when it last ran. Commands
+//! imported from history files have no known directory and are stored with
+//! an empty `cwd`. Each row of `runs` is one [`Run`] the shell hooks recorded.
+//!
+//! Commands the user deleted are remembered in a separate table so that
+//! re-importing a history file that still holds them does not bring them
+//! back. Bookmarked commands, tags, and snippets have tables of their own
+//! too, as do the commands chosen in the picker for each query, by which
+//! th_rs learns what to rank first.
+//!
+//! Built with the `encryption` feature, SQLite is SQLCipher, and a store can
+//! be encrypted as a whole with a key derived from a passphrase; see
+//! [`Store::open_encrypted`].
+
+use super::export::{Kind, Record};
+use super::Run;
+use crate::error::{Result, ThError};
+use crate::history::backup::Backups;
+use crate::history::{prune, Entry};
+use crate::paths;
+use crate::search::rank::{self, Ranking};
+use crate::search::{CommandStats, FrequencyMap};
+use crate::shell::Shell;
+use rusqlite::{params, Connection};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS history (
+        command   TEXT NOT NULL,
+        shell     TEXT NOT NULL,
+        cwd       TEXT NOT NULL DEFAULT '',
+        count     INTEGER NOT NULL,
+        timestamp INTEGER,
+        PRIMARY KEY (command, shell, cwd)
+    );
+    CREATE TABLE IF NOT EXISTS deleted (
+        command TEXT PRIMARY KEY
+    );
+    CREATE TABLE IF NOT EXISTS runs (
+        id        INTEGER PRIMARY KEY,
+        command   TEXT NOT NULL,
+        shell     TEXT NOT NULL,
+        cwd       TEXT NOT NULL,
+        exit      INTEGER,
+        duration  INTEGER,
+        hostname  TEXT NOT NULL,
+        session   TEXT NOT NULL,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runs_by_command ON runs (command);
+    CREATE TABLE IF NOT EXISTS bookmarks (
+        command TEXT PRIMARY KEY
+    );
+    CREATE TABLE IF NOT EXISTS tags (
+        command TEXT NOT NULL,
+        tag     TEXT NOT NULL,
+        PRIMARY KEY (command, tag)
+    );
+    CREATE TABLE IF NOT EXISTS snippets (
+        name    TEXT PRIMARY KEY,
+        command TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS picks (
+        prefix  TEXT NOT NULL,
+        command TEXT NOT NULL,
+        count   INTEGER NOT NULL,
+        PRIMARY KEY (prefix, command)
+    );
+";
+
+/// How long to wait for another th_rs, e.g. the hook of another shell, to
+/// finish writing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// An open history database.
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Opens the database at `path`, creating it and its directory if needed.
+    pub fn open(path: &Path) -> Result<Store> {
+        create_private(path)?;
+        Store::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens the database at `path` encrypted with `passphrase`, creating it
+    /// and its directory if needed. SQLCipher derives the key from the
+    /// passphrase with PBKDF2 and encrypts every page with AES-256.
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted(path: &Path, passphrase: &str) -> Result<Store> {
+        create_private(path)?;
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "key", passphrase)?;
+        // A wrong key only shows once something is read.
+        conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+            .map_err(|_| {
+                ThError::Encryption(format!(
+                    "could not open {}: the passphrase is wrong, or it is not encrypted yet (see `th_rs encrypt`)",
+                    path.display()
+                ))
+            })?;
+        Store::from_connection(conn)
+    }
+
+    /// Always fails: without the `encryption` feature there is no SQLCipher.
+    #[cfg(not(feature = "encryption"))]
+    pub fn open_encrypted(_path: &Path, _passphrase: &str) -> Result<Store> {
+        Err(not_built_in())
+    }
+
+    /// Encrypts the plain database at `path` in place with `passphrase`, so
+    /// that [`Store::open_encrypted`] opens it from then on.
+    #[cfg(feature = "encryption")]
+    pub fn encrypt(path: &Path, passphrase: &str) -> Result<()> {
+        let mut encrypted = path.as_os_str().to_owned();
+        encrypted.push(".encrypting");
+        match fs::remove_file(&encrypted) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        // SQLite would create the copy readable by all.
+        paths::private_file().create_new(true).open(&encrypted)?;
+        let conn = Connection::open(path)?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            params![encrypted.to_string_lossy(), passphrase],
+        )?;
+        conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+        conn.execute("DETACH DATABASE encrypted", [])?;
+        drop(conn);
+        fs::rename(&encrypted, path)?;
+        Ok(())
+    }
+
+    /// Always fails: without the `encryption` feature there is no SQLCipher.
+    #[cfg(not(feature = "encryption"))]
+    pub fn encrypt(_path: &Path, _passphrase: &str) -> Result<()> {
+        Err(not_built_in())
+    }
+
+    /// Opens a throwaway database that lives only in memory.
+    pub fn open_in_memory() -> Result<Store> {
+        Store::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Store> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Store { conn })
+    }
+
+    /// Imports the entries of a `shell` history file. Files from an unknown
+    /// shell are stored with an empty `shell`.
+    ///
+    /// A history file is the complete record for its shell, so the imported
+    /// counts replace earlier ones rather than adding to them: importing the
+    /// same file twice leaves the store unchanged. Deleted commands are
+    /// skipped. Returns the number of distinct commands imported.
+    pub fn import(&mut self, shell: Option<Shell>, entries: &[Entry]) -> Result<usize> {
+        let shell = shell.map(Shell::name).unwrap_or_default();
+        let deleted = self.deleted()?;
+        let mut commands: HashMap<&str, (i64, Option<i64>)> = HashMap::new();
+        for entry in entries.iter().filter(|e| !deleted.contains(&e.command)) {
+            let (count, last) = commands.entry(&entry.command).or_insert((0, None));
+            *count += 1;
+            *last = (*last).max(entry.timestamp);
+        }
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut upsert = tx.prepare(
+                "INSERT INTO history (command, shell, cwd, count, timestamp)
+                 VALUES (?1, ?2, '', ?3, ?4)
+                 ON CONFLICT (command, shell, cwd) DO UPDATE SET
+                     count = excluded.count,
+                     timestamp = coalesce(max(timestamp, excluded.timestamp),
+                                          timestamp, excluded.timestamp)",
+            )?;
+            for (command, (count, timestamp)) in &commands {
+                upsert.execute(params![command, shell, count, timestamp])?;
+            }
+        }
+        tx.commit()?;
+        Ok(commands.len())
+    }
+
+    /// Adds one run of a command, as reported by a shell hook.
+    pub fn record(&mut self, run: &Run) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO runs (command, shell, cwd, exit, duration, hostname, session, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                run.command,
+                run.shell.map(Shell::name).unwrap_or_default(),
+                run.cwd,
+                run.exit,
+                run.duration,
+                run.hostname,
+                run.session,
+                run.timestamp,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Deletes `command` from every shell and directory, and keeps later
+    /// imports from adding it again. Returns how many rows were removed.
+    pub fn delete(&mut self, command: &str) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let removed = tx.execute("DELETE FROM history WHERE command = ?1", [command])?
+            + tx.execute("DELETE FROM runs WHERE command = ?1", [command])?;
+        tx.execute("DELETE FROM bookmarks WHERE command = ?1", [command])?;
+        tx.execute("DELETE FROM tags WHERE command = ?1", [command])?;
+        tx.execute("DELETE FROM picks WHERE command = ?1", [command])?;
+        tx.execute(
+            "INSERT OR IGNORE INTO deleted (command) VALUES (?1)",
+            [command],
+        )?;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Removes the runs `rules` prune, imported and recorded alike, and
+    /// returns how many runs of each command went. With `dry_run`, only says
+    /// what would go. Unlike deleted commands, pruned ones may be imported
+    /// again. The database is backed up to `backups` first.
+    ///
+    /// An imported command stands for all its runs in a shell at its latest
+    /// time, so deduplicating keeps one run of the latest row.
+    pub fn prune(
+        &mut self,
+        rules: &prune::Rules,
+        dry_run: bool,
+        backups: &Backups,
+    ) -> Result<BTreeMap<String, usize>> {
+        // Whether each row is recorded, its id, and what it stands for.
+        let mut rows: Vec<(bool, i64, prune::Item)> = Vec::new();
+        {
+            let mut stmt = self
+                .conn
+                .prepare("SELECT rowid, command, count, timestamp FROM history")?;
+            let mut imported = stmt.query([])?;
+            while let Some(row) = imported.next()? {
+                let item = prune::Item {
+                    command: row.get(1)?,
+                    timestamp: row.get(3)?,
+                    runs: row.get::<_, i64>(2)?.max(1) as usize,
+                };
+                rows.push((false, row.get(0)?, item));
+            }
+            let mut stmt = self
+                .conn
+                .prepare("SELECT id, command, timestamp FROM runs")?;
+            let mut recorded = stmt.query([])?;
+            while let Some(row) = recorded.next()? {
+                let item = prune::Item {
+                    command: row.get(1)?,
+                    timestamp: Some(row.get(2)?),
+                    runs: 1,
+                };
+                rows.push((true, row.get(0)?, item));
+            }
+        }
+        rows.sort_by_key(|(_, _, item)| item.timestamp);
+        let items: Vec<prune::Item> = rows.iter().map(|(_, _, item)| item.clone()).collect();
+        let keep = prune::keep(&items, rules);
+        let mut removed = prune::removed(&items, &keep);
+        let mut squashed = Vec::new();
+        if rules.dedupe {
+            for ((recorded, id, item), _) in rows.iter().zip(&keep).filter(|(_, &keep)| keep) {
+                if !recorded && item.runs > 1 {
+                    *removed.entry(item.command.clone()).or_default() += item.runs - 1;
+                    squashed.push(*id);
+                }
+            }
+        }
+        if dry_run || removed.is_empty() {
+            return Ok(removed);
+        }
+        self.back_up(backups)?;
+        let tx = self.conn.transaction()?;
+        for ((recorded, id, _), _) in rows.iter().zip(&keep).filter(|(_, &keep)| !keep) {
+            if *recorded {
+                tx.execute("DELETE FROM runs WHERE id = ?1", [id])?;
+            } else {
+                tx.execute("DELETE FROM history WHERE rowid = ?1", [id])?;
+            }
+        }
+        for id in squashed {
+            tx.execute("UPDATE history SET count = 1 WHERE rowid = ?1", [id])?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Backs up the database to `backups`, encrypted as the store is. One
+    /// in memory is not backed up.
+    fn back_up(&self, backups: &Backups) -> Result<()> {
+        let Some(path) = self.conn.path().filter(|path| !path.is_empty()) else {
+            return Ok(());
+        };
+        backups
+            .save_with(Path::new(path), |copy| {
+                self.conn
+                    .execute("VACUUM INTO ?1", [copy.to_string_lossy()])?;
+                Ok(())
+            })
+            .map(drop)
+    }
+
+    /// Everything in the store: imported commands, recorded runs,
+    /// bookmarks, snippets, and deleted commands, in that order.
+    pub fn export(&self) -> Result<Vec<Record>> {
+        let mut records = Vec::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT command, shell, cwd, count, timestamp FROM history
+             ORDER BY command, shell, cwd",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            records.push(Record {
+                shell: row.get::<_, String>(1)?.parse().ok(),
+                cwd: Some(row.get::<_, String>(2)?).filter(|cwd| !cwd.is_empty()),
+                count: row.get(3)?,
+                timestamp: row.get(4)?,
+                ..Record::new(Kind::History, row.get(0)?)
+            });
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT command, shell, cwd, exit, duration, hostname, session, timestamp
+             FROM runs ORDER BY timestamp, id",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            records.push(Record {
+                shell: row.get::<_, String>(1)?.parse().ok(),
+                cwd: row.get(2)?,
+                exit: row.get(3)?,
+                duration: row.get(4)?,
+                hostname: row.get(5)?,
+                session: row.get(6)?,
+                timestamp: row.get(7)?,
+                ..Record::new(Kind::Run, row.get(0)?)
+            });
+        }
+        records.extend(
+            self.bookmarks()?
+                .into_iter()
+                .map(|command| Record::new(Kind::Bookmark, command)),
+        );
+        records.extend(self.snippets()?.into_iter().map(|(name, command)| Record {
+            name: Some(name),
+            ..Record::new(Kind::Snippet, command)
+        }));
+        let mut deleted: Vec<String> = self.deleted()?.into_iter().collect();
+        deleted.sort();
+        records.extend(
+            deleted
+                .into_iter()
+                .map(|command| Record::new(Kind::Deleted, command)),
+        );
+        Ok(records)
+    }
+
+    /// Adds `records` from [`Store::export`], e.g. of another machine's
+    /// store, to this one.
+    ///
+    /// Loading an export twice changes nothing the second time: imported
+    /// commands keep the higher count and later time of the two, and runs
+    /// already stored are skipped. Deleted commands are deleted here too.
+    /// Returns the number of records loaded.
+    pub fn load(&mut self, records: &[Record]) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut forget = tx.prepare("INSERT OR IGNORE INTO deleted (command) VALUES (?1)")?;
+            for record in records.iter().filter(|r| r.kind == Kind::Deleted) {
+                forget.execute([&record.command])?;
+            }
+            tx.execute(
+                "DELETE FROM history WHERE command IN (SELECT command FROM deleted)",
+                [],
+            )?;
+            tx.execute(
+                "DELETE FROM runs WHERE command IN (SELECT command FROM deleted)",
+                [],
+            )?;
+        }
+        let deleted = {
+            let mut stmt = tx.prepare("SELECT command FROM deleted")?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<HashSet<String>>>()?
+        };
+        let mut loaded = 0;
+        {
+            let mut upsert = tx.prepare(
+                "INSERT INTO history (command, shell, cwd, count, timestamp)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (command, shell, cwd) DO UPDATE SET
+                     count = max(count, excluded.count),
+                     timestamp = coalesce(max(timestamp, excluded.timestamp),
+                                          timestamp, excluded.timestamp)",
+            )?;
+            let mut insert = tx.prepare(
+                "INSERT INTO runs (command, shell, cwd, exit, duration, hostname, session, timestamp)
+                 SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8
+                 WHERE NOT EXISTS (
+                     SELECT 1 FROM runs WHERE command = ?1 AND shell = ?2 AND cwd = ?3
+                         AND hostname = ?6 AND session = ?7 AND timestamp = ?8
+                 )",
+            )?;
+            for record in records.iter().filter(|r| !deleted.contains(&r.command)) {
+                let shell = record.shell.map(Shell::name).unwrap_or_default();
+                let cwd = record.cwd.as_deref().unwrap_or_default();
+                match record.kind {
+                    Kind::History => upsert.execute(params![
+                        record.command,
+                        shell,
+                        cwd,
+                        record.count.unwrap_or(1),
+                        record.timestamp,
+                    ])?,
+                    Kind::Run => insert.execute(params![
+                        record.command,
+                        shell,
+                        cwd,
+                        record.exit,
+                        record.duration,
+                        record.hostname.as_deref().unwrap_or_default(),
+                        record.session.as_deref().unwrap_or_default(),
+                        record.timestamp,
+                    ])?,
+                    Kind::Bookmark => tx.execute(
+                        "INSERT OR IGNORE INTO bookmarks (command) VALUES (?1)",
+                        [&record.command],
+                    )?,
+                    Kind::Snippet => tx.execute(
+                        "INSERT OR REPLACE INTO snippets (name, command) VALUES (?1, ?2)",
+                        params![record.name.as_deref().unwrap_or_default(), record.command],
+                    )?,
+                    Kind::Deleted => continue,
+                };
+                loaded += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(loaded)
+    }
+
+    /// The bookmarked commands, in alphabetical order.
+    pub fn bookmarks(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT command FROM bookmarks ORDER BY command")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Bookmarks `command`. Returns whether it was not bookmarked already.
+    pub fn bookmark(&mut self, command: &str) -> Result<bool> {
+        let added = self.conn.execute(
+            "INSERT OR IGNORE INTO bookmarks (command) VALUES (?1)",
+            [command],
+        )?;
+        Ok(added > 0)
+    }
+
+    /// Removes the bookmark on `command`. Returns whether there was one.
+    pub fn unbookmark(&mut self, command: &str) -> Result<bool> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM bookmarks WHERE command = ?1", [command])?;
+        Ok(removed > 0)
+    }
+
+    /// Every tagged command with one of its tags, by command then tag.
+    pub fn tags(&self) -> Result<Vec<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT command, tag FROM tags ORDER BY command, tag")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Tags `command` with `tag`. Returns whether it was not tagged so
+    /// already.
+    pub fn tag(&mut self, command: &str, tag: &str) -> Result<bool> {
+        let added = self.conn.execute(
+            "INSERT OR IGNORE INTO tags (command, tag) VALUES (?1, ?2)",
+            [command, tag],
+        )?;
+        Ok(added > 0)
+    }
+
+    /// Takes `tag` off `command`. Returns whether it had it.
+    pub fn untag(&mut self, command: &str, tag: &str) -> Result<bool> {
+        let removed = self.conn.execute(
+            "DELETE FROM tags WHERE command = ?1 AND tag = ?2",
+            [command, tag],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// Every snippet, as its name and command, by name.
+    pub fn snippets(&self) -> Result<Vec<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, command FROM snippets ORDER BY name")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Saves `command` as the snippet `name`, replacing any of that name.
+    /// Returns whether one was replaced.
+    pub fn add_snippet(&mut self, name: &str, command: &str) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        let replaced = tx.execute("DELETE FROM snippets WHERE name = ?1", [name])?;
+        tx.execute(
+            "INSERT INTO snippets (name, command) VALUES (?1, ?2)",
+            [name, command],
+        )?;
+        tx.commit()?;
+        Ok(replaced > 0)
+    }
+
+    /// Removes the snippet `name`. Returns whether there was one.
+    pub fn remove_snippet(&mut self, name: &str) -> Result<bool> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM snippets WHERE name = ?1", [name])?;
+        Ok(removed > 0)
+    }
+
+    /// How often each command was chosen for each query; see [`rank::Picks`].
+    pub fn picks(&self) -> Result<rank::Picks> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT prefix, command, count FROM picks")?;
+        let mut rows = stmt.query([])?;
+        let mut picks = rank::Picks::default();
+        while let Some(row) = rows.next()? {
+            picks.insert(row.get(0)?, row.get(1)?, row.get(2)?);
+        }
+        Ok(picks)
+    }
+
+    /// Notes that `command` was chosen in the picker for `query`. Nothing is
+    /// noted for an empty query.
+    pub fn record_pick(&mut self, query: &str, command: &str) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        for prefix in rank::Picks::prefixes(query) {
+            tx.execute(
+                "INSERT INTO picks (prefix, command, count) VALUES (?1, ?2, 1)
+                 ON CONFLICT (prefix, command) DO UPDATE SET count = count + 1",
+                [prefix.as_str(), command],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn deleted(&self) -> Result<HashSet<String>> {
+        let mut stmt = self.conn.prepare("SELECT command FROM deleted")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Builds a frequency map over every shell and directory in the store.
+    ///
+    /// Imported history keeps only the latest run of each command, so for
+    /// frecency its runs are treated as having happened then, and for time
+    /// filters only that run has a time. Recorded runs each count at their
+    /// own time. Those run in the shell session `session` mark their
+    /// commands as such; see [`CommandStats::in_session`].
+    pub fn frequency_map(&self, ranking: &Ranking, session: Option<&str>) -> Result<FrequencyMap> {
+        let mut frequency = self.imported(ranking)?;
+        self.add_recorded(&mut frequency, ranking, session)?;
+        Ok(frequency)
+    }
+
+    /// Every run in the store, for `th_rs stats`: each recorded run, and as
+    /// many of each imported command as it ran, of which only the last has
+    /// a time, as for [`Store::frequency_map`].
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        let mut stmt = self
+            .conn
+            .prepare("SELECT command, shell, nullif(cwd, ''), count, timestamp FROM history")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let last = Entry {
+                timestamp: row.get(4)?,
+                cwd: row.get(2)?,
+                shell: row.get::<_, String>(1)?.parse().ok(),
+                ..Entry::new(row.get::<_, String>(0)?)
+            };
+            let earlier = (row.get::<_, i64>(3)?.max(1) - 1) as usize;
+            entries.extend(std::iter::repeat_n(
+                Entry {
+                    timestamp: None,
+                    ..last.clone()
+                },
+                earlier,
+            ));
+            entries.push(last);
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT command, shell, cwd, exit, duration, timestamp FROM runs
+             ORDER BY timestamp, id",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            entries.push(Entry {
+                shell: row.get::<_, String>(1)?.parse().ok(),
+                cwd: row.get(2)?,
+                exit: row.get(3)?,
+                // Recorded in milliseconds.
+                duration: row.get::<_, Option<u64>>(4)?.map(|ms| ms / 1000),
+                timestamp: row.get(5)?,
+                ..Entry::new(row.get::<_, String>(0)?)
+            });
+        }
+        Ok(entries)
+    }
+
+    fn imported(&self, ranking: &Ranking) -> Result<FrequencyMap> {
+        let now = rank::now();
+        // With a single max() aggregate, SQLite takes the bare `shell` and
+        // `cwd` columns from the row holding the maximum: the latest run.
+        let mut stmt = self.conn.prepare(
+            "SELECT command, sum(count), max(timestamp), shell, nullif(cwd, ''),
+                    group_concat(DISTINCT shell)
+             FROM history GROUP BY command",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let count = row.get::<_, i64>(1)? as usize;
+            let last_used = row.get::<_, Option<i64>>(2)?;
+            let stats = CommandStats {
+                count,
+                last_used,
+                frecency: count as f64 * ranking.run_weight(last_used, now),
+                shell: row.get::<_, String>(3)?.parse().ok(),
+                cwd: row.get(4)?,
+                dirs: row.get::<_, Option<String>>(4)?.into_iter().collect(),
+                shells: row
+                    .get::<_, String>(5)?
+                    .split(',')
+                    .filter_map(|shell| shell.parse().ok())
+                    .collect(),
+                runs: last_used.into_iter().collect(),
+                exit: None,
+                in_session: None,
+                bookmarked: false,
+                snippet: None,
+                description: None,
+                tags: BTreeSet::new(),
+            };
+            Ok((row.get::<_, String>(0)?, stats))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn add_recorded(
+        &self,
+        frequency: &mut FrequencyMap,
+        ranking: &Ranking,
+        session: Option<&str>,
+    ) -> Result<()> {
+        let now = rank::now();
+        let mut stmt = self.conn.prepare(
+            "SELECT command, timestamp, shell, cwd, exit, session FROM runs
+             ORDER BY timestamp, id",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let timestamp: i64 = row.get(1)?;
+            let shell: Option<Shell> = row.get::<_, String>(2)?.parse().ok();
+            let stats = frequency.entry(row.get(0)?).or_default();
+            stats.count += 1;
+            stats.frecency += ranking.run_weight(Some(timestamp), now);
+            stats.runs.add(timestamp);
+            if let Some(shell) = shell {
+                stats.shells.insert(shell);
+            }
+            let cwd: String = row.get(3)?;
+            if Some(timestamp) >= stats.last_used {
+                stats.last_used = Some(timestamp);
+                stats.shell = shell.or(stats.shell);
+                stats.cwd = Some(cwd.clone());
+                stats.exit = row.get(4)?;
+            }
+            stats.dirs.insert(cwd);
+            // An empty id is no session at all, not one shared by every run.
+            let ran_in: String = row.get(5)?;
+            if session.is_some_and(|session| !session.is_empty() && session == ran_in) {
+                stats.in_session = Some(timestamp);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Creates the database at `path` and its directory, unless they exist,
+/// readable by the user alone, as SQLite would not: the store holds every
+/// command recorded. A database from before th_rs made it private is
+/// narrowed, but a directory that already exists is left as it is, since
+/// it need not be th_rs's.
+fn create_private(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.exists()) {
+        paths::create_private_dir(dir)?;
+    }
+    paths::private_file().create(true).open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn not_built_in() -> ThError {
+    ThError::Encryption(
+        "th_rs was built without the `encryption` feature; reinstall it with `--features encryption`"
+            .to_string(),
+    )
+}
@@ -0,0 +1,213 @@
+//! Merging history stores across machines, for `th_rs sync`.
+//!
+//! A sync pulls the shared copy from the remote, loads it into the local
+//! store, and pushes back what the copy lacked. Once every machine has
+//! synced, they all hold the union of their histories: each imported
+//! command with the higher count and the later time, and each recorded run
+//! once. The copy on the remote is encrypted with a key only the machines
+//! have; see [`crypt`].
+//!
+//! The copy is a log: a header, then one sealed segment for each push, each
+//! after its length. A sync appends a segment of the records that are new
+//! or changed since the copy was pulled, and once the log has grown to
+//! `MAX_SEGMENTS`, replaces it with one segment of the whole store.
+//!
+//! ```toml
+//! [sync]
+//! remote = "me@nas:th_rs.sync"
+//! ```
+
+pub mod crypt;
+pub mod remote;
+
+use crate::config;
+use crate::error::{Result, ThError};
+use crate::paths;
+use crate::store::export::Record;
+use crate::store::sqlite::Store;
+use crypt::Key;
+use remote::Remote;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What the shared log starts with.
+const LOG_HEADER: &[u8] = b"th_rs sync log 1\n";
+
+/// How many segments the shared log may hold before a sync replaces them
+/// with one.
+const MAX_SEGMENTS: usize = 32;
+
+/// The `[sync]` section of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Where the shared copy lives; see [`remote`] for the forms it takes.
+    pub remote: Option<Remote>,
+    /// The file holding the key; `sync.key` in the data directory if not
+    /// given.
+    pub key_file: Option<PathBuf>,
+}
+
+impl SyncConfig {
+    /// Where the key is kept.
+    pub fn key_path(&self) -> Result<PathBuf> {
+        match &self.key_file {
+            Some(path) => Ok(config::expand_home(path)),
+            None => Ok(paths::data_dir()?.join("sync.key")),
+        }
+    }
+}
+
+/// What a sync did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Synced {
+    /// How many records were loaded from the remote.
+    pub pulled: usize,
+    /// How many records were pushed back.
+    pub pushed: usize,
+    /// Whether this was the first sync with the remote, so a new key was
+    /// made for the other machines to copy.
+    pub created_key: bool,
+}
+
+/// Merges `store` with the copy on `remote`, encrypted with the key at
+/// `key_path`. The first machine to sync makes the key; the others need a
+/// copy of it before their first sync.
+pub fn sync(store: &mut Store, remote: &Remote, key_path: &Path) -> Result<Synced> {
+    let theirs = remote.pull()?;
+    let mut created_key = false;
+    let key = match (Key::load(key_path)?, &theirs) {
+        (Some(key), _) => key,
+        (None, Some(_)) => {
+            return Err(ThError::Sync(format!(
+            "{} is already synced with another key; copy it to {} from a machine syncing with it",
+            remote,
+            key_path.display()
+        )))
+        }
+        (None, None) => {
+            let key = Key::generate();
+            key.save(key_path)?;
+            created_key = true;
+            key
+        }
+    };
+
+    let mut theirs_records = Vec::new();
+    // Where the next segment can go, if the log can simply be added to.
+    let mut appendable = None;
+    if let Some(log) = &theirs {
+        let segments = match split_log(log) {
+            Some((segments, whole)) => {
+                if whole && segments.len() < MAX_SEGMENTS {
+                    appendable = Some(log.as_slice());
+                }
+                segments
+            }
+            // A copy pushed before there was a log is one segment by itself.
+            None => vec![log.as_slice()],
+        };
+        for sealed in segments {
+            let plaintext = key.open(sealed).ok_or_else(|| {
+                ThError::Sync(format!(
+                    "could not decrypt {} with the key in {}; is it the key the other machines use?",
+                    remote,
+                    key_path.display()
+                ))
+            })?;
+            let records: Vec<Record> = serde_json::from_slice(&plaintext).map_err(|err| {
+                ThError::Sync(format!("{} is not a th_rs store: {}", remote, err))
+            })?;
+            theirs_records.extend(records);
+        }
+    }
+    let pulled = store.load(&theirs_records)?;
+
+    let records = store.export()?;
+    let pushed = match appendable {
+        Some(log) => {
+            let known: HashSet<Record> = theirs_records.into_iter().collect();
+            let delta: Vec<&Record> = records.iter().filter(|r| !known.contains(r)).collect();
+            if !delta.is_empty() {
+                remote.append(log, &segment(&key, &delta)?)?;
+            }
+            delta.len()
+        }
+        None => {
+            remote.push(&[LOG_HEADER, &segment(&key, &records)?].concat())?;
+            records.len()
+        }
+    };
+    Ok(Synced {
+        pulled,
+        pushed,
+        created_key,
+    })
+}
+
+/// `records`, sealed with `key` and framed for the log.
+fn segment<T: serde::Serialize>(key: &Key, records: &[T]) -> Result<Vec<u8>> {
+    let plaintext = serde_json::to_vec(records).map_err(io::Error::from)?;
+    let sealed = key.seal(&plaintext);
+    Ok([&(sealed.len() as u64).to_be_bytes(), sealed.as_slice()].concat())
+}
+
+/// The sealed segments of `log`, and whether it ends after the last of
+/// them, or `None` if it is not a log. A segment cut short, as one being
+/// appended as the log was pulled, is left out.
+fn split_log(log: &[u8]) -> Option<(Vec<&[u8]>, bool)> {
+    let mut rest = log.strip_prefix(LOG_HEADER)?;
+    let mut segments = Vec::new();
+    while let Some((len, after)) = rest.split_first_chunk::<8>() {
+        match usize::try_from(u64::from_be_bytes(*len)) {
+            Ok(len) if len <= after.len() => {
+                let (sealed, after) = after.split_at(len);
+                segments.push(sealed);
+                rest = after;
+            }
+            _ => break,
+        }
+    }
+    Some((segments, rest.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn pushes_only_what_the_remote_lacks() {
+        let dir = std::env::temp_dir().join(format!("th_rs-sync-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let remote = Remote::File(dir.join("th_rs.sync"));
+        let key_path = dir.join("sync.key");
+        let mut laptop = Store::open_in_memory().unwrap();
+        let mut desktop = Store::open_in_memory().unwrap();
+
+        laptop.bookmark("make").unwrap();
+        let synced = sync(&mut laptop, &remote, &key_path).unwrap();
+        assert_eq!(
+            (synced.pulled, synced.pushed, synced.created_key),
+            (0, 1, true)
+        );
+
+        desktop.bookmark("ls").unwrap();
+        let synced = sync(&mut desktop, &remote, &key_path).unwrap();
+        assert_eq!((synced.pulled, synced.pushed), (1, 1));
+
+        let synced = sync(&mut laptop, &remote, &key_path).unwrap();
+        assert_eq!(synced.pushed, 0);
+        assert_eq!(laptop.bookmarks().unwrap(), ["ls", "make"]);
+
+        let log = fs::read(dir.join("th_rs.sync")).unwrap();
+        assert_eq!(
+            split_log(&log).map(|(segments, whole)| (segments.len(), whole)),
+            Some((2, true))
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
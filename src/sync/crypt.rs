@@ -0,0 +1,116 @@
+//! Encrypting what is pushed to a sync remote, so that whoever hosts it
+//! learns nothing about the history but its size.
+//!
+//! The machines share a random 256-bit key, kept in a file on each of them.
+//! A sealed copy is a short header, a random nonce, and the plaintext
+//! encrypted with XChaCha20-Poly1305. The cipher is authenticated, so a copy
+//! altered on the remote, or sealed with another key, fails to open rather
+//! than merging garbage.
+
+use crate::error::{Result, ThError};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// What every sealed copy starts with, naming its format.
+const HEADER: &[u8] = b"th_rs sync 1\n";
+
+/// The length of an XChaCha20 nonce.
+const NONCE_LEN: usize = 24;
+
+/// The key the machines syncing one history share.
+pub struct Key(chacha20poly1305::Key);
+
+impl Key {
+    /// A new random key.
+    pub fn generate() -> Key {
+        Key(XChaCha20Poly1305::generate_key(&mut OsRng))
+    }
+
+    /// The key saved at `path`, or `None` if there is no file.
+    pub fn load(path: &Path) -> Result<Option<Key>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let bytes = STANDARD.decode(text.trim()).unwrap_or_default();
+        if bytes.len() != 32 {
+            return Err(ThError::Sync(format!(
+                "{} does not hold a sync key",
+                path.display()
+            )));
+        }
+        Ok(Some(Key(*chacha20poly1305::Key::from_slice(&bytes))))
+    }
+
+    /// Saves the key at `path`, where there must be no file yet, readable
+    /// only by the user from the moment it is created.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        file.write_all(format!("{}\n", STANDARD.encode(self.0)).as_bytes())?;
+        Ok(())
+    }
+
+    /// `plaintext`, encrypted.
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = XChaCha20Poly1305::new(&self.0)
+            .encrypt(&nonce, plaintext)
+            .expect("encrypting into memory does not fail");
+        [HEADER, nonce.as_slice(), &ciphertext].concat()
+    }
+
+    /// What [`Key::seal`] encrypted, or `None` if `sealed` was not sealed
+    /// with this key or has been altered since.
+    pub fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        let rest = sealed.strip_prefix(HEADER)?;
+        if rest.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        XChaCha20Poly1305::new(&self.0)
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_what_it_sealed() {
+        let key = Key::generate();
+        let sealed = key.seal(b"git push --force");
+        assert!(!sealed.windows(4).any(|window| window == b"push"));
+        assert_eq!(key.open(&sealed).as_deref(), Some(&b"git push --force"[..]));
+    }
+
+    #[test]
+    fn rejects_altered_copies_and_other_keys() {
+        let key = Key::generate();
+        let sealed = key.seal(b"git push --force");
+        for at in [0, HEADER.len(), HEADER.len() + NONCE_LEN, sealed.len() - 1] {
+            let mut altered = sealed.clone();
+            altered[at] ^= 1;
+            assert_eq!(key.open(&altered), None, "altered at {}", at);
+        }
+        assert_eq!(key.open(&sealed[..sealed.len() - 1]), None);
+        assert_eq!(Key::generate().open(&sealed), None);
+    }
+}
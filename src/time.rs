@@ -0,0 +1,188 @@
+//! Points in time as users write them, for filtering history by when it ran.
+//!
+//! A time is either a date (`2024-05-01`, optionally with `14:30`), a day
+//! named relative to today (`today`, `yesterday`, `tuesday` for the latest
+//! Tuesday), or an age (`30m`, `12h`, `7d`, `2w`, `1y`) meaning that long ago.
+//! Dates and days are in local time and cover the whole day, so that
+//! `--since tuesday --until tuesday` is all of Tuesday.
+//!
+//! In a picker query, a word like `@tuesday` or `@7d` is a filter rather than
+//! part of the pattern: `@7d git` finds commands matching `git` run in the
+//! last week. See [`split_filters`].
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Weekday};
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// What a written time refers to, from its first second to just past its
+/// last; an age is a single instant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// The first second, in Unix seconds.
+    pub start: i64,
+    /// The second after the last.
+    pub end: i64,
+}
+
+impl FromStr for Span {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Span::parse(s, Local::now()).ok_or_else(|| {
+            format!(
+                "unknown time: {} (expected a date like 2024-05-01, today, yesterday, a weekday, or an age like 7d)",
+                s
+            )
+        })
+    }
+}
+
+impl Span {
+    /// Parses `s` relative to `now`.
+    pub fn parse(s: &str, now: DateTime<Local>) -> Option<Span> {
+        let s = s.trim().to_ascii_lowercase();
+        if let Some(age) = age(&s) {
+            let instant = (now - age).timestamp();
+            return Some(Span {
+                start: instant,
+                end: instant,
+            });
+        }
+        let today = now.date_naive();
+        let day = match s.as_str() {
+            "today" => Some(today),
+            "yesterday" => today.pred_opt(),
+            _ => match s.parse::<Weekday>() {
+                Ok(weekday) => {
+                    let back = (7 + today.weekday().num_days_from_monday()
+                        - weekday.num_days_from_monday())
+                        % 7;
+                    today.checked_sub_signed(Duration::days(back as i64))
+                }
+                Err(_) => NaiveDate::parse_from_str(&s, "%Y-%m-%d").ok(),
+            },
+        };
+        if let Some(day) = day {
+            return Some(Span {
+                start: local(day.and_hms_opt(0, 0, 0)?)?,
+                end: local(day.succ_opt()?.and_hms_opt(0, 0, 0)?)?,
+            });
+        }
+        // A minute of a day.
+        let minute = ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(&s, format).ok())?;
+        let start = local(minute)?;
+        Some(Span {
+            start,
+            end: start + 60,
+        })
+    }
+}
+
+/// An age like `7d`: a number and one of m, h, d, w, or y (365 days).
+fn age(s: &str) -> Option<Duration> {
+    let unit = s.chars().last()?;
+    let amount: i64 = s[..s.len() - unit.len_utf8()].parse().ok()?;
+    match unit {
+        'm' => Duration::try_minutes(amount),
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        'y' => Duration::try_days(amount.checked_mul(365)?),
+        _ => None,
+    }
+}
+
+/// The Unix time of a local date and time; the earlier one when a DST
+/// change makes it ambiguous.
+fn local(time: NaiveDateTime) -> Option<i64> {
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|t| t.timestamp())
+}
+
+/// The local date and time of the Unix time `timestamp`.
+pub fn local_time(timestamp: i64) -> Option<DateTime<Local>> {
+    Local.timestamp_opt(timestamp, 0).single()
+}
+
+/// The times between which history is wanted; either end may be open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Range {
+    /// The earliest Unix time included.
+    pub since: Option<i64>,
+    /// The first Unix time past the end.
+    pub until: Option<i64>,
+}
+
+impl Range {
+    /// From `since` the start of one span `until` the end of another.
+    pub fn new(since: Option<Span>, until: Option<Span>) -> Range {
+        Range {
+            since: since.map(|span| span.start),
+            until: until.map(|span| span.end),
+        }
+    }
+
+    /// The range a query filter like `@tuesday` stands for: all of a day,
+    /// or for an age like `@7d`, everything since.
+    pub fn of(span: Span) -> Range {
+        Range {
+            since: Some(span.start),
+            until: (span.end > span.start).then_some(span.end),
+        }
+    }
+
+    /// The times in both `self` and `other`.
+    pub fn and(self, other: Range) -> Range {
+        let latest = |a: Option<i64>, b: Option<i64>| a.max(b).or(a).or(b);
+        let earliest = |a: Option<i64>, b: Option<i64>| a.min(b).or(a).or(b);
+        Range {
+            since: latest(self.since, other.since),
+            until: earliest(self.until, other.until),
+        }
+    }
+
+    /// Whether there is no bound at all.
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Whether a run at `timestamp` falls in the range. Runs of unknown time
+    /// are only in an unbounded range.
+    pub fn contains(&self, timestamp: Option<i64>) -> bool {
+        match timestamp {
+            None => self.is_unbounded(),
+            Some(t) => {
+                self.since.is_none_or(|since| t >= since)
+                    && self.until.is_none_or(|until| t < until)
+            }
+        }
+    }
+}
+
+/// Splits a picker query into its time filters and the pattern left to
+/// match. Words starting with `@` that are not times, e.g. `@host` in
+/// `ssh user@host`, stay in the pattern, as does everything when there are
+/// no filters.
+pub fn split_filters(query: &str) -> (Range, Cow<'_, str>) {
+    let now = Local::now();
+    let mut range = Range::default();
+    let mut words = Vec::new();
+    for word in query.split(' ') {
+        match word
+            .strip_prefix('@')
+            .and_then(|time| Span::parse(time, now))
+        {
+            Some(span) => range = range.and(Range::of(span)),
+            None => words.push(word),
+        }
+    }
+    if range.is_unbounded() {
+        return (range, Cow::Borrowed(query));
+    }
+    let pattern: Vec<&str> = words.into_iter().filter(|w| !w.is_empty()).collect();
+    (range, Cow::Owned(pattern.join(" ")))
+}
@@ -0,0 +1,1849 @@
+//! The interactive command picker.
+
+mod backend;
+mod detail;
+mod explain;
+mod group;
+pub mod inline;
+pub mod keys;
+mod line;
+mod pane;
+mod status;
+mod syntax;
+mod terminal;
+pub mod theme;
+mod undo;
+mod view;
+
+use crate::catalog;
+use crate::editor;
+use crate::history::{normalize, Entry};
+use crate::search::rank::Ranking;
+use crate::search::{
+    self, query, CaseMode, CommandStats, ExitFilter, Filter, FrequencyMap, Searcher,
+};
+use crate::security::danger::Danger;
+use crate::security::redact;
+use crate::shell::ShellSet;
+use crate::snippet;
+use backend::{Backend, Session};
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind,
+};
+use detail::Detail;
+use explain::Explanation;
+use inline::Height;
+use keys::{Action, Keymap, Normal, NormalKey};
+use line::LineEditor;
+use ratatui::layout::{Rect, Size};
+use ratatui::{Terminal, TerminalOptions, Viewport};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+use terminal::Guard;
+use theme::Theme;
+use undo::{Change, Undo};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// How an interactive session ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The user accepted a command, or several marked with Tab and joined
+    /// per [`Options::join`].
+    Selected(String),
+    /// The user asked to run a command right away (Ctrl-X); joined as for
+    /// `Selected` when several were marked.
+    Execute(String),
+    /// The user asked to copy a command to the clipboard (Ctrl-Y); joined as
+    /// for `Selected` when several were marked.
+    Copy(String),
+    /// The user pressed Enter but nothing matched the query.
+    NoMatch,
+    /// The user left without choosing anything.
+    Cancelled,
+    /// The user pressed Ctrl-C.
+    Interrupted,
+}
+
+/// How several Tab-marked commands are combined into one selection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Join {
+    /// One command per line, as a script would run them.
+    #[default]
+    Newline,
+    /// A single line chained with `&&`, stopping at the first failure.
+    And,
+}
+
+impl Join {
+    /// Combines `commands`, in the order they were marked.
+    pub fn join(self, commands: &[String]) -> String {
+        commands.join(match self {
+            Join::Newline => "\n",
+            Join::And => " && ",
+        })
+    }
+}
+
+impl fmt::Display for Join {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Join::Newline => "newline",
+            Join::And => "and",
+        })
+    }
+}
+
+impl FromStr for Join {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "newline" => Ok(Join::Newline),
+            "and" => Ok(Join::And),
+            _ => Err(format!("unknown join: {} (expected newline or and)", s)),
+        }
+    }
+}
+
+/// Settings for an interactive session.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// A message shown under the search line, e.g. why history is empty.
+    pub notice: Option<String>,
+    /// How letter case is treated when matching.
+    pub case: CaseMode,
+    /// How matches are ordered.
+    pub ranking: Ranking,
+    /// Which commands are counted as one as history arrives; see
+    /// [`search::fold`].
+    pub normalize: normalize::Rules,
+    /// The shells the history came from, named in the status bar. With more
+    /// than one, each result is tagged with the shells it was run in.
+    pub shells: ShellSet,
+    /// How Tab-marked commands are combined when the session ends.
+    pub join: Join,
+    /// The colors to draw in.
+    pub theme: Theme,
+    /// What each key does.
+    pub keys: Keymap,
+    /// The query the picker opens with.
+    pub query: String,
+    /// Show what look like secrets as they are, rather than masked until
+    /// the reveal key is pressed.
+    pub show_secrets: bool,
+    /// Whether the cancel key switches to a normal mode with vim-style keys
+    /// rather than leaving.
+    pub vim: bool,
+    /// The directory th_rs was started in, which the here key limits
+    /// results to.
+    pub cwd: Option<PathBuf>,
+    /// Whether to start with only commands run in `cwd` or below it.
+    pub here: bool,
+    /// Which commands to start with by how their last run exited.
+    pub exit: ExitFilter,
+    /// Whether to start matching the query against each word of a command
+    /// rather than the whole line.
+    pub arguments: bool,
+    /// Whether the picker takes the mouse: clicks select, double clicks
+    /// choose, and the wheel scrolls. The terminal cannot select text while
+    /// it does.
+    pub mouse: bool,
+    /// Draw the picker inline, this tall, below the prompt rather than on
+    /// the alternate screen.
+    pub height: Option<Height>,
+    /// Whether each result shows a sparkline of its runs over the last
+    /// month, as well as how many there were.
+    pub sparkline: bool,
+    /// The shell command the explain key runs on the selection, if any.
+    pub explain: Option<String>,
+    /// Whether choosing a command runs it, as the execute key does.
+    pub exec: bool,
+    /// The commands that need a yes before they are run.
+    pub danger: Danger,
+}
+
+/// Truncates a given string to fit within the specified width, in terminal
+/// columns: wide characters like CJK and most emoji take two, and combining
+/// marks stay with the character they belong to.
+pub fn truncate_to_width(s: &str, width: u16) -> String {
+    let mut room = width as usize;
+    s.graphemes(true)
+        .take_while(|grapheme| {
+            let fits = grapheme.width() <= room;
+            room = room.saturating_sub(grapheme.width());
+            fits
+        })
+        .collect()
+}
+
+/// Removes a command from wherever the history was loaded from.
+pub type Deleter<'a> = dyn FnMut(&str) -> crate::Result<()> + 'a;
+
+/// Bookmarks a command, or with `false` removes its bookmark, wherever
+/// bookmarks are kept.
+pub type Bookmarker<'a> = dyn FnMut(&str, bool) -> crate::Result<()> + 'a;
+
+/// Tags a command, or with `false` takes the tag off it, wherever tags are
+/// kept.
+pub type Tagger<'a> = dyn FnMut(&str, &str, bool) -> crate::Result<()> + 'a;
+
+/// Notes which command was chosen for which query, to rank it higher the
+/// next time that query is typed.
+pub type Picker<'a> = dyn FnMut(&str, &str) -> crate::Result<()> + 'a;
+
+/// Checks for history written since it was loaded, returning it if any.
+pub type Refresher<'a> = dyn FnMut() -> crate::Result<Option<Update>> + 'a;
+
+/// History that changed while the picker was open.
+#[derive(Debug, Clone)]
+pub enum Update {
+    /// These runs were added.
+    Appended(Vec<Entry>),
+    /// History was reloaded in full and replaces what was shown.
+    Reloaded(FrequencyMap),
+}
+
+/// Progress of history being loaded while the picker is already open.
+#[derive(Debug, Clone)]
+pub enum Load {
+    /// The next part of the history.
+    Entries(Vec<Entry>),
+    /// Everything has been loaded.
+    Done,
+    /// Loading stopped; the message says why.
+    Failed(String),
+}
+
+/// How a session reaches back into wherever its history came from. Without
+/// them the picker works on `frequency` alone.
+#[derive(Default)]
+pub struct Hooks<'a> {
+    /// History still being loaded, to be added to `frequency` as it
+    /// arrives. The loader sends [`Load::Done`] when it is finished.
+    pub loading: Option<Receiver<Load>>,
+    /// Deletes a command from history; enables Ctrl-D. It is called when
+    /// the picker closes, for each command deleted and not undone.
+    pub delete: Option<&'a mut Deleter<'a>>,
+    /// Keeps bookmarks made with Ctrl-B.
+    pub bookmark: Option<&'a mut Bookmarker<'a>>,
+    /// Keeps tags given with Ctrl-T.
+    pub tag: Option<&'a mut Tagger<'a>>,
+    /// Told the query and the command whenever a result is chosen, run, or
+    /// copied.
+    pub pick: Option<&'a mut Picker<'a>>,
+    /// Called about once a second while no key is pressed, so that commands
+    /// run elsewhere show up in the results.
+    pub refresh: Option<&'a mut Refresher<'a>>,
+}
+
+/// How long the picker waits for a key before checking for new history.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the picker takes in loaded history and redraws its spinner.
+const LOADING_INTERVAL: Duration = Duration::from_millis(80);
+
+/// How often an explanation is redrawn while it comes in.
+const EXPLAIN_INTERVAL: Duration = Duration::from_millis(50);
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// How many rows one notch of the mouse wheel scrolls.
+const SCROLL_ROWS: usize = 3;
+
+/// How long a search can take before the keys after it are waited for, about
+/// a frame, and the longest they are waited for.
+const SLOW_SEARCH: Duration = Duration::from_millis(16);
+const MAX_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// How long a search runs before the matches found so far are drawn and
+/// keys taken in, any of which may change the query and so cancel it.
+const SEARCH_FRAME: Duration = Duration::from_millis(50);
+
+/// How soon a second click on a row must follow the first to choose it.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Runs the interactive command search UI over `frequency`, drawing to `out`.
+///
+/// `out` is usually stdout, or stderr when stdout is being captured by a shell
+/// widget. The terminal is put into raw mode on the alternate screen for the
+/// duration of the session, or with a `height` in rows under the cursor, and
+/// restored before returning, including when an I/O error cuts the session
+/// short or the picker panics. A terminal that will not say where the cursor is
+/// gets the alternate screen after all.
+///
+/// With a `delete` hook, Ctrl-D offers to delete the selected command: once
+/// confirmed, it is removed from `frequency`, and passed to the hook when the
+/// session ends unless Ctrl-Z brought it back. Commands that could not be
+/// deleted are reported on stderr. With a `bookmark` hook, Ctrl-B bookmarks the
+/// selected command or removes its bookmark, and with a `tag` hook, Ctrl-T asks
+/// for a tag to give it or take off it. With a `pick` hook, each chosen result
+/// is noted along with the query it was chosen for. With a `refresh` hook, new
+/// history is merged into `frequency` as it appears.
+pub fn run<W: Write>(
+    out: &mut W,
+    frequency: &mut FrequencyMap,
+    options: &Options,
+    hooks: Hooks<'_>,
+) -> io::Result<Outcome> {
+    let guard = Guard::new(out, terminal::System, options.height, options.mouse)?;
+    run_on(guard, frequency, options, hooks)
+}
+
+/// Runs the picker on `session`, as [`run`] does on the terminal.
+fn run_on<S: Session>(
+    mut session: S,
+    frequency: &mut FrequencyMap,
+    options: &Options,
+    mut hooks: Hooks<'_>,
+) -> io::Result<Outcome> {
+    let mut undo = Undo::new(hooks.delete.take());
+    let result = event_loop(session.backend(), frequency, options, hooks, &mut undo);
+
+    // Clear the screen and restore the terminal whichever way the loop ended;
+    // a panic leaves it to the guard.
+    let restored = session.finish();
+    // Deleting may take a while for a large history, and is best done once
+    // the picker is gone.
+    for failure in undo.finish() {
+        eprintln!("th_rs: {}", failure);
+    }
+    let outcome = result?;
+    restored?;
+    Ok(outcome)
+}
+
+/// What the picker shows and how, as keys change it.
+#[derive(Debug, Default)]
+struct State {
+    query: String,
+    /// Which commands are searched, and how; the here, hide-failed, session,
+    /// and arguments keys switch parts of it on and off.
+    filter: Filter,
+    selected_index: usize,
+    /// Index of the first suggestion shown; the list scrolls to keep the
+    /// selection inside the viewport.
+    scroll_offset: usize,
+    /// Commands marked with Tab, in the order they were marked.
+    marked: Vec<String>,
+    /// Whether the preview pane is forced on or off with Ctrl-P; until then
+    /// it shows itself for commands too long for their row.
+    preview: Option<bool>,
+    /// Whether likely secrets are shown rather than masked.
+    revealed: bool,
+    /// Whether each result shows the parts of its score.
+    scores: bool,
+    /// Whether results sharing a prefix are folded together, and the
+    /// prefixes whose commands are listed anyway.
+    grouped: bool,
+    expanded: BTreeSet<String>,
+    /// The result of the last action, shown until the next key.
+    status: Option<String>,
+    /// Shown in the status bar when there is nothing more pressing.
+    notice: Option<String>,
+}
+
+fn event_loop<B: Backend>(
+    backend: B,
+    frequency: &mut FrequencyMap,
+    options: &Options,
+    hooks: Hooks<'_>,
+    undo: &mut Undo<'_>,
+) -> io::Result<Outcome> {
+    let Hooks {
+        mut loading,
+        delete: _,
+        mut bookmark,
+        mut tag,
+        mut pick,
+        mut refresh,
+    } = hooks;
+    let _span = tracing::debug_span!("picker", commands = frequency.len()).entered();
+    // Kept apart from `frequency`, which a reload replaces.
+    let mut bookmarks: BTreeSet<String> = frequency
+        .iter()
+        .filter(|(_, stats)| stats.bookmarked)
+        .map(|(cmd, _)| cmd.clone())
+        .collect();
+    let snippets: Vec<(String, String)> = frequency
+        .iter()
+        .filter_map(|(cmd, stats)| Some((stats.snippet.clone()?, cmd.clone())))
+        .collect();
+    let catalog: Vec<catalog::Item> = frequency
+        .iter()
+        .filter(|(_, stats)| stats.description.is_some())
+        .map(|(cmd, stats)| catalog::Item {
+            command: cmd.clone(),
+            description: stats.description.clone(),
+            tags: Vec::new(),
+        })
+        .collect();
+    // Whether from the store or a catalog, tags are kept here as they are
+    // given and taken off.
+    let mut tags: BTreeSet<(String, String)> = frequency
+        .iter()
+        .flat_map(|(cmd, stats)| stats.tags.iter().map(|tag| (cmd.clone(), tag.clone())))
+        .collect();
+    // A command to keep selected wherever it moves in the results.
+    let mut follow: Option<String> = None;
+    // The snippet whose placeholders are being asked for.
+    let mut filling: Option<Filling> = None;
+    // The selected command being changed before it is chosen, and whether
+    // it is a snippet.
+    let mut editing: Option<(LineEditor, bool)> = None;
+    // How many entries have arrived from `loading`, and the spinner frame.
+    let mut loaded: usize = 0;
+    let mut spinner: usize = 0;
+    // The command a tag is being typed for.
+    let mut tagging: Option<Tagging> = None;
+    // The command awaiting a yes/no before it is deleted.
+    let mut confirming: Option<String> = None;
+    // A destructive command chosen to run, awaiting a yes.
+    let mut running: Option<Risky> = None;
+    // The detail view or an explanation over the picker, while one is open.
+    let mut detail: Option<Detail> = None;
+    let mut explaining: Option<Explanation> = None;
+    // The row last clicked and when, to tell a double click.
+    let mut clicked: Option<(usize, Instant)> = None;
+    let region = backend.region();
+    let mut terminal = match region {
+        Some(region) => {
+            let viewport = Viewport::Fixed(region.area);
+            Terminal::with_options(backend, TerminalOptions { viewport })?
+        }
+        None => Terminal::new(backend)?,
+    };
+    let mut state = State {
+        query: options.query.clone(),
+        filter: Filter {
+            dir: options.cwd.clone().filter(|_| options.here),
+            exit: options.exit,
+            session: false,
+            arguments: options.arguments,
+        },
+        revealed: options.show_secrets,
+        notice: options.notice.clone(),
+        ..State::default()
+    };
+    // Rebuilt whenever `frequency` or `state.filter` changes, since its caches
+    // refer to them.
+    let mut searcher = Searcher::new(
+        frequency,
+        options.case,
+        &options.ranking,
+        state.filter.clone(),
+    );
+    let hints = options.keys.hints(options.vim);
+    // In vim mode, set while in normal mode rather than typing the query.
+    let mut normal: Option<Normal> = None;
+    // The query last searched for, how long that took, and a key read
+    // while waiting to search again that is still to be handled.
+    let mut searched: Option<String> = None;
+    let mut search_took = Duration::ZERO;
+    let mut pending: Option<Event> = None;
+
+    loop {
+        // Keys typed faster than the results come back only change the
+        // query, which is then searched once, rather than for every key.
+        // After a slow search, the next waits that long for more keys.
+        let typing = confirming.is_none()
+            && running.is_none()
+            && filling.is_none()
+            && tagging.is_none()
+            && editing.is_none()
+            && detail.is_none()
+            && explaining.is_none()
+            && normal.is_none();
+        if typing && searched.as_ref() != Some(&state.query) {
+            let wait = match search_took {
+                took if took >= SLOW_SEARCH => took.min(MAX_DEBOUNCE),
+                _ => Duration::ZERO,
+            };
+            while pending.is_none() && terminal.backend_mut().poll(wait)? {
+                let event = terminal.backend_mut().read()?;
+                match query_edit(&event, &options.keys) {
+                    Some(edit) => {
+                        match edit {
+                            Some(c) => state.query.push(c),
+                            None => {
+                                state.query.pop();
+                            }
+                        }
+                        state.selected_index = 0;
+                        state.status = None;
+                    }
+                    None => pending = Some(event),
+                }
+            }
+        }
+        if searched.as_ref() != Some(&state.query) {
+            search_took = Duration::ZERO;
+            searched = Some(state.query.clone());
+        }
+        // Costs nothing once done, until the searcher is rebuilt; until
+        // then, the matches so far are drawn.
+        let started = Instant::now();
+        let searching = !searcher.search_for(&state.query, SEARCH_FRAME);
+        search_took += started.elapsed();
+
+        let Size { width, height } = terminal.size()?;
+        let screen = match terminal.backend().region() {
+            Some(region) => region.area,
+            None => Rect::new(0, 0, width, height),
+        };
+
+        let results = searcher.results();
+        let groups = state
+            .grouped
+            .then(|| group::Groups::new(results, &state.expanded));
+        let rows;
+        let suggestions = match &groups {
+            Some(groups) => {
+                rows = groups.rows();
+                &rows[..]
+            }
+            None => results,
+        };
+        // Time filters like `@7d` and tags are not matched against commands.
+        let matcher = options.case.matcher(&search::pattern(&state.query));
+        if let Some(cmd) = follow.take_if(|_| !searching) {
+            if let Some(index) = suggestions.iter().position(|&(c, _)| c == cmd) {
+                state.selected_index = index;
+            }
+        }
+        if state.selected_index >= suggestions.len() {
+            state.selected_index = suggestions.len().saturating_sub(1);
+        }
+
+        // Unless toggled, the preview pane appears only when the selected
+        // command does not fit on its row.
+        let preview_text = match suggestions.get(state.selected_index) {
+            Some(&(cmd, stats))
+                if state
+                    .preview
+                    .unwrap_or_else(|| !view::fits_on_row(cmd, width)) =>
+            {
+                let max_lines = view::Areas::max_preview_lines(screen);
+                let shown = if state.revealed {
+                    Cow::Borrowed(cmd)
+                } else {
+                    redact::redact(cmd)
+                };
+                Some(view::preview_text(&shown, stats, width, max_lines))
+            }
+            _ => None,
+        };
+        let areas = view::Areas::new(screen, preview_text.as_ref().map_or(0, Vec::len));
+        let visible_rows = areas.list.height.max(1) as usize;
+
+        // Keep the selection inside the scroll window, and the window full
+        // when the terminal grows.
+        state.scroll_offset = state
+            .scroll_offset
+            .min(suggestions.len().saturating_sub(visible_rows));
+        if state.selected_index < state.scroll_offset {
+            state.scroll_offset = state.selected_index;
+        } else if state.selected_index >= state.scroll_offset + visible_rows {
+            state.scroll_offset = state.selected_index + 1 - visible_rows;
+        }
+
+        let prompt = confirming
+            .as_ref()
+            .map(|cmd| format!("Delete \"{}\" from history? (y/n)", cmd))
+            .or_else(|| running.as_ref().map(Risky::prompt))
+            .or_else(|| filling.as_ref().map(Filling::prompt))
+            .or_else(|| tagging.as_ref().map(Tagging::prompt))
+            .or_else(|| {
+                editing
+                    .as_ref()
+                    .map(|_| "Editing: Enter chooses, Esc goes back to the results".to_string())
+            });
+        let progress = match (searching, &loading) {
+            (true, _) => Some(format!("{} Searching…", SPINNER[spinner % SPINNER.len()])),
+            (false, Some(_)) => Some(format!(
+                "{} Loading history… {} entries",
+                SPINNER[spinner % SPINNER.len()],
+                loaded
+            )),
+            (false, None) => None,
+        };
+        let message = prompt
+            .as_ref()
+            .or(state.status.as_ref())
+            .or(progress.as_ref())
+            .or(state.notice.as_ref());
+        let previewing = preview_text.is_some();
+        let view = view::View {
+            query: &state.query,
+            results: suggestions,
+            selected: state.selected_index,
+            offset: state.scroll_offset,
+            marked: &state.marked,
+            matcher: &matcher,
+            show_shells: options.shells.len() > 1,
+            ranking: &options.ranking,
+            filter: &state.filter,
+            groups: groups.as_ref(),
+            editing: editing.as_ref().map(|(line, _)| line),
+            redact: !state.revealed,
+            scores: state.scores,
+            sparkline: options.sparkline,
+            theme: &options.theme,
+            preview: preview_text,
+            normal: normal.is_some(),
+            status: status::StatusBar {
+                matches: groups
+                    .as_ref()
+                    .map_or(suggestions.len(), group::Groups::commands),
+                total: frequency.len(),
+                marked: state.marked.len(),
+                filter: &state.filter,
+                grouped: state.grouped,
+                shells: options.shells,
+                theme: &options.theme,
+                hints: if normal.is_some() {
+                    keys::NORMAL_HINTS
+                } else {
+                    &hints
+                },
+                message: message.map(String::as_str),
+            },
+        };
+        // The terminal keeps the previous frame and only rewrites the cells
+        // that changed, so nothing flickers and little is sent over slow
+        // links.
+        let drawing = Instant::now();
+        terminal.draw(|frame| match &detail {
+            Some(detail) => detail.render(frame, screen, &options.theme),
+            None => {
+                view.render(frame, &areas);
+                if let Some(explanation) = &explaining {
+                    explanation.render(frame, screen, &options.theme);
+                }
+            }
+        })?;
+        tracing::trace!(took = ?drawing.elapsed(), "drew");
+
+        // Wait for input, checking for new history in the meantime.
+        let input = loop {
+            if let Some(event) = pending.take() {
+                break Ok(event);
+            }
+            // A search under way goes on unless a key is waiting.
+            if searching {
+                if terminal.backend_mut().poll(Duration::ZERO)? {
+                    break Ok(terminal.backend_mut().read()?);
+                }
+                spinner += 1;
+                break Err(Update::Appended(Vec::new()));
+            }
+            // An explanation is drawn as it comes in.
+            if let Some(explanation) = explaining.as_mut().filter(|e| e.is_running()) {
+                if terminal.backend_mut().poll(EXPLAIN_INTERVAL)? {
+                    break Ok(terminal.backend_mut().read()?);
+                }
+                if explanation.receive() {
+                    break Err(Update::Appended(Vec::new()));
+                }
+                if loading.is_none() {
+                    continue;
+                }
+            }
+            // Until loading finishes, take in whatever has arrived between
+            // keys; watching for further changes can wait until then.
+            if let Some(receiver) = &loading {
+                if terminal.backend_mut().poll(LOADING_INTERVAL)? {
+                    break Ok(terminal.backend_mut().read()?);
+                }
+                let mut entries = Vec::new();
+                let finished = loop {
+                    match receiver.try_recv() {
+                        Ok(Load::Entries(more)) => entries.extend(more),
+                        Ok(Load::Done) | Err(TryRecvError::Disconnected) => break true,
+                        Ok(Load::Failed(message)) => {
+                            state.notice = Some(message);
+                            break true;
+                        }
+                        Err(TryRecvError::Empty) => break false,
+                    }
+                };
+                if finished {
+                    loading = None;
+                }
+                loaded += entries.len();
+                spinner += 1;
+                break Err(Update::Appended(entries));
+            }
+            let Some(refresh) = refresh.as_mut() else {
+                break Ok(terminal.backend_mut().read()?);
+            };
+            if terminal.backend_mut().poll(REFRESH_INTERVAL)? {
+                break Ok(terminal.backend_mut().read()?);
+            }
+            match refresh() {
+                Ok(Some(update)) => break Err(update),
+                Ok(None) => {}
+                Err(err) => {
+                    state.status = Some(format!("Could not reload history: {}", err));
+                    break Err(Update::Appended(Vec::new()));
+                }
+            }
+        };
+        let event = match input {
+            Ok(event) => {
+                tracing::trace!(event = %logged(&event));
+                event
+            }
+            Err(Update::Appended(entries)) => {
+                if !entries.is_empty() {
+                    tracing::debug!(entries = entries.len(), "more entries came in");
+                    search::add_entries(frequency, entries, &options.ranking);
+                    search::fold(frequency, &options.normalize);
+                    searcher = Searcher::new(
+                        frequency,
+                        options.case,
+                        &options.ranking,
+                        state.filter.clone(),
+                    );
+                }
+                continue;
+            }
+            Err(Update::Reloaded(reloaded)) => {
+                tracing::debug!(commands = reloaded.len(), "history reloaded");
+                *frequency = reloaded;
+                search::add_bookmarks(frequency, &bookmarks);
+                search::add_snippets(frequency, &snippets);
+                search::add_catalog(frequency, &catalog);
+                search::add_tags(frequency, &tags);
+                search::fold(frequency, &options.normalize);
+                // Deleted commands stay gone until the picker deletes them.
+                for command in undo.deleted() {
+                    frequency.remove(command);
+                }
+                searcher = Searcher::new(
+                    frequency,
+                    options.case,
+                    &options.ranking,
+                    state.filter.clone(),
+                );
+                continue;
+            }
+        };
+
+        // Process user input.
+        match event {
+            // Ctrl-C leaves from anywhere, whatever else is going on.
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers,
+                kind: KeyEventKind::Press,
+                ..
+            }) if modifiers.contains(KeyModifiers::CONTROL) => return Ok(Outcome::Interrupted),
+            // Any key but "y" answers a pending deletion with no.
+            Event::Key(KeyEvent {
+                code,
+                kind: KeyEventKind::Press,
+                ..
+            }) if confirming.is_some() => {
+                let command = confirming.take().unwrap_or_default();
+                if code != KeyCode::Char('y') {
+                    continue;
+                }
+                let removed = frequency.remove(&command);
+                searcher = Searcher::new(
+                    frequency,
+                    options.case,
+                    &options.ranking,
+                    state.filter.clone(),
+                );
+                let Some(stats) = removed else {
+                    continue;
+                };
+                state.marked.retain(|m| *m != command);
+                state.status = Some(format!("Deleted \"{}\" (Ctrl-Z to undo)", command));
+                undo.push(Change::Deleted {
+                    command,
+                    stats: Box::new(stats),
+                });
+            }
+            // Only "y" runs a command that looks destructive.
+            Event::Key(KeyEvent {
+                code,
+                kind: KeyEventKind::Press,
+                ..
+            }) if running.is_some() => {
+                let Some(risky) = running.take() else {
+                    continue;
+                };
+                if code == KeyCode::Char('y') {
+                    return Ok(risky.outcome);
+                }
+                state.status = Some("Not run".to_string());
+            }
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: KeyEventKind::Press | KeyEventKind::Repeat,
+                ..
+            }) if filling.is_some() => {
+                let Some(fill) = filling.as_mut() else {
+                    continue;
+                };
+                match code {
+                    KeyCode::Enter => {
+                        if let Some(outcome) = fill.next() {
+                            match hold(outcome, options) {
+                                Ok(outcome) => return Ok(outcome),
+                                Err(risky) => {
+                                    filling = None;
+                                    running = Some(risky);
+                                }
+                            }
+                        }
+                    }
+                    KeyCode::Esc => filling = None,
+                    KeyCode::Backspace => {
+                        fill.input.pop();
+                    }
+                    KeyCode::Char(c)
+                        if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                    {
+                        fill.input.push(c);
+                    }
+                    _ => {}
+                }
+            }
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: KeyEventKind::Press | KeyEventKind::Repeat,
+                ..
+            }) if tagging.is_some() => {
+                let Some(typing) = tagging.as_mut() else {
+                    continue;
+                };
+                match code {
+                    KeyCode::Enter => {
+                        let Some(Tagging { command, input }) = tagging.take() else {
+                            continue;
+                        };
+                        let Some(tagger) = tag.as_mut() else {
+                            continue;
+                        };
+                        let input = input.trim();
+                        let (adding, name) = match input.strip_prefix('-') {
+                            Some(name) => (false, name),
+                            None => (true, input),
+                        };
+                        let name = name.strip_prefix('#').unwrap_or(name).to_string();
+                        let pair = (command.clone(), name.clone());
+                        if !query::is_tag(&name) {
+                            state.status = Some(
+                                "A tag cannot be blank, hold spaces, or start with #".to_string(),
+                            );
+                            continue;
+                        }
+                        if tags.contains(&pair) == adding {
+                            state.status = Some(if adding {
+                                format!("\"{}\" is already tagged {}", command, name)
+                            } else {
+                                format!("\"{}\" is not tagged {}", command, name)
+                            });
+                            continue;
+                        }
+                        if let Err(err) = tagger(&command, &name, adding) {
+                            state.status = Some(format!("Could not tag \"{}\": {}", command, err));
+                            continue;
+                        }
+                        set_tag(frequency, &mut tags, pair, adding);
+                        state.status = Some(if adding {
+                            format!("Tagged \"{}\" {}", command, name)
+                        } else {
+                            format!("Took {} off \"{}\"", name, command)
+                        });
+                        undo.push(Change::Tagged {
+                            command: command.clone(),
+                            tag: name,
+                            added: adding,
+                        });
+                        searcher = Searcher::new(
+                            frequency,
+                            options.case,
+                            &options.ranking,
+                            state.filter.clone(),
+                        );
+                        follow = Some(command);
+                    }
+                    KeyCode::Esc => tagging = None,
+                    KeyCode::Backspace => {
+                        typing.input.pop();
+                    }
+                    KeyCode::Char(c)
+                        if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                    {
+                        typing.input.push(c);
+                    }
+                    _ => {}
+                }
+            }
+            // The detail view and explanations scroll with the arrows, and
+            // any other key closes them, stopping an explainer still running.
+            Event::Key(
+                key @ KeyEvent {
+                    kind: KeyEventKind::Press | KeyEventKind::Repeat,
+                    ..
+                },
+            ) if detail.is_some() || explaining.is_some() => {
+                let page = screen.height.saturating_sub(2) as isize;
+                let rows = match options.keys.action(key) {
+                    Some(Action::Up) => -1,
+                    Some(Action::Down) => 1,
+                    Some(Action::PageUp) => -page,
+                    Some(Action::PageDown) => page,
+                    _ => {
+                        detail = None;
+                        explaining = None;
+                        continue;
+                    }
+                };
+                if let Some(open) = detail.as_mut() {
+                    open.scroll(rows, screen);
+                }
+                if let Some(open) = explaining.as_mut() {
+                    open.scroll(rows, screen);
+                }
+            }
+            Event::Mouse(mouse) if detail.is_some() || explaining.is_some() => {
+                let rows = match mouse.kind {
+                    MouseEventKind::ScrollUp => -(SCROLL_ROWS as isize),
+                    MouseEventKind::ScrollDown => SCROLL_ROWS as isize,
+                    _ => continue,
+                };
+                if let Some(open) = detail.as_mut() {
+                    open.scroll(rows, screen);
+                }
+                if let Some(open) = explaining.as_mut() {
+                    open.scroll(rows, screen);
+                }
+            }
+            // Choosing, running, and copying take the edited command; other
+            // keys edit it.
+            Event::Key(
+                key @ KeyEvent {
+                    kind: KeyEventKind::Press | KeyEventKind::Repeat,
+                    ..
+                },
+            ) if editing.is_some() => {
+                let finish: fn(String) -> Outcome = match options.keys.action(key) {
+                    Some(Action::Accept) => Outcome::Selected,
+                    Some(Action::Execute) => Outcome::Execute,
+                    Some(Action::Copy) => Outcome::Copy,
+                    Some(Action::Cancel) => {
+                        editing = None;
+                        continue;
+                    }
+                    _ => {
+                        if let Some((line, _)) = editing.as_mut() {
+                            line.key(key);
+                        }
+                        continue;
+                    }
+                };
+                let Some((line, snippet)) = editing.take() else {
+                    continue;
+                };
+                match Filling::new(line.into_text(), snippet, finish) {
+                    Ok(outcome) => match hold(outcome, options) {
+                        Ok(outcome) => return Ok(outcome),
+                        Err(risky) => running = Some(risky),
+                    },
+                    Err(fill) => filling = Some(fill),
+                }
+            }
+            // Windows reports key releases as well as presses; act on presses
+            // (and auto-repeats) only, or every key would count twice.
+            event @ (Event::Key(KeyEvent {
+                kind: KeyEventKind::Press | KeyEventKind::Repeat,
+                ..
+            })
+            | Event::Mouse(_)) => {
+                let (key, action) = match event {
+                    Event::Key(key) => match normal.as_mut().map(|normal| normal.key(key)) {
+                        Some(NormalKey::Action(action)) => (Some(key), Some(action)),
+                        Some(NormalKey::Insert) => {
+                            normal = None;
+                            continue;
+                        }
+                        Some(NormalKey::Pending) => continue,
+                        Some(NormalKey::Other) | None => (Some(key), options.keys.action(key)),
+                    },
+                    // The mouse is left alone while a prompt waits for keys.
+                    Event::Mouse(mouse)
+                        if options.mouse
+                            && confirming.is_none()
+                            && running.is_none()
+                            && filling.is_none()
+                            && editing.is_none() =>
+                    {
+                        match mouse.kind {
+                            MouseEventKind::ScrollUp => {
+                                state.scroll_offset =
+                                    state.scroll_offset.saturating_sub(SCROLL_ROWS);
+                                state.selected_index = state
+                                    .selected_index
+                                    .min(state.scroll_offset + visible_rows - 1);
+                                continue;
+                            }
+                            MouseEventKind::ScrollDown => {
+                                state.scroll_offset = (state.scroll_offset + SCROLL_ROWS)
+                                    .min(suggestions.len().saturating_sub(visible_rows));
+                                state.selected_index =
+                                    state.selected_index.max(state.scroll_offset);
+                                continue;
+                            }
+                            MouseEventKind::Down(MouseButton::Left) => {
+                                let list = areas.list;
+                                let index =
+                                    state.scroll_offset + mouse.row.wrapping_sub(list.y) as usize;
+                                if !list.contains((mouse.column, mouse.row).into())
+                                    || index >= suggestions.len()
+                                {
+                                    continue;
+                                }
+                                state.selected_index = index;
+                                // A double click chooses, as the accept key does.
+                                match clicked.take() {
+                                    Some((row, at))
+                                        if row == index && at.elapsed() < DOUBLE_CLICK =>
+                                    {
+                                        (None, Some(Action::Accept))
+                                    }
+                                    _ => {
+                                        clicked = Some((index, Instant::now()));
+                                        continue;
+                                    }
+                                }
+                            }
+                            _ => continue,
+                        }
+                    }
+                    _ => continue,
+                };
+                let header = groups
+                    .as_ref()
+                    .and_then(|groups| groups.header(state.selected_index));
+                match action {
+                    Some(Action::Accept) if header.is_some() && state.marked.is_empty() => {
+                        if let Some(header) = header {
+                            if !state.expanded.remove(header.prefix) {
+                                state.expanded.insert(header.prefix.to_string());
+                            }
+                        }
+                    }
+                    Some(
+                        Action::Execute
+                        | Action::Copy
+                        | Action::Delete
+                        | Action::Bookmark
+                        | Action::Tag
+                        | Action::Mark
+                        | Action::Editor
+                        | Action::Detail
+                        | Action::Explain,
+                    ) if header.is_some() => {
+                        state.status =
+                            Some("Expand the group to pick one of its commands".to_string());
+                    }
+                    Some(Action::Editor) => {
+                        let Some(&(cmd, stats)) = suggestions.get(state.selected_index) else {
+                            continue;
+                        };
+                        let edited = terminal.backend_mut().suspend(|| editor::edit(cmd))?;
+                        if let Some(region) = terminal.backend().region() {
+                            terminal.resize(region.area)?;
+                        }
+                        // Whatever the editor drew is gone with its screen.
+                        terminal.clear()?;
+                        match edited {
+                            Ok(Some(edited)) => {
+                                match Filling::new(
+                                    edited,
+                                    stats.snippet.is_some(),
+                                    Outcome::Selected,
+                                ) {
+                                    Ok(outcome) => match hold(outcome, options) {
+                                        Ok(outcome) => return Ok(outcome),
+                                        Err(risky) => running = Some(risky),
+                                    },
+                                    Err(fill) => filling = Some(fill),
+                                }
+                            }
+                            Ok(None) => {
+                                state.status = Some("Nothing was saved in the editor".to_string())
+                            }
+                            Err(err) => state.status = Some(format!("Could not edit: {}", err)),
+                        }
+                    }
+                    Some(Action::Group) => {
+                        state.grouped = !state.grouped;
+                        state.selected_index = 0;
+                    }
+                    Some(Action::Edit) => {
+                        if let Some(header) = header {
+                            state.expanded.insert(header.prefix.to_string());
+                        } else if let Some(&(cmd, stats)) = suggestions.get(state.selected_index) {
+                            editing =
+                                Some((LineEditor::new(cmd.to_string()), stats.snippet.is_some()));
+                        }
+                    }
+                    Some(Action::Collapse) => {
+                        if let Some(prefix) = groups
+                            .as_ref()
+                            .and_then(|groups| groups.group_of(state.selected_index))
+                        {
+                            state.expanded.remove(prefix);
+                            follow = Some(prefix.to_string());
+                        }
+                    }
+                    Some(Action::Execute) => {
+                        if let Some(cmd) = choice(
+                            &state.marked,
+                            suggestions,
+                            state.selected_index,
+                            options.join,
+                        ) {
+                            learn(&mut pick, &state, suggestions);
+                            let snippet = chose_snippet(
+                                &state.marked,
+                                suggestions,
+                                state.selected_index,
+                                frequency,
+                            );
+                            match Filling::new(cmd, snippet, Outcome::Execute) {
+                                Ok(outcome) => match hold(outcome, options) {
+                                    Ok(outcome) => return Ok(outcome),
+                                    Err(risky) => running = Some(risky),
+                                },
+                                Err(fill) => filling = Some(fill),
+                            }
+                        }
+                    }
+                    Some(Action::Copy) => {
+                        if let Some(cmd) = choice(
+                            &state.marked,
+                            suggestions,
+                            state.selected_index,
+                            options.join,
+                        ) {
+                            learn(&mut pick, &state, suggestions);
+                            let snippet = chose_snippet(
+                                &state.marked,
+                                suggestions,
+                                state.selected_index,
+                                frequency,
+                            );
+                            match Filling::new(cmd, snippet, Outcome::Copy) {
+                                Ok(outcome) => return Ok(outcome),
+                                Err(fill) => filling = Some(fill),
+                            }
+                        }
+                    }
+                    Some(Action::Preview) => {
+                        state.preview = Some(!previewing);
+                    }
+                    Some(Action::Detail) => {
+                        let Some(&(cmd, stats)) = suggestions.get(state.selected_index) else {
+                            continue;
+                        };
+                        let shown = if state.revealed {
+                            Cow::Borrowed(cmd)
+                        } else {
+                            redact::redact(cmd)
+                        };
+                        detail = Some(Detail::new(
+                            cmd,
+                            &shown,
+                            stats,
+                            screen.width,
+                            search::rank::now(),
+                        ));
+                    }
+                    Some(Action::Explain) => {
+                        let Some(&(cmd, _)) = suggestions.get(state.selected_index) else {
+                            continue;
+                        };
+                        let Some(explainer) = &options.explain else {
+                            state.status =
+                                Some("No explainer set; see `explain` in the config".to_string());
+                            continue;
+                        };
+                        // What is masked stays on this machine.
+                        let shown = if state.revealed {
+                            Cow::Borrowed(cmd)
+                        } else {
+                            redact::redact(cmd)
+                        };
+                        match Explanation::start(explainer, &shown) {
+                            Ok(explanation) => explaining = Some(explanation),
+                            Err(err) => {
+                                state.status = Some(format!("Could not run the explainer: {}", err))
+                            }
+                        }
+                    }
+                    Some(Action::Reveal) => state.revealed = !state.revealed,
+                    Some(Action::Scores) => state.scores = !state.scores,
+                    Some(Action::Here) => {
+                        if state.filter.dir.is_some() {
+                            state.filter.dir = None;
+                        } else if frequency.values().any(|stats| !stats.dirs.is_empty()) {
+                            state.filter.dir = options.cwd.clone();
+                        } else {
+                            state.status = Some(
+                                "No directories recorded; see `th_rs init --record`".to_string(),
+                            );
+                            continue;
+                        }
+                        searcher = Searcher::new(
+                            frequency,
+                            options.case,
+                            &options.ranking,
+                            state.filter.clone(),
+                        );
+                        state.selected_index = 0;
+                    }
+                    Some(Action::HideFailed) => {
+                        if state.filter.exit != ExitFilter::Any {
+                            state.filter.exit = ExitFilter::Any;
+                        } else if frequency.values().any(|stats| stats.exit.is_some()) {
+                            state.filter.exit = ExitFilter::Succeeded;
+                        } else {
+                            state.status = Some(
+                                "No exit statuses recorded; see `th_rs init --record`".to_string(),
+                            );
+                            continue;
+                        }
+                        searcher = Searcher::new(
+                            frequency,
+                            options.case,
+                            &options.ranking,
+                            state.filter.clone(),
+                        );
+                        state.selected_index = 0;
+                    }
+                    Some(Action::Session) => {
+                        if state.filter.session {
+                            state.filter.session = false;
+                        } else if frequency.values().any(|stats| stats.in_session.is_some()) {
+                            state.filter.session = true;
+                        } else {
+                            state.status = Some(
+                                "No commands recorded in this session; see `th_rs init --record`"
+                                    .to_string(),
+                            );
+                            continue;
+                        }
+                        searcher = Searcher::new(
+                            frequency,
+                            options.case,
+                            &options.ranking,
+                            state.filter.clone(),
+                        );
+                        state.selected_index = 0;
+                    }
+                    Some(Action::Arguments) => {
+                        state.filter.arguments = !state.filter.arguments;
+                        searcher = Searcher::new(
+                            frequency,
+                            options.case,
+                            &options.ranking,
+                            state.filter.clone(),
+                        );
+                        state.selected_index = 0;
+                    }
+                    Some(Action::Bookmark) => {
+                        let Some(cmd) = suggestions
+                            .get(state.selected_index)
+                            .map(|(cmd, _)| cmd.to_string())
+                        else {
+                            continue;
+                        };
+                        let Some(bookmark) = bookmark.as_mut() else {
+                            continue;
+                        };
+                        let adding = !bookmarks.contains(&cmd);
+                        if let Err(err) = bookmark(&cmd, adding) {
+                            state.status = Some(format!("Could not bookmark \"{}\": {}", cmd, err));
+                            continue;
+                        }
+                        set_bookmark(frequency, &mut bookmarks, &cmd, adding);
+                        state.status = Some(if adding {
+                            format!("Bookmarked \"{}\"", cmd)
+                        } else {
+                            format!("Removed the bookmark on \"{}\"", cmd)
+                        });
+                        undo.push(Change::Bookmarked {
+                            command: cmd.clone(),
+                            added: adding,
+                        });
+                        searcher = Searcher::new(
+                            frequency,
+                            options.case,
+                            &options.ranking,
+                            state.filter.clone(),
+                        );
+                        follow = Some(cmd);
+                    }
+                    Some(Action::Tag) => {
+                        if tag.is_none() {
+                            continue;
+                        }
+                        if let Some(&(cmd, _)) = suggestions.get(state.selected_index) {
+                            tagging = Some(Tagging {
+                                command: cmd.to_string(),
+                                input: String::new(),
+                            });
+                        }
+                    }
+                    Some(Action::Undo) => {
+                        let command = match undo.pop() {
+                            None => {
+                                state.status = Some("Nothing to undo".to_string());
+                                continue;
+                            }
+                            Some(Change::Deleted { command, stats }) => {
+                                frequency.insert(command.clone(), *stats);
+                                state.status = Some(format!("Brought back \"{}\"", command));
+                                command
+                            }
+                            Some(Change::Bookmarked { command, added }) => {
+                                let Some(bookmark) = bookmark.as_mut() else {
+                                    continue;
+                                };
+                                if let Err(err) = bookmark(&command, !added) {
+                                    state.status = Some(format!(
+                                        "Could not undo the bookmark on \"{}\": {}",
+                                        command, err
+                                    ));
+                                    undo.push(Change::Bookmarked { command, added });
+                                    continue;
+                                }
+                                set_bookmark(frequency, &mut bookmarks, &command, !added);
+                                state.status =
+                                    Some(format!("Undid the bookmark change on \"{}\"", command));
+                                command
+                            }
+                            Some(Change::Tagged {
+                                command,
+                                tag: name,
+                                added,
+                            }) => {
+                                let Some(tagger) = tag.as_mut() else {
+                                    continue;
+                                };
+                                if let Err(err) = tagger(&command, &name, !added) {
+                                    state.status = Some(format!(
+                                        "Could not undo the tag on \"{}\": {}",
+                                        command, err
+                                    ));
+                                    undo.push(Change::Tagged {
+                                        command,
+                                        tag: name,
+                                        added,
+                                    });
+                                    continue;
+                                }
+                                set_tag(frequency, &mut tags, (command.clone(), name), !added);
+                                state.status =
+                                    Some(format!("Undid the tag change on \"{}\"", command));
+                                command
+                            }
+                        };
+                        searcher = Searcher::new(
+                            frequency,
+                            options.case,
+                            &options.ranking,
+                            state.filter.clone(),
+                        );
+                        follow = Some(command);
+                    }
+                    Some(Action::Delete) => {
+                        state.status = None;
+                        if undo.can_delete() {
+                            confirming = suggestions
+                                .get(state.selected_index)
+                                .map(|(cmd, _)| cmd.to_string());
+                        }
+                    }
+                    Some(Action::Backspace) => {
+                        state.query.pop();
+                        state.selected_index = 0;
+                        state.status = None;
+                    }
+                    Some(Action::Up) => {
+                        state.selected_index = state.selected_index.saturating_sub(1);
+                    }
+                    Some(Action::Down) => {
+                        if state.selected_index + 1 < suggestions.len() {
+                            state.selected_index += 1;
+                        }
+                    }
+                    Some(Action::PageUp) => {
+                        state.selected_index = state.selected_index.saturating_sub(visible_rows);
+                    }
+                    Some(Action::PageDown) => {
+                        // Over-shooting is clamped to the last result next frame.
+                        state.selected_index += visible_rows;
+                    }
+                    // Marking moves on, so that a run of commands can be marked
+                    // by holding the key down.
+                    Some(Action::Mark) => {
+                        if let Some((cmd, _)) = suggestions.get(state.selected_index) {
+                            match state.marked.iter().position(|m| m == cmd) {
+                                Some(at) => {
+                                    state.marked.remove(at);
+                                }
+                                None => state.marked.push(cmd.to_string()),
+                            }
+                            state.selected_index += 1;
+                        }
+                    }
+                    Some(Action::First) => state.selected_index = 0,
+                    Some(Action::Last) => {
+                        state.selected_index = suggestions.len().saturating_sub(1)
+                    }
+                    Some(Action::Accept) => {
+                        let Some(cmd) = choice(
+                            &state.marked,
+                            suggestions,
+                            state.selected_index,
+                            options.join,
+                        ) else {
+                            return Ok(Outcome::NoMatch);
+                        };
+                        learn(&mut pick, &state, suggestions);
+                        let snippet = chose_snippet(
+                            &state.marked,
+                            suggestions,
+                            state.selected_index,
+                            frequency,
+                        );
+                        match Filling::new(cmd, snippet, Outcome::Selected) {
+                            Ok(outcome) => match hold(outcome, options) {
+                                Ok(outcome) => return Ok(outcome),
+                                Err(risky) => running = Some(risky),
+                            },
+                            Err(fill) => filling = Some(fill),
+                        }
+                    }
+                    Some(Action::Cancel) if options.vim && normal.is_none() => {
+                        normal = Some(Normal::default());
+                    }
+                    Some(Action::Cancel) => return Ok(Outcome::Cancelled),
+                    // Keys bound to nothing type into the query, unless a
+                    // modifier makes them a shortcut or normal mode is on.
+                    None => match key {
+                        Some(KeyEvent {
+                            code: KeyCode::Char(c),
+                            modifiers,
+                            ..
+                        }) if normal.is_none()
+                            && !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                        {
+                            state.query.push(c);
+                            state.selected_index = 0;
+                            state.status = None;
+                        }
+                        _ => {}
+                    },
+                }
+            }
+            Event::Resize(width, height) => {
+                // The UI will redraw on the next loop iteration, full screen
+                // by itself, inline once told where.
+                if let Some(area) = terminal.backend_mut().resize(width, height) {
+                    terminal.resize(area)?;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// What `event` does to the query, if that is all it does: the character it
+/// types, or `None` for the backspace key, which takes the last one off.
+fn query_edit(event: &Event, keys: &Keymap) -> Option<Option<char>> {
+    let Event::Key(
+        key @ KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press | KeyEventKind::Repeat,
+            ..
+        },
+    ) = *event
+    else {
+        return None;
+    };
+    match (keys.action(key), code) {
+        (Some(Action::Backspace), _) => Some(None),
+        (None, KeyCode::Char(c))
+            if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+        {
+            Some(Some(c))
+        }
+        _ => None,
+    }
+}
+
+/// `event` as the debug log shows it: which key it is, but not which
+/// character was typed, nor what was pasted, since that can be a password.
+fn logged(event: &Event) -> String {
+    match event {
+        Event::Key(KeyEvent {
+            code: KeyCode::Char(_),
+            modifiers,
+            ..
+        }) if !modifiers.contains(KeyModifiers::CONTROL) => {
+            format!("a character ({:?})", modifiers)
+        }
+        Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) => format!("{:?} ({:?})", code, modifiers),
+        Event::Paste(text) => format!("a paste of {} bytes", text.len()),
+        Event::Mouse(mouse) => format!("{:?}", mouse.kind),
+        event => format!("{:?}", event),
+    }
+}
+
+/// Tells `pick` that the command under the cursor was chosen for the query,
+/// unless marked commands were chosen instead.
+fn learn(pick: &mut Option<&mut Picker<'_>>, state: &State, suggestions: &[(&str, &CommandStats)]) {
+    let (Some(pick), Some((cmd, _))) = (pick, suggestions.get(state.selected_index)) else {
+        return;
+    };
+    if state.marked.is_empty() {
+        // Time filters say nothing of which command was wanted. Failing to
+        // note the choice is no reason to keep the picker open.
+        let _ = pick(&search::pattern(&state.query), cmd);
+    }
+}
+
+/// Whether what [`choice`] gives includes a snippet.
+fn chose_snippet(
+    marked: &[String],
+    suggestions: &[(&str, &CommandStats)],
+    selected_index: usize,
+    frequency: &FrequencyMap,
+) -> bool {
+    if !marked.is_empty() {
+        return marked.iter().any(|cmd| {
+            frequency
+                .get(cmd)
+                .is_some_and(|stats| stats.snippet.is_some())
+        });
+    }
+    suggestions
+        .get(selected_index)
+        .is_some_and(|(_, stats)| stats.snippet.is_some())
+}
+
+/// Bookmarks `cmd`, or with `adding` false removes its bookmark, in
+/// `bookmarks` and `frequency`.
+fn set_bookmark(
+    frequency: &mut FrequencyMap,
+    bookmarks: &mut BTreeSet<String>,
+    cmd: &str,
+    adding: bool,
+) {
+    if adding {
+        bookmarks.insert(cmd.to_string());
+        frequency.entry(cmd.to_string()).or_default().bookmarked = true;
+        return;
+    }
+    bookmarks.remove(cmd);
+    // A bookmark never run goes with its bookmark.
+    match frequency.get_mut(cmd) {
+        Some(stats) if stats.count == 0 => {
+            frequency.remove(cmd);
+        }
+        Some(stats) => stats.bookmarked = false,
+        None => {}
+    }
+}
+
+/// Gives `cmd` a tag, or with `adding` false takes it off, in `tags` and
+/// `frequency`.
+fn set_tag(
+    frequency: &mut FrequencyMap,
+    tags: &mut BTreeSet<(String, String)>,
+    (cmd, tag): (String, String),
+    adding: bool,
+) {
+    if let Some(stats) = frequency.get_mut(&cmd) {
+        if adding {
+            stats.tags.insert(tag.clone());
+        } else {
+            stats.tags.remove(&tag);
+        }
+    }
+    if adding {
+        tags.insert((cmd, tag));
+    } else {
+        tags.remove(&(cmd, tag));
+    }
+}
+
+/// A command chosen to run that looks destructive, held until it is
+/// confirmed.
+struct Risky {
+    outcome: Outcome,
+    /// What makes it look destructive.
+    reason: String,
+}
+
+impl Risky {
+    /// What the status bar asks.
+    fn prompt(&self) -> String {
+        format!(
+            "This looks destructive ({}). Run it anyway? (y/N)",
+            self.reason
+        )
+    }
+}
+
+/// `outcome`, unless it runs a command `options` finds destructive, which
+/// is then held until it is confirmed.
+fn hold(outcome: Outcome, options: &Options) -> Result<Outcome, Risky> {
+    let command = match &outcome {
+        Outcome::Execute(command) => command,
+        Outcome::Selected(command) if options.exec => command,
+        _ => return Ok(outcome),
+    };
+    match options.danger.check(command) {
+        Some(reason) => Err(Risky {
+            reason: reason.to_string(),
+            outcome,
+        }),
+        None => Ok(outcome),
+    }
+}
+
+/// A chosen snippet whose placeholders are asked for one by one.
+struct Filling {
+    template: String,
+    names: Vec<String>,
+    /// The values given so far, one for each of the first names.
+    values: Vec<(String, String)>,
+    /// The value being typed for the next name.
+    input: String,
+    /// What choosing the filled-in command ends the session with.
+    finish: fn(String) -> Outcome,
+}
+
+impl Filling {
+    /// The outcome of choosing `cmd` right away, or what is left to ask
+    /// first when it holds a snippet with placeholders.
+    fn new(cmd: String, snippet: bool, finish: fn(String) -> Outcome) -> Result<Outcome, Filling> {
+        let names: Vec<String> = if snippet {
+            snippet::placeholders(&cmd)
+                .into_iter()
+                .map(str::to_string)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if names.is_empty() {
+            return Ok(finish(cmd));
+        }
+        Err(Filling {
+            template: cmd,
+            names,
+            values: Vec::new(),
+            input: String::new(),
+            finish,
+        })
+    }
+
+    /// What the status bar asks.
+    fn prompt(&self) -> String {
+        format!(
+            "{} ({}/{}, Esc to go back): {}",
+            self.names[self.values.len()],
+            self.values.len() + 1,
+            self.names.len(),
+            self.input
+        )
+    }
+
+    /// Takes what was typed for the current name, and once every name has a
+    /// value, the outcome with the command filled in.
+    fn next(&mut self) -> Option<Outcome> {
+        let name = self.names[self.values.len()].clone();
+        self.values.push((name, std::mem::take(&mut self.input)));
+        (self.values.len() == self.names.len())
+            .then(|| (self.finish)(snippet::fill(&self.template, &self.values)))
+    }
+}
+
+/// A tag being typed for a command.
+struct Tagging {
+    command: String,
+    input: String,
+}
+
+impl Tagging {
+    /// What the status bar asks.
+    fn prompt(&self) -> String {
+        format!(
+            "Tag \"{}\" (-tag takes one off, Esc to go back): {}",
+            self.command, self.input
+        )
+    }
+}
+
+/// What Enter or Ctrl-X acts on: the marked commands joined per `join` if
+/// any are marked, otherwise the one under the cursor.
+fn choice(
+    marked: &[String],
+    suggestions: &[(&str, &CommandStats)],
+    selected_index: usize,
+    join: Join,
+) -> Option<String> {
+    if !marked.is_empty() {
+        return Some(join.join(marked));
+    }
+    suggestions
+        .get(selected_index)
+        .map(|(cmd, _)| cmd.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::{Guarded, Scripted};
+    use terminal::{count, Fake, ENTER, LEAVE};
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+        Event::Key(KeyEvent::new(code, modifiers))
+    }
+
+    fn typed(text: &str) -> Vec<Event> {
+        text.chars()
+            .map(|c| key(KeyCode::Char(c), KeyModifiers::NONE))
+            .collect()
+    }
+
+    /// A history where `git status` ran most, then `ls`, then `make`.
+    fn history() -> FrequencyMap {
+        let entries = ["make", "ls", "ls", "git status", "git status", "git status"];
+        search::build_frequency_map(entries.map(Entry::new), &Ranking::default())
+    }
+
+    /// Runs the picker over `frequency` on `screen` until its keys run out
+    /// or one ends it.
+    fn pick(
+        frequency: &mut FrequencyMap,
+        screen: &mut Scripted,
+        undo: &mut Undo,
+    ) -> io::Result<Outcome> {
+        event_loop(
+            screen,
+            frequency,
+            &Options::default(),
+            Hooks::default(),
+            undo,
+        )
+    }
+
+    #[test]
+    fn moves_the_selection_and_chooses() {
+        let mut frequency = history();
+        let down = key(KeyCode::Down, KeyModifiers::NONE);
+        let enter = key(KeyCode::Enter, KeyModifiers::NONE);
+        let mut screen = Scripted::new(60, 8, [down, enter.clone()]);
+        let outcome = pick(&mut frequency, &mut screen, &mut Undo::new(None));
+        assert_eq!(outcome.unwrap(), Outcome::Selected("ls".to_string()));
+
+        let mut screen = Scripted::new(60, 8, [typed("mak"), vec![enter]].concat());
+        let outcome = pick(&mut frequency, &mut screen, &mut Undo::new(None));
+        assert_eq!(outcome.unwrap(), Outcome::Selected("make".to_string()));
+        let text = screen.text();
+        assert!(text.contains("Search: mak") && text.contains("1/3 matches"));
+
+        // Keys that never leave the picker run out rather than hang.
+        let mut screen = Scripted::new(60, 8, typed("git"));
+        let outcome = pick(&mut frequency, &mut screen, &mut Undo::new(None));
+        assert_eq!(outcome.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert!(screen.text().contains("git status"));
+    }
+
+    #[test]
+    fn restores_the_terminal_however_the_picker_ends() {
+        let enter = key(KeyCode::Enter, KeyModifiers::NONE);
+        let esc = key(KeyCode::Esc, KeyModifiers::NONE);
+        let ctrl_c = key(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        let ends = [
+            (vec![enter], Ok(Outcome::Selected("git status".to_string()))),
+            (vec![esc], Ok(Outcome::Cancelled)),
+            (vec![ctrl_c], Ok(Outcome::Interrupted)),
+            // Reading a key fails once the script runs out.
+            (typed("ls"), Err(io::ErrorKind::UnexpectedEof)),
+        ];
+        for (keys, ended) in ends {
+            let (mut out, fake) = (Vec::new(), Fake::default());
+            let guard = Guard::new(&mut out, &fake, None, true).unwrap();
+            assert_eq!(fake.state(), (true, true));
+            let session = Guarded {
+                guard,
+                screen: Scripted::new(60, 8, keys),
+            };
+            let outcome = run_on(
+                session,
+                &mut history(),
+                &Options::default(),
+                Hooks::default(),
+            );
+            assert_eq!(outcome.map_err(|err| err.kind()), ended);
+            assert_eq!(fake.state(), (false, false));
+            assert_eq!((count(&out, ENTER), count(&out, LEAVE)), (1, 1));
+        }
+    }
+
+    #[test]
+    fn deletes_once_confirmed_and_undoes() {
+        let delete = key(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        let undo_key = key(KeyCode::Char('z'), KeyModifiers::CONTROL);
+        let esc = key(KeyCode::Esc, KeyModifiers::NONE);
+        let mut deleted = Vec::new();
+        let mut deleter = |command: &str| {
+            deleted.push(command.to_string());
+            Ok(())
+        };
+        let mut frequency = history();
+        let mut undo = Undo::new(Some(&mut deleter));
+        // No keeps `git status`; yes deletes it, then `ls`, which undoing
+        // brings back.
+        let keys = [
+            vec![delete.clone()],
+            typed("n"),
+            vec![delete.clone()],
+            typed("y"),
+            vec![delete],
+            typed("y"),
+            vec![undo_key, esc],
+        ];
+        let mut screen = Scripted::new(60, 8, keys.concat());
+        let outcome = pick(&mut frequency, &mut screen, &mut undo);
+        assert_eq!(outcome.unwrap(), Outcome::Cancelled);
+        assert!(!frequency.contains_key("git status") && frequency.contains_key("ls"));
+        assert!(undo.finish().is_empty());
+        assert_eq!(deleted, ["git status"]);
+    }
+}
@@ -0,0 +1,221 @@
+//! What the picker draws on and takes events from: the terminal, through
+//! crossterm, or in tests a screen in memory fed a script of keys.
+
+use super::inline::Region;
+use super::terminal::{Guard, Tty};
+use crossterm::event::{self, Event};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::Rect;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// A screen the picker can draw on, and where its keys come from.
+pub(super) trait Backend: ratatui::backend::Backend {
+    /// The rows the picker is drawn in, or `None` for the whole screen.
+    fn region(&self) -> Option<Region>;
+
+    /// Fits the picker's rows to a screen resized to `width` by `rows`,
+    /// returning where they now are.
+    fn resize(&mut self, width: u16, rows: u16) -> Option<Rect>;
+
+    /// Runs `f` with the screen handed back for another program, like an
+    /// editor.
+    fn suspend<U>(&mut self, f: impl FnOnce() -> U) -> io::Result<U>;
+
+    /// Whether an event comes within `timeout`.
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool>;
+
+    /// The next event, waiting for it if need be.
+    fn read(&mut self) -> io::Result<Event>;
+}
+
+/// A terminal set up for one run of the picker, which draws on it through
+/// a [`Backend`] and puts it back once done.
+pub(super) trait Session {
+    type Backend<'a>: Backend
+    where
+        Self: 'a;
+
+    /// What the picker draws on.
+    fn backend(&mut self) -> Self::Backend<'_>;
+
+    /// Puts the terminal back as it was.
+    fn finish(self) -> io::Result<()>;
+}
+
+impl<'g, W: Write, T: Tty> Session for Guard<'g, W, T> {
+    type Backend<'a>
+        = CrosstermBackend<&'a mut Guard<'g, W, T>>
+    where
+        Self: 'a;
+
+    fn backend(&mut self) -> Self::Backend<'_> {
+        CrosstermBackend::new(self)
+    }
+
+    fn finish(self) -> io::Result<()> {
+        Guard::finish(self)
+    }
+}
+
+impl<W: Write, T: Tty> Backend for CrosstermBackend<&mut Guard<'_, W, T>> {
+    fn region(&self) -> Option<Region> {
+        self.writer().region
+    }
+
+    fn resize(&mut self, width: u16, rows: u16) -> Option<Rect> {
+        self.writer_mut().resize(width, rows)
+    }
+
+    fn suspend<U>(&mut self, f: impl FnOnce() -> U) -> io::Result<U> {
+        self.writer_mut().suspend(f)
+    }
+
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool> {
+        event::poll(timeout)
+    }
+
+    fn read(&mut self) -> io::Result<Event> {
+        event::read()
+    }
+}
+
+#[cfg(test)]
+pub(super) use scripted::{Guarded, Scripted};
+
+#[cfg(test)]
+mod scripted {
+    use super::*;
+    use crate::ui::terminal::Fake;
+    use ratatui::backend::{ClearType, TestBackend, WindowSize};
+    use ratatui::buffer::Cell;
+    use ratatui::layout::{Position, Size};
+    use std::collections::VecDeque;
+
+    /// A screen in memory, fed `events` one by one as if typed. Every
+    /// event is ready at once; once they run out, reading fails, so that a
+    /// script that never leaves the picker ends anyway.
+    pub struct Scripted {
+        pub screen: TestBackend,
+        events: VecDeque<Event>,
+        /// How many frames have been drawn.
+        pub frames: usize,
+    }
+
+    impl Scripted {
+        pub fn new(width: u16, height: u16, events: impl IntoIterator<Item = Event>) -> Scripted {
+            Scripted {
+                screen: TestBackend::new(width, height),
+                events: events.into_iter().collect(),
+                frames: 0,
+            }
+        }
+
+        /// The last frame drawn, a line of text for each row.
+        pub fn text(&self) -> String {
+            let buffer = self.screen.buffer();
+            (0..buffer.area.height)
+                .map(|y| {
+                    let row: String = (0..buffer.area.width)
+                        .map(|x| buffer[(x, y)].symbol())
+                        .collect();
+                    row.trim_end().to_string()
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    }
+
+    impl ratatui::backend::Backend for &mut Scripted {
+        fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+        where
+            I: Iterator<Item = (u16, u16, &'a Cell)>,
+        {
+            self.frames += 1;
+            self.screen.draw(content)
+        }
+
+        fn hide_cursor(&mut self) -> io::Result<()> {
+            self.screen.hide_cursor()
+        }
+
+        fn show_cursor(&mut self) -> io::Result<()> {
+            self.screen.show_cursor()
+        }
+
+        fn get_cursor_position(&mut self) -> io::Result<Position> {
+            self.screen.get_cursor_position()
+        }
+
+        fn set_cursor_position<P: Into<Position>>(&mut self, position: P) -> io::Result<()> {
+            self.screen.set_cursor_position(position)
+        }
+
+        fn clear(&mut self) -> io::Result<()> {
+            self.screen.clear()
+        }
+
+        fn clear_region(&mut self, clear_type: ClearType) -> io::Result<()> {
+            self.screen.clear_region(clear_type)
+        }
+
+        fn size(&self) -> io::Result<Size> {
+            self.screen.size()
+        }
+
+        fn window_size(&mut self) -> io::Result<WindowSize> {
+            self.screen.window_size()
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            ratatui::backend::Backend::flush(&mut self.screen)
+        }
+    }
+
+    /// A [`Scripted`] screen on a terminal that a [`Guard`] set up, so that
+    /// a test sees the picker put it back.
+    pub struct Guarded<'a> {
+        pub guard: Guard<'a, Vec<u8>, &'a Fake>,
+        pub screen: Scripted,
+    }
+
+    impl Session for Guarded<'_> {
+        type Backend<'a>
+            = &'a mut Scripted
+        where
+            Self: 'a;
+
+        fn backend(&mut self) -> &mut Scripted {
+            &mut self.screen
+        }
+
+        fn finish(self) -> io::Result<()> {
+            self.guard.finish()
+        }
+    }
+
+    impl Backend for &mut Scripted {
+        fn region(&self) -> Option<Region> {
+            None
+        }
+
+        fn resize(&mut self, width: u16, rows: u16) -> Option<Rect> {
+            self.screen.resize(width, rows);
+            None
+        }
+
+        fn suspend<U>(&mut self, f: impl FnOnce() -> U) -> io::Result<U> {
+            Ok(f())
+        }
+
+        fn poll(&mut self, _: Duration) -> io::Result<bool> {
+            Ok(!self.events.is_empty())
+        }
+
+        fn read(&mut self) -> io::Result<Event> {
+            self.events.pop_front().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "the script ran out of keys")
+            })
+        }
+    }
+}
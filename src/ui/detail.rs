@@ -0,0 +1,211 @@
+//! The detail view of one command: the whole of it, what is known about its
+//! runs, and the start of its program's manual.
+//!
+//! The manual is `man <program>`, or `<program> --help` when there is no
+//! page. Either could take long or never finish, so they get [`TIMEOUT`]
+//! between them and are killed after it. A program is only run by name,
+//! when it is found on `PATH`, and never from a path written in the
+//! command; its stdin is closed so it cannot wait for input.
+
+use super::pane::Pane;
+use super::syntax;
+use super::theme::Theme;
+use super::view;
+use crate::history::provider;
+use crate::search::CommandStats;
+use crate::shell::Shell;
+use ratatui::layout::Rect;
+use ratatui::Frame;
+use std::env;
+use std::io::Read;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the manual may take to come, for `man` and `--help` together.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many lines of the manual are kept.
+const HELP_LINES: usize = 200;
+
+/// How many of a command's directories are listed.
+const MAX_DIRS: usize = 5;
+
+/// One command's detail view.
+pub(super) struct Detail {
+    pane: Pane,
+}
+
+impl Detail {
+    /// The view of `command`, shown as `shown` (masked or not), with its
+    /// program's manual formatted for a terminal `width` wide.
+    pub fn new(command: &str, shown: &str, stats: &CommandStats, width: u16, now: i64) -> Detail {
+        let mut lines: Vec<String> = shown.lines().map(str::to_string).collect();
+        lines.push(String::new());
+        lines.extend(about(stats, now));
+
+        let program = syntax::program(command);
+        let title = match program {
+            Some(program) => format!(" {} ", program),
+            None => " Command ".to_string(),
+        };
+        match program.map(|program| (program, help(program, width.saturating_sub(2)))) {
+            Some((_, Some((source, help)))) => {
+                lines.push(String::new());
+                lines.push(format!("── {} ──", source));
+                lines.extend(help);
+            }
+            Some((program, None)) => {
+                lines.push(String::new());
+                lines.push(format!("No manual or --help found for {}", program));
+            }
+            None => {}
+        }
+        Detail {
+            pane: Pane::new(title, lines, false),
+        }
+    }
+
+    /// Scrolls by `rows`, up when negative, within what fits in `area`.
+    pub fn scroll(&mut self, rows: isize, area: Rect) {
+        self.pane.scroll(rows, area);
+    }
+
+    /// Draws the view over all of `area`.
+    pub fn render(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        self.pane
+            .render(frame, area, theme, " ↑/↓ scroll · any other key goes back ");
+    }
+}
+
+/// What is known about a command's runs, a line each.
+fn about(stats: &CommandStats, now: i64) -> Vec<String> {
+    let mut lines = vec![match stats.count {
+        1 => "Run once".to_string(),
+        n => format!("Run {} times", n),
+    }];
+    if let Some(first) = stats.runs.first() {
+        lines.push(format!("First run {}", view::ago(first, now)));
+    }
+    if let Some(last_used) = stats.last_used {
+        lines.push(format!("Last run {}", view::ago(last_used, now)));
+    }
+    match stats.exit {
+        Some(0) => lines.push("Last run succeeded".to_string()),
+        Some(status) => lines.push(format!("Last run failed with status {}", status)),
+        None => {}
+    }
+    if !stats.shells.is_empty() {
+        let names: Vec<&str> = stats.shells.iter().map(Shell::name).collect();
+        lines.push(format!("Shells: {}", names.join(", ")));
+    }
+    if !stats.dirs.is_empty() {
+        let mut dirs: Vec<String> = stats.dirs.iter().take(MAX_DIRS).cloned().collect();
+        if stats.dirs.len() > MAX_DIRS {
+            dirs.push(format!("{} more", stats.dirs.len() - MAX_DIRS));
+        }
+        lines.push(format!("Run in: {}", dirs.join(", ")));
+    }
+    if let Some(name) = &stats.snippet {
+        lines.push(format!("Snippet: {}", name));
+    }
+    if stats.bookmarked {
+        lines.push("Bookmarked".to_string());
+    }
+    if !stats.tags.is_empty() {
+        let tags: Vec<&str> = stats.tags.iter().map(String::as_str).collect();
+        lines.push(format!("Tags: {}", tags.join(", ")));
+    }
+    lines
+}
+
+/// The start of `program`'s manual, formatted `width` columns wide, and
+/// where it came from; `None` if it has none, or is not on `PATH`.
+fn help(program: &str, width: u16) -> Option<(String, Vec<String>)> {
+    if program.contains(['/', '\\']) || !on_path(program) {
+        return None;
+    }
+    let deadline = Instant::now() + TIMEOUT;
+    let mut man = Command::new("man");
+    man.arg(program)
+        .env("MANPAGER", "cat")
+        .env("PAGER", "cat")
+        .env("MANWIDTH", width.max(20).to_string())
+        .env("GROFF_NO_SGR", "1");
+    if let Some(lines) = output(man, true, deadline) {
+        return Some((format!("man {}", program), lines));
+    }
+    let mut command = Command::new(program);
+    command.arg("--help");
+    // Programs that take no `--help` often print their usage and fail.
+    let lines = output(command, false, deadline)?;
+    Some((format!("{} --help", program), lines))
+}
+
+/// Whether `program` is a file in one of the `PATH` directories.
+fn on_path(program: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+    env::split_paths(&path).any(|dir| {
+        let file = dir.join(program);
+        file.is_file() || (cfg!(windows) && file.with_extension("exe").is_file())
+    })
+}
+
+/// The first lines `command` prints, to stdout or else to stderr, if it
+/// prints any and exits by `deadline`, successfully if it `must_succeed`.
+/// It is killed if it has not exited by then.
+fn output(mut command: Command, must_succeed: bool, deadline: Instant) -> Option<Vec<String>> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let out = read_in_background(child.stdout.take()?);
+    let err = read_in_background(child.stderr.take()?);
+    let status = wait(&mut child, deadline)?;
+    // What it started, like `man`'s formatter, may hold the pipes open
+    // after it exits.
+    let remaining = || deadline.saturating_duration_since(Instant::now());
+    let out = out.recv_timeout(remaining()).ok()?;
+    let err = err.recv_timeout(remaining()).unwrap_or_default();
+    if must_succeed && !status.success() {
+        return None;
+    }
+    let text = if out.trim().is_empty() { err } else { out };
+    let lines: Vec<String> = text
+        .lines()
+        .take(HELP_LINES)
+        .map(|line| provider::rendered(line).trim_end().to_string())
+        .collect();
+    lines.iter().any(|line| !line.is_empty()).then_some(lines)
+}
+
+/// Everything read from `pipe`, sent once it closes.
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = pipe.read_to_end(&mut bytes);
+        let _ = sender.send(String::from_utf8_lossy(&bytes).into_owned());
+    });
+    receiver
+}
+
+/// How `child` exited, waiting until `deadline` and killing it after.
+fn wait(child: &mut Child, deadline: Instant) -> Option<std::process::ExitStatus> {
+    loop {
+        if let Some(status) = child.try_wait().ok()? {
+            return Some(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
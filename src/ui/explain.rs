@@ -0,0 +1,177 @@
+//! Explaining the selected command with a program of the user's choosing.
+//!
+//! The `explain` setting is a shell command, like an LLM's command-line
+//! client or a script that asks explainshell.com. It is given the selected
+//! command on stdin, as the picker shows it (masked unless revealed), and
+//! whatever it prints to stdout or stderr shows in a pane as it arrives.
+//! Closing the pane stops it.
+
+use super::pane::Pane;
+use super::theme::Theme;
+use crate::exec;
+use crate::history::provider;
+use ratatui::layout::{Constraint, Flex, Layout, Rect};
+use ratatui::Frame;
+use std::io::{self, Read, Write};
+use std::process::{Child, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// An explainer's output so far, and the explainer while it runs.
+pub(super) struct Explanation {
+    pane: Pane,
+    child: Child,
+    /// What has arrived, by chunk, until both outputs close.
+    receiver: Option<Receiver<Vec<u8>>>,
+    output: Vec<u8>,
+}
+
+impl Explanation {
+    /// Starts `explainer` on `command`.
+    pub fn start(explainer: &str, command: &str) -> io::Result<Explanation> {
+        let mut child = exec::command(explainer)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let (sender, receiver) = mpsc::channel();
+        for pipe in [
+            child
+                .stdout
+                .take()
+                .map(|out| Box::new(out) as Box<dyn Read + Send>),
+            child
+                .stderr
+                .take()
+                .map(|err| Box::new(err) as Box<dyn Read + Send>),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            let sender = sender.clone();
+            thread::spawn(move || stream(pipe, sender));
+        }
+        // Written apart, so that an explainer that prints before it has
+        // read everything cannot block on a full pipe.
+        if let Some(mut stdin) = child.stdin.take() {
+            let command = format!("{}\n", command);
+            thread::spawn(move || stdin.write_all(command.as_bytes()));
+        }
+        Ok(Explanation {
+            pane: Pane::new(" Explanation ".to_string(), Vec::new(), true),
+            child,
+            receiver: Some(receiver),
+            output: Vec::new(),
+        })
+    }
+
+    /// Whether the explainer may still print more.
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /// Takes in what the explainer printed since last time, returning
+    /// whether anything changed.
+    pub fn receive(&mut self) -> bool {
+        let Some(receiver) = &self.receiver else {
+            return false;
+        };
+        let mut changed = false;
+        loop {
+            match receiver.try_recv() {
+                Ok(chunk) => {
+                    self.output.extend(chunk);
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                    changed = true;
+                    break;
+                }
+            }
+        }
+        if changed {
+            self.pane.lines = String::from_utf8_lossy(&self.output)
+                .lines()
+                .map(|line| provider::rendered(line).trim_end().to_string())
+                .collect();
+        }
+        if self.receiver.is_none() {
+            // One that closed its output but keeps going has nothing more to
+            // say.
+            let status = match self.child.try_wait() {
+                Ok(None) => {
+                    let _ = self.child.kill();
+                    let _ = self.child.wait();
+                    None
+                }
+                status => status.ok().flatten(),
+            };
+            match status {
+                Some(status) if !status.success() => self.pane.lines.push(format!(
+                    "(the explainer failed with status {})",
+                    exec::exit_code(status)
+                )),
+                _ if self.output.is_empty() => self
+                    .pane
+                    .lines
+                    .push("(the explainer printed nothing)".to_string()),
+                _ => {}
+            }
+        }
+        changed
+    }
+
+    /// Scrolls by `rows`, up when negative, within what fits over `screen`.
+    pub fn scroll(&mut self, rows: isize, screen: Rect) {
+        self.pane.scroll(rows, area(screen));
+    }
+
+    /// Draws the pane in the middle of `screen`.
+    pub fn render(&self, frame: &mut Frame, screen: Rect, theme: &Theme) {
+        let footer = if self.is_running() {
+            " explaining… · any key but ↑/↓ stops "
+        } else {
+            " ↑/↓ scroll · any other key goes back "
+        };
+        self.pane.render(frame, area(screen), theme, footer);
+    }
+}
+
+impl Drop for Explanation {
+    /// Stops the explainer if it is still going.
+    fn drop(&mut self) {
+        if self.is_running() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Where the pane goes on `screen`: most of it, leaving the edges of the
+/// picker in sight.
+fn area(screen: Rect) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Percentage(90)])
+        .flex(Flex::Center)
+        .areas(screen);
+    let [area] = Layout::vertical([Constraint::Percentage(80)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+/// Sends what `pipe` gives as it comes, until it closes.
+fn stream(mut pipe: Box<dyn Read + Send>, sender: mpsc::Sender<Vec<u8>>) {
+    let mut buffer = [0; 4096];
+    loop {
+        match pipe.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => {
+                if sender.send(buffer[..n].to_vec()).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
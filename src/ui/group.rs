@@ -0,0 +1,150 @@
+//! Folding results that start the same way under one row, so that a
+//! history full of `git commit -m ...` shows it once.
+//!
+//! A command's prefix is its program and, when the next word looks like a
+//! subcommand (lowercase letters, digits, `-` and `_`, as in `git commit` or
+//! `cargo build`), that word too. Commands sharing a prefix with others are
+//! listed under a header with their combined run count, in place of the best
+//! of them; expanding the header lists them below it.
+
+use crate::search::CommandStats;
+use std::collections::{BTreeSet, HashMap};
+
+/// The prefix `command` is grouped by.
+pub(super) fn prefix(command: &str) -> &str {
+    let program = command.find(char::is_whitespace).unwrap_or(command.len());
+    let rest = command[program..].trim_start();
+    let start = command.len() - rest.len();
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let second = &rest[..end];
+    let subcommand = second.starts_with(|c: char| c.is_ascii_lowercase())
+        && second
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if subcommand {
+        &command[..start + end]
+    } else {
+        &command[..program]
+    }
+}
+
+/// A row standing for every command with one prefix.
+#[derive(Debug, Clone)]
+pub(super) struct Header<'a> {
+    pub prefix: &'a str,
+    /// What the commands add up to: their runs, frecency, and shells, and
+    /// the latest time any of them ran.
+    pub stats: CommandStats,
+    /// How many commands it stands for.
+    pub commands: usize,
+    /// Whether they are listed below it.
+    pub expanded: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Row<'a> {
+    /// A command no other shares a prefix with.
+    Single(&'a str, &'a CommandStats),
+    Header(Box<Header<'a>>),
+    /// A command listed under an expanded header.
+    Member(&'a str, &'a CommandStats),
+}
+
+/// The rows of a grouped result list.
+#[derive(Debug, Clone)]
+pub(super) struct Groups<'a> {
+    rows: Vec<Row<'a>>,
+    /// How many results were grouped.
+    commands: usize,
+}
+
+impl<'a> Groups<'a> {
+    /// Groups `results` by prefix, in the order of the best command of each,
+    /// listing the commands of the prefixes in `expanded`.
+    pub fn new(results: &[(&'a str, &'a CommandStats)], expanded: &BTreeSet<String>) -> Self {
+        let mut groups: Vec<(&str, Vec<(&str, &CommandStats)>)> = Vec::new();
+        let mut at: HashMap<&str, usize> = HashMap::new();
+        for &(cmd, stats) in results {
+            let prefix = prefix(cmd);
+            let index = *at.entry(prefix).or_insert_with(|| {
+                groups.push((prefix, Vec::new()));
+                groups.len() - 1
+            });
+            groups[index].1.push((cmd, stats));
+        }
+
+        let mut rows = Vec::with_capacity(groups.len());
+        for (prefix, commands) in groups {
+            if let [(cmd, stats)] = commands[..] {
+                rows.push(Row::Single(cmd, stats));
+                continue;
+            }
+            let mut total = CommandStats::default();
+            for (_, stats) in &commands {
+                total.count += stats.count;
+                total.frecency += stats.frecency;
+                total.last_used = total.last_used.max(stats.last_used);
+                total.shells = total.shells.union(stats.shells);
+            }
+            let expanded = expanded.contains(prefix);
+            rows.push(Row::Header(Box::new(Header {
+                prefix,
+                stats: total,
+                commands: commands.len(),
+                expanded,
+            })));
+            if expanded {
+                rows.extend(
+                    commands
+                        .into_iter()
+                        .map(|(cmd, stats)| Row::Member(cmd, stats)),
+                );
+            }
+        }
+        Groups {
+            rows,
+            commands: results.len(),
+        }
+    }
+
+    /// Each row as a command and its stats; a header's is its prefix.
+    pub fn rows(&self) -> Vec<(&str, &CommandStats)> {
+        self.rows
+            .iter()
+            .map(|row| match row {
+                Row::Single(cmd, stats) | Row::Member(cmd, stats) => (*cmd, *stats),
+                Row::Header(header) => (header.prefix, &header.stats),
+            })
+            .collect()
+    }
+
+    /// How many commands the rows stand for.
+    pub fn commands(&self) -> usize {
+        self.commands
+    }
+
+    /// The header at row `index`, if it is one.
+    pub fn header(&self, index: usize) -> Option<&Header<'a>> {
+        match self.rows.get(index) {
+            Some(Row::Header(header)) => Some(header),
+            _ => None,
+        }
+    }
+
+    /// Whether row `index` is listed under a header.
+    pub fn is_member(&self, index: usize) -> bool {
+        matches!(self.rows.get(index), Some(Row::Member(..)))
+    }
+
+    /// The prefix of the group row `index` is in, whether as its header or
+    /// one of its commands.
+    pub fn group_of(&self, mut index: usize) -> Option<&'a str> {
+        loop {
+            match self.rows.get(index)? {
+                Row::Header(header) => return Some(header.prefix),
+                Row::Member(..) => index = index.checked_sub(1)?,
+                Row::Single(..) => return None,
+            }
+        }
+    }
+}
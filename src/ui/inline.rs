@@ -0,0 +1,196 @@
+//! Drawing the picker inline, in a few rows below the prompt, rather than on
+//! the alternate screen, as with `--height 40%`.
+//!
+//! The picker takes the rows from the cursor's line down, or from the next
+//! line if the cursor is not at the start of its own, scrolling the screen
+//! up first if there are not enough. When it ends it clears those rows and
+//! puts the cursor back where it was, so whatever was on the screen before
+//! stays there.
+
+use crossterm::cursor::{MoveTo, Show};
+use crossterm::execute;
+use crossterm::terminal::{self, Clear, ClearType};
+use ratatui::layout::Rect;
+use serde::Deserialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The fewest rows the picker is drawn in: the search line, one result, and
+/// the status bar.
+const MIN_ROWS: u16 = 3;
+
+/// How long to wait for the terminal to say where the cursor is.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How tall the picker is when drawn inline: a number of rows, or a
+/// percentage of the terminal's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Height {
+    Rows(u16),
+    Percent(u16),
+}
+
+impl Height {
+    /// The rows to take in a terminal `terminal_rows` tall.
+    pub fn rows(self, terminal_rows: u16) -> u16 {
+        let rows = match self {
+            Height::Rows(rows) => rows,
+            Height::Percent(percent) => (terminal_rows as u32 * percent as u32 / 100) as u16,
+        };
+        rows.max(MIN_ROWS).min(terminal_rows)
+    }
+}
+
+impl fmt::Display for Height {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Height::Rows(rows) => write!(f, "{}", rows),
+            Height::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+impl FromStr for Height {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid height: {} (expected rows like 20 or a percentage like 40%)",
+                s
+            )
+        };
+        match s.strip_suffix('%') {
+            Some(percent) => match percent.parse() {
+                Ok(percent @ 1..=100) => Ok(Height::Percent(percent)),
+                _ => Err(invalid()),
+            },
+            None => match s.parse() {
+                Ok(rows @ 1..) => Ok(Height::Rows(rows)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Height {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The rows the picker is drawn in, and where the cursor was before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Region {
+    pub area: Rect,
+    pub height: Height,
+    cursor: (u16, u16),
+}
+
+impl Region {
+    /// Makes room for the picker under the cursor on the terminal behind
+    /// `out`, which is in raw mode.
+    pub fn reserve(out: &mut impl Write, height: Height) -> io::Result<Region> {
+        let (width, terminal_rows) = terminal::size()?;
+        let (column, row) = cursor_position(out)?;
+        let rows = height.rows(terminal_rows);
+        let top = if column > 0 { row + 1 } else { row };
+        // Newlines at the bottom line scroll what is above into the
+        // scrollback, as the shell's own output would.
+        let missing = (top + rows).saturating_sub(terminal_rows);
+        if missing > 0 {
+            execute!(out, MoveTo(0, terminal_rows - 1))?;
+            out.write_all("\n".repeat(missing as usize).as_bytes())?;
+            out.flush()?;
+        }
+        Ok(Region {
+            area: Rect::new(0, top - missing, width, rows),
+            height,
+            cursor: (column, row.saturating_sub(missing)),
+        })
+    }
+
+    /// The region fitted to a terminal resized to `width` by
+    /// `terminal_rows`: as tall as `height` asks, and kept on the screen.
+    pub fn resized(self, width: u16, terminal_rows: u16) -> Region {
+        let rows = self.height.rows(terminal_rows);
+        let top = self.area.y.min(terminal_rows - rows);
+        Region {
+            area: Rect::new(0, top, width, rows),
+            cursor: (self.cursor.0, self.cursor.1.min(top)),
+            ..self
+        }
+    }
+
+    /// Clears the picker's rows and puts the cursor back.
+    pub fn clear(&self, out: &mut impl Write) -> io::Result<()> {
+        execute!(
+            out,
+            MoveTo(0, self.area.y),
+            Clear(ClearType::FromCursorDown),
+            MoveTo(self.cursor.0, self.cursor.1),
+            Show
+        )
+    }
+}
+
+/// Where the cursor is, as a column and row from 0, asked of the terminal
+/// behind `out`.
+///
+/// crossterm can ask too, but always on stdout, which is not the terminal
+/// under `--print`. The reply is read from `/dev/tty` on a thread of its
+/// own, so that a terminal that never answers cannot hang the picker.
+#[cfg(unix)]
+fn cursor_position(out: &mut impl Write) -> io::Result<(u16, u16)> {
+    use std::fs::File;
+    use std::io::Read;
+    use std::sync::mpsc;
+    use std::thread;
+
+    let mut tty = File::open("/dev/tty")?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reply = Vec::new();
+        let mut byte = [0];
+        while tty.read(&mut byte).is_ok_and(|read| read == 1) {
+            reply.push(byte[0]);
+            if byte[0] == b'R' {
+                break;
+            }
+        }
+        let _ = sender.send(reply);
+    });
+    out.write_all(b"\x1b[6n")?;
+    out.flush()?;
+    let unanswered = || {
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the terminal did not say where the cursor is",
+        )
+    };
+    let reply = receiver
+        .recv_timeout(REPLY_TIMEOUT)
+        .map_err(|_| unanswered())?;
+    // The reply is `ESC [ row ; column R`, counting from 1.
+    let reply = String::from_utf8_lossy(&reply);
+    let (row, column) = reply
+        .rsplit_once('[')
+        .and_then(|(_, reply)| reply.strip_suffix('R')?.split_once(';'))
+        .ok_or_else(unanswered)?;
+    match (column.parse::<u16>(), row.parse::<u16>()) {
+        (Ok(column), Ok(row)) => Ok((column.saturating_sub(1), row.saturating_sub(1))),
+        _ => Err(unanswered()),
+    }
+}
+
+/// Where the cursor is, as a column and row from 0. Windows consoles are
+/// asked directly rather than through `out`.
+#[cfg(not(unix))]
+fn cursor_position(_: &mut impl Write) -> io::Result<(u16, u16)> {
+    crossterm::cursor::position()
+}
@@ -0,0 +1,554 @@
+//! Which keys do what in the picker.
+//!
+//! Keys are bound to [`Action`]s rather than handled directly, so that the
+//! config file's `[keys]` section can move them around. Each entry there names
+//! an action and the key or keys that trigger it, replacing that action's
+//! defaults:
+//!
+//! ```toml
+//! [keys]
+//! accept = "enter"
+//! up = ["up", "ctrl-p"]
+//! preview = "ctrl-o"
+//! ```
+//!
+//! A key bound to a new action stops doing what it did before. Printable keys
+//! that are not bound to anything type into the query.
+//!
+//! In vim mode, the cancel key leaves the query for a normal mode instead,
+//! where letters move around rather than type; see [`Normal`].
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Something the picker can be told to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Action {
+    /// Choose the selection, or the marked commands.
+    Accept,
+    /// Mark or unmark the selection and move down.
+    Mark,
+    /// Run the selection right away.
+    Execute,
+    /// Copy the selection to the clipboard.
+    Copy,
+    /// Offer to delete the selection from history.
+    Delete,
+    /// Show or hide the preview pane.
+    Preview,
+    /// Show the selected command in full, with its stats and the start of
+    /// its program's manual.
+    Detail,
+    /// Show what the configured explainer says about the selected command.
+    Explain,
+    /// Show or mask what look like secrets.
+    Reveal,
+    /// Show only commands run in the current directory or below it, or all
+    /// of them again.
+    Here,
+    /// Leave out commands whose last run failed, or show them again.
+    HideFailed,
+    /// Show only commands run in this shell session, latest first, or all
+    /// of them again.
+    Session,
+    /// Match the query against each word of a command, to find every
+    /// command that used a file or flag, or against whole commands again.
+    Arguments,
+    /// Bookmark the selected command, or remove its bookmark.
+    Bookmark,
+    /// Tag the selected command, or take a tag off it.
+    Tag,
+    /// Take back the last deletion, bookmark, or tag change.
+    Undo,
+    /// Change the selected command in `$EDITOR` and choose what is saved.
+    Editor,
+    /// Fold commands sharing a prefix, like `git commit`, under one row, or
+    /// list them all again.
+    Group,
+    /// Edit the selected command before choosing it, or list the commands
+    /// under the selected group.
+    Edit,
+    /// Fold the selected group, or the one the selection is in.
+    Collapse,
+    /// Show how each result's score adds up, or stop showing it.
+    Scores,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    /// Select the best match.
+    First,
+    /// Select the last match.
+    Last,
+    /// Erase the last character of the query.
+    Backspace,
+    /// Leave without choosing anything.
+    Cancel,
+}
+
+impl Action {
+    pub const ALL: [Action; 29] = [
+        Action::Accept,
+        Action::Mark,
+        Action::Execute,
+        Action::Copy,
+        Action::Delete,
+        Action::Preview,
+        Action::Detail,
+        Action::Explain,
+        Action::Reveal,
+        Action::Here,
+        Action::HideFailed,
+        Action::Session,
+        Action::Arguments,
+        Action::Bookmark,
+        Action::Tag,
+        Action::Undo,
+        Action::Editor,
+        Action::Group,
+        Action::Edit,
+        Action::Collapse,
+        Action::Scores,
+        Action::Up,
+        Action::Down,
+        Action::PageUp,
+        Action::PageDown,
+        Action::First,
+        Action::Last,
+        Action::Backspace,
+        Action::Cancel,
+    ];
+
+    /// The name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Accept => "accept",
+            Action::Execute => "execute",
+            Action::Copy => "copy",
+            Action::Delete => "delete",
+            Action::Preview => "preview",
+            Action::Detail => "detail",
+            Action::Explain => "explain",
+            Action::Reveal => "reveal",
+            Action::Here => "here",
+            Action::HideFailed => "hide-failed",
+            Action::Session => "session",
+            Action::Arguments => "arguments",
+            Action::Bookmark => "bookmark",
+            Action::Tag => "tag",
+            Action::Undo => "undo",
+            Action::Editor => "editor",
+            Action::Group => "group",
+            Action::Edit => "edit",
+            Action::Collapse => "collapse",
+            Action::Scores => "scores",
+            Action::Mark => "mark",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::PageUp => "page-up",
+            Action::PageDown => "page-down",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::Backspace => "backspace",
+            Action::Cancel => "cancel",
+        }
+    }
+
+    /// The keys bound to this action unless the config says otherwise.
+    fn defaults(self) -> &'static [&'static str] {
+        match self {
+            Action::Accept => &["enter"],
+            Action::Execute => &["ctrl-x"],
+            Action::Copy => &["ctrl-y"],
+            Action::Delete => &["ctrl-d"],
+            Action::Preview => &["ctrl-p"],
+            Action::Detail => &["ctrl-k"],
+            Action::Explain => &["alt-e"],
+            Action::Reveal => &["ctrl-r"],
+            Action::Here => &["ctrl-f"],
+            Action::HideFailed => &["alt-x"],
+            Action::Session => &["ctrl-s"],
+            Action::Arguments => &["ctrl-a"],
+            Action::Bookmark => &["ctrl-b"],
+            Action::Tag => &["ctrl-t"],
+            Action::Undo => &["ctrl-z"],
+            Action::Editor => &["ctrl-o"],
+            Action::Group => &["alt-g"],
+            Action::Edit => &["ctrl-e", "right"],
+            Action::Collapse => &["left"],
+            Action::Scores => &["ctrl-g"],
+            Action::Mark => &["tab"],
+            Action::Up => &["up"],
+            Action::Down => &["down"],
+            Action::PageUp => &["pageup"],
+            Action::PageDown => &["pagedown"],
+            Action::First => &["home"],
+            Action::Last => &["end"],
+            Action::Backspace => &["backspace"],
+            Action::Cancel => &["esc"],
+        }
+    }
+
+    /// How the status bar describes this action, for those it has room for.
+    fn hint(self) -> Option<&'static str> {
+        match self {
+            Action::Accept => Some("choose"),
+            Action::Mark => Some("mark"),
+            Action::Execute => Some("run"),
+            Action::Copy => Some("copy"),
+            Action::Delete => Some("delete"),
+            Action::Preview => Some("preview"),
+            Action::Cancel => Some("quit"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Action::ALL.iter().map(|a| a.name()).collect();
+                format!(
+                    "unknown action: {} (expected one of {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A key with the modifiers held down, written like `ctrl-d`, `alt-enter`,
+/// or `f2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    /// A key as terminals report it. Shift is folded into the character it
+    /// types, so `A` matches however the terminal sends it, and into
+    /// BackTab.
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        let modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Char(c) => Key {
+                code: KeyCode::Char(c),
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            // Shift-Tab arrives as BackTab, with or without the shift.
+            KeyCode::BackTab => Key {
+                code,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => Key {
+                code: KeyCode::BackTab,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => Key { code, modifiers },
+        }
+    }
+
+    /// The key's short form for the status bar, e.g. `^X` or `Enter`.
+    fn label(&self) -> String {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers == KeyModifiers::CONTROL => {
+                format!("^{}", c.to_ascii_uppercase())
+            }
+            KeyCode::Enter if self.modifiers.is_empty() => "Enter".to_string(),
+            KeyCode::Tab if self.modifiers.is_empty() => "Tab".to_string(),
+            KeyCode::Esc if self.modifiers.is_empty() => "Esc".to_string(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Key {
+        Key::new(event.code, event.modifiers)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl-"),
+            (KeyModifiers::ALT, "alt-"),
+            (KeyModifiers::SHIFT, "shift-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            code => f.write_str(
+                NAMED
+                    .iter()
+                    .find(|(_, named)| *named == code)
+                    .map_or("?", |(name, _)| name),
+            ),
+        }
+    }
+}
+
+/// Keys that are written by name rather than by the character they type.
+const NAMED: [(&str, KeyCode); 17] = [
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("space", KeyCode::Char(' ')),
+    ("escape", KeyCode::Esc),
+];
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        // The last part is the key itself, even if it is a `-`.
+        while let Some((modifier, tail)) = rest.split_once('-').filter(|(_, t)| !t.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier in key {:?}: {}", s, modifier)),
+            };
+            rest = tail;
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            // Control combinations arrive lowercase whatever the case typed.
+            (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => {
+                KeyCode::Char(c.to_ascii_lowercase())
+            }
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let name = rest.to_ascii_lowercase();
+                let function = name
+                    .strip_prefix('f')
+                    .and_then(|n| n.parse::<u8>().ok())
+                    .filter(|n| (1..=12).contains(n));
+                match (function, NAMED.iter().find(|(named, _)| *named == name)) {
+                    (Some(n), _) => KeyCode::F(n),
+                    (None, Some(&(_, code))) => code,
+                    (None, None) => return Err(format!("unknown key: {}", s)),
+                }
+            }
+        };
+        Ok(Key::new(code, modifiers))
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The keys bound to one action in `[keys]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "KeyNames")]
+struct Binding(Vec<Key>);
+
+/// One key or a list of them, as written in the config file. Parsed apart
+/// from [`Binding`] so that a bad key is reported by name.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyNames {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl TryFrom<KeyNames> for Binding {
+    type Error = String;
+
+    fn try_from(names: KeyNames) -> Result<Self, Self::Error> {
+        let names = match names {
+            KeyNames::One(name) => vec![name],
+            KeyNames::Many(names) => names,
+        };
+        names
+            .iter()
+            .map(|name| name.parse())
+            .collect::<Result<_, _>>()
+            .map(Binding)
+    }
+}
+
+/// The `[keys]` section of the config file: actions and the keys that
+/// trigger them in place of their defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct KeysConfig(BTreeMap<Action, Binding>);
+
+impl KeysConfig {
+    /// The default bindings with the configured ones laid over them.
+    pub fn keymap(&self) -> Keymap {
+        let mut keymap = Keymap::default();
+        for action in self.0.keys() {
+            keymap.keys.retain(|_, bound| bound != action);
+        }
+        for (&action, Binding(keys)) in &self.0 {
+            for &key in keys {
+                keymap.keys.insert(key, action);
+            }
+        }
+        keymap
+    }
+}
+
+/// The key each action is bound to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    keys: HashMap<Key, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let keys = Action::ALL
+            .into_iter()
+            .flat_map(|action| {
+                action
+                    .defaults()
+                    .iter()
+                    .map(move |key| (key.parse().expect("valid default key"), action))
+            })
+            .collect();
+        Keymap { keys }
+    }
+}
+
+impl Keymap {
+    /// What pressing `key` should do, if it is bound.
+    pub fn action(&self, key: KeyEvent) -> Option<Action> {
+        self.keys.get(&Key::from(key)).copied()
+    }
+
+    /// The key hints for the status bar, e.g. `Enter choose · ^X run`. In
+    /// vim mode the cancel key switches to normal mode rather than quitting.
+    pub fn hints(&self, vim: bool) -> String {
+        let mut hints = Vec::new();
+        for action in Action::ALL {
+            let hint = match action.hint() {
+                Some(_) if vim && action == Action::Cancel => "normal mode",
+                Some(hint) => hint,
+                None => continue,
+            };
+            // The shortest of an action's keys, so that hints stay the same
+            // from run to run.
+            let key = self
+                .keys
+                .iter()
+                .filter(|(_, bound)| **bound == action)
+                .map(|(key, _)| key.label())
+                .min_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+            if let Some(key) = key {
+                hints.push(format!("{} {}", key, hint));
+            }
+        }
+        hints.join(" · ")
+    }
+}
+
+/// The status bar's key hints in vim mode's normal mode.
+pub const NORMAL_HINTS: &str =
+    "-- NORMAL -- j/k move · gg/G first/last · dd delete · K detail · / search · Enter choose · Esc quit";
+
+/// What a key does in vim mode's normal mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalKey {
+    /// Do this.
+    Action(Action),
+    /// Go back to typing the query.
+    Insert,
+    /// The first key of `gg` or `dd`; wait for the next.
+    Pending,
+    /// Not a normal-mode key; the keymap decides, but it must not type.
+    Other,
+}
+
+/// The vim-style keys of normal mode, which remembers the first half of
+/// `gg` and `dd`.
+#[derive(Debug, Clone, Default)]
+pub struct Normal {
+    pending: Option<char>,
+}
+
+impl Normal {
+    /// What pressing `key` does, given the keys before it.
+    pub fn key(&mut self, key: KeyEvent) -> NormalKey {
+        let key = Key::from(key);
+        let pending = self.pending.take();
+        let KeyCode::Char(c) = key.code else {
+            return match key.code {
+                KeyCode::Esc => NormalKey::Action(Action::Cancel),
+                _ => NormalKey::Other,
+            };
+        };
+        if !key.modifiers.is_empty() {
+            return NormalKey::Other;
+        }
+        match (pending, c) {
+            (Some('g'), 'g') => NormalKey::Action(Action::First),
+            (Some('d'), 'd') => NormalKey::Action(Action::Delete),
+            (_, 'g' | 'd') => {
+                self.pending = Some(c);
+                NormalKey::Pending
+            }
+            (_, 'j') => NormalKey::Action(Action::Down),
+            (_, 'k') => NormalKey::Action(Action::Up),
+            (_, 'G') => NormalKey::Action(Action::Last),
+            (_, 'K') => NormalKey::Action(Action::Detail),
+            (_, 'q') => NormalKey::Action(Action::Cancel),
+            (_, '/' | 'i' | 'a') => NormalKey::Insert,
+            _ => NormalKey::Other,
+        }
+    }
+}
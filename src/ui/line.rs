@@ -0,0 +1,117 @@
+//! A one-line text editor, for changing a command before it is chosen.
+//!
+//! It takes the usual readline keys: arrows, Home and End, Ctrl-A and
+//! Ctrl-E, Alt-B and Alt-F or Ctrl-arrows to move by word, Backspace and
+//! Delete, Ctrl-W to erase a word, and Ctrl-U and Ctrl-K to erase to either
+//! end.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Text being edited, with a cursor in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct LineEditor {
+    text: String,
+    /// The byte offset of the cursor, always on a char boundary.
+    cursor: usize,
+}
+
+impl LineEditor {
+    /// Starts editing `text` with the cursor at its end.
+    pub fn new(text: String) -> LineEditor {
+        LineEditor {
+            cursor: text.len(),
+            text,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
+
+    /// The byte offset of the cursor in the text.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Applies `key`, returning whether it was an editing key at all.
+    pub fn key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Left if ctrl || alt => self.cursor = self.word_start(),
+            KeyCode::Right if ctrl || alt => self.cursor = self.word_end(),
+            KeyCode::Left => self.cursor = self.previous(),
+            KeyCode::Right => self.cursor = self.next(),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            KeyCode::Backspace if ctrl || alt => self.erase_to(self.word_start()),
+            KeyCode::Backspace => self.erase_to(self.previous()),
+            KeyCode::Delete => self.erase_to(self.next()),
+            KeyCode::Char(c) if ctrl => match c {
+                'a' => self.cursor = 0,
+                'e' => self.cursor = self.text.len(),
+                'b' => self.cursor = self.previous(),
+                'f' => self.cursor = self.next(),
+                'w' => self.erase_to(self.word_start()),
+                'h' => self.erase_to(self.previous()),
+                'd' => self.erase_to(self.next()),
+                'u' => self.erase_to(0),
+                'k' => self.erase_to(self.text.len()),
+                _ => return false,
+            },
+            KeyCode::Char(c) if alt => match c {
+                'b' => self.cursor = self.word_start(),
+                'f' => self.cursor = self.word_end(),
+                'd' => self.erase_to(self.word_end()),
+                _ => return false,
+            },
+            KeyCode::Char(c) => {
+                self.text.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Removes the text between the cursor and `to`, on either side of it.
+    fn erase_to(&mut self, to: usize) {
+        let (start, end) = (self.cursor.min(to), self.cursor.max(to));
+        self.text.replace_range(start..end, "");
+        self.cursor = start;
+    }
+
+    fn previous(&self) -> usize {
+        self.text[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(at, _)| at)
+    }
+
+    fn next(&self) -> usize {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+
+    /// Where the word before the cursor starts, skipping blanks first.
+    fn word_start(&self) -> usize {
+        let before = self.text[..self.cursor].trim_end();
+        before.rfind(char::is_whitespace).map_or(0, |at| {
+            at + before[at..].chars().next().map_or(1, char::len_utf8)
+        })
+    }
+
+    /// Where the word after the cursor ends, skipping blanks first.
+    fn word_end(&self) -> usize {
+        let after = &self.text[self.cursor..];
+        let skipped = after.len() - after.trim_start().len();
+        let word = &after[skipped..];
+        self.cursor + skipped + word.find(char::is_whitespace).unwrap_or(word.len())
+    }
+}
@@ -0,0 +1,85 @@
+//! A bordered box of text drawn over the picker, scrolled with the arrows,
+//! for the detail view and explanations.
+
+use super::theme::Theme;
+use super::view;
+use ratatui::layout::Rect;
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+
+/// A pane's text and how far it is scrolled.
+pub(super) struct Pane {
+    pub title: String,
+    pub lines: Vec<String>,
+    scroll: usize,
+    /// Whether the pane stays scrolled to the end as lines are added.
+    follow: bool,
+}
+
+impl Pane {
+    /// A pane scrolled to the top, or kept at the end if it `follow`s.
+    pub fn new(title: String, lines: Vec<String>, follow: bool) -> Pane {
+        Pane {
+            title,
+            lines,
+            scroll: 0,
+            follow,
+        }
+    }
+
+    /// Scrolls by `rows`, up when negative, within what fits in `area`.
+    /// Scrolling to the end follows what is added after.
+    pub fn scroll(&mut self, rows: isize, area: Rect) {
+        let most = self.most(area);
+        let from = if self.follow { most } else { self.scroll };
+        self.scroll = from.saturating_add_signed(rows).min(most);
+        self.follow = self.scroll == most && rows > 0;
+    }
+
+    /// Draws the pane over all of `area`, with `footer` on its bottom border.
+    pub fn render(&self, frame: &mut Frame, area: Rect, theme: &Theme, footer: &str) {
+        let (text, height) = self.wrapped(area);
+        let most = text.len().saturating_sub(height);
+        let scroll = if self.follow {
+            most
+        } else {
+            self.scroll.min(most)
+        };
+        let lines: Vec<Line> = text
+            .iter()
+            .skip(scroll)
+            .take(height)
+            .map(|line| Line::raw(line.as_str()))
+            .collect();
+        let block = Block::new()
+            .borders(Borders::ALL)
+            .border_style(Style::new().fg(theme.count))
+            .title(Line::styled(
+                self.title.as_str(),
+                Style::new().fg(theme.prompt).add_modifier(Modifier::BOLD),
+            ))
+            .title_bottom(footer);
+        frame.render_widget(Clear, area);
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    /// How far the pane can scroll in `area`.
+    fn most(&self, area: Rect) -> usize {
+        let (text, height) = self.wrapped(area);
+        text.len().saturating_sub(height)
+    }
+
+    /// The lines wrapped to fit inside the border in `area`, and how many
+    /// of them fit at once.
+    fn wrapped(&self, area: Rect) -> (Vec<String>, usize) {
+        let width = area.width.saturating_sub(2).max(1) as usize;
+        let text = self
+            .lines
+            .iter()
+            .flat_map(|line| view::wrap(line, width))
+            .collect();
+        (text, area.height.saturating_sub(2) as usize)
+    }
+}
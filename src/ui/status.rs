@@ -0,0 +1,92 @@
+//! The bar along the bottom of the picker: how many commands match, how the
+//! query is matched, which filters are on, and then the key hints or
+//! whatever message is more pressing.
+
+use super::theme::Theme;
+use crate::search::{ExitFilter, Filter};
+use crate::shell::{Shell, ShellSet};
+use ratatui::layout::Rect;
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+
+/// Everything the status bar shows.
+pub(super) struct StatusBar<'a> {
+    /// How many commands match the query, and how many there are in all.
+    pub matches: usize,
+    pub total: usize,
+    /// How many commands are marked with Tab.
+    pub marked: usize,
+    /// Which commands are searched, and how.
+    pub filter: &'a Filter,
+    /// Whether results are folded by prefix.
+    pub grouped: bool,
+    /// The shells the history came from.
+    pub shells: ShellSet,
+    pub theme: &'a Theme,
+    /// What the bar says after the summary when there is no message.
+    pub hints: &'a str,
+    /// What the bar says instead of the key hints.
+    pub message: Option<&'a str>,
+}
+
+impl StatusBar<'_> {
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let line = Line::from(vec![
+            Span::styled(self.summary(), Style::new().fg(self.theme.count)),
+            Span::raw("  "),
+            Span::raw(self.message.unwrap_or(self.hints)),
+        ]);
+        let bar = Paragraph::new(line).style(Style::new().fg(self.theme.status));
+        frame.render_widget(bar, area);
+    }
+
+    /// Like `123/4,567 matches · fuzzy · shell:zsh · dir-filter:on`.
+    fn summary(&self) -> String {
+        let mut parts = vec![format!(
+            "{}/{} matches",
+            thousands(self.matches),
+            thousands(self.total)
+        )];
+        if self.marked > 0 {
+            parts.push(format!("{} marked", thousands(self.marked)));
+        }
+        parts.push(match self.filter.arguments {
+            true => "fuzzy by argument".to_string(),
+            false => "fuzzy".to_string(),
+        });
+        if !self.shells.is_empty() {
+            let names: Vec<&str> = self.shells.iter().map(Shell::name).collect();
+            parts.push(format!("shell:{}", names.join(",")));
+        }
+        if self.filter.dir.is_some() {
+            parts.push("dir-filter:on".to_string());
+        }
+        if self.filter.session {
+            parts.push("session-filter:on".to_string());
+        }
+        match self.filter.exit {
+            ExitFilter::Any => {}
+            ExitFilter::Succeeded => parts.push("exit-filter:ok".to_string()),
+            ExitFilter::Failed => parts.push("exit-filter:failed".to_string()),
+        }
+        if self.grouped {
+            parts.push("grouped".to_string());
+        }
+        parts.join(" · ")
+    }
+}
+
+/// `n` with its thousands separated by commas.
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
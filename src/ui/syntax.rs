@@ -0,0 +1,208 @@
+//! Telling apart the parts of a command line, to color them in the list.
+//!
+//! This is a small lexer, not a parser: it finds the program of each command
+//! in a pipeline or list, flags, quoted strings, and the operators between
+//! commands, which is enough to make long pipelines readable. Whatever it
+//! does not recognize is left plain.
+
+use std::ops::Range;
+
+/// What a part of a command is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    /// The program a command runs, like `git` in `git log | head`.
+    Program,
+    /// An option, like `-la` or `--force`.
+    Flag,
+    /// A quoted string, quotes included.
+    String,
+    /// A pipe, redirection, or list operator such as `&&`, or a shell
+    /// keyword like `if` or `do`.
+    Operator,
+}
+
+/// Programs that run the command after them, which is then the one to
+/// color.
+const WRAPPERS: [&str; 10] = [
+    "sudo", "doas", "time", "nohup", "exec", "command", "builtin", "env", "xargs", "watch",
+];
+
+/// Keywords after which a command starts.
+const KEYWORDS: [&str; 13] = [
+    "if", "then", "else", "elif", "fi", "do", "done", "while", "until", "esac", "!", "{", "}",
+];
+
+/// Keywords followed by a name rather than a command.
+const NAMING_KEYWORDS: [&str; 4] = ["for", "case", "select", "function"];
+
+/// The parts of `command` worth coloring, in order and without overlaps.
+pub(super) fn tokens(command: &str) -> Vec<(Range<usize>, Kind)> {
+    let mut tokens = Vec::new();
+    let end_of = |chars: &mut std::iter::Peekable<std::str::CharIndices>| {
+        chars.peek().map_or(command.len(), |&(at, _)| at)
+    };
+    let mut chars = command.char_indices().peekable();
+    // Whether the next word is a program rather than an argument.
+    let mut starts_command = true;
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            starts_command |= c == '\n';
+            continue;
+        }
+        if is_operator(c) {
+            while chars.next_if(|&(_, c)| is_operator(c)).is_some() {}
+            let end = end_of(&mut chars);
+            // A redirection is followed by a file, not a command.
+            starts_command = !command[start..end].contains(['<', '>']);
+            tokens.push((start..end, Kind::Operator));
+            continue;
+        }
+
+        // A word, which may have quoted parts.
+        let mut strings = Vec::new();
+        let mut quote: Option<(char, usize)> = None;
+        while let Some(&(at, c)) = chars.peek() {
+            match quote {
+                Some((q, from)) => {
+                    chars.next();
+                    if c == '\\' && q == '"' {
+                        chars.next();
+                    } else if c == q {
+                        strings.push(from..at + 1);
+                        quote = None;
+                    }
+                }
+                None if c.is_whitespace() || is_operator(c) => break,
+                None => {
+                    chars.next();
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' | '\'' => quote = Some((c, at)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let end = end_of(&mut chars);
+        // An unclosed quote runs to the end of the word.
+        if let Some((_, from)) = quote {
+            strings.push(from..end);
+        }
+
+        let word = &command[start..end];
+        let kind = if word.starts_with('-') {
+            Some(Kind::Flag)
+        } else if !starts_command || is_assignment(word) {
+            None
+        } else if KEYWORDS.contains(&word) {
+            Some(Kind::Operator)
+        } else if NAMING_KEYWORDS.contains(&word) {
+            starts_command = false;
+            Some(Kind::Operator)
+        } else {
+            starts_command = WRAPPERS.contains(&word);
+            Some(Kind::Program)
+        };
+        match kind {
+            Some(kind) => tokens.push((start..end, kind)),
+            None => tokens.extend(strings.into_iter().map(|range| (range, Kind::String))),
+        }
+    }
+    tokens
+}
+
+/// The program `command` runs first, past any wrapper like `sudo`.
+pub(super) fn program(command: &str) -> Option<&str> {
+    tokens(command)
+        .into_iter()
+        .filter(|(_, kind)| *kind == Kind::Program)
+        .map(|(range, _)| &command[range])
+        .find(|word| !WRAPPERS.contains(word))
+}
+
+fn is_operator(c: char) -> bool {
+    matches!(c, '|' | '&' | ';' | '<' | '>' | '(' | ')')
+}
+
+/// Whether `word` is a `NAME=value` assignment before a command.
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text of each token of `command`, with its kind.
+    fn parts(command: &str) -> Vec<(&str, Kind)> {
+        tokens(command)
+            .into_iter()
+            .map(|(range, kind)| (&command[range], kind))
+            .collect()
+    }
+
+    #[test]
+    fn colors_programs_flags_and_operators_of_a_pipeline() {
+        assert_eq!(
+            parts("git log --oneline | head -n 5 && echo done"),
+            [
+                ("git", Kind::Program),
+                ("--oneline", Kind::Flag),
+                ("|", Kind::Operator),
+                ("head", Kind::Program),
+                ("-n", Kind::Flag),
+                ("&&", Kind::Operator),
+                ("echo", Kind::Program),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_quoted_strings_inside_words() {
+        assert_eq!(
+            parts(r#"git commit -m "fix | the \"lexer\"" msg='a b'"#),
+            [
+                ("git", Kind::Program),
+                ("-m", Kind::Flag),
+                (r#""fix | the \"lexer\"""#, Kind::String),
+                ("'a b'", Kind::String),
+            ]
+        );
+    }
+
+    #[test]
+    fn looks_past_wrappers_assignments_and_redirections() {
+        assert_eq!(
+            parts("RUST_LOG=debug sudo -E cargo run > out.log"),
+            [
+                ("sudo", Kind::Program),
+                ("-E", Kind::Flag),
+                ("cargo", Kind::Program),
+                (">", Kind::Operator),
+            ]
+        );
+        assert_eq!(
+            parts("for f in *.rs; do wc $f; done"),
+            [
+                ("for", Kind::Operator),
+                (";", Kind::Operator),
+                ("do", Kind::Operator),
+                ("wc", Kind::Program),
+                (";", Kind::Operator),
+                ("done", Kind::Operator),
+            ]
+        );
+    }
+
+    #[test]
+    fn program_is_the_first_one_past_wrappers() {
+        assert_eq!(program("RUST_LOG=debug sudo -E cargo run"), Some("cargo"));
+        assert_eq!(program("sudo"), None);
+    }
+}
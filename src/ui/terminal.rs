@@ -0,0 +1,328 @@
+//! Putting the terminal back however the picker ends: when it returns, when
+//! it fails or panics, or when a signal like SIGTERM or SIGHUP stops it.
+//!
+//! The picker sets the terminal up through a [`Guard`], which restores it
+//! when dropped, so that no way out of the picker can skip that. While the
+//! guard is held, what it has done to the terminal is also kept here, so
+//! that the panic hook and the signal handler installed by [`track`] can
+//! undo it before the process goes: a panic's message is printed before the
+//! guard is dropped, and would be lost with the alternate screen.
+
+use super::inline::{Height, Region};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::layout::Rect;
+use std::io::{self, Write};
+use std::mem;
+use std::panic;
+use std::sync::{Mutex, Once};
+
+/// Where the running picker draws: on the alternate screen, or in a
+/// region below the cursor.
+type Screen = Option<Region>;
+
+/// The running picker's screen, or `None` while no picker is running.
+static ACTIVE: Mutex<Option<Screen>> = Mutex::new(None);
+
+static INSTALL: Once = Once::new();
+
+/// What a [`Guard`] does to the terminal besides writing to it, kept apart
+/// so that tests can stand in for the terminal.
+pub(super) trait Tty {
+    /// Puts the terminal into raw mode, or takes it out.
+    fn set_raw(&mut self, raw: bool) -> io::Result<()>;
+
+    /// Records that a picker is drawing on `screen`, or with `None` that it
+    /// no longer is; see [`track`]. Returns whether one was, and so has yet
+    /// to be restored.
+    fn track(&mut self, screen: Option<Screen>) -> bool;
+}
+
+/// The terminal the process runs in.
+pub(super) struct System;
+
+impl Tty for System {
+    fn set_raw(&mut self, raw: bool) -> io::Result<()> {
+        match raw {
+            true => enable_raw_mode(),
+            false => disable_raw_mode(),
+        }
+    }
+
+    fn track(&mut self, screen: Option<Screen>) -> bool {
+        track(screen)
+    }
+}
+
+/// The terminal behind a writer, set up for the picker until the guard is
+/// dropped or finished. Writing to the guard writes to the terminal.
+pub(super) struct Guard<'a, W: Write, T: Tty = System> {
+    out: &'a mut W,
+    tty: T,
+    /// Where the picker draws: rows below the cursor, or for `None`, the
+    /// alternate screen.
+    pub region: Option<Region>,
+    mouse: bool,
+}
+
+impl<'a, W: Write, T: Tty> Guard<'a, W, T> {
+    /// Puts the terminal behind `out` into raw mode, then onto the
+    /// alternate screen or, with a `height` in rows, makes room below the
+    /// cursor; one that will not say where the cursor is gets the alternate
+    /// screen after all. The cursor is hidden, and with `mouse`, mouse
+    /// events turned on. Whatever of this fails is undone again.
+    pub fn new(
+        out: &'a mut W,
+        mut tty: T,
+        height: Option<Height>,
+        mouse: bool,
+    ) -> io::Result<Self> {
+        tty.set_raw(true)?;
+        let region = height.and_then(|height| Region::reserve(out, height).ok());
+        let mut guard = Guard {
+            out,
+            tty,
+            region,
+            mouse,
+        };
+        guard.tty.track(Some(region));
+        guard.enter()?;
+        Ok(guard)
+    }
+
+    /// Fits the picker's rows to a terminal resized to `width` by `rows`,
+    /// returning where they now are. Full screen, nothing needs fitting.
+    pub fn resize(&mut self, width: u16, rows: u16) -> Option<Rect> {
+        let region = self.region.as_mut()?;
+        *region = region.resized(width, rows);
+        self.tty.track(Some(Some(*region)));
+        Some(region.area)
+    }
+
+    /// Runs `f` with the terminal handed back for another program: off the
+    /// alternate screen, or with the picker's rows cleared, and out of raw
+    /// mode, as before [`Guard::new`]. The rows are made again afterwards,
+    /// wherever the cursor is by then.
+    pub fn suspend<U>(&mut self, f: impl FnOnce() -> U) -> io::Result<U> {
+        self.restore()?;
+        let result = f();
+        self.tty.set_raw(true)?;
+        self.tty.track(Some(self.region));
+        if let Some(region) = &mut self.region {
+            *region = Region::reserve(self.out, region.height)?;
+            self.tty.track(Some(Some(*region)));
+        }
+        self.enter()?;
+        Ok(result)
+    }
+
+    /// Restores the terminal now rather than when the guard is dropped, to
+    /// learn whether that worked.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.restore()
+    }
+
+    /// Hides the cursor on the picker's screen, and turns the mouse on.
+    fn enter(&mut self) -> io::Result<()> {
+        match self.region {
+            Some(_) => execute!(self.out, Hide)?,
+            None => execute!(self.out, EnterAlternateScreen, Hide)?,
+        }
+        if self.mouse {
+            execute!(self.out, EnableMouseCapture)?;
+        }
+        Ok(())
+    }
+
+    /// Restores the terminal, unless it already has been, as by the panic
+    /// hook.
+    fn restore(&mut self) -> io::Result<()> {
+        if !self.tty.track(None) {
+            return Ok(());
+        }
+        let restored = restore(self.out, self.region.as_ref());
+        let raw = self.tty.set_raw(false);
+        restored.and(raw)
+    }
+}
+
+impl<W: Write, T: Tty> Drop for Guard<'_, W, T> {
+    fn drop(&mut self) {
+        let _ = self.restore();
+    }
+}
+
+impl<W: Write, T: Tty> Write for Guard<'_, W, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Records that a picker is drawing on `screen`, or with `None` that it no
+/// longer is, returning whether one was. The first call installs the panic
+/// hook and signal handler.
+fn track(screen: Option<Screen>) -> bool {
+    INSTALL.call_once(install);
+    match ACTIVE.lock() {
+        Ok(mut active) => mem::replace(&mut *active, screen).is_some(),
+        Err(_) => true,
+    }
+}
+
+/// Turns the mouse back off and clears the picker from `screen`, leaving
+/// the alternate screen if it was on it. Raw mode is left to the caller.
+fn restore(out: &mut impl Write, screen: Option<&Region>) -> io::Result<()> {
+    execute!(out, DisableMouseCapture).and_then(|_| match screen {
+        Some(region) => region.clear(out),
+        None => execute!(
+            out,
+            Clear(ClearType::All),
+            MoveTo(0, 0),
+            Show,
+            LeaveAlternateScreen
+        ),
+    })
+}
+
+/// Restores the terminal if a picker is running, for when the process is
+/// about to end without it returning.
+fn restore_active() {
+    let Some(screen) = ACTIVE.lock().ok().and_then(|mut active| active.take()) else {
+        return;
+    };
+    let _ = match tty() {
+        Ok(mut tty) => restore(&mut tty, screen.as_ref()),
+        Err(_) => restore(&mut io::stderr(), screen.as_ref()),
+    };
+    let _ = disable_raw_mode();
+}
+
+/// The terminal itself, whichever of stdout and stderr the picker drew on.
+fn tty() -> io::Result<std::fs::File> {
+    #[cfg(unix)]
+    return std::fs::OpenOptions::new().write(true).open("/dev/tty");
+    #[cfg(not(unix))]
+    return Err(io::ErrorKind::Unsupported.into());
+}
+
+fn install() {
+    // The terminal is put back before the message is printed, so that the
+    // message is not lost with the alternate screen.
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_active();
+        previous(info);
+    }));
+    #[cfg(unix)]
+    handle_signals();
+}
+
+/// Restores the terminal on the signals that end a process, then ends it
+/// as the signal would have.
+///
+/// In raw mode Ctrl-C is a key rather than SIGINT, but SIGINT can still
+/// come from elsewhere, as from `kill -INT`.
+#[cfg(unix)]
+fn handle_signals() {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+    use signal_hook::iterator::Signals;
+    use signal_hook::low_level::emulate_default_handler;
+
+    let Ok(mut signals) = Signals::new([SIGHUP, SIGINT, SIGQUIT, SIGTERM]) else {
+        return;
+    };
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            restore_active();
+            let _ = emulate_default_handler(signal);
+        }
+    });
+}
+
+#[cfg(test)]
+pub(super) use fake::{count, Fake, ENTER, LEAVE};
+
+#[cfg(test)]
+mod fake {
+    use super::*;
+    use std::cell::Cell;
+
+    /// A terminal that notes what is done to it.
+    #[derive(Default)]
+    pub struct Fake {
+        raw: Cell<bool>,
+        pub tracked: Cell<bool>,
+    }
+
+    impl Fake {
+        /// Whether it is in raw mode, and whether a picker is tracked as
+        /// drawing on it.
+        pub fn state(&self) -> (bool, bool) {
+            (self.raw.get(), self.tracked.get())
+        }
+    }
+
+    impl Tty for &Fake {
+        fn set_raw(&mut self, raw: bool) -> io::Result<()> {
+            self.raw.set(raw);
+            Ok(())
+        }
+
+        fn track(&mut self, screen: Option<Screen>) -> bool {
+            self.tracked.replace(screen.is_some())
+        }
+    }
+
+    /// What enters and leaves the alternate screen.
+    pub const ENTER: &str = "\x1b[?1049h";
+    pub const LEAVE: &str = "\x1b[?1049l";
+
+    /// How often `sequence` was written to `out`.
+    pub fn count(out: &[u8], sequence: &str) -> usize {
+        String::from_utf8_lossy(out).matches(sequence).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_the_terminal_after_a_panic_once() {
+        let (mut out, fake) = (Vec::new(), Fake::default());
+        let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let _guard = Guard::new(&mut out, &fake, None, true).unwrap();
+            assert_eq!(fake.state(), (true, true));
+            panic!("the picker broke");
+        }));
+        assert_eq!(fake.state(), (false, false));
+        assert_eq!((count(&out, ENTER), count(&out, LEAVE)), (1, 1));
+
+        // Once the panic hook has restored the terminal, the guard leaves
+        // it be.
+        let (mut out, fake) = (Vec::new(), Fake::default());
+        let guard = Guard::new(&mut out, &fake, None, false).unwrap();
+        fake.tracked.set(false);
+        drop(guard);
+        assert_eq!(count(&out, LEAVE), 0);
+    }
+
+    #[test]
+    fn hands_the_terminal_back_while_suspended() {
+        let (mut out, fake) = (Vec::new(), Fake::default());
+        let mut guard = Guard::new(&mut out, &fake, None, false).unwrap();
+        assert_eq!(guard.suspend(|| fake.state()).unwrap(), (false, false));
+        assert_eq!(fake.state(), (true, true));
+        drop(guard);
+        assert_eq!(fake.state(), (false, false));
+        assert_eq!((count(&out, ENTER), count(&out, LEAVE)), (2, 2));
+    }
+}
@@ -0,0 +1,345 @@
+//! Colors of the picker.
+//!
+//! A theme starts from one of the built-in presets and may override single
+//! colors in the config file's `[theme]` section. Colors are written as
+//! names (`yellow`, `light-blue`), 256-color indexes (`244`), or `#rrggbb`,
+//! and are brought down to what the terminal can show: `#rrggbb` needs
+//! truecolor, which terminals announce through `COLORTERM`.
+
+use ratatui::style::Color;
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+/// A built-in theme.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// The terminal's own palette.
+    #[default]
+    Default,
+    /// Ethan Schoonover's Solarized, dark variant.
+    Solarized,
+    /// Arctic Ice Studio's Nord.
+    Nord,
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Preset::Default => "default",
+            Preset::Solarized => "solarized",
+            Preset::Nord => "nord",
+        })
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Preset::Default),
+            "solarized" => Ok(Preset::Solarized),
+            "nord" => Ok(Preset::Nord),
+            _ => Err(format!(
+                "unknown theme: {} (expected default, solarized, or nord)",
+                s
+            )),
+        }
+    }
+}
+
+/// A color from the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ThemeColor(pub Color);
+
+impl TryFrom<String> for ThemeColor {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl FromStr for ThemeColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Color::from_str(s).map(ThemeColor).map_err(|_| {
+            format!(
+                "unknown color: {} (expected a name like yellow, a number from 0 to 255, or #rrggbb)",
+                s
+            )
+        })
+    }
+}
+
+/// The `[theme]` section of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// The preset to start from: `default`, `solarized`, or `nord`.
+    pub name: Preset,
+    /// The `Search:` label.
+    pub prompt: Option<ThemeColor>,
+    /// The background of the selected row.
+    pub selection: Option<ThemeColor>,
+    /// The characters matching the query.
+    pub matched: Option<ThemeColor>,
+    /// The run count after each command.
+    pub count: Option<ThemeColor>,
+    /// The status bar's key hints and messages.
+    pub status: Option<ThemeColor>,
+    /// The program each command runs.
+    pub program: Option<ThemeColor>,
+    /// Options like `-v` and `--force`.
+    pub flag: Option<ThemeColor>,
+    /// Quoted strings.
+    pub string: Option<ThemeColor>,
+    /// Pipes, redirections, `&&` and the like, and shell keywords.
+    pub operator: Option<ThemeColor>,
+}
+
+impl ThemeConfig {
+    /// The configured theme, fitted to a terminal with `depth` colors.
+    pub fn theme(&self, depth: ColorDepth) -> Theme {
+        let preset = Theme::preset(self.name);
+        let pick =
+            |color: Option<ThemeColor>, fallback: Color| depth.fit(color.map_or(fallback, |c| c.0));
+        Theme {
+            prompt: pick(self.prompt, preset.prompt),
+            selection: pick(self.selection, preset.selection),
+            matched: pick(self.matched, preset.matched),
+            count: pick(self.count, preset.count),
+            status: pick(self.status, preset.status),
+            mark: depth.fit(preset.mark),
+            shells: depth.fit(preset.shells),
+            succeeded: depth.fit(preset.succeeded),
+            failed: depth.fit(preset.failed),
+            bookmark: depth.fit(preset.bookmark),
+            program: pick(self.program, preset.program),
+            flag: pick(self.flag, preset.flag),
+            string: pick(self.string, preset.string),
+            operator: pick(self.operator, preset.operator),
+        }
+    }
+}
+
+/// The colors the picker is drawn in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub prompt: Color,
+    pub selection: Color,
+    pub matched: Color,
+    pub count: Color,
+    pub status: Color,
+    /// The `*` beside commands marked with Tab.
+    pub mark: Color,
+    /// The shell badges of merged histories.
+    pub shells: Color,
+    /// The marks of commands whose last run succeeded or failed.
+    pub succeeded: Color,
+    pub failed: Color,
+    /// The `★` beside bookmarked commands, and the names of snippets.
+    pub bookmark: Color,
+    /// The parts of each command: the programs it runs, its flags, quoted
+    /// strings, and the pipes and other operators between them.
+    pub program: Color,
+    pub flag: Color,
+    pub string: Color,
+    pub operator: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::preset(Preset::Default)
+    }
+}
+
+impl Theme {
+    /// The color of the chip `tag` is shown in, always the same for the
+    /// same tag.
+    pub fn tag(&self, tag: &str) -> Color {
+        let colors = [
+            self.mark,
+            self.shells,
+            self.succeeded,
+            self.bookmark,
+            self.program,
+            self.operator,
+        ];
+        let hash = tag.bytes().fold(0usize, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(byte as usize)
+        });
+        colors[hash % colors.len()]
+    }
+
+    /// A built-in theme, as designed; see [`ColorDepth::fit`] for terminals
+    /// with fewer colors.
+    pub fn preset(preset: Preset) -> Theme {
+        match preset {
+            Preset::Default => Theme {
+                prompt: Color::Reset,
+                selection: Color::DarkGray,
+                matched: Color::Yellow,
+                count: Color::DarkGray,
+                status: Color::DarkGray,
+                mark: Color::Cyan,
+                shells: Color::Blue,
+                succeeded: Color::Green,
+                failed: Color::Red,
+                bookmark: Color::Yellow,
+                program: Color::LightBlue,
+                flag: Color::Cyan,
+                string: Color::Green,
+                operator: Color::Magenta,
+            },
+            Preset::Solarized => Theme {
+                prompt: Color::Rgb(0x26, 0x8b, 0xd2),
+                selection: Color::Rgb(0x07, 0x36, 0x42),
+                matched: Color::Rgb(0xb5, 0x89, 0x00),
+                count: Color::Rgb(0x58, 0x6e, 0x75),
+                status: Color::Rgb(0x65, 0x7b, 0x83),
+                mark: Color::Rgb(0x2a, 0xa1, 0x98),
+                shells: Color::Rgb(0x6c, 0x71, 0xc4),
+                succeeded: Color::Rgb(0x85, 0x99, 0x00),
+                failed: Color::Rgb(0xdc, 0x32, 0x2f),
+                bookmark: Color::Rgb(0xb5, 0x89, 0x00),
+                program: Color::Rgb(0x26, 0x8b, 0xd2),
+                flag: Color::Rgb(0x2a, 0xa1, 0x98),
+                string: Color::Rgb(0x85, 0x99, 0x00),
+                operator: Color::Rgb(0xd3, 0x36, 0x82),
+            },
+            Preset::Nord => Theme {
+                prompt: Color::Rgb(0x88, 0xc0, 0xd0),
+                selection: Color::Rgb(0x3b, 0x42, 0x52),
+                matched: Color::Rgb(0xeb, 0xcb, 0x8b),
+                count: Color::Rgb(0x4c, 0x56, 0x6a),
+                status: Color::Rgb(0x61, 0x6e, 0x88),
+                mark: Color::Rgb(0xa3, 0xbe, 0x8c),
+                shells: Color::Rgb(0x81, 0xa1, 0xc1),
+                succeeded: Color::Rgb(0xa3, 0xbe, 0x8c),
+                failed: Color::Rgb(0xbf, 0x61, 0x6a),
+                bookmark: Color::Rgb(0xeb, 0xcb, 0x8b),
+                program: Color::Rgb(0x88, 0xc0, 0xd0),
+                flag: Color::Rgb(0x8f, 0xbc, 0xbb),
+                string: Color::Rgb(0xa3, 0xbe, 0x8c),
+                operator: Color::Rgb(0x81, 0xa1, 0xc1),
+            },
+        }
+    }
+}
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// Any `#rrggbb` color.
+    TrueColor,
+    /// The xterm 256-color palette.
+    Ansi256,
+    /// The 16 basic colors.
+    Ansi16,
+}
+
+/// The xterm defaults for the 16 basic colors, to find the nearest one.
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// The levels of each channel in the 6×6×6 cube of the 256-color palette.
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorDepth {
+    /// Guesses the terminal's depth from `COLORTERM` and `TERM`. Windows
+    /// Terminal sets neither but always has truecolor.
+    pub fn detect() -> ColorDepth {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" || env::var_os("WT_SESSION").is_some() {
+            return ColorDepth::TrueColor;
+        }
+        match env::var("TERM") {
+            Ok(term) if term.contains("256color") => ColorDepth::Ansi256,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+
+    /// The closest color to `color` that this depth can show.
+    pub fn fit(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, _) => color,
+            (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(nearest_256(r, g, b)),
+            (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => nearest_16(r, g, b),
+            (ColorDepth::Ansi16, Color::Indexed(i)) if i >= 16 => {
+                let (r, g, b) = rgb_of_256(i);
+                nearest_16(r, g, b)
+            }
+            _ => color,
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn nearest_16(r: u8, g: u8, b: u8) -> Color {
+    ANSI16
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb, (r, g, b)))
+        .map_or(Color::Reset, |&(color, _)| color)
+}
+
+/// The nearest entry of the 256-color palette past the basic 16: either in
+/// the color cube or on the gray ramp, whichever is closer.
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |v: u8| {
+        (0..CUBE.len())
+            .min_by_key(|&i| (CUBE[i] as i32 - v as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray = 232 + ((average.saturating_sub(8)) / 10).min(23) as u8;
+    if distance(rgb_of_256(gray), (r, g, b)) < distance(rgb_of_256(cube), (r, g, b)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn rgb_of_256(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..=15 => ANSI16[i as usize].1,
+        16..=231 => {
+            let i = i - 16;
+            (
+                CUBE[(i / 36) as usize],
+                CUBE[(i / 6 % 6) as usize],
+                CUBE[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let v = 8 + 10 * (i - 232);
+            (v, v, v)
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Taking back deletions, bookmarks, and tags made in the picker.
+//!
+//! A deleted command leaves the results at once, but is only deleted from
+//! history when the picker closes, so that the undo key can bring it back
+//! until then. Bookmarks and tags are kept as they are made, and undoing
+//! one sets it back.
+
+use super::Deleter;
+use crate::search::CommandStats;
+
+/// One change made in the picker.
+pub(super) enum Change {
+    /// A command was deleted; `stats` is what it is put back with.
+    Deleted {
+        command: String,
+        stats: Box<CommandStats>,
+    },
+    /// A command was bookmarked, or its bookmark removed.
+    Bookmarked { command: String, added: bool },
+    /// A command was tagged, or a tag taken off it.
+    Tagged {
+        command: String,
+        tag: String,
+        added: bool,
+    },
+}
+
+/// The changes made so far, latest last, and where deletions go when the
+/// picker closes.
+pub(super) struct Undo<'a> {
+    changes: Vec<Change>,
+    delete: Option<&'a mut Deleter<'a>>,
+}
+
+impl<'a> Undo<'a> {
+    /// Deleting commands is possible only with a `delete` hook.
+    pub fn new(delete: Option<&'a mut Deleter<'a>>) -> Undo<'a> {
+        Undo {
+            changes: Vec::new(),
+            delete,
+        }
+    }
+
+    /// Whether commands can be deleted.
+    pub fn can_delete(&self) -> bool {
+        self.delete.is_some()
+    }
+
+    pub fn push(&mut self, change: Change) {
+        self.changes.push(change);
+    }
+
+    /// The latest change, taken off the stack.
+    pub fn pop(&mut self) -> Option<Change> {
+        self.changes.pop()
+    }
+
+    /// The commands deleted and not brought back.
+    pub fn deleted(&self) -> impl Iterator<Item = &str> {
+        self.changes.iter().filter_map(|change| match change {
+            Change::Deleted { command, .. } => Some(command.as_str()),
+            Change::Bookmarked { .. } | Change::Tagged { .. } => None,
+        })
+    }
+
+    /// Deletes the commands deleted in the picker from history, returning
+    /// what went wrong for each that could not be.
+    pub fn finish(self) -> Vec<String> {
+        let Some(delete) = self.delete else {
+            return Vec::new();
+        };
+        self.changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Deleted { command, .. } => delete(command)
+                    .err()
+                    .map(|err| format!("could not delete \"{}\": {}", command, err)),
+                Change::Bookmarked { .. } | Change::Tagged { .. } => None,
+            })
+            .collect()
+    }
+}
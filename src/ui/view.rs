@@ -0,0 +1,576 @@
+//! Drawing one frame of the picker.
+//!
+//! The screen is split into a prompt bar at the top, the result list with a
+//! scrollbar, an optional preview pane under it, and a status bar at the
+//! bottom. Drawing takes a [`View`] of the session and never changes it; the
+//! event loop owns all state.
+
+use super::group::{Groups, Header};
+use super::line::LineEditor;
+use super::status::StatusBar;
+use super::syntax::{self, Kind};
+use super::theme::Theme;
+use crate::search::query::Query;
+use crate::search::rank::Ranking;
+use crate::search::{self, rank, words};
+use crate::search::{CommandStats, Filter};
+use crate::security::redact;
+use crate::shell::Shell;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Block, Borders, HighlightSpacing, List, ListItem, ListState, Paragraph, Scrollbar,
+    ScrollbarOrientation, ScrollbarState,
+};
+use ratatui::Frame;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+const PROMPT: &str = "Search: ";
+const EDIT_PROMPT: &str = "Edit: ";
+
+/// Columns taken before the command on each row: the selection symbol and
+/// the mark.
+const ROW_PREFIX: usize = 4;
+
+/// The fewest rows a preview pane is opened in, its border included, and
+/// the fewest it leaves the list; with less room there is no pane.
+const MIN_PREVIEW_ROWS: u16 = 2;
+const MIN_LIST_ROWS: u16 = 3;
+
+/// How many days back a sparkline goes, one character each: as many as
+/// the runs are counted by day for.
+const SPARKLINE_DAYS: usize = search::RUN_DAYS;
+
+/// A sparkline's bars, from a day without runs to its busiest day.
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Where each part of the screen goes.
+pub(super) struct Areas {
+    pub prompt: Rect,
+    pub list: Rect,
+    pub preview: Option<Rect>,
+    pub status: Rect,
+}
+
+impl Areas {
+    /// Splits `area`, giving the preview pane `preview_lines` lines of text
+    /// (none for no pane).
+    ///
+    /// A terminal too short for everything loses the status bar first, at
+    /// two rows, and then the search line, leaving a single row of results.
+    pub fn new(area: Rect, preview_lines: usize) -> Areas {
+        let (prompt_rows, status_rows) = match area.height {
+            0 | 1 => (0, 0),
+            2 => (1, 0),
+            _ => (1, 1),
+        };
+        let [prompt, body, status] = Layout::vertical([
+            Constraint::Length(prompt_rows),
+            Constraint::Fill(1),
+            Constraint::Length(status_rows),
+        ])
+        .areas(area);
+        if preview_lines == 0 || body.height < MIN_LIST_ROWS + MIN_PREVIEW_ROWS {
+            return Areas {
+                prompt,
+                list: body,
+                preview: None,
+                status,
+            };
+        }
+        // One more row for the pane's border.
+        let height = (preview_lines as u16 + 1)
+            .min(body.height / 2)
+            .max(MIN_PREVIEW_ROWS);
+        let [list, preview] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(height)]).areas(body);
+        Areas {
+            prompt,
+            list,
+            preview: Some(preview),
+            status,
+        }
+    }
+
+    /// The most lines of text the preview pane may take in `area`.
+    pub fn max_preview_lines(area: Rect) -> usize {
+        (area.height.saturating_sub(2) / 2).saturating_sub(1).max(1) as usize
+    }
+}
+
+/// Everything a frame shows.
+pub(super) struct View<'a> {
+    pub query: &'a str,
+    /// The matching commands, best first.
+    pub results: &'a [(&'a str, &'a CommandStats)],
+    pub selected: usize,
+    /// The index of the first result on screen.
+    pub offset: usize,
+    pub marked: &'a [String],
+    pub matcher: &'a Query,
+    pub show_shells: bool,
+    /// How results are ranked, for marking those run in the current
+    /// repository.
+    pub ranking: &'a Ranking,
+    /// Which commands are shown at all.
+    pub filter: &'a Filter,
+    /// How the results are folded by prefix, if they are.
+    pub groups: Option<&'a Groups<'a>>,
+    /// The command being edited in place of the query, if one is.
+    pub editing: Option<&'a LineEditor>,
+    /// Whether what look like secrets are masked.
+    pub redact: bool,
+    /// Whether each result shows the parts of its score.
+    pub scores: bool,
+    /// Whether each result shows how often it ran on each recent day.
+    pub sparkline: bool,
+    pub theme: &'a Theme,
+    /// The preview pane's text, if it is open.
+    pub preview: Option<Vec<String>>,
+    /// Whether vim mode's normal mode is on, so the query is not being
+    /// typed.
+    pub normal: bool,
+    pub status: StatusBar<'a>,
+}
+
+impl View<'_> {
+    pub fn render(&self, frame: &mut Frame, areas: &Areas) {
+        self.render_prompt(frame, areas.prompt);
+        self.render_list(frame, areas.list);
+        if let (Some(area), Some(text)) = (areas.preview, &self.preview) {
+            let lines: Vec<Line> = text.iter().map(|line| Line::raw(line.as_str())).collect();
+            let block = Block::new()
+                .borders(Borders::TOP)
+                .border_style(Style::new().fg(self.theme.count))
+                .title(" Preview ");
+            frame.render_widget(Paragraph::new(lines).block(block), area);
+        }
+        if !areas.status.is_empty() {
+            self.status.render(frame, areas.status);
+        }
+    }
+
+    fn render_prompt(&self, frame: &mut Frame, area: Rect) {
+        if area.is_empty() {
+            return;
+        }
+        if let Some(line) = self.editing {
+            self.render_editing(frame, area, line);
+            return;
+        }
+        let prompt = Line::from(vec![
+            Span::styled(
+                PROMPT,
+                Style::new()
+                    .fg(self.theme.prompt)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(self.query),
+        ]);
+        frame.render_widget(prompt, area);
+        // Without a cursor, the terminal hides it.
+        if self.normal {
+            return;
+        }
+        let cursor = (PROMPT.width() + self.query.width()) as u16;
+        frame.set_cursor_position((area.x + cursor.min(area.width.saturating_sub(1)), area.y));
+    }
+
+    /// The command being edited, scrolled sideways to keep the cursor on
+    /// screen.
+    fn render_editing(&self, frame: &mut Frame, area: Rect, line: &LineEditor) {
+        let text = line.text().replace('\n', "⏎");
+        let column = line.text()[..line.cursor()].replace('\n', "⏎").width();
+        let room = (area.width as usize).saturating_sub(EDIT_PROMPT.width() + 1);
+        let mut hidden = 0;
+        let shown: String = text
+            .graphemes(true)
+            .skip_while(|grapheme| {
+                let skip = hidden < column.saturating_sub(room);
+                if skip {
+                    hidden += grapheme.width();
+                }
+                skip
+            })
+            .collect();
+        let prompt = Line::from(vec![
+            Span::styled(
+                EDIT_PROMPT,
+                Style::new()
+                    .fg(self.theme.prompt)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(shown),
+        ]);
+        frame.render_widget(prompt, area);
+        let cursor = (EDIT_PROMPT.width() + column - hidden) as u16;
+        frame.set_cursor_position((area.x + cursor.min(area.width.saturating_sub(1)), area.y));
+    }
+
+    fn render_list(&self, frame: &mut Frame, area: Rect) {
+        let window = self
+            .results
+            .iter()
+            .skip(self.offset)
+            .take(area.height as usize);
+        let items: Vec<ListItem> = window
+            .enumerate()
+            .map(|(i, &(cmd, stats))| {
+                let index = self.offset + i;
+                match self.groups {
+                    Some(groups) => match groups.header(index) {
+                        Some(header) => self.header_row(header),
+                        None => self.row(cmd, stats, groups.is_member(index)),
+                    },
+                    None => self.row(cmd, stats, false),
+                }
+            })
+            .collect();
+        let list = List::new(items)
+            .highlight_symbol("> ")
+            .highlight_spacing(HighlightSpacing::Always)
+            .highlight_style(
+                Style::new()
+                    .add_modifier(Modifier::BOLD)
+                    .bg(self.theme.selection),
+            );
+        let mut state = ListState::default()
+            .with_selected((!self.results.is_empty()).then(|| self.selected - self.offset));
+        frame.render_stateful_widget(list, area, &mut state);
+
+        let hidden = self.results.len().saturating_sub(area.height as usize);
+        if hidden > 0 {
+            let mut scrollbar = ScrollbarState::new(hidden).position(self.offset);
+            frame.render_stateful_widget(
+                Scrollbar::new(ScrollbarOrientation::VerticalRight),
+                area,
+                &mut scrollbar,
+            );
+        }
+    }
+
+    /// A group of results: whether it is expanded, the prefix with the
+    /// matched chars highlighted, the runs of all its commands, and how many
+    /// there are.
+    fn header_row(&self, header: &Header) -> ListItem<'static> {
+        let fold = if header.expanded { "▾ " } else { "▸ " };
+        let matched = self
+            .matcher
+            .fuzzy_match(header.prefix)
+            .map(|m| m.positions)
+            .unwrap_or_default();
+        let matched_style = Style::new()
+            .fg(self.theme.matched)
+            .add_modifier(Modifier::BOLD);
+        let mut spans = vec![Span::styled(fold, Style::new().fg(self.theme.mark))];
+        spans.extend(highlighted(
+            header.prefix,
+            &self.syntax(header.prefix),
+            &matched,
+            matched_style,
+            &[],
+            Style::new(),
+        ));
+        spans.push(Span::styled(
+            format!(" ({}) · {} commands", header.stats.count, header.commands),
+            Style::new().fg(self.theme.count),
+        ));
+        ListItem::new(Line::from(spans))
+    }
+
+    /// The colors of the parts of `cmd`.
+    fn syntax(&self, cmd: &str) -> Vec<(Range<usize>, Style)> {
+        syntax::tokens(cmd)
+            .into_iter()
+            .map(|(range, kind)| {
+                let color = match kind {
+                    Kind::Program => self.theme.program,
+                    Kind::Flag => self.theme.flag,
+                    Kind::String => self.theme.string,
+                    Kind::Operator => self.theme.operator,
+                };
+                (range, Style::new().fg(color))
+            })
+            .collect()
+    }
+
+    /// One result: its mark and bookmark, the command colored by its parts
+    /// with the matched chars highlighted, its run count and perhaps a
+    /// sparkline of its recent runs, and which shells ran it. Commands
+    /// listed under a group are indented.
+    fn row(&self, cmd: &str, stats: &CommandStats, member: bool) -> ListItem<'static> {
+        let mark = match (self.marked.iter().any(|m| m == cmd), member) {
+            (true, false) => "* ",
+            (false, false) => "  ",
+            (true, true) => "  * ",
+            (false, true) => "    ",
+        };
+        let matched = if self.filter.arguments {
+            words::fuzzy_match(self.matcher, cmd)
+        } else {
+            self.matcher.fuzzy_match(cmd)
+        };
+        let matched = matched.map(|m| m.positions).unwrap_or_default();
+        let secrets = if self.redact {
+            redact::secrets(cmd)
+        } else {
+            Vec::new()
+        };
+        let mut spans = vec![Span::styled(mark, Style::new().fg(self.theme.mark))];
+        if stats.bookmarked {
+            spans.push(Span::styled("★ ", Style::new().fg(self.theme.bookmark)));
+        }
+        if let Some(name) = &stats.snippet {
+            spans.push(Span::styled(
+                format!("{}: ", name),
+                Style::new().fg(self.theme.bookmark),
+            ));
+        }
+        let matched_style = Style::new()
+            .fg(self.theme.matched)
+            .add_modifier(Modifier::BOLD);
+        let masked_style = Style::new().fg(self.theme.count);
+        spans.extend(highlighted(
+            cmd,
+            &self.syntax(cmd),
+            &matched,
+            matched_style,
+            &secrets,
+            masked_style,
+        ));
+        // Bookmarks need not have been run.
+        if stats.count > 0 {
+            spans.push(Span::styled(
+                format!(" ({})", stats.count),
+                Style::new().fg(self.theme.count),
+            ));
+        }
+        // Without timestamps there is nothing to draw.
+        if self.sparkline && !stats.runs.is_empty() {
+            let per_day = stats.runs.per_day(SPARKLINE_DAYS, rank::now());
+            spans.push(Span::styled(
+                format!(" {}", sparkline(&per_day)),
+                Style::new().fg(self.theme.count),
+            ));
+        }
+        match stats.exit {
+            Some(0) => spans.push(Span::styled(" ✓", Style::new().fg(self.theme.succeeded))),
+            Some(_) => spans.push(Span::styled(" ✗", Style::new().fg(self.theme.failed))),
+            None => {}
+        }
+        if self.ranking.in_repo(&stats.dirs) {
+            spans.push(Span::styled(" ⎇", Style::new().fg(self.theme.mark)));
+        }
+        if self.show_shells && !stats.shells.is_empty() {
+            let names: Vec<&str> = stats.shells.iter().map(Shell::name).collect();
+            spans.push(Span::styled(
+                format!(" [{}]", names.join(" ")),
+                Style::new().fg(self.theme.shells),
+            ));
+        }
+        let dimmed = Style::new()
+            .fg(self.theme.count)
+            .add_modifier(Modifier::DIM);
+        if let Some(description) = &stats.description {
+            spans.push(Span::styled(format!("  {}", description), dimmed));
+        }
+        for tag in &stats.tags {
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(
+                format!(" {} ", tag),
+                Style::new().fg(Color::Black).bg(self.theme.tag(tag)),
+            ));
+        }
+        if self.scores {
+            let explained = search::explain(
+                self.matcher,
+                self.ranking,
+                self.filter.arguments,
+                cmd,
+                stats,
+            );
+            if let Some(score) = explained {
+                spans.push(Span::styled(
+                    format!("  {}", breakdown(&score)),
+                    Style::new().fg(self.theme.count),
+                ));
+            }
+        }
+        ListItem::new(Line::from(spans))
+    }
+}
+
+/// One bar per count, as tall against the others as the count is against
+/// the largest. Any runs at all get more than the lowest bar.
+fn sparkline(counts: &[usize]) -> String {
+    let most = counts.iter().copied().max().unwrap_or(0);
+    counts
+        .iter()
+        .map(|&count| match count {
+            0 => BARS[0],
+            _ => BARS[1 + (count * (BARS.len() - 1) - 1) / most],
+        })
+        .collect()
+}
+
+/// Like `= 37.4: match 24.0 + frecency 13.4`.
+fn breakdown(score: &rank::Score) -> String {
+    let parts: Vec<String> = score
+        .parts()
+        .into_iter()
+        .map(|(name, value)| format!("{} {:.1}", name, value))
+        .collect();
+    format!("= {:.1}: {}", score.total(), parts.join(" + "))
+}
+
+/// `text` split into spans for one row, with the byte ranges of `syntax` in
+/// their styles, the chars at `positions` in `style` over them, and the byte
+/// ranges of `secrets` masked in `masked_style`. Rows are one line high, so
+/// line breaks are shown as `⏎`; the preview has the later lines.
+fn highlighted(
+    text: &str,
+    syntax: &[(Range<usize>, Style)],
+    positions: &[usize],
+    style: Style,
+    secrets: &[Range<usize>],
+    masked_style: Style,
+) -> Vec<Span<'static>> {
+    let mut syntax = syntax.iter().peekable();
+    let mut positions = positions.iter().peekable();
+    let mut secrets = secrets.iter().peekable();
+    let mut spans = Vec::new();
+    // The run of unmatched graphemes so far, all in one style.
+    let mut plain = String::new();
+    let mut plain_style = Style::new();
+    let flush = |spans: &mut Vec<Span<'static>>, plain: &mut String, plain_style: Style| {
+        if !plain.is_empty() {
+            spans.push(Span::styled(std::mem::take(plain), plain_style));
+        }
+    };
+    // Positions count chars, but a grapheme like an accented letter or a
+    // flag is drawn as one, and is highlighted if any of its chars matched.
+    let mut i = 0;
+    for (at, grapheme) in text.grapheme_indices(true) {
+        let chars = grapheme.chars().count();
+        let matched = {
+            while positions.next_if(|&&p| p < i).is_some() {}
+            positions.peek().is_some_and(|&&p| p < i + chars)
+        };
+        i += chars;
+        while syntax.next_if(|(range, _)| range.end <= at).is_some() {}
+        while secrets.next_if(|secret| secret.end <= at).is_some() {}
+        let end = at + grapheme.len();
+        if let Some(secret) = secrets.peek().filter(|secret| secret.start < end) {
+            if secret.start >= at {
+                flush(&mut spans, &mut plain, plain_style);
+                spans.push(Span::styled(redact::MASK, masked_style));
+            }
+            continue;
+        }
+        let base = syntax
+            .peek()
+            .filter(|(range, _)| range.contains(&at))
+            .map_or(Style::new(), |&(_, style)| *style);
+        let grapheme = if matches!(grapheme, "\n" | "\r\n") {
+            "⏎"
+        } else {
+            grapheme
+        };
+        if matched {
+            flush(&mut spans, &mut plain, plain_style);
+            spans.push(Span::styled(grapheme.to_string(), base.patch(style)));
+        } else {
+            if base != plain_style {
+                flush(&mut spans, &mut plain, plain_style);
+                plain_style = base;
+            }
+            plain.push_str(grapheme);
+        }
+    }
+    flush(&mut spans, &mut plain, plain_style);
+    spans
+}
+
+/// Whether `command` is shown in full on its row of a list `width` wide.
+pub(super) fn fits_on_row(command: &str, width: u16) -> bool {
+    !command.contains('\n') && command.width() + ROW_PREFIX < width as usize
+}
+
+/// `line` cut into lines of at most `width` columns, between graphemes.
+pub(super) fn wrap(line: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for grapheme in line.graphemes(true) {
+        if !current.is_empty() && current.width() + grapheme.width() > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        current.push_str(grapheme);
+    }
+    lines.push(current);
+    lines
+}
+
+/// The preview pane's lines: `command` wrapped to `width` and cut to
+/// `max_lines`, then a line of what else is known about it.
+pub(super) fn preview_text(
+    command: &str,
+    stats: &CommandStats,
+    width: u16,
+    max_lines: usize,
+) -> Vec<String> {
+    let width = (width as usize).max(1);
+    let mut lines: Vec<String> = command.lines().flat_map(|line| wrap(line, width)).collect();
+    if lines.len() >= max_lines {
+        lines.truncate(max_lines.saturating_sub(1).max(1));
+        if let Some(last) = lines.last_mut() {
+            while last.width() + 1 > width {
+                match last.grapheme_indices(true).next_back() {
+                    Some((at, _)) => last.truncate(at),
+                    None => break,
+                }
+            }
+            last.push('…');
+        }
+    }
+
+    let mut details = vec![match stats.count {
+        1 => "run once".to_string(),
+        n => format!("run {} times", n),
+    }];
+    if let Some(last_used) = stats.last_used {
+        details.push(format!("last {}", ago(last_used, rank::now())));
+    }
+    if !stats.shells.is_empty() {
+        let names: Vec<&str> = stats.shells.iter().map(Shell::name).collect();
+        details.push(names.join(", "));
+    }
+    if let Some(cwd) = &stats.cwd {
+        details.push(format!("in {}", cwd));
+    }
+    match stats.exit {
+        Some(0) => details.push("succeeded".to_string()),
+        Some(status) => details.push(format!("failed with status {}", status)),
+        None => {}
+    }
+    lines.push(details.join(" · "));
+    lines
+}
+
+/// Describes how long before `now` the Unix time `then` was, e.g. "3 days
+/// ago".
+pub(super) fn ago(then: i64, now: i64) -> String {
+    let seconds = now.saturating_sub(then);
+    let (amount, unit) = match seconds {
+        i64::MIN..=59 => return "just now".to_string(),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86_399 => (seconds / 3600, "hour"),
+        86_400..=2_591_999 => (seconds / 86_400, "day"),
+        2_592_000..=31_535_999 => (seconds / 2_592_000, "month"),
+        _ => (seconds / 31_536_000, "year"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{} {}{} ago", amount, unit, plural)
+}